@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::chats;
+use crate::settings;
+
+// Local-only usage statistics. Nothing here is ever sent anywhere: counts
+// are kept in memory, written to analytics.json and shown by analytics_report.
+
+// Counts reach disk this often; analytics_report writes them right away
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+// Called continuously by the UI, so counting them says nothing
+const IGNORED: &[&str] = &["record_activity", "app_lock_status", "analytics_report"];
+
+// None until loaded from disk; `dirty` marks counts not written yet
+static STATS: Mutex<Option<(Stats, bool)>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureStats {
+    pub uses: u64,
+    pub errors: u64,
+    pub last_used: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Stats {
+    // When counting started, millis since the epoch
+    pub since: u64,
+    // Command name -> counts
+    pub features: BTreeMap<String, FeatureStats>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureReport {
+    pub feature: String,
+    pub uses: u64,
+    pub errors: u64,
+    // errors / uses, for the features that report failures
+    pub error_rate: f64,
+    pub last_used: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsReport {
+    pub enabled: bool,
+    pub since: u64,
+    // Most used first
+    pub features: Vec<FeatureReport>,
+}
+
+fn stats_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("analytics.json"))
+}
+
+fn load(app: &tauri::AppHandle) -> Result<Stats, String> {
+    let path = stats_path(app)?;
+    if !path.exists() {
+        return Ok(Stats { since: chats::now_millis() as u64, ..Default::default() });
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read analytics: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid analytics file: {}", e))
+}
+
+fn enabled(app: &tauri::AppHandle) -> bool {
+    settings::load(app).is_ok_and(|s| s.local_analytics)
+}
+
+fn update(app: &tauri::AppHandle, feature: &str, f: impl FnOnce(&mut FeatureStats)) {
+    if IGNORED.contains(&feature) || !enabled(app) {
+        return;
+    }
+    let Ok(mut stats) = STATS.lock() else {
+        return;
+    };
+    if stats.is_none() {
+        match load(app) {
+            Ok(loaded) => *stats = Some((loaded, false)),
+            Err(e) => {
+                log::warn!("Failed to load analytics: {}", e);
+                return;
+            }
+        }
+    }
+    if let Some((stats, dirty)) = stats.as_mut() {
+        f(stats.features.entry(feature.to_string()).or_default());
+        *dirty = true;
+    }
+}
+
+fn record_use(app: &tauri::AppHandle, feature: &str) {
+    update(app, feature, |stats| {
+        stats.uses += 1;
+        stats.last_used = chats::now_millis() as u64;
+    });
+}
+
+// Wraps the invoke handler so every command counts as a use of itself
+pub fn counted(
+    handler: impl Fn(tauri::ipc::Invoke) -> bool + Send + Sync + 'static,
+) -> impl Fn(tauri::ipc::Invoke) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        record_use(invoke.message.webview_ref().app_handle(), invoke.message.command());
+        handler(invoke)
+    }
+}
+
+// Counts a failure of `feature` when `result` is an error and passes it on
+pub fn track<T>(app: &tauri::AppHandle, feature: &str, result: Result<T, String>) -> Result<T, String> {
+    if result.is_err() {
+        update(app, feature, |stats| stats.errors += 1);
+    }
+    result
+}
+
+fn flush(app: &tauri::AppHandle) -> Result<(), String> {
+    let mut stats = STATS.lock().map_err(|e| e.to_string())?;
+    let Some((stats, dirty)) = stats.as_mut().filter(|(_, dirty)| *dirty) else {
+        return Ok(());
+    };
+    let content = serde_json::to_string_pretty(stats).map_err(|e| e.to_string())?;
+    fs::write(stats_path(app)?, content).map_err(|e| format!("Failed to write analytics: {}", e))?;
+    *dirty = false;
+    Ok(())
+}
+
+pub fn schedule_flush(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(FLUSH_INTERVAL);
+        if let Err(e) = flush(&app) {
+            log::warn!("Failed to save analytics: {}", e);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn analytics_report(app: tauri::AppHandle) -> Result<AnalyticsReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    flush(&app)?;
+    let stats = match STATS.lock().map_err(|e| e.to_string())?.as_ref() {
+        Some((stats, _)) => stats.clone(),
+        None => load(&app)?,
+    };
+    let mut features: Vec<FeatureReport> = stats
+        .features
+        .into_iter()
+        .map(|(feature, s)| FeatureReport {
+            feature,
+            uses: s.uses,
+            errors: s.errors,
+            error_rate: if s.uses == 0 { 0.0 } else { s.errors as f64 / s.uses as f64 },
+            last_used: s.last_used,
+        })
+        .collect();
+    features.sort_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.feature.cmp(&b.feature)));
+    Ok(AnalyticsReport { enabled: enabled(&app), since: stats.since, features })
+}
+
+// Deletes the counts and starts over
+#[tauri::command]
+pub fn reset_analytics(app: tauri::AppHandle) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut stats = STATS.lock().map_err(|e| e.to_string())?;
+    *stats = None;
+    let path = stats_path(&app)?;
+    if path.exists() {
+        fs::remove_file(path).map_err(|e| format!("Failed to delete analytics: {}", e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::completion::{ChatMessage, Completion};
+use crate::settings::{ProviderConfig, Settings};
+
+// Swaps names, email addresses and long numbers for placeholders like
+// [NAME_1] before a request goes to a cloud provider, and puts the originals
+// back in what comes out. The mapping lives in memory only, one per chat, so
+// a person keeps the same placeholder for as long as the app runs.
+
+// Shorter digit runs (years, counts, prices) are left readable
+const MIN_NUMBER_DIGITS: usize = 5;
+// Longest placeholder the streaming restorer waits for, e.g. "[NUMBER_123]"
+const MAX_PLACEHOLDER_LEN: usize = 16;
+// Capitalised words that start sentences rather than names
+const STOPWORDS: &[&str] = &[
+    "A", "An", "And", "Are", "As", "At", "But", "By", "Can", "Dear", "Do", "For", "From", "He", "Hello", "Her",
+    "Hi", "His", "How", "I", "If", "In", "Is", "It", "Its", "My", "Of", "On", "Or", "Our", "Please", "She", "So",
+    "Thanks", "That", "The", "Their", "These", "They", "This", "Those", "To", "We", "What", "When", "Where",
+    "Which", "Who", "Why", "With", "You", "Your",
+];
+
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+static MAPPINGS: Mutex<Option<HashMap<String, Mapping>>> = Mutex::new(None);
+
+// What a request is anonymized with; set on CompletionRequest by key()
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    // The chat id, or a one-off key for requests outside a chat
+    pub key: String,
+    pub names: Vec<String>,
+    pub detect_names: bool,
+}
+
+#[derive(Default)]
+struct Mapping {
+    placeholders: HashMap<String, String>,
+    originals: HashMap<String, String>,
+    counts: HashMap<&'static str, usize>,
+}
+
+impl Mapping {
+    fn placeholder(&mut self, kind: &'static str, original: &str) -> String {
+        if let Some(placeholder) = self.placeholders.get(original) {
+            return placeholder.clone();
+        }
+        let count = self.counts.entry(kind).or_default();
+        *count += 1;
+        let placeholder = format!("[{}_{}]", kind, count);
+        self.placeholders.insert(original.to_string(), placeholder.clone());
+        self.originals.insert(placeholder.clone(), original.to_string());
+        placeholder
+    }
+}
+
+// Some(scope) when requests to `provider` should be anonymized, which is
+// whenever they leave this machine. Without a chat the key is one-off and
+// the caller should forget it afterwards.
+pub fn key(settings: &Settings, provider: &ProviderConfig, chat_id: Option<&str>) -> Option<Scope> {
+    let anonymize = &settings.anonymize;
+    if !anonymize.enabled || provider.is_on_device() {
+        return None;
+    }
+    let key = match chat_id {
+        Some(id) => id.to_string(),
+        None => format!("request_{}", NEXT_KEY.fetch_add(1, Ordering::Relaxed)),
+    };
+    Some(Scope { key, names: anonymize.names.clone(), detect_names: anonymize.detect_names })
+}
+
+pub fn forget(key: &str) {
+    if let Ok(mut mappings) = MAPPINGS.lock() {
+        if let Some(mappings) = mappings.as_mut() {
+            mappings.remove(key);
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn at_word_boundary(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+// Configured names, matched whole-word and ignoring ASCII case
+fn find_names(text: &str, names: &[String], spans: &mut Vec<(usize, usize, &'static str)>) {
+    let bytes = text.as_bytes();
+    for name in names.iter().map(|n| n.trim()).filter(|n| n.len() >= 2) {
+        for (start, _) in text.char_indices() {
+            let end = start + name.len();
+            if end <= bytes.len()
+                && text.is_char_boundary(end)
+                && bytes[start..end].eq_ignore_ascii_case(name.as_bytes())
+                && at_word_boundary(text, start, end)
+            {
+                spans.push((start, end, "NAME"));
+            }
+        }
+    }
+}
+
+// Two or more capitalised words in a row, e.g. "Jane Doe"
+fn detect_names(text: &str, spans: &mut Vec<(usize, usize, &'static str)>) {
+    let is_capitalised = |word: &str| {
+        let mut chars = word.chars();
+        chars.next().is_some_and(char::is_uppercase) && word.chars().count() > 1 && chars.all(char::is_lowercase)
+    };
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (c.is_alphabetic(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let mut run: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in words.into_iter().chain(std::iter::once((text.len(), text.len()))) {
+        let word = &text[start..end];
+        // Only a single space joins the words of a name
+        let joined = run.last().is_some_and(|&(_, last)| &text[last..start] == " ");
+        if !joined {
+            end_run(&mut run, spans);
+        }
+        if is_capitalised(word) && !(run.is_empty() && STOPWORDS.contains(&word)) {
+            run.push((start, end));
+        } else {
+            end_run(&mut run, spans);
+        }
+    }
+}
+
+fn end_run(run: &mut Vec<(usize, usize)>, spans: &mut Vec<(usize, usize, &'static str)>) {
+    if run.len() >= 2 {
+        spans.push((run[0].0, run[run.len() - 1].1, "NAME"));
+    }
+    run.clear();
+}
+
+fn is_email(token: &str) -> bool {
+    let Some((local, domain)) = token.split_once('@') else {
+        return false;
+    };
+    let tld = domain.rsplit('.').next().unwrap_or_default();
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn find_emails(text: &str, spans: &mut Vec<(usize, usize, &'static str)>) {
+    let is_email_char = |c: char| c.is_ascii_alphanumeric() || "._%+-@".contains(c);
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (is_email_char(c), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                let token = text[s..i].trim_end_matches('.');
+                if is_email(token) {
+                    spans.push((s, s + token.len(), "EMAIL"));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Phone, account and card numbers: digit runs with the usual separators
+fn find_numbers(text: &str, spans: &mut Vec<(usize, usize, &'static str)>) {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let next_is_digit = chars.get(i + 1).is_some_and(|(_, n)| n.is_ascii_digit());
+        let starts_number = c.is_ascii_digit() || (c == '+' && next_is_digit);
+        if !starts_number || text[..start].chars().next_back().is_some_and(is_word_char) {
+            i += 1;
+            continue;
+        }
+        let (mut j, mut digits) = (i + 1, c.is_ascii_digit() as usize);
+        while j < chars.len() {
+            if chars[j].1.is_ascii_digit() {
+                digits += 1;
+                j += 1;
+                continue;
+            }
+            // Up to two separators, as in "(02) 1234-5678"
+            let separators = chars[j..].iter().take(2).take_while(|(_, c)| " -.()".contains(*c)).count();
+            if separators > 0 && chars.get(j + separators).is_some_and(|(_, c)| c.is_ascii_digit()) {
+                j += separators;
+            } else {
+                break;
+            }
+        }
+        let end = chars.get(j).map(|(p, _)| *p).unwrap_or(text.len());
+        if digits >= MIN_NUMBER_DIGITS && !text[end..].chars().next().is_some_and(is_word_char) {
+            spans.push((start, end, "NUMBER"));
+        }
+        i = j;
+    }
+}
+
+fn anonymize_text(mapping: &mut Mapping, scope: &Scope, text: &str) -> String {
+    let mut spans = Vec::new();
+    find_names(text, &scope.names, &mut spans);
+    find_emails(text, &mut spans);
+    find_numbers(text, &mut spans);
+    if scope.detect_names {
+        detect_names(text, &mut spans);
+    }
+    // Earliest first, and the longer of two that start together
+    spans.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end, kind) in spans {
+        if start < cursor {
+            continue;
+        }
+        out.push_str(&text[cursor..start]);
+        out.push_str(&mapping.placeholder(kind, &text[start..end]));
+        cursor = end;
+    }
+    out.push_str(&text[cursor..]);
+    out
+}
+
+// Anonymizes message text and tool call arguments in place
+pub fn messages(scope: &Scope, messages: &mut [ChatMessage]) -> Result<(), String> {
+    let mut mappings = MAPPINGS.lock().map_err(|e| e.to_string())?;
+    let mapping = mappings.get_or_insert_with(HashMap::new).entry(scope.key.clone()).or_default();
+    for message in messages {
+        message.content = anonymize_text(mapping, scope, &message.content);
+        for call in &mut message.tool_calls {
+            call.arguments = anonymize_text(mapping, scope, &call.arguments);
+        }
+    }
+    Ok(())
+}
+
+// Puts the originals back for every placeholder the mapping knows
+pub fn restore(key: &str, text: &str) -> String {
+    let Ok(mappings) = MAPPINGS.lock() else {
+        return text.to_string();
+    };
+    let Some(mapping) = mappings.as_ref().and_then(|m| m.get(key)) else {
+        return text.to_string();
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let candidate = rest[open..].find(']').map(|close| &rest[open..=open + close]);
+        match candidate.and_then(|c| mapping.originals.get(c).map(|original| (c, original))) {
+            Some((placeholder, original)) => {
+                out.push_str(original);
+                rest = &rest[open + placeholder.len()..];
+            }
+            None => {
+                out.push('[');
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn restore_completion(key: &str, completion: &mut Completion) {
+    completion.content = restore(key, &completion.content);
+    for call in &mut completion.tool_calls {
+        call.arguments = restore(key, &call.arguments);
+    }
+}
+
+// Restores streamed text, holding back what could be the start of a
+// placeholder split across deltas
+pub struct Restorer {
+    key: String,
+    pending: String,
+}
+
+impl Restorer {
+    pub fn new(key: &str) -> Self {
+        Self { key: key.to_string(), pending: String::new() }
+    }
+
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let split = self
+            .pending
+            .rfind('[')
+            .filter(|&open| !self.pending[open..].contains(']') && self.pending.len() - open < MAX_PLACEHOLDER_LEN)
+            .unwrap_or(self.pending.len());
+        let ready: String = self.pending.drain(..split).collect();
+        restore(&self.key, &ready)
+    }
+
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        restore(&self.key, &rest)
+    }
+}
@@ -0,0 +1,355 @@
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::pbkdf2;
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::chats;
+use crate::encryption;
+use crate::settings;
+
+pub const LOCKED: &str = "Anchor is locked";
+// Sent when the app locks so the UI can switch to the unlock screen
+pub const LOCKED_EVENT: &str = "locked";
+const HASH_LEN: usize = 32;
+const BIOMETRIC_REASON: &str = "unlock Anchor";
+// How often the idle timeout is checked
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+static APP_LOCKED: AtomicBool = AtomicBool::new(false);
+// Millis since the epoch of the last command or reported UI activity
+static LAST_ACTIVITY: AtomicU64 = AtomicU64::new(0);
+
+// Written to lock.json when a lock passphrase is set. Only a PBKDF2 hash of
+// it is kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Config {
+    salt: String,
+    iterations: u32,
+    hash: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppLockStatus {
+    pub enabled: bool,
+    pub locked: bool,
+    // Whether this platform can unlock with Touch ID or Windows Hello
+    pub biometric_available: bool,
+}
+
+fn config_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("lock.json"))
+}
+
+fn config(app: &tauri::AppHandle) -> Result<Option<Config>, String> {
+    let path = config_path(app)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read lock settings: {}", e))?;
+    serde_json::from_str(&content).map(Some).map_err(|e| format!("Invalid lock settings: {}", e))
+}
+
+fn iterations(config: &Config) -> NonZeroU32 {
+    NonZeroU32::new(config.iterations.max(1)).unwrap()
+}
+
+fn verify(config: &Config, passphrase: &str) -> Result<(), String> {
+    let salt = STANDARD.decode(&config.salt).map_err(|e| e.to_string())?;
+    let hash = STANDARD.decode(&config.hash).map_err(|e| e.to_string())?;
+    pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, iterations(config), &salt, passphrase.as_bytes(), &hash)
+        .map_err(|_| "Wrong passphrase".to_string())
+}
+
+fn hash(passphrase: &str) -> Result<Config, String> {
+    let salt = encryption::random(encryption::SALT_LEN)?;
+    let mut config = Config { salt: STANDARD.encode(&salt), iterations: encryption::ITERATIONS, hash: String::new() };
+    let mut hash = [0u8; HASH_LEN];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations(&config), &salt, passphrase.as_bytes(), &mut hash);
+    config.hash = STANDARD.encode(hash);
+    Ok(config)
+}
+
+pub fn enabled(app: &tauri::AppHandle) -> bool {
+    config_path(app).is_ok_and(|path| path.exists())
+}
+
+pub fn is_locked() -> bool {
+    APP_LOCKED.load(Ordering::SeqCst)
+}
+
+pub fn touch() {
+    LAST_ACTIVITY.store(chats::now_millis() as u64, Ordering::SeqCst);
+}
+
+// For every command that reads or changes user data, see the test below;
+// using them counts as activity
+pub fn ensure_unlocked() -> Result<(), String> {
+    if is_locked() {
+        return Err(LOCKED.to_string());
+    }
+    touch();
+    Ok(())
+}
+
+// Enters the locked state when a passphrase is set and drops the chat store
+// key when it came from a passphrase. `reason` ("manual", "minimized" or
+// "idle") goes out with the locked event.
+pub fn lock(app: &tauri::AppHandle, reason: &str) {
+    let dropped_key = encryption::lock(app);
+    let locked = enabled(app) && !APP_LOCKED.swap(true, Ordering::SeqCst);
+    if dropped_key || locked {
+        log::info!("Locked ({})", reason);
+        let _ = app.emit(LOCKED_EVENT, reason);
+    }
+}
+
+// Starts locked when a passphrase is set and lock-on-startup is on
+pub fn init(app: &tauri::AppHandle) {
+    let lock_on_startup = settings::load(app).map(|s| s.app_lock.lock_on_startup).unwrap_or(true);
+    if enabled(app) && lock_on_startup {
+        APP_LOCKED.store(true, Ordering::SeqCst);
+    }
+    touch();
+}
+
+// Locks once nothing has happened for the idle timeout in settings
+pub fn schedule_idle_lock(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(IDLE_CHECK_INTERVAL);
+        let minutes = settings::load(&app).map(|s| s.app_lock.idle_timeout_minutes).unwrap_or(0);
+        let idle = (chats::now_millis() as u64).saturating_sub(LAST_ACTIVITY.load(Ordering::SeqCst));
+        if minutes > 0 && idle >= minutes * 60 * 1000 {
+            lock(&app, "idle");
+        }
+    });
+}
+
+// Locks when a window is minimized and lock-on-minimize is on
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if !matches!(event, tauri::WindowEvent::Resized(_)) || !window.is_minimized().unwrap_or(false) {
+        return;
+    }
+    let app = window.app_handle();
+    if settings::load(app).is_ok_and(|s| s.app_lock.lock_on_minimize) {
+        lock(app, "minimized");
+    }
+}
+
+fn biometric_available() -> bool {
+    cfg!(any(target_os = "macos", windows)) && !cfg!(mobile)
+}
+
+// Touch ID through LocalAuthentication, via JavaScript for Automation so no
+// native bridge is needed
+fn biometric_script() -> String {
+    format!(
+        "ObjC.import('LocalAuthentication'); ObjC.import('Foundation');
+         const context = $.LAContext.alloc.init;
+         let verified = null;
+         context.evaluatePolicyLocalizedReasonReply(1, '{}', (ok, error) => {{ verified = ok; }});
+         while (verified === null) {{
+           $.NSRunLoop.currentRunLoop.runUntilDate($.NSDate.dateWithTimeIntervalSinceNow(0.1));
+         }}
+         verified ? 'Verified' : 'Denied';",
+        BIOMETRIC_REASON
+    )
+}
+
+// Windows Hello through UserConsentVerifier
+fn hello_script() -> String {
+    format!(
+        "Add-Type -AssemblyName System.Runtime.WindowsRuntime; \
+         $null = [Windows.Security.Credentials.UI.UserConsentVerifier,Windows.Security.Credentials.UI,\
+         ContentType=WindowsRuntime]; \
+         $op = [Windows.Security.Credentials.UI.UserConsentVerifier]::RequestVerificationAsync('{}'); \
+         $asTask = [System.WindowsRuntimeSystemExtensions].GetMethods() | Where-Object {{ $_.Name -eq 'AsTask' \
+         -and $_.GetParameters().Count -eq 1 -and $_.GetParameters()[0].ParameterType.Name -eq 'IAsyncOperation`1' }} \
+         | Select-Object -First 1; \
+         $task = $asTask.MakeGenericMethod([Windows.Security.Credentials.UI.UserConsentVerificationResult])\
+         .Invoke($null, @($op)); \
+         $task.Wait(); $task.Result",
+        BIOMETRIC_REASON
+    )
+}
+
+// Asks the OS to verify the user. Mobile would need a native plugin, so it
+// isn't offered there.
+fn verify_biometric() -> Result<(), String> {
+    if !biometric_available() {
+        return Err("Biometric unlock isn't available on this platform".to_string());
+    }
+    let mut cmd = if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("osascript");
+        cmd.args(["-l", "JavaScript", "-e", &biometric_script()]);
+        cmd
+    } else {
+        let mut cmd = Command::new("powershell");
+        cmd.args(["-NoProfile", "-Command", &hello_script()]);
+        cmd
+    };
+    let output = cmd.output().map_err(|e| format!("Failed to start biometric check: {}", e))?;
+    let result = String::from_utf8_lossy(&output.stdout);
+    if output.status.success() && result.trim() == "Verified" {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(format!("Biometric check failed: {}", if stderr.trim().is_empty() { result.trim() } else { stderr.trim() }))
+}
+
+// Sets, changes or (with no passphrase) removes the lock passphrase. The
+// current one is needed when a passphrase is already set.
+#[tauri::command]
+pub async fn set_app_passphrase(
+    app: tauri::AppHandle,
+    passphrase: Option<String>,
+    current: Option<String>,
+) -> Result<(), String> {
+    ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Some(config) = config(&app)? {
+            verify(&config, current.as_deref().unwrap_or_default())?;
+        }
+        let path = config_path(&app)?;
+        match passphrase.filter(|p| !p.is_empty()) {
+            Some(passphrase) => {
+                let content = serde_json::to_string_pretty(&hash(&passphrase)?).map_err(|e| e.to_string())?;
+                fs::write(path, content).map_err(|e| format!("Failed to write lock settings: {}", e))?;
+            }
+            None if path.exists() => {
+                fs::remove_file(path).map_err(|e| format!("Failed to remove lock settings: {}", e))?;
+            }
+            None => {}
+        }
+        // Titles come and go with the lock outside the app, see os_search.rs
+        // and jump_list.rs
+        crate::os_search::configure(&app);
+        crate::jump_list::chats_changed();
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn lock_app(app: tauri::AppHandle) -> Result<(), String> {
+    if !enabled(&app) && !encryption::enabled(&app) {
+        return Err("No lock passphrase is set".to_string());
+    }
+    lock(&app, "manual");
+    Ok(())
+}
+
+// Called by the UI on user input so the idle timeout counts from there
+#[tauri::command]
+pub fn record_activity() {
+    if !is_locked() {
+        touch();
+    }
+}
+
+// Also unlocks a passphrase-encrypted chat store when it uses the same
+// passphrase; otherwise that still needs unlock_chat_store.
+#[tauri::command]
+pub async fn unlock(app: tauri::AppHandle, passphrase: String) -> Result<(), String> {
+    let config = config(&app)?.ok_or("No lock passphrase is set")?;
+    let attempt = passphrase.clone();
+    tauri::async_runtime::spawn_blocking(move || verify(&config, &attempt)).await.map_err(|e| e.to_string())??;
+    APP_LOCKED.store(false, Ordering::SeqCst);
+    touch();
+    if encryption::is_locked(&app) {
+        let _ = encryption::unlock_chat_store(app, passphrase).await;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unlock_with_biometrics(app: tauri::AppHandle) -> Result<(), String> {
+    if !settings::load(&app)?.app_lock.biometric_unlock {
+        return Err("Biometric unlock is turned off".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(verify_biometric).await.map_err(|e| e.to_string())??;
+    APP_LOCKED.store(false, Ordering::SeqCst);
+    touch();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn app_lock_status(app: tauri::AppHandle) -> AppLockStatus {
+    AppLockStatus { enabled: enabled(&app), locked: is_locked(), biometric_available: biometric_available() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    // Commands that work while locked: unlocking, and ones that touch no
+    // user data
+    const OPEN_WHILE_LOCKED: &[&str] = &[
+        "app_lock::lock_app",
+        "app_lock::unlock",
+        "app_lock::unlock_with_biometrics",
+        "app_lock::record_activity",
+        "app_lock::app_lock_status",
+        "encryption::unlock_chat_store",
+        "encryption::encryption_status",
+        "appearance::system_appearance",
+        "power::power_state",
+        "tts::stop_speaking",
+        "quick_prompt::hide_quick_prompt",
+        "mini_chat::hide_mini_chat",
+        "sync::sync_status",
+        "incognito::start_incognito_chat",
+        "tray::take_tray_action",
+        "deep_link::take_deep_link",
+        "instance::take_launch_arguments",
+        "chat_windows::chat_window_scope",
+    ];
+
+    fn handlers() -> Vec<(String, String)> {
+        let lib = include_str!("lib.rs");
+        let start = lib.find("generate_handler![").expect("no handler list") + "generate_handler![".len();
+        let end = start + lib[start..].find(']').expect("unterminated handler list");
+        lib[start..end]
+            .split(',')
+            .filter_map(|entry| entry.trim().split_once("::"))
+            .map(|(module, command)| (module.to_string(), command.to_string()))
+            .collect()
+    }
+
+    // From the command's signature to the closing brace at the start of a line
+    fn body<'a>(source: &'a str, command: &str) -> Option<&'a str> {
+        let start = [format!("pub fn {}(", command), format!("pub async fn {}(", command)]
+            .iter()
+            .find_map(|signature| source.find(signature.as_str()))?;
+        let end = source[start..].find("\n}\n")?;
+        Some(&source[start..start + end])
+    }
+
+    #[test]
+    fn every_command_checks_the_lock() {
+        let handlers = handlers();
+        assert!(handlers.len() > 100, "the handler list wasn't read");
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut unchecked = Vec::new();
+        for (module, command) in &handlers {
+            let name = format!("{}::{}", module, command);
+            if OPEN_WHILE_LOCKED.contains(&name.as_str()) {
+                continue;
+            }
+            let source = std::fs::read_to_string(src.join(format!("{}.rs", module))).unwrap_or_default();
+            if !body(&source, command).is_some_and(|body| body.contains("ensure_unlocked()?")) {
+                unchecked.push(name);
+            }
+        }
+        assert!(unchecked.is_empty(), "commands that don't check the app lock: {:?}", unchecked);
+    }
+}
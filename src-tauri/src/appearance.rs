@@ -0,0 +1,168 @@
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use crate::settings::{self, WindowTheme};
+use crate::transcription::find_in_path;
+
+// The OS's light or dark mode and accent color, read from where each system
+// keeps them rather than from the webview, whose prefers-color-scheme follows
+// the window's theme once that's pinned in settings. The UI gets them from
+// system_appearance and, when either changes, an event; window chrome follows
+// the window theme setting, and exports pick up the accent, see exports.rs.
+// Nothing tells the backend when the accent changes, so it's polled.
+
+const CHANGED_EVENT: &str = "system-appearance-changed";
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+// For when the OS has no accent color or it can't be read
+pub const DEFAULT_ACCENT: &str = "#0a84ff";
+
+static CURRENT: Mutex<Option<Appearance>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Appearance {
+    pub scheme: ColorScheme,
+    // "#rrggbb"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent: Option<String>,
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(find_in_path(program)?).args(args).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !text.is_empty()).then_some(text)
+}
+
+// The key is only there in dark mode, and AppleAccentColor is missing for
+// the default multicolor accent, which is blue
+#[cfg(target_os = "macos")]
+fn detect() -> Appearance {
+    let dark = output("defaults", &["read", "-g", "AppleInterfaceStyle"]).is_some_and(|s| s == "Dark");
+    let accent = match output("defaults", &["read", "-g", "AppleAccentColor"]).as_deref() {
+        Some("-1") => "#8c8c8c",
+        Some("0") => "#ff453a",
+        Some("1") => "#ff9f0a",
+        Some("2") => "#ffd60a",
+        Some("3") => "#32d74b",
+        Some("5") => "#bf5af2",
+        Some("6") => "#ff375f",
+        _ => "#0a84ff",
+    };
+    Appearance { scheme: if dark { ColorScheme::Dark } else { ColorScheme::Light }, accent: Some(accent.to_string()) }
+}
+
+// `    AccentColor    REG_DWORD    0xffd77800`
+#[cfg(target_os = "windows")]
+fn registry_dword(key: &str, value: &str) -> Option<u32> {
+    let out = output("reg", &["query", key, "/v", value])?;
+    let line = out.lines().find(|line| line.trim_start().starts_with(value))?;
+    u32::from_str_radix(line.split_whitespace().last()?.trim_start_matches("0x"), 16).ok()
+}
+
+// The accent is stored as 0xAABBGGRR
+#[cfg(target_os = "windows")]
+fn detect() -> Appearance {
+    let personalize = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize";
+    let light = registry_dword(personalize, "AppsUseLightTheme").map_or(true, |v| v != 0);
+    let accent = registry_dword(r"HKCU\Software\Microsoft\Windows\DWM", "AccentColor")
+        .map(|v| format!("#{:02x}{:02x}{:02x}", v & 0xff, (v >> 8) & 0xff, (v >> 16) & 0xff));
+    Appearance { scheme: if light { ColorScheme::Light } else { ColorScheme::Dark }, accent }
+}
+
+// GNOME's settings, which most other desktops mirror; accent colors are
+// GNOME 47 and later
+#[cfg(target_os = "linux")]
+fn detect() -> Appearance {
+    let gsetting = |key: &str| {
+        output("gsettings", &["get", "org.gnome.desktop.interface", key]).map(|v| v.trim_matches('\'').to_string())
+    };
+    let dark = match gsetting("color-scheme").as_deref() {
+        Some("prefer-dark") => true,
+        Some("prefer-light") => false,
+        _ => gsetting("gtk-theme").is_some_and(|theme| theme.to_lowercase().contains("dark")),
+    };
+    let accent = gsetting("accent-color").and_then(|name| {
+        let hex = match name.as_str() {
+            "blue" => "#3584e4",
+            "teal" => "#2190a4",
+            "green" => "#3a944a",
+            "yellow" => "#c88800",
+            "orange" => "#ed5b00",
+            "red" => "#e62d42",
+            "pink" => "#d56199",
+            "purple" => "#9141ac",
+            "slate" => "#6f8396",
+            _ => return None,
+        };
+        Some(hex.to_string())
+    });
+    Appearance { scheme: if dark { ColorScheme::Dark } else { ColorScheme::Light }, accent }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn detect() -> Appearance {
+    let _ = output;
+    Appearance { scheme: ColorScheme::Light, accent: None }
+}
+
+// Reads the appearance again and tells the UI when it changed
+fn refresh(app: &tauri::AppHandle) -> Appearance {
+    let appearance = detect();
+    let changed = CURRENT
+        .lock()
+        .is_ok_and(|mut current| current.replace(appearance.clone()).as_ref() != Some(&appearance));
+    if changed {
+        let _ = app.emit(CHANGED_EVENT, &appearance);
+    }
+    appearance
+}
+
+// The accent color as last read, for exported pages
+pub fn accent() -> String {
+    let current = CURRENT.lock().ok().and_then(|current| current.as_ref().and_then(|a| a.accent.clone()));
+    current.unwrap_or_else(|| DEFAULT_ACCENT.to_string())
+}
+
+pub fn init(app: &tauri::AppHandle) {
+    configure(app);
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        refresh(&app);
+        std::thread::sleep(POLL_INTERVAL);
+    });
+}
+
+// Applies the window theme setting to every window; called at startup and
+// when the settings are saved
+pub fn configure(app: &tauri::AppHandle) {
+    let theme = match settings::load(app).map(|s| s.window_theme).unwrap_or_default() {
+        WindowTheme::System => None,
+        WindowTheme::Light => Some(tauri::Theme::Light),
+        WindowTheme::Dark => Some(tauri::Theme::Dark),
+    };
+    app.set_theme(theme);
+}
+
+// Windows following the system hear about a switch before the poll does
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if matches!(event, tauri::WindowEvent::ThemeChanged(_)) {
+        let app = window.app_handle().clone();
+        std::thread::spawn(move || refresh(&app));
+    }
+}
+
+#[tauri::command]
+pub async fn system_appearance(app: tauri::AppHandle) -> Result<Appearance, String> {
+    tauri::async_runtime::spawn_blocking(move || refresh(&app)).await.map_err(|e| e.to_string())
+}
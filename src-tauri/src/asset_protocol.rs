@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{UriSchemeContext, UriSchemeResponder};
+
+use crate::attachments;
+use crate::thumbnails;
+
+// Served at anchor-asset://localhost/<attachment id> (http://anchor-asset.localhost/<id> on Windows)
+pub const SCHEME: &str = "anchor-asset";
+// Largest slice returned for an open-ended range, so media players stream
+// big files instead of pulling them into memory at once
+const MAX_RANGE_BYTES: u64 = 4 * 1024 * 1024;
+
+fn error(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(message.as_bytes().to_vec())
+        .unwrap()
+}
+
+// Single ranges only ("bytes=start-end", "bytes=start-" or "bytes=-suffix"),
+// which is all browsers send for media
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        (start, "") => {
+            let start: u64 = start.parse().ok()?;
+            (start, (start + MAX_RANGE_BYTES - 1).min(len.checked_sub(1)?))
+        }
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len.checked_sub(1)?)),
+    };
+    (start <= end && start < len).then_some((start, end))
+}
+
+// "/<id>" is the stored file, "/<id>/thumbnail?size=<px>" a cached thumbnail
+fn resolve(app: &tauri::AppHandle, request: &Request<Vec<u8>>) -> Result<(PathBuf, String), (StatusCode, String)> {
+    let not_found = |e: String| (StatusCode::NOT_FOUND, e);
+    let mut segments = request.uri().path().trim_matches('/').split('/');
+    let id = segments.next().unwrap_or_default();
+
+    match segments.next() {
+        None => {
+            let attachment = attachments::get(app, id).map_err(not_found)?;
+            Ok((attachments::path(app, id).map_err(not_found)?, attachment.mime))
+        }
+        Some("thumbnail") => {
+            let size = request
+                .uri()
+                .query()
+                .and_then(|q| q.split('&').find_map(|pair| pair.strip_prefix("size=")))
+                .and_then(|size| size.parse().ok());
+            let path = thumbnails::thumbnail(app, id, size).map_err(not_found)?;
+            let is_png = path.extension().is_some_and(|ext| ext == "png");
+            Ok((path, if is_png { "image/png" } else { "image/jpeg" }.to_string()))
+        }
+        Some(_) => Err(not_found(format!("Unknown asset path: {}", request.uri().path()))),
+    }
+}
+
+fn serve(app: &tauri::AppHandle, request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, (StatusCode, String)> {
+    // Checked without counting as activity, since the page loads these itself
+    if crate::app_lock::is_locked() {
+        return Err((StatusCode::FORBIDDEN, crate::app_lock::LOCKED.to_string()));
+    }
+    let (path, mime) = resolve(app, request)?;
+
+    let mut file = File::open(&path).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let len = file
+        .metadata()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .len();
+
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        // Content-addressed, so a given URL never changes
+        .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable");
+
+    let range = request.headers().get(header::RANGE).and_then(|v| v.to_str().ok());
+    let Some(range) = range else {
+        let mut data = Vec::with_capacity(len as usize);
+        file.read_to_end(&mut data)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Ok(builder.body(data).unwrap());
+    };
+
+    let Some((start, end)) = parse_range(range, len) else {
+        return Ok(Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(Vec::new())
+            .unwrap());
+    };
+
+    let mut data = vec![0; (end - start + 1) as usize];
+    file.seek(SeekFrom::Start(start))
+        .and_then(|_| file.read_exact(&mut data))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(builder
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len))
+        .body(data)
+        .unwrap())
+}
+
+pub fn handle(ctx: UriSchemeContext<'_, tauri::Wry>, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    let app = ctx.app_handle().clone();
+    // File reads stay off the webview's thread
+    tauri::async_runtime::spawn_blocking(move || {
+        let response = serve(&app, &request).unwrap_or_else(|(status, message)| error(status, &message));
+        responder.respond(response);
+    });
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chats;
+use crate::completion::{ChatMessage, CompletionRequest};
+use crate::presets::PresetParams;
+use crate::templates;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assistant {
+    pub id: String,
+    pub name: String,
+    // Emoji or image path/URL, rendered by the UI
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub params: PresetParams,
+    // Tools the assistant may use; empty means none
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+fn assistants_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("assistants.json"))
+}
+
+pub fn load(app: &tauri::AppHandle) -> Result<Vec<Assistant>, String> {
+    let path = assistants_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read assistants: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid assistants file: {}", e))
+}
+
+pub fn save(app: &tauri::AppHandle, assistants: &[Assistant]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(assistants).map_err(|e| e.to_string())?;
+    fs::write(assistants_path(app)?, content).map_err(|e| format!("Failed to write assistants: {}", e))?;
+    crate::sync::record_change();
+    Ok(())
+}
+
+// Fills in whatever the request leaves open from the chat's assistant. Tools
+// are limited to the assistant's allowed set.
+pub fn apply(app: &tauri::AppHandle, request: &mut CompletionRequest) -> Result<(), String> {
+    let Some(session) = request.chat_id.as_deref().and_then(|id| chats::read(app, id).ok()) else {
+        return Ok(());
+    };
+    let Some(assistant_id) = session["assistantId"].as_str() else {
+        return Ok(());
+    };
+    let Some(assistant) = load(app)?.into_iter().find(|a| a.id == assistant_id) else {
+        log::warn!("Chat assistant {} no longer exists", assistant_id);
+        return Ok(());
+    };
+
+    if request.provider_id.is_none() {
+        request.provider_id = assistant.provider_id.clone();
+    }
+    if request.model.is_empty() {
+        request.model = assistant.model.clone().unwrap_or_default();
+    }
+
+    if request.tools.is_empty() {
+        request.tools = assistant.tools.clone();
+    } else {
+        request.tools.retain(|tool| assistant.tools.contains(tool));
+    }
+
+    assistant.params.fill(&mut request.options);
+
+    let has_system = request.messages.iter().any(|m| m.role == "system");
+    if !has_system && !assistant.system_prompt.trim().is_empty() {
+        let content = templates::render(app, &assistant.system_prompt, &HashMap::new()).unwrap_or_else(|e| {
+            log::warn!("Failed to render assistant prompt for {}: {}", assistant.name, e);
+            assistant.system_prompt.clone()
+        });
+        request.messages.insert(0, ChatMessage::new("system", content));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_assistants(app: tauri::AppHandle) -> Result<Vec<Assistant>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    load(&app)
+}
+
+#[tauri::command]
+pub fn save_assistant(app: tauri::AppHandle, mut assistant: Assistant) -> Result<Assistant, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if assistant.name.trim().is_empty() {
+        return Err("Assistant name is required".to_string());
+    }
+    for tool in &assistant.tools {
+        crate::tools::find(tool).ok_or_else(|| format!("Unknown tool: {}", tool))?;
+    }
+    if assistant.id.is_empty() {
+        assistant.id = format!("assistant_{}", chats::now_millis());
+    }
+
+    let mut assistants = load(&app)?;
+    match assistants.iter_mut().find(|a| a.id == assistant.id) {
+        Some(existing) => *existing = assistant.clone(),
+        None => assistants.push(assistant.clone()),
+    }
+    save(&app, &assistants)?;
+    Ok(assistant)
+}
+
+#[tauri::command]
+pub fn delete_assistant(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut assistants = load(&app)?;
+    assistants.retain(|a| a.id != id);
+    save(&app, &assistants)
+}
+
+#[tauri::command]
+pub fn assign_assistant(
+    app: tauri::AppHandle,
+    chat_id: String,
+    assistant_id: Option<String>,
+) -> Result<Option<Assistant>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut session = chats::read(&app, &chat_id)?;
+
+    let assistant = match &assistant_id {
+        Some(id) => Some(
+            load(&app)?
+                .into_iter()
+                .find(|a| &a.id == id)
+                .ok_or_else(|| format!("Unknown assistant: {}", id))?,
+        ),
+        None => None,
+    };
+
+    session["assistantId"] = json!(assistant_id);
+    chats::write(&app, session)?;
+    Ok(assistant)
+}
@@ -0,0 +1,634 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::completion::ChatMessage;
+use crate::shred;
+
+// Serializes index updates; commands run on a thread pool
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+static NEXT_INCOMING: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AttachmentSource {
+    Path(String),
+    Bytes(Vec<u8>),
+}
+
+// Files are stored once under their SHA-256; `id` is that hash. Every message
+// that points at an attachment holds one reference.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    pub ref_count: u32,
+}
+
+fn attachments_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("attachments");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create attachments directory: {}", e))?;
+    }
+    Ok(dir)
+}
+
+fn index_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(attachments_dir(app)?.join("index.json"))
+}
+
+fn load_index(app: &tauri::AppHandle) -> Result<HashMap<String, Attachment>, String> {
+    let path = index_path(app)?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read attachment index: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid attachment index: {}", e))
+}
+
+fn save_index(app: &tauri::AppHandle, index: &HashMap<String, Attachment>) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    fs::write(index_path(app)?, content).map_err(|e| format!("Failed to write attachment index: {}", e))
+}
+
+fn valid_id(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Sharded by the first two hex chars so no single directory gets huge
+fn blob_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(&id[..2]).join(id)
+}
+
+pub(crate) fn mime_for(name: &str) -> &'static str {
+    let ext = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "txt" | "md" => "text/plain",
+        "json" => "application/json",
+        "csv" => "text/csv",
+        "tsv" => "text/tab-separated-values",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "m4a" => "audio/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+// Files are read through a buffer this size; only this much is ever in memory
+const COPY_BUFFER: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TooLarge {
+    pub name: String,
+    pub size: u64,
+    pub limit: u64,
+}
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is too large ({}, the limit is {})", self.name, format_bytes(self.size), format_bytes(self.limit))
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub(crate) fn hash_file(path: &Path) -> Result<(String, u64), String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut io::BufReader::with_capacity(COPY_BUFFER, file), &mut hasher).map_err(|e| e.to_string())?;
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+// Hashes whatever passes through on its way to disk
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: io::Write> io::Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Copies `source` to `dest` in one buffered pass, hashing as it goes, and
+// stops as soon as the file turns out to be over `limit`.
+fn copy_hashing(source: &Path, dest: &Path, name: &str, limit: u64) -> Result<(String, u64), String> {
+    let input = fs::File::open(source).map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let output = fs::File::create(dest).map_err(|e| format!("Failed to store attachment: {}", e))?;
+    let mut reader = io::BufReader::with_capacity(COPY_BUFFER, input).take(limit + 1);
+    let mut writer = HashingWriter { inner: io::BufWriter::with_capacity(COPY_BUFFER, output), hasher: Sha256::new() };
+
+    let size = io::copy(&mut reader, &mut writer).map_err(|e| format!("Failed to store attachment: {}", e))?;
+    if size > limit {
+        let size = fs::metadata(source).map(|m| m.len()).unwrap_or(size);
+        return Err(TooLarge { name: name.to_string(), size, limit }.to_string());
+    }
+    io::Write::flush(&mut writer).map_err(|e| format!("Failed to store attachment: {}", e))?;
+    Ok((format!("{:x}", writer.hasher.finalize()), size))
+}
+
+// Images up to the image limit are cleaned up in memory. Bigger ones, and
+// anything that isn't a readable image, are stored as they are; an image
+// over the limit is refused when it's sent anyway.
+fn strip_metadata(source: AttachmentSource, name: &str, limit: u64) -> Result<AttachmentSource, String> {
+    let bytes = match source {
+        AttachmentSource::Path(path) if mime_for(name).starts_with("image/") => {
+            if fs::metadata(&path).map(|m| m.len()).unwrap_or(0) > limit {
+                return Ok(AttachmentSource::Path(path));
+            }
+            fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?
+        }
+        AttachmentSource::Bytes(bytes) if bytes.len() as u64 <= limit => bytes,
+        source => return Ok(source),
+    };
+    crate::image_metadata::strip(bytes).map(AttachmentSource::Bytes)
+}
+
+pub fn save(app: &tauri::AppHandle, source: AttachmentSource, name: Option<String>) -> Result<Attachment, String> {
+    let dir = attachments_dir(app)?;
+    let settings = crate::settings::load(app)?;
+    let limit = settings.attachment_limits.max_file_bytes;
+
+    let name = name.or_else(|| match &source {
+        AttachmentSource::Path(path) => Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()),
+        AttachmentSource::Bytes(_) => None,
+    });
+    let name = name.unwrap_or_else(|| "attachment".to_string());
+    let source = if settings.strip_image_metadata {
+        strip_metadata(source, &name, settings.attachment_limits.max_image_bytes)?
+    } else {
+        source
+    };
+
+    // Both sources land in a staging file inside the store, then move into
+    // place. Its name is unique, since saves run side by side.
+    let suffix = crate::share::hex(&crate::encryption::random(4)?);
+    let partial = dir.join(format!("incoming_{}_{}.part", NEXT_INCOMING.fetch_add(1, Ordering::SeqCst), suffix));
+    let staged = match source {
+        AttachmentSource::Path(path) => {
+            copy_hashing(Path::new(&path), &partial, &name, limit).map(|(id, size)| (id, size, name))
+        }
+        AttachmentSource::Bytes(bytes) => {
+            let size = bytes.len() as u64;
+            if size > limit {
+                return Err(TooLarge { name, size, limit }.to_string());
+            }
+            let id = format!("{:x}", Sha256::digest(&bytes));
+            fs::write(&partial, bytes)
+                .map(|_| (id, size, name))
+                .map_err(|e| format!("Failed to store attachment: {}", e))
+        }
+    };
+    let (id, size, name) = match staged {
+        Ok(staged) => staged,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+    };
+
+    let result = (|| {
+        let _guard = INDEX_LOCK.lock().map_err(|e| e.to_string())?;
+        let mut index = load_index(app)?;
+
+        let blob = blob_path(&dir, &id);
+        if !blob.exists() {
+            fs::create_dir_all(blob.parent().unwrap()).map_err(|e| e.to_string())?;
+            fs::rename(&partial, &blob).map_err(|e| format!("Failed to store attachment: {}", e))?;
+        }
+
+        let attachment = index.entry(id.clone()).or_insert_with(|| Attachment {
+            id,
+            mime: mime_for(&name).to_string(),
+            name,
+            size,
+            ref_count: 0,
+        });
+        attachment.ref_count += 1;
+        let attachment = attachment.clone();
+        save_index(app, &index)?;
+        Ok(attachment)
+    })();
+
+    // Still there when the content was already stored, or on failure
+    let _ = fs::remove_file(&partial);
+    result
+}
+
+pub fn path(app: &tauri::AppHandle, id: &str) -> Result<PathBuf, String> {
+    if !valid_id(id) {
+        return Err(format!("Invalid attachment id: {}", id));
+    }
+    let path = blob_path(&attachments_dir(app)?, id);
+    if !path.exists() {
+        return Err(format!("Attachment not found: {}", id));
+    }
+    Ok(path)
+}
+
+pub fn get(app: &tauri::AppHandle, id: &str) -> Result<Attachment, String> {
+    load_index(app)?.remove(id).ok_or_else(|| format!("Attachment not found: {}", id))
+}
+
+// Drops one reference and deletes the file once nothing points at it.
+pub fn release(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    if !valid_id(id) {
+        return Err(format!("Invalid attachment id: {}", id));
+    }
+    let _guard = INDEX_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut index = load_index(app)?;
+    let Some(attachment) = index.get_mut(id) else {
+        return Ok(());
+    };
+
+    attachment.ref_count = attachment.ref_count.saturating_sub(1);
+    if attachment.ref_count == 0 {
+        index.remove(id);
+        let blob = blob_path(&attachments_dir(app)?, id);
+        if blob.exists() {
+            shred::remove_file(app, &blob).map_err(|e| format!("Failed to delete attachment: {}", e))?;
+        }
+        if let Err(e) = crate::thumbnails::remove(app, id) {
+            log::warn!("Failed to delete thumbnails for {}: {}", id, e);
+        }
+        if let Err(e) = crate::extraction::remove_cached(app, id) {
+            log::warn!("Failed to delete extracted text for {}: {}", id, e);
+        }
+    }
+    save_index(app, &index)
+}
+
+// Points images that reference an attachment at the stored file.
+pub fn resolve_images(app: &tauri::AppHandle, messages: &mut [ChatMessage]) -> Result<(), String> {
+    for image in messages.iter_mut().flat_map(|m| m.images.iter_mut()) {
+        if let (Some(id), None) = (&image.attachment_id, &image.path) {
+            image.path = Some(path(app, id)?.to_string_lossy().to_string());
+        }
+    }
+    Ok(())
+}
+
+// Refuses images and documents too big to send before any of them is read.
+pub fn check_limits(app: &tauri::AppHandle, messages: &[ChatMessage]) -> Result<(), TooLarge> {
+    let Ok(settings) = crate::settings::load(app) else {
+        return Ok(());
+    };
+    let limits = settings.attachment_limits;
+    let index = load_index(app).unwrap_or_default();
+
+    for message in messages {
+        for image in &message.images {
+            let (name, size) = match (&image.attachment_id, &image.path) {
+                (Some(id), _) => match index.get(id) {
+                    Some(attachment) => (attachment.name.clone(), attachment.size),
+                    None => continue,
+                },
+                (None, Some(path)) => match fs::metadata(path) {
+                    Ok(metadata) => (path.clone(), metadata.len()),
+                    Err(_) => continue,
+                },
+                (None, None) => continue,
+            };
+            if size > limits.max_image_bytes {
+                return Err(TooLarge { name, size, limit: limits.max_image_bytes });
+            }
+        }
+        // Audio is transcribed locally, so only its transcript is sent
+        let documents = message.attachments.iter().filter_map(|id| index.get(id));
+        for attachment in documents.filter(|a| !a.mime.starts_with("audio/")) {
+            if attachment.size > limits.max_document_bytes {
+                return Err(TooLarge {
+                    name: attachment.name.clone(),
+                    size: attachment.size,
+                    limit: limits.max_document_bytes,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Attachment ids a stored chat points at, one per reference
+pub fn referenced_by(session: &serde_json::Value) -> Vec<String> {
+    let mut ids = Vec::new();
+    for message in session["messages"].as_array().into_iter().flatten() {
+        for image in message["images"].as_array().into_iter().flatten() {
+            if let Some(id) = image["attachmentId"].as_str() {
+                ids.push(id.to_string());
+            }
+        }
+        for id in message["attachments"].as_array().into_iter().flatten() {
+            if let Some(id) = id.as_str() {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    ids
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedAttachment {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    pub chat_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupReport {
+    pub dry_run: bool,
+    pub scanned_chats: usize,
+    // Stored once but used by several chats
+    pub shared: Vec<SharedAttachment>,
+    // Bytes that separate per-chat copies of the shared files would have taken
+    pub shared_savings_bytes: u64,
+    // Images referenced by file path that were moved into the store
+    pub consolidated_references: usize,
+    pub fixed_ref_counts: usize,
+    pub removed_files: usize,
+    pub reclaimed_bytes: u64,
+}
+
+// Unreferenced attachments younger than this may belong to a chat the UI
+// hasn't saved yet
+const ORPHAN_GRACE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > ORPHAN_GRACE)
+}
+
+// Moves images that chats still reference by file path into the store, so
+// identical files across chats end up as one blob.
+fn consolidate_paths(app: &tauri::AppHandle, session: &mut serde_json::Value, dry_run: bool) -> Result<usize, String> {
+    let mut consolidated = 0;
+    let Some(messages) = session["messages"].as_array_mut() else {
+        return Ok(0);
+    };
+    for image in messages.iter_mut().filter_map(|m| m["images"].as_array_mut()).flatten() {
+        let Some(path) = image["path"].as_str().map(str::to_string) else {
+            continue;
+        };
+        if image.get("attachmentId").is_some() || !Path::new(&path).is_file() {
+            continue;
+        }
+        consolidated += 1;
+        if !dry_run {
+            let attachment = save(app, AttachmentSource::Path(path), None)?;
+            if let Some(image) = image.as_object_mut() {
+                image.remove("path");
+                image.insert("attachmentId".to_string(), serde_json::Value::String(attachment.id));
+            }
+        }
+    }
+    Ok(consolidated)
+}
+
+// Attachment id -> ids of the chats (and knowledge bases) referencing it,
+// once per reference
+fn chat_references(app: &tauri::AppHandle) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut references: HashMap<String, Vec<String>> = HashMap::new();
+    for session in crate::chats::list_chats(app.clone())? {
+        let chat_id = session["id"].as_str().unwrap_or_default().to_string();
+        for id in referenced_by(&session) {
+            references.entry(id).or_default().push(chat_id.clone());
+        }
+    }
+    for session in crate::incognito::sessions() {
+        let chat_id = session["id"].as_str().unwrap_or_default().to_string();
+        for id in referenced_by(&session) {
+            references.entry(id).or_default().push(chat_id.clone());
+        }
+    }
+    for (id, kb_id) in crate::knowledge::references(app)? {
+        references.entry(id).or_default().push(kb_id);
+    }
+    Ok(references)
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GcReport {
+    pub dry_run: bool,
+    // Attachments no chat or knowledge base references any more
+    pub orphans: Vec<Attachment>,
+    // Blobs missing from the index and leftovers from interrupted writes
+    pub stray_files: usize,
+    pub reclaimed_bytes: u64,
+}
+
+// Removes attachments that no chat references, going by the chats themselves
+// rather than the stored ref counts, which drift when messages are edited.
+pub fn collect_garbage(app: &tauri::AppHandle, dry_run: bool) -> Result<GcReport, String> {
+    let mut report = GcReport { dry_run, ..Default::default() };
+    let references = chat_references(app)?;
+
+    let dir = attachments_dir(app)?;
+    let _guard = INDEX_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut index = load_index(app)?;
+
+    let orphans: Vec<String> = index
+        .keys()
+        .filter(|id| !references.contains_key(*id) && is_stale(&blob_path(&dir, id)))
+        .cloned()
+        .collect();
+    for id in &orphans {
+        if let Some(attachment) = index.remove(id) {
+            report.reclaimed_bytes += attachment.size;
+            report.orphans.push(attachment);
+            if !dry_run {
+                let _ = shred::remove_file(app, &blob_path(&dir, id));
+                let _ = crate::thumbnails::remove(app, id);
+                let _ = crate::extraction::remove_cached(app, id);
+            }
+        }
+    }
+
+    for shard in fs::read_dir(&dir).map_err(|e| e.to_string())?.flatten() {
+        let shard_path = shard.path();
+        let stray_part = shard_path.extension().is_some_and(|ext| ext == "part") && is_stale(&shard_path);
+        if stray_part {
+            report.stray_files += 1;
+            report.reclaimed_bytes += fs::metadata(&shard_path).map(|m| m.len()).unwrap_or(0);
+            if !dry_run {
+                let _ = fs::remove_file(&shard_path);
+            }
+            continue;
+        }
+        if !shard_path.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&shard_path).map_err(|e| e.to_string())?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Orphans were counted above
+            let known = index.contains_key(&name) || orphans.contains(&name);
+            if known || !is_stale(&entry.path()) {
+                continue;
+            }
+            report.stray_files += 1;
+            report.reclaimed_bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            if !dry_run {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    if !dry_run {
+        save_index(app, &index)?;
+    }
+    Ok(report)
+}
+
+pub fn cleanup(app: &tauri::AppHandle, dry_run: bool) -> Result<CleanupReport, String> {
+    let mut report = CleanupReport { dry_run, ..Default::default() };
+
+    for mut session in crate::chats::list_chats(app.clone())? {
+        report.scanned_chats += 1;
+        let consolidated = consolidate_paths(app, &mut session, dry_run)?;
+        if consolidated > 0 && !dry_run {
+            crate::chats::write(app, session)?;
+        }
+        report.consolidated_references += consolidated;
+    }
+    let references = chat_references(app)?;
+
+    {
+        let _guard = INDEX_LOCK.lock().map_err(|e| e.to_string())?;
+        let mut index = load_index(app)?;
+        for (id, attachment) in index.iter_mut() {
+            let chats = references.get(id).cloned().unwrap_or_default();
+            let count = chats.len() as u32;
+            if count != attachment.ref_count {
+                report.fixed_ref_counts += 1;
+                attachment.ref_count = count;
+            }
+
+            let mut distinct = chats;
+            distinct.sort();
+            distinct.dedup();
+            if distinct.len() > 1 {
+                report.shared_savings_bytes += attachment.size * (distinct.len() as u64 - 1);
+                report.shared.push(SharedAttachment {
+                    id: id.clone(),
+                    name: attachment.name.clone(),
+                    size: attachment.size,
+                    chat_ids: distinct,
+                });
+            }
+        }
+        if !dry_run {
+            save_index(app, &index)?;
+        }
+    }
+
+    let gc = collect_garbage(app, dry_run)?;
+    report.removed_files = gc.orphans.len() + gc.stray_files;
+    report.reclaimed_bytes = gc.reclaimed_bytes;
+    Ok(report)
+}
+
+// Runs collect_garbage in the background every `attachment_gc_interval_hours`,
+// re-reading the setting each round so changes apply without a restart.
+pub fn schedule_gc(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        let hours = crate::settings::load(&app).map(|s| s.attachment_gc_interval_hours).unwrap_or(0);
+        if hours > 0 {
+            match collect_garbage(&app, false) {
+                Ok(report) if !report.orphans.is_empty() || report.stray_files > 0 => log::info!(
+                    "Attachment GC removed {} orphans and {} stray files ({} bytes)",
+                    report.orphans.len(),
+                    report.stray_files,
+                    report.reclaimed_bytes
+                ),
+                Ok(_) => {}
+                Err(e) => log::warn!("Attachment GC failed: {}", e),
+            }
+        }
+        // Check hourly while disabled so turning it on takes effect
+        std::thread::sleep(std::time::Duration::from_secs(hours.max(1) * 60 * 60));
+    });
+}
+
+#[tauri::command]
+pub fn save_attachment(
+    app: tauri::AppHandle,
+    source: AttachmentSource,
+    name: Option<String>,
+) -> Result<Attachment, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let attachment = save(&app, source, name)?;
+    crate::transcription::schedule_auto(&app, &attachment.id);
+    Ok(attachment)
+}
+
+#[tauri::command]
+pub fn get_attachment_path(app: tauri::AppHandle, id: String) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    Ok(path(&app, &id)?.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn release_attachment(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    release(&app, &id)
+}
+
+#[tauri::command]
+pub async fn clean_up_attachments(app: tauri::AppHandle, dry_run: Option<bool>) -> Result<CleanupReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || cleanup(&app, dry_run.unwrap_or(true)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Lists (dry run, the default) or removes attachments no chat references.
+#[tauri::command]
+pub async fn collect_attachment_garbage(app: tauri::AppHandle, dry_run: Option<bool>) -> Result<GcReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || collect_garbage(&app, dry_run.unwrap_or(true)))
+        .await
+        .map_err(|e| e.to_string())?
+}
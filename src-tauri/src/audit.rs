@@ -0,0 +1,220 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri_plugin_http::reqwest::Url;
+
+use crate::chats;
+use crate::keychain;
+use crate::settings;
+
+// The last entry's sequence number and hash, kept in the OS keychain so
+// cutting entries off the end (or deleting the log) shows up too
+const HEAD_ACCOUNT: &str = "audit-log-head";
+
+// Set at startup; records come from places that have no handle of their own,
+// like the shared HTTP client
+static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+// Guards appends; holds the sequence number and hash of the last entry
+// once the log has been read
+static LAST: Mutex<Option<(u64, String)>> = Mutex::new(None);
+// The newest head not yet in the keychain, and whether a thread is storing
+// it; keychain calls are slow, and entries come from the HTTP client
+static PENDING_HEAD: Mutex<Option<(u64, String)>> = Mutex::new(None);
+static STORING_HEAD: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    // "network", "file", "shell" or "export"
+    pub kind: String,
+    pub detail: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+    // Whether every entry still hashes to what the next one recorded
+    pub intact: bool,
+    // Sequence number of the first entry that doesn't check out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broken_at: Option<u64>,
+}
+
+pub fn init(app: &tauri::AppHandle) {
+    let _ = APP.set(app.clone());
+}
+
+fn log_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("audit.jsonl"))
+}
+
+// Each entry's hash covers the previous entry's, so editing, reordering or
+// removing one breaks the chain from there on
+fn entry_hash(seq: u64, timestamp: u64, kind: &str, detail: &str, prev_hash: &str) -> String {
+    let digest = Sha256::digest(format!("{}\n{}\n{}\n{}\n{}", seq, timestamp, kind, detail, prev_hash).as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// The entries up to the first line that doesn't parse, and where that line
+// is. A mangled line is the chain breaking there, not a log that can't be
+// read at all.
+fn read_entries(path: &Path) -> Result<(Vec<AuditEntry>, Option<u64>), String> {
+    if !path.exists() {
+        return Ok((Vec::new(), None));
+    }
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read the audit log: {}", e))?;
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                log::warn!("Invalid audit log entry {}: {}", index, e);
+                return Ok((entries, Some(index as u64)));
+            }
+        }
+    }
+    Ok((entries, None))
+}
+
+fn stored_head() -> Option<(u64, String)> {
+    let head = keychain::load(HEAD_ACCOUNT).ok()?;
+    let (seq, hash) = head.split_once(' ')?;
+    Some((seq.parse().ok()?, hash.to_string()))
+}
+
+// One thread at a time writes the newest head, so an older one never lands
+// after a newer one
+fn store_head(seq: u64, hash: &str) {
+    if let Ok(mut pending) = PENDING_HEAD.lock() {
+        *pending = Some((seq, hash.to_string()));
+    }
+    if STORING_HEAD.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(|| loop {
+        match PENDING_HEAD.lock().ok().and_then(|mut pending| pending.take()) {
+            Some((seq, hash)) => {
+                if let Err(e) = keychain::store(HEAD_ACCOUNT, &format!("{} {}", seq, hash)) {
+                    log::warn!("Failed to keep the audit log head in the keychain: {}", e);
+                }
+            }
+            None => {
+                STORING_HEAD.store(false, Ordering::SeqCst);
+                // A head may have come in just before the flag dropped
+                let more = PENDING_HEAD.lock().is_ok_and(|pending| pending.is_some());
+                if !more || STORING_HEAD.swap(true, Ordering::SeqCst) {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+// The log may have grown past the stored head, but the entry the head names
+// has to still be there as it was
+fn verify(entries: &[AuditEntry], head: Option<(u64, String)>) -> Option<u64> {
+    let mut prev_hash = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let expected = entry_hash(entry.seq, entry.timestamp, &entry.kind, &entry.detail, &entry.prev_hash);
+        if entry.seq != index as u64 || entry.prev_hash != prev_hash || entry.hash != expected {
+            return Some(entry.seq);
+        }
+        prev_hash = entry.hash.clone();
+    }
+    let (seq, hash) = head?;
+    match entries.get(seq as usize) {
+        Some(entry) if entry.hash == hash => None,
+        _ => Some(seq.min(entries.len() as u64)),
+    }
+}
+
+fn append(app: &tauri::AppHandle, kind: &str, detail: &str) -> Result<(), String> {
+    let path = log_path(app)?;
+    let mut last = LAST.lock().map_err(|e| e.to_string())?;
+    if last.is_none() {
+        *last = read_entries(&path)?.0.last().map(|e| (e.seq, e.hash.clone()));
+    }
+    let (seq, prev_hash) = match last.as_ref() {
+        Some((seq, hash)) => (seq + 1, hash.clone()),
+        None => (0, String::new()),
+    };
+    let timestamp = chats::now_millis() as u64;
+    let hash = entry_hash(seq, timestamp, kind, detail, &prev_hash);
+    let (kind, detail) = (kind.to_string(), detail.to_string());
+    let entry = AuditEntry { seq, timestamp, kind, detail, prev_hash, hash: hash.clone() };
+    let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Failed to write the audit log: {}", e))?;
+    store_head(seq, &hash);
+    *last = Some((seq, hash));
+    Ok(())
+}
+
+// Adds an entry when the audit log is turned on. Failures are logged rather
+// than failing whatever was being audited.
+pub fn record(kind: &str, detail: &str) {
+    let Some(app) = APP.get() else {
+        return;
+    };
+    if !settings::load(app).is_ok_and(|s| s.audit_log) {
+        return;
+    }
+    if let Err(e) = append(app, kind, detail) {
+        log::warn!("Failed to record {} in the audit log: {}", kind, e);
+    }
+}
+
+// Query strings are left out; some APIs take keys there
+pub fn network(method: &str, url: &Url) {
+    let mut url = url.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    let _ = url.set_password(None);
+    record("network", &format!("{} {}", method, url));
+}
+
+#[tauri::command]
+pub fn audit_log(app: tauri::AppHandle, limit: Option<usize>) -> Result<AuditLog, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let _guard = LAST.lock().map_err(|e| e.to_string())?;
+    let (mut entries, bad_line) = read_entries(&log_path(&app)?)?;
+    let broken_at = bad_line.into_iter().chain(verify(&entries, stored_head())).min();
+    // Newest first
+    entries.reverse();
+    entries.truncate(limit.unwrap_or(usize::MAX));
+    Ok(AuditLog { entries, intact: broken_at.is_none(), broken_at })
+}
+
+// Copies the log as is, so the copy can be checked the same way, and
+// password-protected when a password is given. Returns the number of entries.
+#[tauri::command]
+pub fn export_audit_log(app: tauri::AppHandle, destination: String, password: Option<String>) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let _guard = LAST.lock().map_err(|e| e.to_string())?;
+    let path = log_path(&app)?;
+    let (entries, _) = read_entries(&path)?;
+    if entries.is_empty() {
+        return Err("The audit log is empty".to_string());
+    }
+    match password.filter(|p| !p.is_empty()) {
+        Some(password) => crate::sealed::encrypt_file(&path, Path::new(&destination), &password)?,
+        None => {
+            fs::copy(&path, &destination).map_err(|e| format!("Failed to export the audit log: {}", e))?;
+        }
+    }
+    Ok(entries.len())
+}
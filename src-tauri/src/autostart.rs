@@ -0,0 +1,54 @@
+use tauri::Manager;
+use tauri_plugin_autostart::AutoLaunchManager;
+
+use crate::settings;
+
+// Starting Anchor when the user logs in, through a launch agent on macOS, the
+// Run key in the registry on Windows and an autostart .desktop entry on
+// Linux. The login launch gets HIDDEN_ARG, and with start_hidden on it opens
+// to the tray with no window, so the quick-prompt shortcut is there from the
+// start without a window in the way.
+
+pub const HIDDEN_ARG: &str = "--hidden";
+
+pub fn plugin() -> tauri::plugin::TauriPlugin<tauri::Wry> {
+    // A launch agent on macOS is the builder's default
+    tauri_plugin_autostart::Builder::new().arg(HIDDEN_ARG).build()
+}
+
+// Whether this is the launch at login; its window waits, see on_setup
+pub fn hidden_launch(argv: &[String]) -> bool {
+    argv.iter().skip(1).any(|arg| arg == HIDDEN_ARG)
+}
+
+// Shows the window a login launch held back, unless it's meant to stay in
+// the tray. Without the tray icon there'd be no way back to it but the
+// shortcut, so it's shown then too.
+pub fn on_setup(app: &tauri::AppHandle, hidden: bool) {
+    if !hidden {
+        return;
+    }
+    let settings = settings::load(app).unwrap_or_default();
+    if settings.start_hidden && settings.tray_icon {
+        return;
+    }
+    if let Err(e) = crate::tray::show_window(app) {
+        log::warn!("{}", e);
+    }
+}
+
+// Registers or removes the login item to match the setting
+pub fn configure(app: &tauri::AppHandle) {
+    let Some(manager) = app.try_state::<AutoLaunchManager>() else {
+        return;
+    };
+    let wanted = settings::load(app).is_ok_and(|s| s.launch_at_login);
+    let result = match manager.is_enabled() {
+        Ok(enabled) if enabled == wanted => Ok(()),
+        _ if wanted => manager.enable(),
+        _ => manager.disable(),
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to {} launching at login: {}", if wanted { "turn on" } else { "turn off" }, e);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tauri::{Manager, WebviewUrl, WebviewWindowBuilder};
+
+// Chats opened in windows of their own, next to the main one. Each loads the
+// same UI, asks chat_window_scope which chat it's for and shows only that
+// one. Saves from all windows go through chats.rs one at a time, which
+// tells every window about each change and turns down a save from a window
+// that missed one.
+
+const LABEL_PREFIX: &str = "chat-";
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 640.0;
+
+// Window label -> chat id
+static SCOPES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+// Window labels only take letters, numbers and a few marks
+fn label(chat_id: &str) -> String {
+    let clean: String = chat_id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '-' }).collect();
+    format!("{}{}", LABEL_PREFIX, clean)
+}
+
+#[tauri::command]
+pub fn open_chat_window(app: tauri::AppHandle, id: String) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let session = crate::chats::read(&app, &id)?;
+    let label = label(&id);
+    if let Some(window) = app.get_webview_window(&label) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        window.set_focus().map_err(|e| e.to_string())?;
+        return Ok(label);
+    }
+    SCOPES.lock().map_err(|e| e.to_string())?.get_or_insert_with(HashMap::new).insert(label.clone(), id);
+
+    let title = session["title"].as_str().filter(|t| !t.trim().is_empty()).unwrap_or("Untitled chat");
+    let builder = WebviewWindowBuilder::new(&app, &label, WebviewUrl::App("index.html".into()))
+        .title(title)
+        .inner_size(WIDTH, HEIGHT)
+        .min_inner_size(360.0, 320.0);
+    // The same title bar as the main window
+    #[cfg(target_os = "macos")]
+    let builder = builder.title_bar_style(tauri::TitleBarStyle::Overlay).hidden_title(true);
+    let window = match builder.build() {
+        Ok(window) => window,
+        Err(e) => {
+            forget(&label);
+            return Err(format!("Failed to open the chat window: {}", e));
+        }
+    };
+    crate::window_state::restore(&window);
+    Ok(label)
+}
+
+fn forget(label: &str) {
+    if let Ok(mut scopes) = SCOPES.lock() {
+        if let Some(scopes) = scopes.as_mut() {
+            scopes.remove(label);
+        }
+    }
+}
+
+// The chat a window is for; none for the main window
+#[tauri::command]
+pub fn chat_window_scope(window: tauri::Window) -> Option<String> {
+    SCOPES.lock().ok()?.as_ref()?.get(window.label()).cloned()
+}
+
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if matches!(event, tauri::WindowEvent::Destroyed) && window.label().starts_with(LABEL_PREFIX) {
+        forget(window.label());
+    }
+}
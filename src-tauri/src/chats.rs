@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use crate::anonymize;
+use crate::app_lock;
+use crate::attachments;
+use crate::completion::ChatMessage;
+use crate::encryption;
+use crate::git_sync;
+use crate::history;
+use crate::incognito;
+use crate::shred;
+
+// Fields the backend maintains on a session. The UI saves whole sessions it
+// loaded earlier, so these are carried over from disk when it leaves them out.
+const BACKEND_KEYS: &[&str] = &[
+    "contextSummary",
+    "summary",
+    "tags",
+    "systemPrompt",
+    "assistantId",
+    "knowledgeBaseIds",
+    "retrieval",
+    "historyMemory",
+    "conflictOf",
+    "conflictedAt",
+    "conflictDevice",
+    "createdBy",
+    "fromIncognito",
+    "folder",
+    "gist",
+    "crdtClock",
+    "deletedMessages",
+];
+
+// Sent to every window when a chat is saved or deleted, so windows showing
+// the same chat stay current
+const CHANGED_EVENT: &str = "chat-changed";
+
+// Saves from several windows and background work go one at a time, and
+// wait while encryption is turned on or off, see encryption.rs
+pub(crate) static WRITE_LOCK: Mutex<()> = Mutex::new(());
+// Chat id -> label of the window that last saved it, see save_chat
+static LAST_WRITER: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatChanged {
+    pub chat_id: String,
+    pub updated_at: u64,
+    // The window that saved it; none for the backend's own changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub deleted: bool,
+}
+
+pub fn now_millis() -> u128 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis()
+}
+
+pub fn get_chats_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let chats_dir = crate::app_data_dir(app_handle)?.join("chats");
+    
+    if !chats_dir.exists() {
+        fs::create_dir_all(&chats_dir)
+            .map_err(|e| format!("Failed to create chats directory: {}", e))?;
+    }
+    
+    Ok(chats_dir)
+}
+
+// Every stored chat file
+pub fn chat_files(app: &tauri::AppHandle) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(get_chats_dir(app)?).map_err(|e| e.to_string())?;
+    Ok(entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("json"))
+        .collect())
+}
+
+// Decrypted when the chat store is encrypted
+fn read_file(path: &std::path::Path) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    String::from_utf8(encryption::open(data)?).map_err(|e| e.to_string())
+}
+
+pub fn write(app: &tauri::AppHandle, session: serde_json::Value) -> Result<String, String> {
+    write_from(app, session, None)
+}
+
+fn write_from(app: &tauri::AppHandle, session: serde_json::Value, source: Option<&str>) -> Result<String, String> {
+    let _guard = WRITE_LOCK.lock().map_err(|e| e.to_string())?;
+    let id = session["id"].as_str().unwrap_or_default().to_string();
+    let id = if id.is_empty() {
+        format!("chat_{}", now_millis())
+    } else {
+        id
+    };
+
+    let filename = format!("{}.json", id);
+    let path = get_chats_dir(app)?.join(&filename);
+
+    // Ensure the session has the ID
+    let mut session_obj = session.as_object().ok_or("Invalid session format")?.clone();
+    session_obj.insert("id".to_string(), serde_json::Value::String(id.clone()));
+    // Sync compares these to tell which copy of a chat is newer
+    let updated_at = now_millis() as u64;
+    session_obj.insert("updatedAt".to_string(), serde_json::json!(updated_at));
+    // Which device made the chat and which last changed it, see devices.rs
+    if let Ok(device) = crate::devices::id(app) {
+        let previous = read(app, &id).ok();
+        if previous.is_none() {
+            session_obj.entry("createdBy").or_insert_with(|| serde_json::Value::String(device.clone()));
+        }
+        crate::crdt::stamp(previous.as_ref(), &mut session_obj, &device);
+        session_obj.insert("updatedBy".to_string(), serde_json::Value::String(device));
+    }
+    let changed = ChatChanged { chat_id: id.clone(), updated_at, source: source.map(str::to_string), deleted: false };
+    if incognito::is_incognito(&id) {
+        incognito::store(app, &id, serde_json::Value::Object(session_obj))?;
+        let _ = app.emit(CHANGED_EVENT, changed);
+        return Ok(id);
+    }
+    
+    let content = serde_json::to_string_pretty(&session_obj).map_err(|e| e.to_string())?;
+    // Written beside the chat and moved over it, so nothing ever reads half
+    // a file
+    let partial = path.with_extension("json.part");
+    fs::write(&partial, encryption::seal(app, content.into_bytes())?).map_err(|e| e.to_string())?;
+    fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    let session = serde_json::Value::Object(session_obj);
+    git_sync::record_save(app, &id, &session);
+    crate::sync::record_change();
+    crate::tray::chats_changed();
+    crate::jump_list::chats_changed();
+    crate::os_search::chat_changed(app, &session);
+    let _ = app.emit(CHANGED_EVENT, changed);
+
+    Ok(id)
+}
+
+pub fn read(app: &tauri::AppHandle, id: &str) -> Result<serde_json::Value, String> {
+    if incognito::is_incognito(id) {
+        return incognito::read(id);
+    }
+    let filename = format!("{}.json", id);
+    let path = get_chats_dir(app)?.join(filename);
+    
+    if !path.exists() {
+        return Err("Chat not found".to_string());
+    }
+    let content = read_file(&path)?;
+    let data = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    
+    Ok(data)
+}
+
+// The session's transcript in the shape the completion pipeline takes
+pub fn messages(session: &serde_json::Value) -> Vec<ChatMessage> {
+    session["messages"]
+        .as_array()
+        .map(|messages| {
+            messages
+                .iter()
+                .filter_map(|m| Some(ChatMessage::new(m["role"].as_str()?, m["content"].as_str().unwrap_or_default())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Turns down a save from a window holding an older copy of a chat another
+// open window has saved since; it has to load the chat again first
+fn check_writer(
+    app: &tauri::AppHandle,
+    window: &str,
+    session: &serde_json::Value,
+    stored: &serde_json::Value,
+    id: &str,
+) -> Result<(), String> {
+    let last = LAST_WRITER.lock().ok().and_then(|writers| writers.as_ref()?.get(id).cloned());
+    let Some(last) = last.filter(|last| last != window) else {
+        return Ok(());
+    };
+    let theirs = stored["updatedAt"].as_u64().unwrap_or(0);
+    let ours = session["updatedAt"].as_u64().unwrap_or(0);
+    if ours < theirs && app.get_webview_window(&last).is_some() {
+        return Err("The chat changed in another window; load it again before saving".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn save_chat(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    mut session: serde_json::Value,
+) -> Result<String, String> {
+    app_lock::ensure_unlocked()?;
+    if let Some(id) = session["id"].as_str().filter(|id| !id.is_empty()).map(str::to_string) {
+        if let Ok(existing) = read(&app, &id) {
+            check_writer(&app, window.label(), &session, &existing, &id)?;
+            if let Some(obj) = session.as_object_mut() {
+                for key in BACKEND_KEYS {
+                    if !obj.contains_key(*key) && !existing[*key].is_null() {
+                        obj.insert(key.to_string(), existing[*key].clone());
+                    }
+                }
+            }
+        }
+    }
+    let id = write_from(&app, session, Some(window.label()))?;
+    if let Ok(mut writers) = LAST_WRITER.lock() {
+        writers.get_or_insert_with(HashMap::new).insert(id.clone(), window.label().to_string());
+    }
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn list_chats(app: tauri::AppHandle) -> Result<Vec<serde_json::Value>, String> {
+    app_lock::ensure_unlocked()?;
+    let dir = get_chats_dir(&app)?;
+    // An empty list would look like every chat is gone, e.g. to attachment GC
+    if encryption::is_locked(&app) {
+        return Err(encryption::LOCKED.to_string());
+    }
+    let mut sessions = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("json") {
+                if let Ok(content) = read_file(&path) {
+                    if let Ok(mut data) = serde_json::from_str::<serde_json::Value>(&content) {
+                        // Add filename/timestamp if missing for sorting
+                        if let Some(obj) = data.as_object_mut() {
+                            // Force ID to match filename to ensure deletion works
+                            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                                obj.insert("id".to_string(), serde_json::Value::String(stem.to_string()));
+                            }
+
+                            if !obj.contains_key("timestamp") {
+                                if let Ok(metadata) = fs::metadata(&path) {
+                                    if let Ok(created) = metadata.created() {
+                                        if let Ok(duration) = created.duration_since(std::time::UNIX_EPOCH) {
+                                            obj.insert("timestamp".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(duration.as_secs_f64() * 1000.0).unwrap()));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        sessions.push(data);
+                    }
+                }
+            }
+        }
+    }
+
+    // Sort by timestamp descending
+    sessions.sort_by(|a, b| {
+        let t_a = a["timestamp"].as_f64().unwrap_or(0.0);
+        let t_b = b["timestamp"].as_f64().unwrap_or(0.0);
+        t_b.partial_cmp(&t_a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Ok(sessions)
+}
+
+#[tauri::command]
+pub fn load_chat(app: tauri::AppHandle, id: String) -> Result<serde_json::Value, String> {
+    app_lock::ensure_unlocked()?;
+    read(&app, &id)
+}
+
+// Files the chat under `folder`, e.g. "Work/Clients"; empty takes it out
+#[tauri::command]
+pub fn set_chat_folder(app: tauri::AppHandle, chat_id: String, folder: String) -> Result<(), String> {
+    app_lock::ensure_unlocked()?;
+    let mut session = read(&app, &chat_id)?;
+    let obj = session.as_object_mut().ok_or("Invalid session format")?;
+    let folder = folder.trim().trim_matches('/');
+    if folder.is_empty() {
+        obj.remove("folder");
+    } else {
+        obj.insert("folder".to_string(), serde_json::Value::String(folder.to_string()));
+    }
+    write(&app, session).map(|_| ())
+}
+
+#[tauri::command]
+pub fn delete_chat(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    app_lock::ensure_unlocked()?;
+    remove(app, id)
+}
+
+// Also for sync, which keeps going while the app is locked
+pub fn remove(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    anonymize::forget(&id);
+    crate::permissions::forget_chat(&app, &id);
+    if incognito::is_incognito(&id) {
+        incognito::remove(&app, &id);
+        return Ok(());
+    }
+    let filename = format!("{}.json", id);
+    let path = get_chats_dir(&app)?.join(filename);
+
+    let session = read(&app, &id).unwrap_or_default();
+    let referenced = attachments::referenced_by(&session);
+    shred::remove_file(&app, &path).map_err(|e| e.to_string())?;
+    git_sync::record_delete(&app, &id, &session);
+    crate::sync::record_change();
+    crate::tray::chats_changed();
+    crate::jump_list::chats_changed();
+    crate::os_search::chat_deleted(&app, &id);
+    let changed = ChatChanged { chat_id: id.clone(), updated_at: now_millis() as u64, source: None, deleted: true };
+    let _ = app.emit(CHANGED_EVENT, changed);
+    history::forget(&app, &id);
+    if let Err(e) = crate::memory::forget_chat(&app, &id) {
+        log::warn!("Failed to forget memories from {}: {}", id, e);
+    }
+
+    for attachment in referenced {
+        if let Err(e) = attachments::release(&app, &attachment) {
+            log::warn!("Failed to release attachment {}: {}", attachment, e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use tauri::ipc::{Channel, InvokeResponseBody};
+
+use crate::completion::{self, CompletionRequest};
+use crate::exports::{self, ExportFormat};
+use crate::permissions::{self, Grant};
+use crate::{chats, imports, settings};
+
+// Subcommands for scripting against the chat library from a terminal:
+//   anchor list [--json]
+//   anchor export <id> [--md | --html | --json | --anchorchat] [--out <file>]
+//   anchor ask "question" [--model <m>] [--provider <id>] [--chat <id>] [--save]
+//   anchor import <file> [--password <p>]
+//   anchor share [--text] [<file>…]
+// They run on the same modules as the app, with no window, tray or
+// background work. Commands that write chats are refused while the app is
+// running, since the two would write the same files, except share, which
+// the running app is handed to store itself. The app lock and an encrypted
+// chat store apply here too. Tools that need a grant are turned
+// down, since there's nobody to ask.
+
+const USAGE: &str = "Usage:
+  anchor list [--json]
+  anchor export <id> [--md | --html | --json | --anchorchat] [--out <file>]
+  anchor ask \"question\" [--model <model>] [--provider <id>] [--chat <id>] [--save]
+  anchor import <file> [--password <password>]
+  anchor share [--text] [<file>...]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    Markdown,
+    Html,
+    Json,
+    Anchorchat,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    List { json: bool },
+    Export { id: String, output: Output, out: Option<String> },
+    Ask { prompt: String, model: Option<String>, provider_id: Option<String>, chat_id: Option<String>, save: bool },
+    Import { path: String, password: Option<String> },
+    // --text reads text to share from stdin, see share_service.rs
+    Share { text: bool, files: Vec<String> },
+    Help,
+}
+
+// `--flag` and its value, for the flags that take one
+type Flags = Vec<(String, Option<String>)>;
+
+// Splits arguments into positional ones and flags
+fn options(args: &[String], with_value: &[&str]) -> Result<(Vec<String>, Flags), String> {
+    let mut positional = Vec::new();
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            positional.push(arg.clone());
+            continue;
+        };
+        if with_value.contains(&flag) {
+            let value = args.next().ok_or_else(|| format!("--{} needs a value", flag))?;
+            flags.push((flag.to_string(), Some(value.clone())));
+        } else {
+            flags.push((flag.to_string(), None));
+        }
+    }
+    Ok((positional, flags))
+}
+
+fn value(flags: &Flags, name: &str) -> Option<String> {
+    flags.iter().rev().find(|(flag, _)| flag == name).and_then(|(_, value)| value.clone())
+}
+
+fn only(flags: &Flags, allowed: &[&str]) -> Result<(), String> {
+    match flags.iter().find(|(flag, _)| !allowed.contains(&flag.as_str())) {
+        Some((flag, _)) => Err(format!("Unknown option --{}", flag)),
+        None => Ok(()),
+    }
+}
+
+// The command in the process arguments, or None when Anchor was started as
+// the app (the arguments may then be files to open, see instance.rs)
+pub fn parse(argv: &[String]) -> Option<Result<Command, String>> {
+    let name = argv.get(1)?;
+    let rest = &argv[2..];
+    let command = match name.as_str() {
+        "list" | "export" | "ask" | "import" | "share" => name.as_str(),
+        "help" | "--help" | "-h" => return Some(Ok(Command::Help)),
+        _ => return None,
+    };
+    Some(parse_command(command, rest))
+}
+
+fn parse_command(command: &str, args: &[String]) -> Result<Command, String> {
+    match command {
+        "list" => {
+            let (_, flags) = options(args, &[])?;
+            only(&flags, &["json"])?;
+            Ok(Command::List { json: !flags.is_empty() })
+        }
+        "export" => {
+            let (positional, flags) = options(args, &["out"])?;
+            only(&flags, &["md", "html", "json", "anchorchat", "out"])?;
+            let id = positional.first().cloned().ok_or("Which chat? Pass its id, see anchor list")?;
+            let output = match flags.iter().rev().find(|(flag, _)| flag != "out").map(|(flag, _)| flag.as_str()) {
+                Some("html") => Output::Html,
+                Some("json") => Output::Json,
+                Some("anchorchat") => Output::Anchorchat,
+                _ => Output::Markdown,
+            };
+            let out = value(&flags, "out");
+            if output == Output::Anchorchat && out.is_none() {
+                return Err("--anchorchat needs --out <file>".to_string());
+            }
+            Ok(Command::Export { id, output, out })
+        }
+        "ask" => {
+            let (positional, flags) = options(args, &["model", "provider", "chat"])?;
+            only(&flags, &["model", "provider", "chat", "save"])?;
+            let prompt = positional.join(" ");
+            if prompt.trim().is_empty() {
+                return Err("Ask what? Pass the question in quotes".to_string());
+            }
+            Ok(Command::Ask {
+                prompt,
+                model: value(&flags, "model"),
+                provider_id: value(&flags, "provider"),
+                chat_id: value(&flags, "chat"),
+                save: flags.iter().any(|(flag, _)| flag == "save"),
+            })
+        }
+        "share" => {
+            let (files, flags) = options(args, &[])?;
+            only(&flags, &["text"])?;
+            if flags.is_empty() && files.is_empty() {
+                return Err("Share what? Pass --text to read it from stdin, or files".to_string());
+            }
+            Ok(Command::Share { text: !flags.is_empty(), files })
+        }
+        _ => {
+            let (positional, flags) = options(args, &["password"])?;
+            only(&flags, &["password"])?;
+            let path = positional.first().cloned().ok_or("Which file? Pass its path")?;
+            Ok(Command::Import { path, password: value(&flags, "password") })
+        }
+    }
+}
+
+fn list(app: &tauri::AppHandle, as_json: bool) -> Result<(), String> {
+    let sessions = chats::list_chats(app.clone())?;
+    let summaries: Vec<Value> = sessions
+        .iter()
+        .filter(|s| s["conflictOf"].is_null())
+        .map(|s| {
+            json!({
+                "id": s["id"],
+                "title": s["title"].as_str().filter(|t| !t.trim().is_empty()).unwrap_or("Untitled chat"),
+                "messages": s["messages"].as_array().map_or(0, Vec::len),
+                "updatedAt": s["updatedAt"].as_u64().or(s["timestamp"].as_f64().map(|t| t as u64)),
+            })
+        })
+        .collect();
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&summaries).map_err(|e| e.to_string())?);
+        return Ok(());
+    }
+    for summary in summaries {
+        let updated = summary["updatedAt"].as_u64().and_then(|t| chrono::DateTime::from_timestamp_millis(t as i64));
+        let updated = updated.map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string());
+        let id = summary["id"].as_str().unwrap_or_default();
+        let title = summary["title"].as_str().unwrap_or_default();
+        println!("{}\t{}\t{}", id, updated.unwrap_or_default(), title);
+    }
+    Ok(())
+}
+
+fn export(app: &tauri::AppHandle, id: &str, output: Output, out: Option<&str>) -> Result<(), String> {
+    let content = match output {
+        Output::Markdown => exports::render_markdown(app, id)?.1,
+        Output::Html => exports::render_html(app, id)?,
+        Output::Json => serde_json::to_string_pretty(&chats::read(app, id)?).map_err(|e| e.to_string())?,
+        Output::Anchorchat => {
+            let destination = out.ok_or("--anchorchat needs --out <file>")?;
+            let report = exports::export(app, id, ExportFormat::Anchorchat, Path::new(destination), None)?;
+            crate::audit::record("export", &format!("{} as Anchorchat to {}", id, destination));
+            eprintln!("Exported to {} with {} attachments", report.path, report.attachments);
+            return Ok(());
+        }
+    };
+    match out {
+        Some(destination) => {
+            std::fs::write(destination, content).map_err(|e| format!("Failed to write {}: {}", destination, e))?;
+            crate::audit::record("export", &format!("{} to {}", id, destination));
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+// Prints the answer as it streams in
+fn printer() -> Channel<completion::StreamEvent> {
+    Channel::new(|body| {
+        let InvokeResponseBody::Json(body) = body else {
+            return Ok(());
+        };
+        let event: Value = serde_json::from_str(&body).unwrap_or_default();
+        match event["type"].as_str() {
+            Some("delta") => {
+                print!("{}", event["content"].as_str().unwrap_or_default());
+                let _ = std::io::stdout().flush();
+            }
+            Some("permissionRequested") => {
+                let tool = event["call"]["name"].as_str().unwrap_or("a tool");
+                eprintln!("\n[{} needs a grant, which the command line can't give]", tool);
+                if let Some(id) = event["requestId"].as_str() {
+                    let _ = permissions::respond_tool_permission(id.to_string(), Grant::Deny);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })
+}
+
+async fn ask(
+    app: &tauri::AppHandle,
+    prompt: String,
+    model: Option<String>,
+    provider_id: Option<String>,
+    chat_id: Option<String>,
+    save: bool,
+) -> Result<(), String> {
+    let mut session = match &chat_id {
+        Some(id) => chats::read(app, id)?,
+        None => json!({ "id": "", "title": "", "timestamp": chats::now_millis() as u64, "messages": [] }),
+    };
+    let quick = settings::load(app)?.quick_prompt;
+    let model = model
+        .or_else(|| Some(quick.model.trim().to_string()).filter(|m| !m.is_empty()))
+        .ok_or("Pass --model, or pick a model for quick prompts in settings")?;
+    let messages = session["messages"].as_array_mut().ok_or("Invalid session format")?;
+    messages.push(json!({ "role": "user", "content": prompt, "timestamp": chats::now_millis() as u64 }));
+
+    let request = CompletionRequest {
+        provider_id: provider_id.or(quick.provider_id),
+        chat_id: chat_id.clone(),
+        model: model.clone(),
+        messages: chats::messages(&session),
+        ..Default::default()
+    };
+    let reply = completion::stream_completion(app.clone(), request, printer()).await?;
+    println!();
+    if !save && chat_id.is_none() {
+        return Ok(());
+    }
+    let messages = session["messages"].as_array_mut().ok_or("Invalid session format")?;
+    messages.push(json!({
+        "role": "assistant",
+        "content": reply,
+        "model": model,
+        "timestamp": chats::now_millis() as u64,
+    }));
+    let id = chats::write(app, session)?;
+    eprintln!("Saved to {}", id);
+    Ok(())
+}
+
+fn import(app: &tauri::AppHandle, path: &str, password: Option<&str>) -> Result<(), String> {
+    let report = imports::import(app, Path::new(path), password)?.ok_or("No chats Anchor can read in that file")?;
+    for id in &report.chat_ids {
+        println!("{}", id);
+    }
+    eprintln!("Imported {} chats and {} attachments", report.chat_ids.len(), report.attachments);
+    Ok(())
+}
+
+fn share(app: &tauri::AppHandle, read_text: bool, files: &[String]) -> Result<(), String> {
+    let mut text = String::new();
+    if read_text {
+        std::io::stdin().read_to_string(&mut text).map_err(|e| format!("Failed to read stdin: {}", e))?;
+    }
+    let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+    if crate::instance::running(app) {
+        return crate::share_service::forward(app, &text, &files);
+    }
+    let id = crate::share_service::share(app, &text, &files)?;
+    println!("{}", id);
+    crate::share_service::open(&id)
+}
+
+// Runs the command and returns the exit code
+pub fn run(app: &tauri::AppHandle, command: Result<Command, String>) -> i32 {
+    let command = match command {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    crate::audit::init(app);
+    crate::app_lock::init(app);
+    crate::encryption::init(app);
+    let writes = match &command {
+        Command::Import { .. } => true,
+        Command::Ask { chat_id, save, .. } => *save || chat_id.is_some(),
+        _ => false,
+    };
+    if writes && crate::instance::running(app) {
+        eprintln!("anchor: Anchor is running; quit it first, or do this in the app");
+        return 1;
+    }
+    let result = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::List { json } => list(app, json),
+        Command::Export { id, output, out } => export(app, &id, output, out.as_deref()),
+        Command::Ask { prompt, model, provider_id, chat_id, save } => {
+            tauri::async_runtime::block_on(ask(app, prompt, model, provider_id, chat_id, save))
+        }
+        Command::Import { path, password } => import(app, &path, password.as_deref()),
+        Command::Share { text, files } => share(app, text, &files),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("anchor: {}", e);
+            1
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::io::Cursor;
+
+use crate::attachments::{self, Attachment, AttachmentSource};
+
+// Stores the clipboard image as a PNG attachment, so the webview never has to
+// hold the pixels as a data URL.
+pub fn paste_image(app: &tauri::AppHandle) -> Result<Attachment, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("Clipboard is unavailable: {}", e))?;
+    let data = clipboard.get_image().map_err(|e| match e {
+        arboard::Error::ContentNotAvailable => "There is no image on the clipboard".to_string(),
+        e => format!("Failed to read the clipboard: {}", e),
+    })?;
+
+    let pixels = image::RgbaImage::from_raw(data.width as u32, data.height as u32, data.bytes.into_owned())
+        .ok_or("Clipboard image has an unexpected size")?;
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgba8(pixels)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode clipboard image: {}", e))?;
+
+    let name = format!("Pasted image {}.png", chrono::Local::now().format("%Y-%m-%d %H.%M.%S"));
+    attachments::save(app, AttachmentSource::Bytes(png), Some(name))
+}
+
+#[tauri::command]
+pub async fn paste_clipboard_image(app: tauri::AppHandle) -> Result<Attachment, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || paste_image(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
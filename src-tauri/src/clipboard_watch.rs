@@ -0,0 +1,188 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::deep_link::{self, Navigation};
+use crate::settings::{self, ClipboardWatchSettings};
+use crate::transcription::find_in_path;
+
+// Opt-in: while on, the backend keeps an eye on the clipboard and notes the
+// app each copied text came from, as far as the OS tells. Pressing the
+// trigger shortcut starts a new chat with the last copied text put into the
+// prompt template ("Explain this: …"), left for the user to send. Text from
+// denied apps (password managers by default), or from apps outside the
+// allow list when there is one, is never picked up. Nothing is kept on disk.
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_TEXT_CHARS: usize = 8000;
+
+struct Copied {
+    text: String,
+    // The app in front when it was copied, when that can be told
+    source: Option<String>,
+    at: Instant,
+}
+
+static WATCHING: AtomicBool = AtomicBool::new(false);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static LAST: Mutex<Option<Copied>> = Mutex::new(None);
+static REGISTERED: Mutex<Option<Shortcut>> = Mutex::new(None);
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(find_in_path(program)?).args(args).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !text.is_empty()).then_some(text)
+}
+
+// `"LSDisplayName"="Terminal"`
+#[cfg(target_os = "macos")]
+fn frontmost_app() -> Option<String> {
+    let front = output("lsappinfo", &["front"])?;
+    let info = output("lsappinfo", &["info", "-only", "name", &front])?;
+    Some(info.rsplit('=').next()?.trim_matches('"').to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn frontmost_app() -> Option<String> {
+    let script = "Add-Type -Name W -Namespace F -MemberDefinition \
+                  '[DllImport(\"user32.dll\")] public static extern IntPtr GetForegroundWindow(); \
+                  [DllImport(\"user32.dll\")] \
+                  public static extern int GetWindowThreadProcessId(IntPtr h, out int p);'; \
+                  $p = 0; [void][F.W]::GetWindowThreadProcessId([F.W]::GetForegroundWindow(), [ref]$p); \
+                  (Get-Process -Id $p).ProcessName";
+    output("powershell", &["-NoProfile", "-NonInteractive", "-Command", script])
+}
+
+// X11 only; Wayland doesn't say which window is in front
+#[cfg(target_os = "linux")]
+fn frontmost_app() -> Option<String> {
+    output("xdotool", &["getactivewindow", "getwindowclassname"])
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn frontmost_app() -> Option<String> {
+    let _ = output;
+    None
+}
+
+fn listed(list: &[String], app: &str) -> bool {
+    let app = app.to_lowercase();
+    list.iter().map(|entry| entry.trim().to_lowercase()).any(|entry| !entry.is_empty() && app.contains(&entry))
+}
+
+// Whether text copied in `source` may be picked up
+fn allowed(settings: &ClipboardWatchSettings, source: Option<&str>) -> bool {
+    let allow_list = settings.allowed_apps.iter().any(|a| !a.trim().is_empty());
+    match source {
+        Some(app) => !listed(&settings.denied_apps, app) && (!allow_list || listed(&settings.allowed_apps, app)),
+        // Unknown apps only pass when nothing is singled out
+        None => !allow_list,
+    }
+}
+
+fn watch(app: tauri::AppHandle) {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            log::warn!("Clipboard is unavailable: {}", e);
+            RUNNING.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+    // What's on the clipboard when watching starts wasn't copied while it was on
+    let mut seen = clipboard.get_text().ok();
+    while WATCHING.load(Ordering::SeqCst) {
+        std::thread::sleep(POLL_INTERVAL);
+        let Ok(text) = clipboard.get_text() else {
+            continue;
+        };
+        if seen.as_ref() == Some(&text) {
+            continue;
+        }
+        seen = Some(text.clone());
+        let source = frontmost_app();
+        let settings = settings::load(&app).map(|s| s.clipboard_watch).unwrap_or_default();
+        let copied = (!text.trim().is_empty() && allowed(&settings, source.as_deref()))
+            .then(|| Copied { text, source, at: Instant::now() });
+        // A copy from a denied app also drops the one before it, so the
+        // trigger never picks up something older than the user expects
+        if let Ok(mut last) = LAST.lock() {
+            *last = copied;
+        }
+    }
+    if let Ok(mut last) = LAST.lock() {
+        *last = None;
+    }
+    RUNNING.store(false, Ordering::SeqCst);
+}
+
+// Starts or stops watching and registers the trigger from settings; called
+// at startup and when the settings are saved
+pub fn configure(app: &tauri::AppHandle) {
+    let settings = settings::load(app).map(|s| s.clipboard_watch).unwrap_or_default();
+    WATCHING.store(settings.enabled, Ordering::SeqCst);
+    if settings.enabled && !RUNNING.swap(true, Ordering::SeqCst) {
+        let app = app.clone();
+        std::thread::spawn(move || watch(app));
+    }
+    let wanted = Some(settings).filter(|s| s.enabled).and_then(|s| match s.shortcut.parse::<Shortcut>() {
+        Ok(shortcut) => Some(shortcut),
+        Err(e) => {
+            log::warn!("Invalid clipboard shortcut {}: {}", s.shortcut, e);
+            None
+        }
+    });
+    let Ok(mut registered) = REGISTERED.lock() else {
+        return;
+    };
+    if *registered == wanted {
+        return;
+    }
+    if let Some(previous) = registered.take() {
+        if let Err(e) = app.global_shortcut().unregister(previous) {
+            log::warn!("Failed to release the clipboard shortcut: {}", e);
+        }
+    }
+    if let Some(shortcut) = wanted {
+        match app.global_shortcut().register(shortcut) {
+            Ok(()) => *registered = Some(shortcut),
+            Err(e) => log::warn!("Failed to register the clipboard shortcut: {}", e),
+        }
+    }
+}
+
+fn prompt(template: &str, text: &str) -> String {
+    let text = text.trim();
+    let text = if text.chars().count() > MAX_TEXT_CHARS {
+        format!("{}…", text.chars().take(MAX_TEXT_CHARS - 1).collect::<String>())
+    } else {
+        text.to_string()
+    };
+    if template.contains("{text}") {
+        template.replace("{text}", &text)
+    } else {
+        format!("{}\n\n{}", template.trim_end(), text)
+    }
+}
+
+pub fn on_shortcut(app: &tauri::AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state != ShortcutState::Pressed || REGISTERED.lock().map_or(true, |r| r.as_ref() != Some(shortcut)) {
+        return;
+    }
+    let settings = settings::load(app).map(|s| s.clipboard_watch).unwrap_or_default();
+    let max_age = Duration::from_secs(settings.max_age_minutes * 60);
+    let copied = LAST.lock().ok().and_then(|last| {
+        last.as_ref().filter(|c| c.at.elapsed() <= max_age).map(|c| (c.text.clone(), c.source.clone()))
+    });
+    let Some((text, source)) = copied else {
+        log::info!("Nothing usable was copied for the clipboard shortcut");
+        return;
+    };
+    log::debug!("Clipboard prompt from {}", source.as_deref().unwrap_or("an unknown app"));
+    let prompt = Some(prompt(&settings.prompt, &text));
+    let navigation = Navigation::NewChat { prompt, model: None, provider_id: None };
+    deep_link::navigate(app, navigation, true);
+}
@@ -0,0 +1,143 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::FolderSettings;
+
+// A folder that iCloud Drive, OneDrive, Dropbox or similar keeps in sync.
+// Those clients upload files while they're being written and keep evicted
+// files as placeholders, so writes go to a temporary name and are renamed
+// into place, and a file that isn't on this device yet is an error to retry
+// later rather than a missing (deleted) one.
+
+const LOCK: &str = "anchor-sync.lock";
+const TEMP_SUFFIX: &str = ".anchor-tmp";
+// A lock this old is from a device that crashed or went offline mid-sync
+const STALE_LOCK_MILLIS: u64 = 10 * 60 * 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Lock {
+    owner: String,
+    acquired_at: u64,
+}
+
+// Unique to this run of the app, to recognise our own lock
+fn owner() -> &'static str {
+    static OWNER: OnceLock<String> = OnceLock::new();
+    OWNER.get_or_init(|| {
+        let host = std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).unwrap_or_default();
+        format!("{}-{}-{}", host, std::process::id(), crate::chats::now_millis())
+    })
+}
+
+pub struct CloudFolder {
+    dir: PathBuf,
+}
+
+impl CloudFolder {
+    pub fn new(settings: &FolderSettings) -> Result<Self, String> {
+        let path = settings.path.trim();
+        if path.is_empty() {
+            return Err("Choose the sync folder first".to_string());
+        }
+        let dir = PathBuf::from(path);
+        if !dir.is_absolute() {
+            return Err(format!("The sync folder must be an absolute path: {}", path));
+        }
+        Ok(Self { dir })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // iCloud Drive keeps an evicted "name" as ".name.icloud"
+    fn placeholder(&self, name: &str) -> PathBuf {
+        self.dir.join(format!(".{}.icloud", name))
+    }
+
+    fn write_atomic(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        let temp = self.path(&format!(".{}{}", name, TEMP_SUFFIX));
+        let mut file = fs::File::create(&temp).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        file.write_all(data).and_then(|_| file.sync_all()).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        drop(file);
+        fs::rename(&temp, self.path(name)).map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("Failed to write {}: {}", name, e)
+        })
+    }
+
+    fn read_lock(&self) -> Option<Lock> {
+        let content = fs::read(self.path(LOCK)).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    // Takes the folder's lock, unless another device holds a fresh one
+    pub async fn prepare(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create the sync folder: {}", e))?;
+        let now = crate::chats::now_millis() as u64;
+        if let Some(lock) = self.read_lock() {
+            let stale = now.saturating_sub(lock.acquired_at) > STALE_LOCK_MILLIS;
+            if lock.owner != owner() && !stale {
+                return Err("Another device is syncing to this folder; trying again later".to_string());
+            }
+            if stale {
+                log::warn!("Taking over a stale sync lock from {}", lock.owner);
+            }
+        }
+        let lock = Lock { owner: owner().to_string(), acquired_at: now };
+        self.write_atomic(LOCK, &serde_json::to_vec(&lock).map_err(|e| e.to_string())?)
+    }
+
+    pub async fn finish(&self) -> Result<(), String> {
+        if self.read_lock().is_some_and(|lock| lock.owner == owner()) {
+            fs::remove_file(self.path(LOCK)).map_err(|e| format!("Failed to release the sync lock: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let path = self.path(name);
+        if !path.exists() {
+            let placeholder = self.placeholder(name);
+            if !placeholder.exists() {
+                return Ok(None);
+            }
+            request_download(&path);
+            return Err(format!("{} isn't downloaded to this device yet; trying again later", name));
+        }
+        let data = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        // What a sync client shows while the content is still on its way
+        if data.is_empty() {
+            return Err(format!("{} is still syncing; trying again later", name));
+        }
+        Ok(Some(data))
+    }
+
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        self.write_atomic(name, &data)
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        for path in [self.path(name), self.placeholder(name)] {
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| format!("Failed to delete {}: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Asks iCloud to bring an evicted file back; elsewhere reading does that
+fn request_download(path: &Path) {
+    #[cfg(target_os = "macos")]
+    if let Some(brctl) = crate::transcription::find_in_path("brctl") {
+        let _ = std::process::Command::new(brctl).arg("download").arg(path).spawn();
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = path;
+}
@@ -0,0 +1,306 @@
+use std::path::Path;
+
+use serde::Serialize;
+use tree_sitter::{Node, Parser};
+
+use crate::attachments;
+use crate::extraction::Section;
+
+pub const DEFAULT_MAX_LINES: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeLanguage {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Go,
+}
+
+impl CodeLanguage {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let ext = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        match ext.as_str() {
+            "rs" => Some(Self::Rust),
+            "py" | "pyi" => Some(Self::Python),
+            "js" | "jsx" | "mjs" | "cjs" => Some(Self::JavaScript),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    // Also the Markdown fence tag
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Python => "python",
+            Self::JavaScript => "javascript",
+            Self::TypeScript => "typescript",
+            Self::Tsx => "tsx",
+            Self::Go => "go",
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    // Node kinds that make a chunk of their own
+    fn definitions(self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &[
+                "function_item",
+                "impl_item",
+                "struct_item",
+                "enum_item",
+                "trait_item",
+                "mod_item",
+                "macro_definition",
+                "union_item",
+            ],
+            Self::Python => &["function_definition", "class_definition", "decorated_definition"],
+            Self::JavaScript | Self::TypeScript | Self::Tsx => &[
+                "function_declaration",
+                "generator_function_declaration",
+                "class_declaration",
+                "method_definition",
+                "export_statement",
+                "lexical_declaration",
+                "interface_declaration",
+                "type_alias_declaration",
+                "enum_declaration",
+                "abstract_class_declaration",
+            ],
+            Self::Go => &["function_declaration", "method_declaration", "type_declaration"],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeChunk {
+    pub path: String,
+    pub language: String,
+    // 1-based, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    // e.g. "Settings::provider" for a method inside an impl
+    pub symbol: Option<String>,
+    // Syntax node kind, or "code" for imports and other top-level glue
+    pub kind: String,
+    pub text: String,
+}
+
+impl CodeChunk {
+    pub fn section(&self) -> Section {
+        let symbol = self.symbol.as_ref().map(|s| format!(" {}", s)).unwrap_or_default();
+        Section {
+            title: Some(format!("{}:{}-{}{}", self.path, self.start_line, self.end_line, symbol)),
+            page: None,
+            text: format!("```{}\n{}\n```", self.language, self.text),
+        }
+    }
+}
+
+struct Chunker<'a> {
+    language: Option<CodeLanguage>,
+    path: &'a str,
+    source: &'a [u8],
+    lines: Vec<&'a str>,
+    max_lines: usize,
+    chunks: Vec<CodeChunk>,
+}
+
+impl Chunker<'_> {
+    fn text(&self, node: Node) -> String {
+        node.utf8_text(self.source).unwrap_or_default().to_string()
+    }
+
+    fn symbol(&self, node: Node) -> Option<String> {
+        let field = |name: &str| node.child_by_field_name(name).map(|n| self.text(n));
+        match node.kind() {
+            "impl_item" => {
+                let target = field("type")?;
+                Some(match field("trait") {
+                    Some(trait_name) => format!("{} for {}", trait_name, target),
+                    None => target,
+                })
+            }
+            "decorated_definition" => self.symbol(node.child_by_field_name("definition")?),
+            "export_statement" => self.symbol(node.child_by_field_name("declaration")?),
+            "lexical_declaration" => {
+                let mut cursor = node.walk();
+                let declarator = node.named_children(&mut cursor).find(|c| c.kind() == "variable_declarator")?;
+                declarator.child_by_field_name("name").map(|n| self.text(n))
+            }
+            "type_declaration" => {
+                let mut cursor = node.walk();
+                let spec = node.named_children(&mut cursor).find(|c| c.kind() == "type_spec")?;
+                spec.child_by_field_name("name").map(|n| self.text(n))
+            }
+            _ => field("name"),
+        }
+    }
+
+    // Containers whose members are worth splitting out when the whole is too big
+    fn body<'t>(&self, node: Node<'t>) -> Option<Node<'t>> {
+        match node.kind() {
+            "decorated_definition" => self.body(node.child_by_field_name("definition")?),
+            "export_statement" => self.body(node.child_by_field_name("declaration")?),
+            "impl_item" | "trait_item" | "mod_item" | "class_definition" | "class_declaration"
+            | "abstract_class_declaration" => node.child_by_field_name("body"),
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, start: usize, end: usize, symbol: Option<String>, kind: &str) {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        if self.lines.is_empty() || start > end {
+            return;
+        }
+        let text = self.lines[start..=end].join("\n");
+        if text.trim().is_empty() {
+            return;
+        }
+        self.chunks.push(CodeChunk {
+            path: self.path.to_string(),
+            language: self.language.map(CodeLanguage::name).unwrap_or_default().to_string(),
+            start_line: start + 1,
+            end_line: end + 1,
+            symbol,
+            kind: kind.to_string(),
+            text,
+        });
+    }
+
+    // Fallback for ranges no syntax boundary splits small enough; the windows
+    // are evened out so there's no two-line leftover at the end
+    fn push_windows(&mut self, start: usize, end: usize, symbol: Option<String>, kind: &str) {
+        let total = end.saturating_sub(start) + 1;
+        let size = total.div_ceil(total.div_ceil(self.max_lines));
+        let mut from = start;
+        while from <= end {
+            let to = (from + size - 1).min(end);
+            self.push(from, to, symbol.clone(), kind);
+            from = to + 1;
+        }
+    }
+
+    // Walks the children of `node`. Definitions become chunks of their own,
+    // with any comments right above them; everything between them is
+    // gathered into "code" chunks.
+    fn collect(&mut self, node: Node, parent: Option<&str>) {
+        let mut glue: Option<(usize, usize)> = None;
+        // Comments and attributes are held back so they stay with the item below
+        let mut comments: Option<(usize, usize)> = None;
+        let definitions = self.language.map_or(&[][..], CodeLanguage::definitions);
+
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.named_children(&mut cursor).collect();
+        for child in children {
+            let (start, end) = (child.start_position().row, child.end_position().row);
+            // Rust attributes belong to the item below them as well
+            if child.kind().contains("comment") || child.kind() == "attribute_item" {
+                comments.get_or_insert((start, end)).1 = end;
+                continue;
+            }
+            if !definitions.contains(&child.kind()) {
+                let from = comments.take().map_or(start, |(s, _)| s);
+                let range = glue.get_or_insert((from, end));
+                range.1 = end;
+                if range.1 - range.0 + 1 >= self.max_lines {
+                    let (s, e) = glue.take().unwrap_or_default();
+                    self.push_windows(s, e, parent.map(str::to_string), "code");
+                }
+                continue;
+            }
+
+            if let Some((s, e)) = glue.take() {
+                self.push_windows(s, e, parent.map(str::to_string), "code");
+            }
+            let from = comments.take().map_or(start, |(s, _)| s);
+            let name = self.symbol(child);
+            let symbol = match (parent, &name) {
+                (Some(parent), Some(name)) => Some(format!("{}::{}", parent, name)),
+                (Some(parent), None) => Some(parent.to_string()),
+                (None, name) => name.clone(),
+            };
+
+            if end - from < self.max_lines {
+                self.push(from, end, symbol, child.kind());
+            } else if let Some(body) = self.body(child) {
+                // Header lines up to the body's opening, then its members
+                let body_start = body.start_position().row;
+                self.push(from, body_start, symbol.clone(), child.kind());
+                self.collect(body, symbol.as_deref());
+            } else {
+                self.push_windows(from, end, symbol, child.kind());
+            }
+        }
+
+        if let Some((start, end)) = comments {
+            glue.get_or_insert((start, end)).1 = end;
+        }
+        if let Some((s, e)) = glue {
+            self.push_windows(s, e, parent.map(str::to_string), "code");
+        }
+    }
+}
+
+// Splits source along function, class and impl boundaries. Unsupported or
+// unparseable files fall back to fixed windows of `max_lines`.
+pub fn chunk(path: &str, source: &str, max_lines: usize) -> Vec<CodeChunk> {
+    let max_lines = max_lines.max(10);
+    let language = CodeLanguage::from_name(path);
+    let mut chunker = Chunker {
+        language,
+        path,
+        source: source.as_bytes(),
+        lines: source.lines().collect(),
+        max_lines,
+        chunks: Vec::new(),
+    };
+
+    let mut parser = Parser::new();
+    let tree = language
+        .filter(|l| parser.set_language(&l.grammar()).is_ok())
+        .and_then(|_| parser.parse(source, None));
+    match tree {
+        Some(tree) => chunker.collect(tree.root_node(), None),
+        None => {
+            let last = chunker.lines.len().saturating_sub(1);
+            chunker.push_windows(0, last, None, "code");
+        }
+    }
+    chunker.chunks
+}
+
+pub fn chunk_attachment(app: &tauri::AppHandle, id: &str, max_lines: usize) -> Result<Vec<CodeChunk>, String> {
+    let attachment = attachments::get(app, id)?;
+    let bytes = std::fs::read(attachments::path(app, id)?).map_err(|e| e.to_string())?;
+    Ok(chunk(&attachment.name, &String::from_utf8_lossy(&bytes), max_lines))
+}
+
+#[tauri::command]
+pub async fn chunk_code(
+    app: tauri::AppHandle,
+    attachment_id: String,
+    max_lines: Option<usize>,
+) -> Result<Vec<CodeChunk>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        chunk_attachment(&app, &attachment_id, max_lines.unwrap_or(DEFAULT_MAX_LINES))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
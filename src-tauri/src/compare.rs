@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::ipc::{Channel, JavaScriptChannelId};
+
+use crate::chats;
+use crate::completion::{self, CompletionRequest, StreamEvent};
+use crate::settings;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareTarget {
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    pub model: String,
+    // Each candidate streams on its own channel so the UI can render columns independently
+    pub channel: JavaScriptChannelId,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    pub provider_id: String,
+    pub model: String,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn compare_models(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    chat_id: String,
+    targets: Vec<CompareTarget>,
+    options: Option<serde_json::Map<String, Value>>,
+) -> Result<Value, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if targets.is_empty() {
+        return Err("No models to compare".to_string());
+    }
+
+    let mut session = chats::read(&app, &chat_id)?;
+    let messages = chats::messages(&session);
+    if messages.last().map(|m| m.role.as_str()) != Some("user") {
+        return Err("The chat must end with a user message to compare responses".to_string());
+    }
+
+    let settings = settings::load(&app)?;
+    let mut handles = Vec::new();
+    for target in targets {
+        let provider = completion::resolve_provider(&settings, target.provider_id.as_deref())?;
+        let channel: Channel<StreamEvent> = target.channel.channel_on(webview.clone());
+        let request = CompletionRequest {
+            provider_id: Some(provider.id.clone()),
+            model: target.model.clone(),
+            messages: messages.clone(),
+            options: options.clone().unwrap_or_default(),
+            anonymize: crate::anonymize::key(&settings, &provider, Some(&chat_id)),
+            ..Default::default()
+        };
+
+        handles.push(tauri::async_runtime::spawn(async move {
+            let result = completion::complete(&provider, &request, |delta| {
+                let _ = channel.send(StreamEvent::Delta { content: delta.to_string() });
+            })
+            .await;
+
+            let (content, error) = match result {
+                Ok(content) => (content, None),
+                Err(e) => (String::new(), Some(e)),
+            };
+            let _ = channel.send(StreamEvent::Done { content: content.clone(), messages: Vec::new() });
+            Candidate { provider_id: provider.id, model: target.model, content, error }
+        }));
+    }
+
+    let mut candidates = Vec::with_capacity(handles.len());
+    for handle in handles {
+        candidates.push(handle.await.map_err(|e| e.to_string())?);
+    }
+
+    // Show the first answer that succeeded until the user picks one
+    let selected = candidates.iter().position(|c| c.error.is_none()).unwrap_or(0);
+    let message = json!({
+        "role": "assistant",
+        "content": candidates[selected].content,
+        "model": candidates[selected].model,
+        "timestamp": chats::now_millis() as u64,
+        "candidates": candidates,
+        "selectedCandidate": selected,
+    });
+    session["messages"]
+        .as_array_mut()
+        .ok_or("Invalid session format")?
+        .push(message);
+
+    chats::write(&app, session.clone())?;
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn select_candidate(
+    app: tauri::AppHandle,
+    chat_id: String,
+    message_index: usize,
+    candidate_index: usize,
+) -> Result<Value, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut session = chats::read(&app, &chat_id)?;
+    let message = session["messages"]
+        .get_mut(message_index)
+        .ok_or("Message not found")?;
+    let candidate = message["candidates"]
+        .get(candidate_index)
+        .cloned()
+        .ok_or("Candidate not found")?;
+
+    message["content"] = candidate["content"].clone();
+    message["model"] = candidate["model"].clone();
+    message["selectedCandidate"] = json!(candidate_index);
+
+    chats::write(&app, session.clone())?;
+    Ok(session)
+}
@@ -0,0 +1,525 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::ipc::Channel;
+
+use crate::analytics;
+use crate::anonymize::{self, Scope};
+use crate::assistants;
+use crate::attachments::{self, TooLarge};
+use crate::context::{self, ContextReport};
+use crate::extraction;
+use crate::history::{self, Recollection};
+use crate::memory;
+use crate::permissions;
+use crate::presets;
+use crate::prompts;
+use crate::retrieval::{self, Citation, RetrievedChunk};
+use crate::secrets::{self, SecretFinding, SecretScan};
+use crate::settings::{self, ProviderConfig, Settings};
+use crate::titles;
+use crate::tools;
+use crate::vision::{self, ImageInput};
+
+const DEFAULT_MAX_TOOL_STEPS: u32 = 8;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    // Raw JSON text exactly as the model produced it
+    pub arguments: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageInput>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    // Attachment ids of documents whose text goes along with the message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+    // Knowledge base chunks the answer was written from, see retrieval.rs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            images: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            attachments: Vec::new(),
+            citations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionRequest {
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    // Set when the request belongs to a stored chat, so per-chat state can be kept
+    #[serde(default)]
+    pub chat_id: Option<String>,
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    // Extra payload fields (temperature, max_tokens, thinking, ...) passed through verbatim
+    #[serde(default)]
+    pub options: serde_json::Map<String, Value>,
+    // Registered tool names the model may call, see tools.rs
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub max_tool_steps: Option<u32>,
+    // Sampling preset to apply, see presets.rs
+    #[serde(default)]
+    pub preset_id: Option<String>,
+    // Set from settings; images for cloud providers are stripped unless this is on
+    #[serde(skip)]
+    pub keep_image_metadata: bool,
+    // Set for cloud providers when anonymization is on, see anonymize.rs
+    #[serde(skip)]
+    pub anonymize: Option<Scope>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StreamEvent {
+    Delta { content: String },
+    // Sent before the request fails, so the UI can show which file and the limit
+    AttachmentTooLarge { error: TooLarge },
+    ContextTrimmed { report: ContextReport },
+    // Knowledge base chunks added to the prompt for this turn
+    ContextRetrieved { chunks: Vec<RetrievedChunk> },
+    // Exchanges from earlier chats added to the prompt, see history.rs
+    HistoryRecalled { recollections: Vec<Recollection> },
+    // Sent before the request goes out; when blocked it fails right after
+    SecretsDetected { findings: Vec<SecretFinding>, blocked: bool },
+    ToolCall { call: ToolCall },
+    // Waits for respond_tool_permission before the call runs
+    PermissionRequested { request_id: String, call: ToolCall },
+    ToolResult { call_id: String, name: String, result: Value, is_error: bool },
+    Done { content: String, messages: Vec<ChatMessage> },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Completion {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+// Offline, cloud providers are refused here rather than at the network layer
+// so the error names the provider
+pub fn resolve_provider(settings: &Settings, id: Option<&str>) -> Result<ProviderConfig, String> {
+    let provider = match id {
+        Some(id) => settings.provider(id),
+        None => settings.providers.iter().find(|p| p.enabled && (!settings.offline_mode || p.is_on_device())),
+    };
+    let provider = provider
+        .cloned()
+        .ok_or_else(|| format!("Unknown provider: {}", id.unwrap_or("(none configured)")))?;
+    if settings.offline_mode && !provider.is_on_device() {
+        return Err(format!("Offline mode is on, so {} can't be used; pick a local model", provider.name));
+    }
+    Ok(provider)
+}
+
+async fn message_payload(
+    provider: &ProviderConfig,
+    message: &ChatMessage,
+    keep_metadata: bool,
+) -> Result<Value, String> {
+    let mut payload = if message.images.is_empty() {
+        json!({ "role": message.role, "content": message.content })
+    } else {
+        let mut parts = vec![json!({ "type": "text", "text": message.content })];
+        for image in &message.images {
+            parts.push(vision::image_part(provider, image, keep_metadata).await?);
+        }
+        json!({ "role": message.role, "content": parts })
+    };
+
+    if !message.tool_calls.is_empty() {
+        payload["tool_calls"] = message
+            .tool_calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": call.arguments }
+                })
+            })
+            .collect();
+    }
+    if let Some(id) = &message.tool_call_id {
+        payload["tool_call_id"] = json!(id);
+    }
+    Ok(payload)
+}
+
+async fn build_payload(provider: &ProviderConfig, request: &CompletionRequest) -> Result<Value, String> {
+    let mut messages = Vec::with_capacity(request.messages.len());
+    for message in &request.messages {
+        messages.push(message_payload(provider, message, request.keep_image_metadata).await?);
+    }
+
+    let mut payload = json!({
+        "model": request.model,
+        "messages": messages,
+        "stream": true,
+    });
+    if !request.tools.is_empty() {
+        payload["tools"] = Value::Array(tools::definitions(&request.tools)?);
+    }
+    if let Some(obj) = payload.as_object_mut() {
+        for (key, value) in &request.options {
+            obj.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(payload)
+}
+
+// Tool calls arrive as fragments keyed by index: the first carries id and
+// name, later ones append to the arguments string.
+fn merge_tool_call_deltas(calls: &mut Vec<ToolCall>, deltas: &Value) {
+    let Some(deltas) = deltas.as_array() else {
+        return;
+    };
+    for delta in deltas {
+        let index = delta["index"].as_u64().unwrap_or(calls.len() as u64) as usize;
+        while calls.len() <= index {
+            calls.push(ToolCall::default());
+        }
+        let call = &mut calls[index];
+        if let Some(id) = delta["id"].as_str() {
+            call.id = id.to_string();
+        }
+        if let Some(name) = delta["function"]["name"].as_str() {
+            call.name.push_str(name);
+        }
+        if let Some(arguments) = delta["function"]["arguments"].as_str() {
+            call.arguments.push_str(arguments);
+        }
+    }
+}
+
+// Pulls the text out of one SSE chunk, covering both OpenAI-style deltas and
+// Claude-style content blocks (thinking is wrapped in <think> like the UI expects).
+fn delta_text(data: &Value) -> String {
+    if let Some(content) = data["choices"][0]["delta"]["content"].as_str() {
+        return content.to_string();
+    }
+
+    let mut content = String::new();
+    let blocks = match &data["delta"]["content"] {
+        Value::Array(blocks) => blocks.clone(),
+        Value::Null => Vec::new(),
+        block => vec![block.clone()],
+    };
+    for block in blocks {
+        match block["type"].as_str() {
+            Some("thinking") => {
+                if let Some(thinking) = block["thinking"].as_str() {
+                    content.push_str(&format!("<think>{}</think>\n\n", thinking));
+                }
+            }
+            Some("text") => {
+                if let Some(text) = block["text"].as_str() {
+                    content.push_str(text);
+                }
+            }
+            _ => {}
+        }
+    }
+    content
+}
+
+// Sends anonymized messages when the request asks for it and restores the
+// originals in everything that comes back, deltas included
+pub async fn complete_turn(
+    provider: &ProviderConfig,
+    request: &CompletionRequest,
+    mut on_delta: impl FnMut(&str),
+) -> Result<Completion, String> {
+    let Some(scope) = &request.anonymize else {
+        return send_turn(provider, request, on_delta).await;
+    };
+    let mut anonymized = request.clone();
+    anonymize::messages(scope, &mut anonymized.messages)?;
+    let mut restorer = anonymize::Restorer::new(&scope.key);
+    let mut turn = send_turn(provider, &anonymized, |delta| {
+        let restored = restorer.push(delta);
+        if !restored.is_empty() {
+            on_delta(&restored);
+        }
+    })
+    .await?;
+    let rest = restorer.finish();
+    if !rest.is_empty() {
+        on_delta(&rest);
+    }
+    anonymize::restore_completion(&scope.key, &mut turn);
+    Ok(turn)
+}
+
+async fn send_turn(
+    provider: &ProviderConfig,
+    request: &CompletionRequest,
+    mut on_delta: impl FnMut(&str),
+) -> Result<Completion, String> {
+    secrets::check(provider, &request.messages)?;
+    let payload = build_payload(provider, request).await?;
+
+    let mut http_request = crate::http::client()
+        .post(format!("{}/chat/completions", provider.openai_base()))
+        .json(&payload);
+    if !provider.api_key.trim().is_empty() {
+        http_request = http_request.bearer_auth(provider.api_key.trim());
+    }
+
+    let mut response = crate::http::send(http_request)
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", provider.name, e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|v| v["error"]["message"].as_str().or(v["message"].as_str()).map(str::to_string))
+            .unwrap_or(body);
+        return Err(format!("API Error {}: {}", status.as_u16(), message));
+    }
+
+    let is_stream = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+
+    // Some gateways ignore `stream: true` and answer with a single JSON body
+    if !is_stream {
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        let data: Value = serde_json::from_str(&body).map_err(|_| format!("Unexpected response: {}", body))?;
+        if let Some(message) = data["error"]["message"].as_str() {
+            return Err(format!("API Error: {}", message));
+        }
+        let message = &data["choices"][0]["message"];
+        let content = message["content"].as_str().unwrap_or_default().to_string();
+        let mut tool_calls = Vec::new();
+        merge_tool_call_deltas(&mut tool_calls, &message["tool_calls"]);
+        on_delta(&content);
+        return Ok(Completion { content, tool_calls });
+    }
+
+    let mut full = Completion::default();
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Stream interrupted - connection may have been lost: {}", e))?
+    {
+        buffer.extend_from_slice(&chunk);
+
+        // Only decode complete lines so multi-byte characters split across chunks survive
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+
+            let Some(data) = line.strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                return Ok(full);
+            }
+
+            match serde_json::from_str::<Value>(data) {
+                Ok(value) => {
+                    merge_tool_call_deltas(&mut full.tool_calls, &value["choices"][0]["delta"]["tool_calls"]);
+                    let content = delta_text(&value);
+                    if !content.is_empty() {
+                        full.content.push_str(&content);
+                        on_delta(&content);
+                    }
+                }
+                Err(_) => log::warn!("Failed to parse SSE message: {}", data),
+            }
+        }
+    }
+
+    Ok(full)
+}
+
+pub async fn complete(
+    provider: &ProviderConfig,
+    request: &CompletionRequest,
+    on_delta: impl FnMut(&str),
+) -> Result<String, String> {
+    Ok(complete_turn(provider, request, on_delta).await?.content)
+}
+
+// Runs the model, executes any tools it asks for, feeds the results back and
+// repeats until it answers without tool calls. Returns the messages added to
+// the conversation (assistant turns and tool results) in order.
+pub async fn run_with_tools(
+    app: &tauri::AppHandle,
+    provider: &ProviderConfig,
+    mut request: CompletionRequest,
+    on_event: &Channel<StreamEvent>,
+) -> Result<Vec<ChatMessage>, String> {
+    let max_steps = request.max_tool_steps.unwrap_or(DEFAULT_MAX_TOOL_STEPS);
+    let mut added = Vec::new();
+
+    for step in 0.. {
+        // Out of steps: make one last call without tools so the model has to answer
+        if step >= max_steps {
+            request.tools.clear();
+        }
+
+        let turn = complete_turn(provider, &request, |delta| {
+            let _ = on_event.send(StreamEvent::Delta { content: delta.to_string() });
+        })
+        .await?;
+
+        let mut assistant = ChatMessage::new("assistant", turn.content);
+        if step < max_steps {
+            assistant.tool_calls = turn.tool_calls.into_iter().filter(|call| !call.name.is_empty()).collect();
+        }
+        let calls = assistant.tool_calls.clone();
+        request.messages.push(assistant.clone());
+        added.push(assistant);
+
+        if calls.is_empty() {
+            break;
+        }
+
+        for call in calls {
+            let _ = on_event.send(StreamEvent::ToolCall { call: call.clone() });
+            let chat_id = request.chat_id.as_deref();
+            let outcome = match permissions::request(app, chat_id, &call, on_event).await {
+                Ok(()) => {
+                    let private = chat_id.is_some_and(crate::incognito::is_incognito);
+                    tools::execute(app, &call.name, &call.arguments, private).await
+                }
+                Err(e) => Err(e),
+            };
+            let (result, is_error) = match outcome {
+                Ok(result) => (result, false),
+                Err(e) => (json!({ "error": e }), true),
+            };
+            let _ = on_event.send(StreamEvent::ToolResult {
+                call_id: call.id.clone(),
+                name: call.name.clone(),
+                result: result.clone(),
+                is_error,
+            });
+
+            let mut message = ChatMessage::new("tool", result.to_string());
+            message.tool_call_id = Some(call.id);
+            request.messages.push(message.clone());
+            added.push(message);
+        }
+    }
+
+    Ok(added)
+}
+
+#[tauri::command]
+pub async fn stream_completion(
+    app: tauri::AppHandle,
+    request: CompletionRequest,
+    on_event: Channel<StreamEvent>,
+) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let chat_id = request.chat_id.clone();
+    let started = std::time::Instant::now();
+    let generation = crate::jobs::generating(&app, chat_id.as_deref());
+    let result = stream(app.clone(), request, on_event).await;
+    drop(generation);
+    if let Ok(content) = &result {
+        crate::notifications::finished(&app, chat_id.as_deref(), content, started.elapsed());
+    }
+    analytics::track(&app, "stream_completion", result)
+}
+
+async fn stream(
+    app: tauri::AppHandle,
+    mut request: CompletionRequest,
+    on_event: Channel<StreamEvent>,
+) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if let Err(error) = attachments::check_limits(&app, &request.messages) {
+        let message = error.to_string();
+        let _ = on_event.send(StreamEvent::AttachmentTooLarge { error });
+        return Err(message);
+    }
+    attachments::resolve_images(&app, &mut request.messages)?;
+    extraction::inline_documents(&app, &mut request).await?;
+    let settings = settings::load(&app)?;
+    // A prompt assigned to the chat itself takes precedence over its assistant's
+    prompts::apply(&app, &mut request)?;
+    assistants::apply(&app, &mut request)?;
+    memory::apply(&app, &settings, &mut request);
+    let retrieved = retrieval::apply(&app, &mut request).await;
+    if !retrieved.is_empty() {
+        let _ = on_event.send(StreamEvent::ContextRetrieved { chunks: retrieved.clone() });
+    }
+
+    let provider = resolve_provider(&settings, request.provider_id.as_deref())?;
+    let recollections = history::apply(&app, &settings, &mut request).await;
+    if !recollections.is_empty() {
+        let _ = on_event.send(StreamEvent::HistoryRecalled { recollections });
+    }
+    presets::apply(&app, &settings, &provider.id, &mut request);
+    request.keep_image_metadata = !settings.strip_image_metadata;
+    request.anonymize = anonymize::key(&settings, &provider, request.chat_id.as_deref());
+    let one_off = request.anonymize.as_ref().filter(|_| request.chat_id.is_none()).map(|s| s.key.clone());
+
+    if let Some(report) = context::fit_to_window(&app, &settings, &provider, &mut request).await? {
+        let _ = on_event.send(StreamEvent::ContextTrimmed { report });
+    }
+
+    let findings = secrets::findings(secrets::mode(), &provider, &request.messages);
+    if !findings.is_empty() {
+        let blocked = secrets::mode() == SecretScan::Block;
+        let _ = on_event.send(StreamEvent::SecretsDetected { findings, blocked });
+    }
+
+    let model = request.model.clone();
+    let mut transcript = request.messages.clone();
+    let chat_id = request.chat_id.clone();
+
+    let result = run_with_tools(&app, &provider, request, &on_event).await;
+    if let Some(key) = one_off {
+        anonymize::forget(&key);
+    }
+    let mut messages = result?;
+    if let Some(answer) = messages.last_mut().filter(|m| m.role == "assistant") {
+        answer.citations = retrieval::citations(&retrieved, &answer.content);
+    }
+    let content = messages.last().map(|m| m.content.clone()).unwrap_or_default();
+
+    if let Some(chat_id) = chat_id {
+        transcript.extend(messages.iter().cloned());
+        history::schedule(&app, &settings, chat_id.clone(), transcript.clone());
+        memory::schedule(&app, &settings, &provider, &model, chat_id.clone(), transcript.clone());
+        titles::schedule(&app, &settings, &provider, &model, chat_id, transcript);
+    }
+
+    let _ = on_event.send(StreamEvent::Done { content: content.clone(), messages });
+    Ok(content)
+}
@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::chats;
+use crate::crdt;
+
+// Reconciles a chat that changed on two devices since their last sync. Chats
+// whose messages carry sequence stamps on both sides always merge, see
+// crdt.rs. Older copies are merged message by message against the last
+// synced copy, so edits to different messages (or additions on only one
+// side) combine. When both sides changed the same message, the newer copy
+// keeps the chat and the other is saved beside it as a conflict copy for the
+// user to resolve.
+
+// Fields that say when or where rather than what, ignored when comparing
+const VOLATILE_KEYS: &[&str] = &["updatedAt", "timestamp", "updatedBy"];
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    // The copy's own chat id
+    pub id: String,
+    // The chat it conflicted with
+    pub chat_id: String,
+    pub title: String,
+    pub conflicted_at: u64,
+    // The device the copy's changes were made on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    // Keep the chat, drop the conflict copy
+    Original,
+    // Replace the chat with the conflict copy
+    Conflict,
+    // Keep both as separate chats
+    Both,
+}
+
+pub enum Merged {
+    Clean(Value),
+    // The chat as it should be kept, and the copy to save next to it
+    Conflicted { keep: Value, copy: Value },
+}
+
+// Three-way merge of one value; None when both sides changed it differently
+fn merge_value(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Option<Option<Value>> {
+    if ours == theirs || theirs == base {
+        Some(ours.cloned())
+    } else if ours == base {
+        Some(theirs.cloned())
+    } else {
+        None
+    }
+}
+
+fn merge_messages(base: Option<&Value>, ours: &Value, theirs: &Value) -> Option<Vec<Value>> {
+    let list = |session: Option<&Value>| session.and_then(|s| s["messages"].as_array().cloned()).unwrap_or_default();
+    let (base, ours, theirs) = (list(base), list(Some(ours)), list(Some(theirs)));
+    let len = base.len().max(ours.len()).max(theirs.len());
+    let mut merged = Vec::new();
+    let mut ended = false;
+    for i in 0..len {
+        match merge_value(base.get(i), ours.get(i), theirs.get(i))? {
+            // A message after one that was removed means the sides disagree
+            Some(_) if ended => return None,
+            Some(message) => merged.push(message),
+            None => ended = true,
+        }
+    }
+    Some(merged)
+}
+
+// Lists whose items are told apart by id, like prompts and assistants
+fn is_library(value: &Value) -> bool {
+    value.as_array().is_some_and(|items| items.iter().all(|item| item["id"].is_string()))
+}
+
+fn conflict_copy(item: &Value) -> Value {
+    let mut copy = item.clone();
+    let id = format!("{}_conflict_{}", item["id"].as_str().unwrap_or_default(), chats::now_millis());
+    copy["id"] = Value::String(id);
+    if let Some(name) = item["name"].as_str() {
+        copy["name"] = Value::String(format!("{} (conflict)", name));
+    }
+    copy
+}
+
+fn merge_items(base: Option<&Value>, ours: &Value, theirs: &Value, ours_newer: bool) -> Vec<Value> {
+    let find = |list: Option<&Value>, id: &str| {
+        list.and_then(Value::as_array).and_then(|items| items.iter().find(|item| item["id"] == id)).cloned()
+    };
+    let mut ids: Vec<&str> = Vec::new();
+    for item in ours.as_array().into_iter().chain(theirs.as_array()).flatten() {
+        if let Some(id) = item["id"].as_str().filter(|id| !ids.contains(id)) {
+            ids.push(id);
+        }
+    }
+    let mut merged = Vec::new();
+    for id in ids {
+        let (base, ours, theirs) = (find(base, id), find(Some(ours), id), find(Some(theirs), id));
+        if let Some(item) = merge_value(base.as_ref(), ours.as_ref(), theirs.as_ref()) {
+            merged.extend(item);
+            continue;
+        }
+        let (newer, older) = if ours_newer { (ours, theirs) } else { (theirs, ours) };
+        match (newer, older) {
+            (Some(newer), Some(older)) => {
+                merged.push(newer);
+                merged.push(conflict_copy(&older));
+            }
+            // Removed on one side and changed on the other: kept
+            (newer, older) => merged.extend(newer.or(older)),
+        }
+    }
+    merged
+}
+
+// Three-way merge of a settings file or a library like the prompts. Objects
+// are merged key by key and libraries item by item. An item changed on both
+// sides is kept twice, the older version as a conflict copy; any other value
+// changed on both sides takes the newer side's.
+pub fn merge_document(base: Option<&Value>, ours: &Value, theirs: &Value, ours_newer: bool) -> Value {
+    if let Some(Some(value)) = merge_value(base, Some(ours), Some(theirs)) {
+        return value;
+    }
+    match (ours, theirs) {
+        (Value::Object(ours_obj), Value::Object(theirs_obj)) => {
+            let mut merged = serde_json::Map::new();
+            for key in ours_obj.keys().chain(theirs_obj.keys()) {
+                if merged.contains_key(key) {
+                    continue;
+                }
+                let base = base.and_then(|b| b.get(key));
+                let value = match (ours_obj.get(key), theirs_obj.get(key)) {
+                    (Some(o), Some(t)) => Some(merge_document(base, o, t, ours_newer)),
+                    (o, t) => merge_value(base, o, t).unwrap_or_else(|| o.or(t).cloned()),
+                };
+                if let Some(value) = value {
+                    merged.insert(key.clone(), value);
+                }
+            }
+            Value::Object(merged)
+        }
+        _ if is_library(ours) && is_library(theirs) => Value::Array(merge_items(base, ours, theirs, ours_newer)),
+        _ if ours_newer => ours.clone(),
+        _ => theirs.clone(),
+    }
+}
+
+fn updated_at(session: &Value) -> u64 {
+    session["updatedAt"].as_f64().or(session["timestamp"].as_f64()).unwrap_or(0.0) as u64
+}
+
+pub fn merge(base: Option<&Value>, ours: &Value, theirs: &Value) -> Result<Merged, String> {
+    let (ours_obj, theirs_obj) = match (ours.as_object(), theirs.as_object()) {
+        (Some(o), Some(t)) => (o, t),
+        _ => return Err("Invalid session format".to_string()),
+    };
+    let (newer, older) = if updated_at(ours) >= updated_at(theirs) { (ours, theirs) } else { (theirs, ours) };
+    let sequence = (crdt::tracked(ours) && crdt::tracked(theirs)).then(|| crdt::merge(base, ours, theirs));
+    let messages = match &sequence {
+        Some(sequence) => sequence.messages.clone(),
+        None => match merge_messages(base, ours, theirs) {
+            Some(messages) => messages,
+            None => return Ok(Merged::Conflicted { keep: newer.clone(), copy: older.clone() }),
+        },
+    };
+
+    let mut merged = serde_json::Map::new();
+    for key in ours_obj.keys().chain(theirs_obj.keys()) {
+        if key == "messages" || merged.contains_key(key) {
+            continue;
+        }
+        let value = if VOLATILE_KEYS.contains(&key.as_str()) {
+            None
+        } else {
+            merge_value(base.and_then(|b| b.get(key)), ours.get(key), theirs.get(key))
+        };
+        // Titles, tags and such aren't worth a conflict copy: the newer side wins
+        if let Some(value) = value.unwrap_or_else(|| newer.get(key).cloned()) {
+            merged.insert(key.clone(), value);
+        }
+    }
+    merged.insert("messages".to_string(), Value::Array(messages));
+    if let Some(sequence) = sequence {
+        merged.insert("crdtClock".to_string(), Value::from(sequence.clock));
+        merged.insert("deletedMessages".to_string(), Value::from(sequence.deleted));
+    }
+    Ok(Merged::Clean(Value::Object(merged)))
+}
+
+// Saves `copy` as a new chat marked as conflicting with `chat_id`
+pub fn save_copy(app: &tauri::AppHandle, chat_id: &str, mut copy: Value) -> Result<String, String> {
+    let now = chats::now_millis() as u64;
+    let obj = copy.as_object_mut().ok_or("Invalid session format")?;
+    obj.insert("id".to_string(), Value::String(format!("{}_conflict_{}", chat_id, now)));
+    obj.insert("conflictOf".to_string(), Value::String(chat_id.to_string()));
+    obj.insert("conflictedAt".to_string(), serde_json::json!(now));
+    // Saving stamps this device as the last to change it
+    if let Some(device) = obj.get("updatedBy").cloned() {
+        obj.insert("conflictDevice".to_string(), device);
+    }
+    chats::write(app, copy)
+}
+
+#[tauri::command]
+pub fn list_conflicts(app: tauri::AppHandle) -> Result<Vec<Conflict>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    Ok(chats::list_chats(app)?
+        .into_iter()
+        .filter_map(|session| {
+            Some(Conflict {
+                id: session["id"].as_str()?.to_string(),
+                chat_id: session["conflictOf"].as_str()?.to_string(),
+                title: session["title"].as_str().unwrap_or_default().to_string(),
+                conflicted_at: session["conflictedAt"].as_u64().unwrap_or(0),
+                device: session["conflictDevice"].as_str().map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn resolve_conflict(app: tauri::AppHandle, conflict_id: String, keep: Resolution) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut copy = chats::read(&app, &conflict_id)?;
+    let chat_id = copy["conflictOf"].as_str().ok_or("That chat isn't a conflict copy")?.to_string();
+    if let Some(obj) = copy.as_object_mut() {
+        obj.remove("conflictOf");
+        obj.remove("conflictedAt");
+    }
+    match keep {
+        Resolution::Original => chats::delete_chat(app, conflict_id),
+        Resolution::Conflict => {
+            copy["id"] = Value::String(chat_id);
+            chats::write(&app, copy)?;
+            chats::delete_chat(app, conflict_id)
+        }
+        Resolution::Both => chats::write(&app, copy).map(|_| ()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn session(messages: &[&str]) -> Value {
+        json!({ "messages": messages.iter().map(|m| json!({ "role": "user", "content": m })).collect::<Vec<_>>() })
+    }
+
+    #[test]
+    fn merge_messages_takes_each_sides_changes() {
+        let base = session(&["hi", "hello"]);
+        let merged = merge_messages(Some(&base), &session(&["hi", "hello there"]), &session(&["hi", "hello", "bye"]));
+        assert_eq!(merged, Some(session(&["hi", "hello there", "bye"])["messages"].as_array().cloned().unwrap()));
+    }
+
+    #[test]
+    fn merge_messages_refuses_different_edits_to_one_message() {
+        let base = session(&["hi", "hello"]);
+        assert_eq!(merge_messages(Some(&base), &session(&["hi", "one"]), &session(&["hi", "two"])), None);
+    }
+
+    #[test]
+    fn merge_messages_refuses_a_message_after_a_removed_one() {
+        let base = session(&["hi", "hello"]);
+        assert_eq!(merge_messages(Some(&base), &session(&["hi"]), &session(&["hi", "hello", "bye"])), None);
+    }
+
+    #[test]
+    fn merge_without_stamps_keeps_both_copies_on_conflict() {
+        let base = session(&["hi"]);
+        let (ours, theirs) = (session(&["hi", "one"]), session(&["hi", "two"]));
+        match merge(Some(&base), &ours, &theirs) {
+            Ok(Merged::Conflicted { keep, copy }) => assert_eq!((keep, copy), (ours, theirs)),
+            _ => panic!("expected a conflict"),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chats;
+use crate::completion::{self, ChatMessage, CompletionRequest};
+use crate::settings::{ProviderConfig, Settings};
+
+const DEFAULT_OUTPUT_RESERVE: usize = 4096;
+const SUMMARY_MAX_TOKENS: u64 = 1024;
+// Per-message framing tokens (role markers etc.) most chat formats add
+const MESSAGE_OVERHEAD: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextStrategy {
+    #[default]
+    Summarize,
+    Truncate,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextSummary {
+    pub text: String,
+    // How many leading non-system messages of the chat the summary stands in for
+    pub covered_messages: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextReport {
+    pub strategy: ContextStrategy,
+    pub window: usize,
+    pub estimated_tokens: usize,
+    pub dropped_messages: usize,
+    pub summary: Option<String>,
+}
+
+// Mirrors the context windows shown in the model picker (utils/modelMetadata.ts).
+fn known_window(model: &str) -> usize {
+    let lower = model.to_lowercase();
+    if lower.contains("o1") || lower.contains("o3") || lower.contains("claude") {
+        200_000
+    } else if lower.contains("gemini") {
+        1_000_000
+    } else if lower.contains("gpt-4") || lower.contains("llama-3") || lower.contains("llama3") {
+        128_000
+    } else if lower.contains("deepseek") {
+        64_000
+    } else if lower.contains("mistral") || lower.contains("mixtral") || lower.contains("qwen") {
+        32_000
+    } else if lower.contains("gpt-3.5") {
+        16_000
+    } else {
+        8_192
+    }
+}
+
+pub fn context_window(settings: &Settings, model: &str) -> usize {
+    settings.context_windows.get(model).copied().unwrap_or_else(|| known_window(model))
+}
+
+// Rough but tokenizer-free: ~4 chars per token for Latin text, ~1 per CJK character.
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(a, o), c| {
+        if c.is_ascii() {
+            (a + 1, o)
+        } else {
+            (a, o + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+fn message_tokens(message: &ChatMessage) -> usize {
+    let tool_args: usize = message.tool_calls.iter().map(|c| estimate_tokens(&c.arguments)).sum();
+    estimate_tokens(&message.content) + tool_args + MESSAGE_OVERHEAD
+}
+
+pub fn conversation_tokens(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(message_tokens).sum()
+}
+
+fn output_reserve(request: &CompletionRequest) -> usize {
+    ["max_tokens", "max_completion_tokens"]
+        .iter()
+        .find_map(|key| request.options.get(*key).and_then(|v| v.as_u64()))
+        .map(|v| v as usize)
+        .unwrap_or(DEFAULT_OUTPUT_RESERVE)
+}
+
+pub(crate) fn transcript(summary: Option<&str>, messages: &[ChatMessage]) -> String {
+    let mut out = String::new();
+    if let Some(summary) = summary {
+        out.push_str(&format!("Summary so far:\n{}\n\n", summary));
+    }
+    for message in messages {
+        out.push_str(&format!("{}: {}\n\n", message.role, message.content));
+    }
+    out
+}
+
+async fn summarize(
+    provider: &ProviderConfig,
+    request: &CompletionRequest,
+    previous: Option<&str>,
+    messages: &[ChatMessage],
+) -> Result<String, String> {
+    let mut request = CompletionRequest {
+        provider_id: Some(provider.id.clone()),
+        model: request.model.clone(),
+        messages: vec![
+            ChatMessage::new(
+                "system",
+                "Summarize the conversation below so it can replace the original messages as context. \
+                 Keep decisions, facts, names, code identifiers and open questions. Be concise.",
+            ),
+            ChatMessage::new("user", transcript(previous, messages)),
+        ],
+        anonymize: request.anonymize.clone(),
+        ..Default::default()
+    };
+    request.options.insert("max_tokens".to_string(), json!(SUMMARY_MAX_TOKENS));
+
+    let summary = completion::complete(provider, &request, |_| {}).await?;
+    Ok(summary.trim().to_string())
+}
+
+fn summary_message(summary: &str) -> ChatMessage {
+    ChatMessage::new("system", format!("Summary of the earlier part of this conversation:\n{}", summary))
+}
+
+// Makes the request fit the model's window, summarizing (or dropping) the
+// oldest turns. Returns None when nothing had to change.
+pub async fn fit_to_window(
+    app: &tauri::AppHandle,
+    settings: &Settings,
+    provider: &ProviderConfig,
+    request: &mut CompletionRequest,
+) -> Result<Option<ContextReport>, String> {
+    let window = context_window(settings, &request.model);
+    let budget = window.saturating_sub(output_reserve(request));
+    let estimated_tokens = conversation_tokens(&request.messages);
+    if estimated_tokens <= budget {
+        return Ok(None);
+    }
+
+    let (system, mut history): (Vec<ChatMessage>, Vec<ChatMessage>) =
+        request.messages.drain(..).partition(|m| m.role == "system");
+    if history.is_empty() {
+        return Err("The system prompt alone exceeds the model's context window".to_string());
+    }
+    let system_tokens = conversation_tokens(&system);
+
+    // Keep the most recent turns that fit in the lower part of the budget,
+    // leaving headroom for the summary itself
+    let recent_budget = budget
+        .saturating_sub(system_tokens)
+        .saturating_sub(SUMMARY_MAX_TOKENS as usize)
+        * 3
+        / 4;
+    let mut keep_from = history.len();
+    let mut recent_tokens = 0;
+    while keep_from > 1 {
+        let tokens = message_tokens(&history[keep_from - 1]);
+        if recent_tokens + tokens > recent_budget {
+            break;
+        }
+        recent_tokens += tokens;
+        keep_from -= 1;
+    }
+    // Never start on a tool result whose call we're about to drop
+    while keep_from < history.len() - 1 && history[keep_from].role == "tool" {
+        keep_from += 1;
+    }
+    let recent = history.split_off(keep_from);
+    let dropped = history;
+
+    let mut session = match &request.chat_id {
+        Some(id) => chats::read(app, id).ok(),
+        None => None,
+    };
+
+    let summary = match settings.context_strategy {
+        ContextStrategy::Truncate => None,
+        ContextStrategy::Summarize => {
+            let previous: Option<ContextSummary> = session
+                .as_ref()
+                .and_then(|s| serde_json::from_value(s["contextSummary"].clone()).ok());
+
+            // Reuse the stored summary for the prefix it already covers and only
+            // summarize what's new since then
+            let (previous_text, pending) = match &previous {
+                Some(prev) if prev.covered_messages <= dropped.len() => {
+                    (Some(prev.text.as_str()), &dropped[prev.covered_messages..])
+                }
+                _ => (None, &dropped[..]),
+            };
+            let text = if pending.is_empty() {
+                previous_text.unwrap_or_default().to_string()
+            } else {
+                summarize(provider, request, previous_text, pending).await?
+            };
+
+            if let Some(session) = session.as_mut() {
+                session["contextSummary"] =
+                    json!(ContextSummary { text: text.clone(), covered_messages: dropped.len() });
+                chats::write(app, session.clone())?;
+            }
+            Some(text)
+        }
+    };
+
+    request.messages = system;
+    if let Some(summary) = &summary {
+        request.messages.push(summary_message(summary));
+    }
+    request.messages.extend(recent);
+
+    Ok(Some(ContextReport {
+        strategy: settings.context_strategy,
+        window,
+        estimated_tokens,
+        dropped_messages: dropped.len(),
+        summary,
+    }))
+}
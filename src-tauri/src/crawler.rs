@@ -0,0 +1,232 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri_plugin_http::reqwest::{self, Url};
+
+use crate::web::{self, WebPage};
+
+// Matched against robots.txt groups besides "*"
+const ROBOTS_AGENT: &str = "anchor";
+const ROBOTS_TIMEOUT: Duration = Duration::from_secs(10);
+// Between requests, unless robots.txt asks for more
+const DEFAULT_DELAY: Duration = Duration::from_millis(250);
+// Crawl-delay values past this are capped rather than honoured
+const MAX_DELAY: Duration = Duration::from_secs(10);
+// Hard limit whatever the options say
+const MAX_PAGES_LIMIT: usize = 1000;
+// Links to these are never pages
+const SKIPPED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "svg", "ico", "css", "js", "mjs", "map", "woff", "woff2", "ttf", "zip",
+    "gz", "tgz", "tar", "dmg", "exe", "msi", "mp3", "mp4", "webm", "pdf",
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CrawlOptions {
+    // Link hops from the start page; 0 fetches just that page
+    pub max_depth: usize,
+    pub max_pages: usize,
+    // Only URLs whose path starts with this are followed; defaults to the
+    // start page's directory, so /docs/intro stays within /docs/
+    pub path_prefix: Option<String>,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self { max_depth: 2, max_pages: 50, path_prefix: None }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageStatus {
+    pub url: String,
+    pub depth: usize,
+    // "fetched", "failed", "blocked" (by robots.txt) or "skipped" (redirected
+    // off the site)
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Robots {
+    // (allow, pattern)
+    rules: Vec<(bool, String)>,
+    delay: Option<Duration>,
+}
+
+impl Robots {
+    // Groups for "*" and for us; rules for us replace the "*" ones
+    fn parse(text: &str) -> Self {
+        let mut generic = Robots::default();
+        let mut specific: Option<Robots> = None;
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            if key == "user-agent" {
+                // A user-agent line after rules starts a new group
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_lowercase());
+                continue;
+            }
+            in_rules = true;
+            let target = if agents.iter().any(|a| a.contains(ROBOTS_AGENT)) {
+                specific.get_or_insert_with(Robots::default)
+            } else if agents.iter().any(|a| a == "*") {
+                &mut generic
+            } else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => target.rules.push((key == "allow", value.to_string())),
+                "crawl-delay" => target.delay = value.parse::<f64>().ok().map(|s| Duration::from_secs_f64(s.max(0.0))),
+                _ => {}
+            }
+        }
+        specific.unwrap_or(generic)
+    }
+
+    // The longest matching rule decides; allow wins ties
+    fn allows(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| pattern_matches(pattern, &path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+}
+
+// robots.txt patterns: a prefix match where `*` is any run of characters and
+// a trailing `$` anchors the end
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if !path.starts_with(parts[0]) {
+        return false;
+    }
+    let mut position = parts[0].len();
+    for (index, part) in parts.iter().enumerate().skip(1) {
+        let last = index == parts.len() - 1;
+        if last && anchored {
+            return path.len() >= position + part.len() && path.ends_with(part);
+        }
+        match path[position..].find(part) {
+            Some(found) => position += found + part.len(),
+            None => return false,
+        }
+    }
+    !anchored || position == path.len()
+}
+
+// A missing or unreadable robots.txt allows everything
+async fn robots(start: &Url) -> Robots {
+    let Ok(url) = start.join("/robots.txt") else {
+        return Robots::default();
+    };
+    let response = crate::http::send(crate::http::client().get(url).timeout(ROBOTS_TIMEOUT)).await;
+    match response {
+        Ok(response) if response.status().is_success() => {
+            response.text().await.map(|text| Robots::parse(&text)).unwrap_or_default()
+        }
+        _ => Robots::default(),
+    }
+}
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme() && a.host_str() == b.host_str() && a.port_or_known_default() == b.port_or_known_default()
+}
+
+fn in_scope(url: &Url, start: &Url, prefix: &str) -> bool {
+    let extension = url.path().rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    same_origin(url, start) && url.path().starts_with(prefix) && !SKIPPED_EXTENSIONS.contains(&extension.as_str())
+}
+
+async fn pause(delay: Duration) {
+    let _ = tauri::async_runtime::spawn_blocking(move || std::thread::sleep(delay)).await;
+}
+
+// Breadth-first from `start`, staying on its origin and under the path
+// prefix. `report` is called once per URL visited with the number of pages
+// fetched so far.
+pub async fn crawl(
+    start: &str,
+    options: &CrawlOptions,
+    mut report: impl FnMut(&PageStatus, usize),
+) -> Result<(Vec<WebPage>, Vec<PageStatus>), String> {
+    let mut start = Url::parse(start.trim()).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !matches!(start.scheme(), "http" | "https") {
+        return Err("Only http and https sites can be crawled".to_string());
+    }
+    start.set_fragment(None);
+    let prefix = options
+        .path_prefix
+        .clone()
+        .unwrap_or_else(|| start.path()[..start.path().rfind('/').map_or(0, |i| i + 1)].to_string());
+    let max_pages = options.max_pages.clamp(1, MAX_PAGES_LIMIT);
+    let robots = robots(&start).await;
+    let delay = robots.delay.unwrap_or(DEFAULT_DELAY).min(MAX_DELAY);
+
+    let mut seen: HashSet<Url> = HashSet::from([start.clone()]);
+    let mut queue: VecDeque<(Url, usize)> = VecDeque::from([(start.clone(), 0)]);
+    let mut pages: Vec<WebPage> = Vec::new();
+    let mut statuses = Vec::new();
+    while let Some((url, depth)) = queue.pop_front() {
+        if pages.len() >= max_pages {
+            break;
+        }
+        let mut status = PageStatus { url: url.to_string(), depth, status: "fetched", error: None };
+        if !robots.allows(&url) {
+            status.status = "blocked";
+            report(&status, pages.len());
+            statuses.push(status);
+            continue;
+        }
+        if !statuses.is_empty() {
+            pause(delay).await;
+        }
+
+        match web::fetch_with_links(url.as_str()).await {
+            Ok((page, links)) => {
+                let landed = reqwest::Url::parse(&page.url).unwrap_or_else(|_| url.clone());
+                if !in_scope(&landed, &start, &prefix) {
+                    status.status = "skipped";
+                    status.error = Some(format!("Redirected to {}", landed));
+                } else {
+                    seen.insert(landed);
+                    if depth < options.max_depth {
+                        for link in links.into_iter().filter(|l| in_scope(l, &start, &prefix)) {
+                            if seen.insert(link.clone()) {
+                                queue.push_back((link, depth + 1));
+                            }
+                        }
+                    }
+                    pages.push(page);
+                }
+            }
+            Err(e) => {
+                status.status = "failed";
+                status.error = Some(e);
+            }
+        }
+        report(&status, pages.len());
+        statuses.push(status);
+    }
+    Ok((pages, statuses))
+}
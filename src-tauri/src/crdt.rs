@@ -0,0 +1,343 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// A chat's messages as a replicated sequence (RGA), so copies changed on two
+// devices merge without losing either side's messages. Each message carries
+// a stamp: an id, the message it was added after, and Lamport times for when
+// it was added and last changed. Messages added after the same one on both
+// devices are ordered by when they were added, each followed by whatever
+// came after it, so each device's run of turns stays together. Edits to the
+// same message go to the later one, and the other version is kept on the
+// message. Removed messages leave their id and place behind, so merges
+// don't bring them back and what was added after them stays where it was.
+
+const KEY: &str = "crdt";
+const CLOCK_KEY: &str = "crdtClock";
+const DELETED_KEY: &str = "deletedMessages";
+const OVERWRITTEN_KEY: &str = "concurrentEdits";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Stamp {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<String>,
+    at: u64,
+    rev: u64,
+    // The device behind the last change, which also breaks ties
+    by: String,
+}
+
+// What's left of a removed message. Older copies kept the id alone.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Tombstone {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<String>,
+    #[serde(default)]
+    at: u64,
+}
+
+impl Tombstone {
+    fn placed(&self) -> bool {
+        self.at > 0 || self.after.is_some()
+    }
+}
+
+pub struct Merged {
+    pub messages: Vec<Value>,
+    pub clock: u64,
+    // Tombstones, as stored under deletedMessages
+    pub deleted: Vec<Value>,
+}
+
+fn stamp_of(message: &Value) -> Option<Stamp> {
+    serde_json::from_value(message.get(KEY)?.clone()).ok()
+}
+
+// The message without its stamp
+fn content(message: &Value) -> Value {
+    let mut message = message.clone();
+    if let Some(obj) = message.as_object_mut() {
+        obj.remove(KEY);
+    }
+    message
+}
+
+fn messages(session: Option<&Value>) -> &[Value] {
+    session.and_then(|s| s["messages"].as_array()).map_or(&[], Vec::as_slice)
+}
+
+fn tombstones(session: Option<&Value>) -> BTreeMap<String, Tombstone> {
+    let mut tombstones = BTreeMap::new();
+    for entry in session.and_then(|s| s[DELETED_KEY].as_array()).into_iter().flatten() {
+        let tombstone = match entry.as_str() {
+            Some(id) => Tombstone { id: id.to_string(), after: None, at: 0 },
+            None => match serde_json::from_value::<Tombstone>(entry.clone()) {
+                Ok(tombstone) => tombstone,
+                Err(_) => continue,
+            },
+        };
+        bury(&mut tombstones, tombstone);
+    }
+    tombstones
+}
+
+// Keeps the one that knows where the message was
+fn bury(tombstones: &mut BTreeMap<String, Tombstone>, tombstone: Tombstone) {
+    match tombstones.get(&tombstone.id) {
+        Some(existing) if existing.placed() || !tombstone.placed() => {}
+        _ => {
+            tombstones.insert(tombstone.id.clone(), tombstone);
+        }
+    }
+}
+
+fn stored(tombstones: BTreeMap<String, Tombstone>) -> Vec<Value> {
+    tombstones.into_values().filter_map(|t| serde_json::to_value(t).ok()).collect()
+}
+
+fn clock(session: Option<&Value>) -> u64 {
+    let latest = messages(session).iter().filter_map(stamp_of).map(|s| s.at.max(s.rev)).max();
+    session.and_then(|s| s[CLOCK_KEY].as_u64()).unwrap_or(0).max(latest.unwrap_or(0))
+}
+
+// Whether every message has a stamp, so the chat can be merged with merge()
+pub fn tracked(session: &Value) -> bool {
+    messages(Some(session)).iter().all(|m| stamp_of(m).is_some())
+}
+
+// Stamps the messages of a session about to be saved, against the copy saved
+// before it: new messages get an id, changed ones a new revision, and the ids
+// of removed ones are kept
+pub fn stamp(previous: Option<&Value>, session: &mut Map<String, Value>, device: &str) {
+    let before = messages(previous);
+    let before_by_id: HashMap<String, &Value> = before.iter().filter_map(|m| Some((stamp_of(m)?.id, m))).collect();
+    let current = Value::Object(session.clone());
+    let mut time = clock(previous).max(clock(Some(&current)));
+    let mut deleted = tombstones(previous);
+    for tombstone in tombstones(Some(&current)).into_values() {
+        bury(&mut deleted, tombstone);
+    }
+    let Some(list) = session.get_mut("messages").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    let carried: HashSet<String> = list.iter().filter_map(stamp_of).map(|s| s.id).collect();
+    let mut kept = HashSet::new();
+    let mut after: Option<String> = None;
+    for (i, message) in list.iter_mut().enumerate() {
+        if !message.is_object() {
+            continue;
+        }
+        // The UI may hand messages back without their stamps; the one in the
+        // same place before is taken to be the same message
+        let known = stamp_of(message)
+            .or_else(|| before.get(i).and_then(stamp_of).filter(|s| !carried.contains(&s.id) && !kept.contains(&s.id)));
+        let stamp = match known {
+            Some(mut stamp) => {
+                let old = before_by_id.get(&stamp.id);
+                let old_stamp = old.and_then(|old| stamp_of(old));
+                // A change that arrived with its own stamp, e.g. from a merge,
+                // is left as it is
+                let edited = old.is_some_and(|old| content(old) != content(message))
+                    && old_stamp.is_some_and(|old| old.rev == stamp.rev);
+                if edited {
+                    time += 1;
+                    stamp.rev = time;
+                    stamp.by = device.to_string();
+                }
+                stamp
+            }
+            None => {
+                time += 1;
+                let id = format!("{}-{}", device, time);
+                Stamp { id, after: after.clone(), at: time, rev: time, by: device.to_string() }
+            }
+        };
+        after = Some(stamp.id.clone());
+        kept.insert(stamp.id.clone());
+        message[KEY] = serde_json::to_value(&stamp).unwrap_or_default();
+    }
+    for (id, message) in before_by_id.into_iter().filter(|(id, _)| !kept.contains(id)) {
+        let stamp = stamp_of(message);
+        let (after, at) = stamp.map_or((None, 0), |s| (s.after, s.at));
+        bury(&mut deleted, Tombstone { id, after, at });
+    }
+
+    session.insert(CLOCK_KEY.to_string(), Value::from(time));
+    session.insert(DELETED_KEY.to_string(), Value::from(stored(deleted)));
+}
+
+pub fn merge(base: Option<&Value>, ours: &Value, theirs: &Value) -> Merged {
+    let base_revs: HashMap<String, u64> = messages(base).iter().filter_map(stamp_of).map(|s| (s.id, s.rev)).collect();
+    let mut deleted = tombstones(Some(ours));
+    for tombstone in tombstones(Some(theirs)).into_values() {
+        bury(&mut deleted, tombstone);
+    }
+
+    let mut elements: HashMap<String, (Stamp, Value)> = HashMap::new();
+    for message in messages(Some(ours)).iter().chain(messages(Some(theirs))) {
+        let Some(stamp) = stamp_of(message) else {
+            continue;
+        };
+        let Some((existing, value)) = elements.get_mut(&stamp.id) else {
+            elements.insert(stamp.id.clone(), (stamp, message.clone()));
+            continue;
+        };
+        if content(value) == content(message) {
+            continue;
+        }
+        let newer = (stamp.rev, &stamp.by) > (existing.rev, &existing.by);
+        let loser = if newer { std::mem::replace(value, message.clone()) } else { message.clone() };
+        if newer {
+            *existing = stamp.clone();
+        }
+        // Both sides edited it since the last sync: keep the losing version
+        let base_rev = base_revs.get(&stamp.id).copied();
+        let loser_rev = stamp_of(&loser).map_or(0, |l| l.rev);
+        let concurrent = base_rev.is_some_and(|rev| loser_rev > rev && existing.rev > rev);
+        if concurrent && loser["content"] != value["content"] {
+            let entry = serde_json::json!({ "content": loser["content"], "by": stamp_of(&loser).map(|s| s.by) });
+            if let Some(obj) = value.as_object_mut() {
+                let list = obj.entry(OVERWRITTEN_KEY).or_insert_with(|| Value::Array(Vec::new()));
+                if let Some(list) = list.as_array_mut().filter(|list| !list.contains(&entry)) {
+                    list.push(entry);
+                }
+            }
+        }
+    }
+
+    // Every place in the sequence, id -> (added after, added at): the
+    // messages, and removed ones that are gone from both sides, which stay
+    // as anchors for whatever was added after them
+    let mut places: HashMap<&str, (Option<&str>, u64)> =
+        elements.values().map(|(s, _)| (s.id.as_str(), (s.after.as_deref(), s.at))).collect();
+    for tombstone in deleted.values().filter(|t| t.placed()) {
+        places.entry(tombstone.id.as_str()).or_insert((tombstone.after.as_deref(), tombstone.at));
+    }
+
+    // Places by the one they were added after, oldest first. The id names
+    // the device that added it; `by` changes with edits.
+    let mut children: HashMap<Option<&str>, Vec<(u64, &str)>> = HashMap::new();
+    for (&id, &(after, at)) in &places {
+        let parent = after.filter(|after| places.contains_key(after));
+        children.entry(parent).or_default().push((at, id));
+    }
+    for siblings in children.values_mut() {
+        siblings.sort();
+    }
+    let mut ordered = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack: Vec<&str> = children.get(&None).into_iter().flatten().rev().map(|&(_, id)| id).collect();
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        if let Some((_, message)) = elements.get(id).filter(|_| !deleted.contains_key(id)) {
+            ordered.push(message.clone());
+        }
+        stack.extend(children.get(&Some(id)).into_iter().flatten().rev().map(|&(_, id)| id));
+    }
+    // Only reachable through a broken chain of ids; kept rather than lost
+    let mut stray: Vec<&Stamp> = elements.values().map(|(s, _)| s).collect();
+    stray.retain(|s| !visited.contains(s.id.as_str()));
+    stray.sort_by(|a, b| (a.at, &a.id).cmp(&(b.at, &b.id)));
+    for stamp in stray.into_iter().filter(|s| !deleted.contains_key(&s.id)) {
+        ordered.push(elements[&stamp.id].1.clone());
+    }
+
+    Merged { messages: ordered, clock: clock(Some(ours)).max(clock(Some(theirs))), deleted: stored(deleted) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(id: &str, after: Option<&str>, at: u64, content: &str) -> Value {
+        let by = id.split('-').next().unwrap_or_default();
+        json!({
+            "role": "user",
+            "content": content,
+            KEY: { "id": id, "after": after, "at": at, "rev": at, "by": by },
+        })
+    }
+
+    fn session(messages: Vec<Value>, deleted: Vec<Value>) -> Value {
+        json!({ "messages": messages, DELETED_KEY: deleted })
+    }
+
+    fn contents(merged: &Merged) -> Vec<&str> {
+        merged.messages.iter().map(|m| m["content"].as_str().unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn merge_keeps_each_sides_run_together() {
+        let first = message("a-1", None, 1, "first");
+        let base = session(vec![first.clone()], vec![]);
+        let ours = session(
+            vec![first.clone(), message("a-2", Some("a-1"), 2, "a1"), message("a-3", Some("a-2"), 3, "a2")],
+            vec![],
+        );
+        let theirs = session(
+            vec![first, message("b-2", Some("a-1"), 2, "b1"), message("b-4", Some("b-2"), 4, "b2")],
+            vec![],
+        );
+        let merged = merge(Some(&base), &ours, &theirs);
+        assert_eq!(contents(&merged), ["first", "a1", "a2", "b1", "b2"]);
+        assert_eq!(merged.clock, 4);
+    }
+
+    #[test]
+    fn merge_leaves_removed_messages_out() {
+        let (first, second) = (message("a-1", None, 1, "first"), message("a-2", Some("a-1"), 2, "second"));
+        let ours = session(vec![first.clone()], vec![json!({ "id": "a-2", "after": "a-1", "at": 2 })]);
+        let theirs = session(vec![first, second], vec![]);
+        let merged = merge(None, &ours, &theirs);
+        assert_eq!(contents(&merged), ["first"]);
+        assert_eq!(merged.deleted, [json!({ "id": "a-2", "after": "a-1", "at": 2 })]);
+    }
+
+    // A message added after one the other device removed stays after its
+    // place rather than moving to the end
+    #[test]
+    fn merge_keeps_removed_messages_as_anchors() {
+        let first = message("a-1", None, 1, "first");
+        let ours = session(
+            vec![first.clone(), message("a-5", Some("a-1"), 5, "ours")],
+            vec![json!({ "id": "a-2", "after": "a-1", "at": 2 })],
+        );
+        let theirs = session(vec![first, message("b-3", Some("a-2"), 3, "theirs")], vec![json!("a-2")]);
+        let merged = merge(None, &ours, &theirs);
+        assert_eq!(contents(&merged), ["first", "theirs", "ours"]);
+    }
+
+    #[test]
+    fn merge_keeps_the_losing_side_of_concurrent_edits() {
+        let base = session(vec![message("a-1", None, 1, "original")], vec![]);
+        let mut ours = message("a-1", None, 1, "ours");
+        ours[KEY]["rev"] = json!(2);
+        let mut theirs = message("a-1", None, 1, "theirs");
+        theirs[KEY]["rev"] = json!(3);
+        theirs[KEY]["by"] = json!("b");
+        let merged = merge(Some(&base), &session(vec![ours], vec![]), &session(vec![theirs], vec![]));
+        assert_eq!(contents(&merged), ["theirs"]);
+        assert_eq!(merged.messages[0][OVERWRITTEN_KEY], json!([{ "content": "ours", "by": "a" }]));
+    }
+
+    #[test]
+    fn stamp_records_where_a_removed_message_was() {
+        let (first, second) = (message("a-1", None, 1, "first"), message("a-2", Some("a-1"), 2, "second"));
+        let previous = session(vec![first, second], vec![]);
+        let mut current = json!({ "messages": [{ "role": "user", "content": "first" }] });
+        let current = current.as_object_mut().unwrap();
+        stamp(Some(&previous), current, "b");
+        assert_eq!(current["messages"][0][KEY]["id"], "a-1");
+        assert_eq!(current[DELETED_KEY], json!([{ "id": "a-2", "after": "a-1", "at": 2 }]));
+        assert_eq!(current[CLOCK_KEY], 2);
+    }
+}
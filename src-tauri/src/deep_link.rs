@@ -0,0 +1,159 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager, Url};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+use crate::settings;
+
+// anchor:// links from the browser or other apps:
+//   anchor://chat/<id>                     opens a chat
+//   anchor://new?prompt=…&model=…&provider=…  starts a chat, all optional
+//   anchor://handoff?addr=…&id=…&key=…       a chat handed off from another
+//                                            device, see handoff.rs
+//   anchor://share/<id>                    stores what `anchor share` left in
+//                                            the inbox, see share_service.rs
+// Each link is checked here and handed to the UI as a navigation. A link
+// that started the app waits in take_deep_link until the UI has loaded, the
+// same way picks from the tray do.
+
+pub const SCHEME: &str = "anchor";
+const NAVIGATE_EVENT: &str = "deep-link-navigate";
+const MAX_ID_CHARS: usize = 128;
+const MAX_PROMPT_CHARS: usize = 8000;
+const MAX_MODEL_CHARS: usize = 200;
+
+static PENDING: Mutex<Option<Navigation>> = Mutex::new(None);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Navigation {
+    #[serde(rename_all = "camelCase")]
+    OpenChat { chat_id: String },
+    #[serde(rename_all = "camelCase")]
+    NewChat {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prompt: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        provider_id: Option<String>,
+    },
+    // The UI asks before fetching it with receive_handoff, since the link
+    // could come from anywhere
+    Handoff { code: String },
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_CHARS && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+}
+
+pub fn parse(app: &tauri::AppHandle, url: &Url) -> Result<Navigation, String> {
+    if url.scheme() != SCHEME {
+        return Err(format!("Not an {} link: {}", SCHEME, url));
+    }
+    // anchor://chat/<id> puts the action in the host, anchor:chat/<id> in the path
+    let mut parts: Vec<&str> = url.host_str().into_iter().collect();
+    parts.extend(url.path().split('/').filter(|p| !p.is_empty()));
+    match parts.as_slice() {
+        ["chat", id] => {
+            if !valid_id(id) {
+                return Err(format!("Invalid chat id in link: {}", id));
+            }
+            Ok(Navigation::OpenChat { chat_id: id.to_string() })
+        }
+        ["new"] => {
+            let (mut prompt, mut model, mut provider_id) = (None, None, None);
+            for (key, value) in url.query_pairs() {
+                let value = value.trim().to_string();
+                if value.is_empty() {
+                    continue;
+                }
+                match key.as_ref() {
+                    "prompt" => prompt = Some(value),
+                    "model" => model = Some(value),
+                    "provider" => provider_id = Some(value),
+                    other => log::warn!("Ignoring {} in an {} link", other, SCHEME),
+                }
+            }
+            if prompt.as_ref().is_some_and(|p| p.chars().count() > MAX_PROMPT_CHARS) {
+                return Err(format!("The prompt in the link is longer than {} characters", MAX_PROMPT_CHARS));
+            }
+            if model.as_ref().is_some_and(|m| m.len() > MAX_MODEL_CHARS || m.chars().any(char::is_control)) {
+                return Err("Invalid model in link".to_string());
+            }
+            if let Some(id) = &provider_id {
+                if settings::load(app)?.provider(id).is_none() {
+                    return Err(format!("Unknown provider in link: {}", id));
+                }
+            }
+            Ok(Navigation::NewChat { prompt, model, provider_id })
+        }
+        // Stored as it's parsed, then opened like any other chat
+        ["share", id] => {
+            let chat_id = crate::share_service::take_from_inbox(app, id)?;
+            Ok(Navigation::OpenChat { chat_id })
+        }
+        ["handoff"] => {
+            let has = |name: &str| url.query_pairs().any(|(key, value)| key == name && !value.is_empty());
+            if !["addr", "id", "key"].iter().all(|name| has(name)) {
+                return Err("The handoff link is incomplete".to_string());
+            }
+            // Always in the anchor://handoff form, which receive_handoff expects
+            let code = format!("{}://handoff?{}", SCHEME, url.query().unwrap_or_default());
+            Ok(Navigation::Handoff { code })
+        }
+        _ => Err(format!("Unsupported {} link: {}", SCHEME, url)),
+    }
+}
+
+fn open(app: &tauri::AppHandle, urls: Vec<Url>, emit: bool) {
+    // Only the last link counts when several arrive at once
+    let Some(navigation) = urls.iter().rev().find_map(|url| match parse(app, url) {
+        Ok(navigation) => Some(navigation),
+        Err(e) => {
+            log::warn!("{}", e);
+            None
+        }
+    }) else {
+        return;
+    };
+    navigate(app, navigation, emit);
+}
+
+// Opens the window at `navigation`, straight away when it has loaded and
+// through take_deep_link otherwise
+pub fn navigate(app: &tauri::AppHandle, navigation: Navigation, emit: bool) {
+    let loaded = emit && app.get_webview_window("main").is_some();
+    if let Err(e) = crate::tray::show_window(app) {
+        log::warn!("{}", e);
+    }
+    if loaded {
+        let _ = app.emit(NAVIGATE_EVENT, navigation);
+    } else if let Ok(mut pending) = PENDING.lock() {
+        *pending = Some(navigation);
+    }
+}
+
+pub fn init(app: &tauri::AppHandle) {
+    // Installed builds register the scheme through their bundle; this covers
+    // AppImages and development builds
+    #[cfg(any(windows, target_os = "linux"))]
+    if let Err(e) = app.deep_link().register_all() {
+        log::warn!("Failed to register the {} scheme: {}", SCHEME, e);
+    }
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| open(&handle, event.urls(), true));
+    // The link this launch was started with, before the UI is there to hear it
+    match app.deep_link().get_current() {
+        Ok(Some(urls)) => open(app, urls, false),
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to read the launch link: {}", e),
+    }
+}
+
+// The link the app was opened with before the window had loaded
+#[tauri::command]
+pub fn take_deep_link() -> Option<Navigation> {
+    PENDING.lock().ok().and_then(|mut pending| pending.take())
+}
@@ -0,0 +1,142 @@
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+use std::process::Command;
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+use crate::transcription::find_in_path;
+
+// What the OS says about the battery and the network, so background work can
+// wait for a better moment. Each answer is None wherever it can't be told,
+// which callers treat as "go ahead".
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(find_in_path(program)?).args(args).output().ok()?;
+    out.status.success().then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+// The charge in percent while running on battery; None on mains power
+#[cfg(target_os = "linux")]
+pub fn battery_discharging() -> Option<u8> {
+    let read = |path: std::path::PathBuf| std::fs::read_to_string(path).map(|s| s.trim().to_string()).ok();
+    std::fs::read_dir("/sys/class/power_supply").ok()?.flatten().find_map(|entry| {
+        let dir = entry.path();
+        let battery = read(dir.join("type")).as_deref() == Some("Battery");
+        if !battery || read(dir.join("status")).as_deref() != Some("Discharging") {
+            return None;
+        }
+        read(dir.join("capacity"))?.parse().ok()
+    })
+}
+
+// "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=..)	85%; discharging; .."
+#[cfg(target_os = "macos")]
+pub fn battery_discharging() -> Option<u8> {
+    let out = output("pmset", &["-g", "batt"])?;
+    if !out.contains("'Battery Power'") {
+        return None;
+    }
+    let percent = out.split('%').next()?.rsplit(|c: char| !c.is_ascii_digit()).next()?;
+    percent.parse().ok()
+}
+
+// BatteryStatus 1 is discharging
+#[cfg(target_os = "windows")]
+pub fn battery_discharging() -> Option<u8> {
+    let script = "$b = Get-CimInstance Win32_Battery | Select-Object -First 1; \
+                  if ($b.BatteryStatus -eq 1) { $b.EstimatedChargeRemaining }";
+    output("powershell", &["-NoProfile", "-NonInteractive", "-Command", script])?.parse().ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn battery_discharging() -> Option<u8> {
+    None
+}
+
+// macOS's Low Power Mode, Windows' battery saver, or the power-saver
+// profile of power-profiles-daemon on Linux
+#[cfg(target_os = "linux")]
+pub fn low_power_mode() -> Option<bool> {
+    Some(output("powerprofilesctl", &["get"])? == "power-saver")
+}
+
+// " lowpowermode         1"
+#[cfg(target_os = "macos")]
+pub fn low_power_mode() -> Option<bool> {
+    let out = output("pmset", &["-g"])?;
+    let line = out.lines().find(|line| line.trim_start().starts_with("lowpowermode"))?;
+    Some(line.split_whitespace().nth(1)? == "1")
+}
+
+// SystemStatusFlag of GetSystemPowerStatus is 1 while battery saver is on
+#[cfg(target_os = "windows")]
+pub fn low_power_mode() -> Option<bool> {
+    let script = "Add-Type -Name P -Namespace S -MemberDefinition \
+                  '[StructLayout(LayoutKind.Sequential)] public struct Status \
+                  { public byte Line, Flag, Percent, Saver; public int Life, Full; } \
+                  [DllImport(\"kernel32.dll\")] public static extern bool GetSystemPowerStatus(out Status s);'; \
+                  $s = New-Object 'S.P+Status'; [void][S.P]::GetSystemPowerStatus([ref]$s); $s.Saver";
+    Some(output("powershell", &["-NoProfile", "-NonInteractive", "-Command", script])? == "1")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn low_power_mode() -> Option<bool> {
+    None
+}
+
+// Whether the OS is holding the CPU back to cool down. "CPU_Speed_Limit = 80"
+// once it is; nothing is listed before the first thermal event.
+#[cfg(target_os = "macos")]
+pub fn thermal_throttled() -> Option<bool> {
+    let out = output("pmset", &["-g", "therm"])?;
+    let mut limits = out.lines().filter_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        key.trim().ends_with("_Limit").then(|| value.trim().parse::<u32>().ok()).flatten()
+    });
+    Some(limits.any(|limit| limit < 100))
+}
+
+// Linux has no one flag for it, so a zone near where CPUs start to throttle
+#[cfg(target_os = "linux")]
+pub fn thermal_throttled() -> Option<bool> {
+    const HOT_MILLIDEGREES: i64 = 90_000;
+    let zones = std::fs::read_dir("/sys/class/thermal").ok()?.flatten();
+    let temps: Vec<i64> = zones
+        .filter(|zone| zone.file_name().to_string_lossy().starts_with("thermal_zone"))
+        .filter_map(|zone| std::fs::read_to_string(zone.path().join("temp")).ok()?.trim().parse().ok())
+        .collect();
+    (!temps.is_empty()).then(|| temps.iter().any(|&t| t >= HOT_MILLIDEGREES))
+}
+
+// Windows only tells administrators
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn thermal_throttled() -> Option<bool> {
+    None
+}
+
+// NetworkManager marks connections metered, or guesses, e.g. for phone
+// hotspots; "yes (guessed)" counts too
+#[cfg(target_os = "linux")]
+pub fn metered() -> Option<bool> {
+    let out = output("nmcli", &["-t", "-f", "GENERAL.METERED", "device", "show"])?;
+    Some(out.lines().any(|line| line.trim_start_matches("GENERAL.METERED:").starts_with("yes")))
+}
+
+#[cfg(target_os = "windows")]
+pub fn metered() -> Option<bool> {
+    let script = "[void][Windows.Networking.Connectivity.NetworkInformation,Windows.Networking.Connectivity,\
+                  ContentType=WindowsRuntime]; \
+                  [Windows.Networking.Connectivity.NetworkInformation]::GetInternetConnectionProfile().\
+                  GetConnectionCost().NetworkCostType";
+    let cost = output("powershell", &["-NoProfile", "-NonInteractive", "-Command", script])?;
+    match cost.as_str() {
+        "Unrestricted" => Some(false),
+        "Fixed" | "Variable" => Some(true),
+        _ => None,
+    }
+}
+
+// macOS has no command-line way to ask about Low Data Mode
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn metered() -> Option<bool> {
+    None
+}
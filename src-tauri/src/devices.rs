@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{chats, encryption, lan_sync, settings};
+
+// Who this installation is: a random id that lasts as long as the app data,
+// and a name the user can change. Chats are stamped with the device that
+// created them and the one that last changed them, and devices tell each
+// other their names through sync, so an id on a chat can be shown as the
+// laptop or phone it came from.
+
+const IDENTITY_FILE: &str = "device.json";
+// Where LAN sync kept its id before there was one for the whole app; adopted
+// so existing pairings stay valid
+const LAN_IDENTITY_FILE: &str = "lan_identity.json";
+const REGISTRY_FILE: &str = "devices.json";
+// How stale this device's entry on a remote may get before it's refreshed
+const REFRESH_MILLIS: u64 = 60 * 60 * 1000;
+
+// Ids are this many random bytes, in hex
+const ID_BYTES: usize = 8;
+
+static ID: OnceLock<String> = OnceLock::new();
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Identity {
+    id: String,
+}
+
+// What one device knows about another; also the format of the list kept on
+// sync remotes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Known {
+    pub name: String,
+    pub last_seen: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub this_device: bool,
+    // Paired for LAN sync
+    pub paired: bool,
+    // When it last synced, as far as this device knows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+    pub created_chats: usize,
+    pub updated_chats: usize,
+}
+
+pub fn id(app: &tauri::AppHandle) -> Result<String, String> {
+    if let Some(id) = ID.get() {
+        return Ok(id.clone());
+    }
+    let dir = crate::app_data_dir(app)?;
+    let read = |name: &str| -> Option<Identity> {
+        fs::read_to_string(dir.join(name)).ok().and_then(|c| serde_json::from_str(&c).ok())
+    };
+    let identity = match read(IDENTITY_FILE) {
+        Some(identity) => identity,
+        None => {
+            let identity = match read(LAN_IDENTITY_FILE) {
+                Some(identity) => identity,
+                None => Identity { id: crate::share::hex(&encryption::random(ID_BYTES)?) },
+            };
+            let content = serde_json::to_string_pretty(&identity).map_err(|e| e.to_string())?;
+            fs::write(dir.join(IDENTITY_FILE), content)
+                .map_err(|e| format!("Failed to save the device identity: {}", e))?;
+            identity
+        }
+    };
+    Ok(ID.get_or_init(|| identity.id).clone())
+}
+
+// Whether `id` looks like one of ours; ids from other devices end up in
+// keychain account names and file names
+pub fn valid_id(id: &str) -> bool {
+    id.len() == ID_BYTES * 2 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+pub fn name(app: &tauri::AppHandle) -> String {
+    let configured = settings::load(app).map(|s| s.lan_sync.device_name).unwrap_or_default();
+    if !configured.trim().is_empty() {
+        return configured.trim().to_string();
+    }
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "Anchor device".to_string())
+}
+
+fn registry_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join(REGISTRY_FILE))
+}
+
+fn registry(app: &tauri::AppHandle) -> BTreeMap<String, Known> {
+    registry_path(app)
+        .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_registry(app: &tauri::AppHandle, devices: &BTreeMap<String, Known>) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(devices).map_err(|e| e.to_string())?;
+    fs::write(registry_path(app)?, content).map_err(|e| format!("Failed to save the device list: {}", e))
+}
+
+// Another device just synced with this one directly
+pub fn seen(app: &tauri::AppHandle, device_id: &str, name: &str) {
+    let mut devices = registry(app);
+    let known = Known { name: name.to_string(), last_seen: chats::now_millis() as u64 };
+    devices.insert(device_id.to_string(), known);
+    if let Err(e) = save_registry(app, &devices) {
+        log::warn!("{}", e);
+    }
+}
+
+// Combines a remote's device list with ours, keeping the newer entry of each
+// device, and returns what the remote should list from now on
+pub fn merge(app: &tauri::AppHandle, theirs: &BTreeMap<String, Known>) -> Result<BTreeMap<String, Known>, String> {
+    let me = id(app)?;
+    let mut devices = registry(app);
+    for (device_id, known) in theirs {
+        if *device_id == me || devices.get(device_id).is_some_and(|ours| ours.last_seen >= known.last_seen) {
+            continue;
+        }
+        devices.insert(device_id.clone(), known.clone());
+    }
+    save_registry(app, &devices)?;
+
+    let now = chats::now_millis() as u64;
+    let name = name(app);
+    let listed = theirs.get(&me).filter(|k| k.name == name && now.saturating_sub(k.last_seen) < REFRESH_MILLIS);
+    let mine = listed.cloned().unwrap_or(Known { name, last_seen: now });
+    devices.insert(me, mine);
+    Ok(devices)
+}
+
+#[tauri::command]
+pub fn list_devices(app: tauri::AppHandle) -> Result<Vec<Device>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let me = id(&app)?;
+    let mut created: HashMap<String, usize> = HashMap::new();
+    let mut updated: HashMap<String, usize> = HashMap::new();
+    for session in chats::list_chats(app.clone())? {
+        if let Some(device) = session["createdBy"].as_str() {
+            *created.entry(device.to_string()).or_default() += 1;
+        }
+        if let Some(device) = session["updatedBy"].as_str() {
+            *updated.entry(device.to_string()).or_default() += 1;
+        }
+    }
+
+    let mut known = registry(&app);
+    // Paired before sync kept a device list
+    for (device_id, name) in lan_sync::paired_names(&app) {
+        known.entry(device_id).or_insert(Known { name, last_seen: 0 });
+    }
+    let paired = lan_sync::paired_ids(&app);
+    let device = |id: String, name: String, last_seen: Option<u64>| Device {
+        this_device: id == me,
+        paired: paired.contains(&id),
+        created_chats: created.get(&id).copied().unwrap_or(0),
+        updated_chats: updated.get(&id).copied().unwrap_or(0),
+        last_seen,
+        name,
+        id,
+    };
+    let mut devices = vec![device(me.clone(), name(&app), None)];
+    for (device_id, k) in known.into_iter().filter(|(device_id, _)| *device_id != me) {
+        devices.push(device(device_id, k.name, Some(k.last_seen).filter(|&t| t > 0)));
+    }
+    // Ids chats were stamped with by devices this one never heard from
+    let mut strangers: Vec<&String> = created.keys().chain(updated.keys()).collect();
+    strangers.sort();
+    strangers.dedup();
+    for device_id in strangers {
+        if !devices.iter().any(|d| &d.id == device_id) {
+            devices.push(device(device_id.clone(), "Unknown device".to_string(), None));
+        }
+    }
+    Ok(devices)
+}
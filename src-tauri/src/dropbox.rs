@@ -0,0 +1,88 @@
+use serde_json::{json, Value};
+use tauri_plugin_http::reqwest::{RequestBuilder, StatusCode};
+
+use crate::http;
+use crate::settings::SyncBackend;
+
+// Dropbox through an app registered with "App folder" access: every path is
+// under Apps/<app name>/ in the user's Dropbox, and the app can't see the
+// rest of it. Signing in is in oauth.rs.
+
+const API: &str = "https://api.dropboxapi.com/2";
+const CONTENT: &str = "https://content.dropboxapi.com/2";
+
+pub struct Dropbox {
+    token: String,
+}
+
+// Dropbox's reason for a 409, e.g. "path/not_found/.."
+fn error_summary(body: &[u8]) -> String {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|v| v["error_summary"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).to_string())
+}
+
+impl Dropbox {
+    pub fn new(token: String) -> Self {
+        Self { token }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<(StatusCode, Vec<u8>), String> {
+        let response = http::send(request.bearer_auth(&self.token))
+            .await
+            .map_err(|e| format!("Failed to reach Dropbox: {}", e))?;
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED {
+            crate::oauth::forget_token(SyncBackend::Dropbox);
+            return Err("Dropbox turned down the sign-in; trying again later".to_string());
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err("Dropbox is limiting requests; trying again later".to_string());
+        }
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        Ok((status, body.to_vec()))
+    }
+
+    pub async fn prepare(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let request = http::client()
+            .post(format!("{}/files/download", CONTENT))
+            // Has to be ASCII, which chat ids are
+            .header("Dropbox-API-Arg", json!({ "path": format!("/{}", name) }).to_string());
+        let (status, body) = self.send(request).await?;
+        match status {
+            status if status.is_success() => Ok(Some(body)),
+            StatusCode::CONFLICT if error_summary(&body).contains("not_found") => Ok(None),
+            status => Err(format!("Failed to download {}: {} {}", name, status, error_summary(&body))),
+        }
+    }
+
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        let arg = json!({ "path": format!("/{}", name), "mode": "overwrite", "mute": true });
+        let request = http::client()
+            .post(format!("{}/files/upload", CONTENT))
+            .header("Dropbox-API-Arg", arg.to_string())
+            .header("Content-Type", "application/octet-stream")
+            .body(data);
+        let (status, body) = self.send(request).await?;
+        if !status.is_success() {
+            return Err(format!("Failed to upload {}: {} {}", name, status, error_summary(&body)));
+        }
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        let request = http::client()
+            .post(format!("{}/files/delete_v2", API))
+            .json(&json!({ "path": format!("/{}", name) }));
+        let (status, body) = self.send(request).await?;
+        if !(status.is_success() || (status == StatusCode::CONFLICT && error_summary(&body).contains("not_found"))) {
+            return Err(format!("Failed to delete {}: {} {}", name, status, error_summary(&body)));
+        }
+        Ok(())
+    }
+}
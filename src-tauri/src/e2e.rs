@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::encryption;
+use crate::keychain;
+
+// Client-side encryption for everything the sync engine sends to a backend,
+// so WebDAV servers, buckets and cloud folders only store ciphertext. Every
+// device writes with its own random key; pairing two devices over the LAN
+// trades their keyrings, so each can read what the other wrote, and devices
+// that never meet on a LAN can copy them over as a key code instead. A file
+// starts with MAGIC and the id of the key that sealed it, and its name is
+// authenticated too, so the backend can't pass one chat off as another.
+
+const MAGIC: &[u8] = b"ANCHORSYNC1";
+const KEY_LEN: usize = 32;
+const KEY_ID_LEN: usize = 8;
+const KEYCHAIN_ACCOUNT: &str = "sync-keyring";
+const CODE_PREFIX: &str = "anchor-sync-keys:";
+
+static KEYRING: Mutex<Option<Keyring>> = Mutex::new(None);
+
+// Key id (hex) -> key (base64); kept whole in the OS keychain
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Keyring {
+    own: String,
+    keys: BTreeMap<String, String>,
+}
+
+// Written next to the keychain entry; holds nothing secret, but tells a
+// missing keyring from one the keychain failed to return
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Config {
+    own_key_id: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncKey {
+    pub id: String,
+    // This device's own key, the one it writes with
+    pub own: bool,
+}
+
+fn config_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("sync_keys.json"))
+}
+
+fn key_id(key: &[u8]) -> String {
+    Sha256::digest(key)[..KEY_ID_LEN / 2].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn store_keyring(keyring: &Keyring) -> Result<(), String> {
+    let content = serde_json::to_string(keyring).map_err(|e| e.to_string())?;
+    keychain::store(KEYCHAIN_ACCOUNT, &content)?;
+    if let Ok(mut cached) = KEYRING.lock() {
+        *cached = Some(keyring.clone());
+    }
+    Ok(())
+}
+
+// Loads the keyring, making this device's key the first time
+fn keyring(app: &tauri::AppHandle) -> Result<Keyring, String> {
+    if let Some(keyring) = KEYRING.lock().ok().and_then(|k| k.clone()) {
+        return Ok(keyring);
+    }
+    let path = config_path(app)?;
+    if path.exists() {
+        let content = keychain::load(KEYCHAIN_ACCOUNT).map_err(|e| format!("Failed to load the sync keys: {}", e))?;
+        let keyring: Keyring = serde_json::from_str(&content).map_err(|e| format!("Invalid sync keys: {}", e))?;
+        if let Ok(mut cached) = KEYRING.lock() {
+            *cached = Some(keyring.clone());
+        }
+        return Ok(keyring);
+    }
+
+    let key = encryption::random(KEY_LEN)?;
+    let id = key_id(&key);
+    let keyring = Keyring { own: id.clone(), keys: BTreeMap::from([(id.clone(), STANDARD.encode(&key))]) };
+    store_keyring(&keyring)?;
+    let content = serde_json::to_string_pretty(&Config { own_key_id: id }).map_err(|e| e.to_string())?;
+    fs::write(path, content).map_err(|e| format!("Failed to save the sync key settings: {}", e))?;
+    Ok(keyring)
+}
+
+fn cipher(keyring: &Keyring, id: &str) -> Result<LessSafeKey, String> {
+    let key = keyring
+        .keys
+        .get(id)
+        .and_then(|key| STANDARD.decode(key).ok())
+        .ok_or(
+            "Synced data was encrypted by a device this one doesn't have the key of; \
+             pair the two over the LAN or import the other device's sync key code",
+        )?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| "Invalid sync key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// MAGIC, the key id, a random nonce, then ciphertext and tag
+pub fn seal(app: &tauri::AppHandle, name: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let keyring = keyring(app)?;
+    let nonce: [u8; NONCE_LEN] = encryption::random(NONCE_LEN)?.try_into().unwrap();
+    let mut data = plaintext.to_vec();
+    cipher(&keyring, &keyring.own)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(name.as_bytes()), &mut data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok([MAGIC, keyring.own.as_bytes(), &nonce, &data].concat())
+}
+
+// Backend data as it was before sealing. Plaintext only passes with
+// `allow_plain`: while end-to-end encryption is off, or for this device's
+// own uploads from before it was on; anything else unsealed could have been
+// put there by the backend.
+pub fn open(app: &tauri::AppHandle, name: &str, data: Vec<u8>, allow_plain: bool) -> Result<Vec<u8>, String> {
+    if !is_sealed(&data) {
+        return match allow_plain {
+            true => Ok(data),
+            false => Err(format!("{} on the sync remote isn't end-to-end encrypted, so it was refused", name)),
+        };
+    }
+    let body = &data[MAGIC.len()..];
+    if body.len() < KEY_ID_LEN + NONCE_LEN {
+        return Err(format!("{} is truncated", name));
+    }
+    let (id, body) = body.split_at(KEY_ID_LEN);
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+    let id = String::from_utf8_lossy(id);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce".to_string())?;
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = cipher(&keyring(app)?, &id)?
+        .open_in_place(nonce, Aad::from(name.as_bytes()), &mut ciphertext)
+        .map_err(|_| format!("{} failed to decrypt; it may have been tampered with", name))?;
+    Ok(plaintext.to_vec())
+}
+
+// The keys this device knows, for a paired device to add to its own
+pub fn export_keys(app: &tauri::AppHandle) -> Result<BTreeMap<String, String>, String> {
+    Ok(keyring(app)?.keys)
+}
+
+pub fn import_keys(app: &tauri::AppHandle, keys: BTreeMap<String, String>) -> Result<(), String> {
+    let mut keyring = keyring(app)?;
+    let before = keyring.keys.len();
+    for (id, key) in keys {
+        // Ids are derived from the key, so a mismatched pair is dropped
+        if STANDARD.decode(&key).is_ok_and(|k| k.len() == KEY_LEN && key_id(&k) == id) {
+            keyring.keys.entry(id).or_insert(key);
+        }
+    }
+    if keyring.keys.len() == before {
+        return Ok(());
+    }
+    store_keyring(&keyring)
+}
+
+// Every key this device knows as one code, for a device that can't pair over
+// the LAN; whoever has it can read the synced chats
+#[tauri::command]
+pub fn export_sync_keys(app: tauri::AppHandle) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let keys = serde_json::to_vec(&export_keys(&app)?).map_err(|e| e.to_string())?;
+    crate::audit::record("sync", "exported the sync keys");
+    Ok(format!("{}{}", CODE_PREFIX, STANDARD.encode(keys)))
+}
+
+#[tauri::command]
+pub fn import_sync_keys(app: tauri::AppHandle, code: String) -> Result<Vec<SyncKey>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let invalid = || "That isn't a sync key code".to_string();
+    let encoded = code.trim().strip_prefix(CODE_PREFIX).ok_or_else(invalid)?;
+    let keys = STANDARD.decode(encoded.trim()).map_err(|_| invalid())?;
+    let keys: BTreeMap<String, String> = serde_json::from_slice(&keys).map_err(|_| invalid())?;
+    import_keys(&app, keys)?;
+    crate::audit::record("sync", "imported sync keys");
+    sync_keys(app)
+}
+
+#[tauri::command]
+pub fn sync_keys(app: tauri::AppHandle) -> Result<Vec<SyncKey>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let keyring = keyring(&app)?;
+    Ok(keyring.keys.keys().map(|id| SyncKey { id: id.clone(), own: *id == keyring.own }).collect())
+}
@@ -0,0 +1,260 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use serde::Serialize;
+use serde_json::json;
+use tauri::State;
+use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+
+use crate::settings::{self, Settings};
+
+pub const LOCAL_PREFIX: &str = "local";
+const LOCAL_MODEL: &str = "all-minilm-l6-v2";
+const LOCAL_MODEL_URL: &str = "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main";
+const LOCAL_MODEL_FILES: &[&str] = &["config.json", "tokenizer.json", "model.safetensors"];
+const MAX_TOKENS: usize = 256;
+const BATCH_SIZE: usize = 32;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Embeddings {
+    pub model: String,
+    pub dimensions: usize,
+    pub vectors: Vec<Vec<f32>>,
+}
+
+struct LocalModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+#[derive(Default)]
+pub struct EmbeddingState {
+    local: Mutex<Option<Arc<LocalModel>>>,
+}
+
+fn local_model_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("embeddings").join(LOCAL_MODEL);
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create embeddings directory: {}", e))?;
+    }
+    Ok(dir)
+}
+
+async fn ensure_local_files(app: &tauri::AppHandle, dir: &Path) -> Result<(), String> {
+    let missing: Vec<_> = LOCAL_MODEL_FILES.iter().filter(|file| !dir.join(file).exists()).collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let job = crate::jobs::start(app);
+    for (done, file) in missing.iter().enumerate() {
+        job.progress(done as u64, missing.len() as u64);
+        let path = dir.join(file);
+
+        log::info!("Downloading local embedding model file {}", file);
+        let request = crate::http::client().get(format!("{}/{}", LOCAL_MODEL_URL, file));
+        let response = crate::http::send(request)
+            .await
+            .map_err(|e| format!("Failed to download embedding model: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Embedding model download returned {}", response.status()));
+        }
+        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+
+        let partial = path.with_extension("part");
+        fs::write(&partial, &bytes).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn load_local(dir: &Path) -> Result<LocalModel, String> {
+    let device = Device::Cpu;
+    let config: Config = serde_json::from_str(
+        &fs::read_to_string(dir.join("config.json")).map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| format!("Invalid embedding model config: {}", e))?;
+
+    let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| e.to_string())?;
+    tokenizer.with_padding(Some(PaddingParams::default()));
+    tokenizer
+        .with_truncation(Some(TruncationParams { max_length: MAX_TOKENS, ..Default::default() }))
+        .map_err(|e| e.to_string())?;
+
+    // Safety: the weights file is ours and isn't modified while mapped
+    let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[dir.join("model.safetensors")], DTYPE, &device) }
+        .map_err(|e| format!("Failed to load embedding weights: {}", e))?;
+    let model = BertModel::load(vb, &config).map_err(|e| e.to_string())?;
+
+    Ok(LocalModel { model, tokenizer, device })
+}
+
+fn embed_batch(local: &LocalModel, texts: &[String]) -> candle_core::Result<Vec<Vec<f32>>> {
+    let encodings = local
+        .tokenizer
+        .encode_batch(texts.to_vec(), true)
+        .map_err(candle_core::Error::msg)?;
+
+    let ids = encodings
+        .iter()
+        .map(|e| Tensor::new(e.get_ids(), &local.device))
+        .collect::<candle_core::Result<Vec<_>>>()?;
+    let masks = encodings
+        .iter()
+        .map(|e| Tensor::new(e.get_attention_mask(), &local.device))
+        .collect::<candle_core::Result<Vec<_>>>()?;
+    let ids = Tensor::stack(&ids, 0)?;
+    let mask = Tensor::stack(&masks, 0)?;
+
+    let hidden = local.model.forward(&ids, &ids.zeros_like()?, Some(&mask))?;
+
+    // Mean pooling over real tokens, then L2 normalize like sentence-transformers does
+    let mask = mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+    let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+    let pooled = summed.broadcast_div(&mask.sum(1)?)?;
+    let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+    pooled.broadcast_div(&norms)?.to_vec2::<f32>()
+}
+
+async fn embed_local(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>, String> {
+    let loaded = state.local.lock().map_err(|e| e.to_string())?.clone();
+    let local = match loaded {
+        Some(local) => local,
+        None => {
+            let dir = local_model_dir(app)?;
+            ensure_local_files(app, &dir).await?;
+            let local = Arc::new(
+                tauri::async_runtime::spawn_blocking(move || load_local(&dir))
+                    .await
+                    .map_err(|e| e.to_string())??,
+            );
+            *state.local.lock().map_err(|e| e.to_string())? = Some(local.clone());
+            local
+        }
+    };
+
+    let threads = crate::power::threads(app);
+    tauri::async_runtime::spawn_blocking(move || {
+        let run = || {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(BATCH_SIZE) {
+                vectors.extend(embed_batch(&local, batch).map_err(|e| format!("Local embedding failed: {}", e))?);
+            }
+            Ok(vectors)
+        };
+        // The model's matrix work runs on whichever rayon pool it's called in
+        match threads {
+            Some(threads) => rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| e.to_string())?
+                .install(run),
+            None => run(),
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn embed_provider(
+    settings: &Settings,
+    provider_id: &str,
+    model: &str,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, String> {
+    let provider = settings
+        .provider(provider_id)
+        .ok_or_else(|| format!("Unknown provider: {}", provider_id))?;
+
+    let mut request = crate::http::client()
+        .post(format!("{}/embeddings", provider.openai_base()))
+        .json(&json!({ "model": model, "input": texts }));
+    if !provider.api_key.trim().is_empty() {
+        request = request.bearer_auth(provider.api_key.trim());
+    }
+
+    let response = crate::http::send(request)
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", provider.name, e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("{} embeddings returned {}: {}", provider.name, status, body));
+    }
+    let body: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+
+    let mut items: Vec<(u64, Vec<f32>)> = body["data"]
+        .as_array()
+        .ok_or("Embedding response has no data")?
+        .iter()
+        .map(|item| {
+            let vector = item["embedding"]
+                .as_array()
+                .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+                .unwrap_or_default();
+            (item["index"].as_u64().unwrap_or(0), vector)
+        })
+        .collect();
+    items.sort_by_key(|(index, _)| *index);
+
+    if items.len() != texts.len() {
+        return Err(format!("Expected {} embeddings, got {}", texts.len(), items.len()));
+    }
+    Ok(items.into_iter().map(|(_, vector)| vector).collect())
+}
+
+// Checks that `model` names the bundled model or a configured provider
+pub fn validate_model(settings: &Settings, model: &str) -> Result<(), String> {
+    match model.split_once(':') {
+        Some((LOCAL_PREFIX, name)) if name == LOCAL_MODEL => Ok(()),
+        Some((LOCAL_PREFIX, name)) => Err(format!("Unknown local embedding model: {}", name)),
+        Some((provider_id, name)) if !name.trim().is_empty() => {
+            settings.provider(provider_id).map(|_| ()).ok_or_else(|| format!("Unknown provider: {}", provider_id))
+        }
+        _ => Err(format!("Embedding model must be \"<provider>:<model>\", got {}", model)),
+    }
+}
+
+// `model` is "local:<name>" for the bundled model or "<provider id>:<model>".
+pub async fn embed_texts(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    texts: Vec<String>,
+    model: Option<String>,
+) -> Result<Embeddings, String> {
+    let settings = settings::load(app)?;
+    let model = model.unwrap_or_else(|| settings.embedding_model.clone());
+
+    let vectors = match model.split_once(':') {
+        Some((LOCAL_PREFIX, name)) if name == LOCAL_MODEL => embed_local(app, state, texts).await?,
+        Some((LOCAL_PREFIX, name)) => return Err(format!("Unknown local embedding model: {}", name)),
+        Some((provider_id, name)) => embed_provider(&settings, provider_id, name, &texts).await?,
+        None => return Err(format!("Embedding model must be \"<provider>:<model>\", got {}", model)),
+    };
+
+    Ok(Embeddings {
+        dimensions: vectors.first().map(Vec::len).unwrap_or(0),
+        model,
+        vectors,
+    })
+}
+
+#[tauri::command]
+pub async fn embed(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    texts: Vec<String>,
+    model: Option<String>,
+) -> Result<Embeddings, String> {
+    crate::app_lock::ensure_unlocked()?;
+    embed_texts(&app, &state, texts, model).await
+}
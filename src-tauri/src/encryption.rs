@@ -0,0 +1,356 @@
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::chats;
+use crate::keychain;
+
+// Starts every encrypted file; anything else is read as plaintext
+const MAGIC: &[u8] = b"ANCHORENC1";
+const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+// OWASP's 2023 recommendation for PBKDF2-HMAC-SHA256
+pub const ITERATIONS: u32 = 600_000;
+const KEYCHAIN_ACCOUNT: &str = "chat-store-key";
+// Sealed with the key when encryption is set up, to check passphrases
+const VERIFIER: &[u8] = b"anchor chat store";
+pub const LOCKED: &str = "The chat store is locked";
+
+// The chat store key while unlocked
+static KEY: Mutex<Option<[u8; KEY_LEN]>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    // Derived from a passphrase the user enters to unlock
+    Passphrase,
+    // Random, kept in the OS keychain and loaded at startup
+    Keychain,
+}
+
+// Written to encryption.json when encryption is turned on; holds nothing secret
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Config {
+    key_source: KeySource,
+    salt: String,
+    iterations: u32,
+    verifier: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub key_source: Option<KeySource>,
+    pub unlocked: bool,
+    // Chat files not encrypted yet, see encrypt_existing_chats
+    pub plaintext_chats: usize,
+}
+
+fn config_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("encryption.json"))
+}
+
+fn config(app: &tauri::AppHandle) -> Result<Option<Config>, String> {
+    let path = config_path(app)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read encryption settings: {}", e))?;
+    serde_json::from_str(&content).map(Some).map_err(|e| format!("Invalid encryption settings: {}", e))
+}
+
+pub fn random(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| "No secure randomness available".to_string())?;
+    Ok(bytes)
+}
+
+fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    let iterations = NonZeroU32::new(iterations.max(1)).unwrap();
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    key
+}
+
+fn cipher(key: &[u8; KEY_LEN]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("key length matches the algorithm"))
+}
+
+// MAGIC, a random nonce, then ChaCha20-Poly1305 ciphertext and tag. Nonces
+// are 96 bits; collisions are out of reach at chat-store write counts.
+fn seal_with(key: &[u8; KEY_LEN], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce: [u8; NONCE_LEN] = random(NONCE_LEN)?.try_into().unwrap();
+    let mut data = plaintext.to_vec();
+    cipher(key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(MAGIC), &mut data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok([MAGIC, &nonce, &data].concat())
+}
+
+fn open_with(key: &[u8; KEY_LEN], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    let body = &sealed[MAGIC.len()..];
+    if body.len() < NONCE_LEN {
+        return Err("Encrypted file is truncated".to_string());
+    }
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce".to_string())?;
+    let mut data = ciphertext.to_vec();
+    let plaintext = cipher(key)
+        .open_in_place(nonce, Aad::from(MAGIC), &mut data)
+        .map_err(|_| "Decryption failed; wrong key or a damaged file".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn key() -> Option<[u8; KEY_LEN]> {
+    KEY.lock().ok().and_then(|key| *key)
+}
+
+fn set_key(key: Option<[u8; KEY_LEN]>) {
+    if let Ok(mut current) = KEY.lock() {
+        *current = key;
+    }
+}
+
+pub fn enabled(app: &tauri::AppHandle) -> bool {
+    config_path(app).is_ok_and(|path| path.exists())
+}
+
+pub fn is_locked(app: &tauri::AppHandle) -> bool {
+    enabled(app) && key().is_none()
+}
+
+// What a chat file should hold: encrypted when encryption is on
+pub fn seal(app: &tauri::AppHandle, plaintext: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !enabled(app) {
+        return Ok(plaintext);
+    }
+    seal_with(&key().ok_or(LOCKED)?, &plaintext)
+}
+
+// A chat file's contents, decrypted if need be
+pub fn open(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !is_sealed(&data) {
+        return Ok(data);
+    }
+    open_with(&key().ok_or(LOCKED)?, &data)
+}
+
+// Forgets a passphrase-derived key so the store needs unlocking again, e.g.
+// when the app locks. Keychain keys are kept since they'd be loaded right
+// back without asking. Returns whether a key was dropped.
+pub fn lock(app: &tauri::AppHandle) -> bool {
+    let passphrase = config(app).ok().flatten().is_some_and(|c| c.key_source == KeySource::Passphrase);
+    if !passphrase || key().is_none() {
+        return false;
+    }
+    set_key(None);
+    true
+}
+
+// Loads the key from the OS keychain at startup when that's where it lives.
+// Passphrase-protected stores stay locked until unlock_chat_store.
+pub fn init(app: &tauri::AppHandle) {
+    let Ok(Some(config)) = config(app) else {
+        return;
+    };
+    if config.key_source != KeySource::Keychain {
+        return;
+    }
+    let loaded = keychain::load(KEYCHAIN_ACCOUNT).and_then(|encoded| {
+        let key: [u8; KEY_LEN] = STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|k| k.try_into().ok())
+            .ok_or("Invalid chat store key in the keychain")?;
+        verify(&config, &key).map(|_| key)
+    });
+    match loaded {
+        Ok(key) => set_key(Some(key)),
+        Err(e) => log::warn!("Chat store stays locked: {}", e),
+    }
+}
+
+fn verify(config: &Config, key: &[u8; KEY_LEN]) -> Result<(), String> {
+    let verifier = STANDARD.decode(&config.verifier).map_err(|e| e.to_string())?;
+    match open_with(key, &verifier) {
+        Ok(plaintext) if plaintext == VERIFIER => Ok(()),
+        _ => Err("Wrong passphrase".to_string()),
+    }
+}
+
+// Other files holding chat text, sealed along with the chats: the metadata
+// of every vector index (the history index keeps message text there),
+// extracted memories and knowledge base chunks
+fn store_files(app: &tauri::AppHandle) -> Result<Vec<PathBuf>, String> {
+    let data = crate::app_data_dir(app)?;
+    let mut paths = vec![data.join("memories.json")];
+    for (dir, file) in [("vectors", "index.json"), ("knowledge", "chunks.json")] {
+        if let Ok(entries) = fs::read_dir(data.join(dir)) {
+            paths.extend(entries.flatten().map(|entry| entry.path().join(file)));
+        }
+    }
+    Ok(paths.into_iter().filter(|path| path.is_file()).collect())
+}
+
+// Rewrites every chat file and the stores with `transform`, skipping files
+// it leaves as they are. Returns the number of chats rewritten.
+fn rewrite_chats(
+    app: &tauri::AppHandle,
+    transform: impl Fn(Vec<u8>) -> Result<Option<Vec<u8>>, String>,
+) -> Result<usize, String> {
+    let count = rewrite(chats::chat_files(app)?, &transform)?;
+    rewrite(store_files(app)?, &transform)?;
+    Ok(count)
+}
+
+fn rewrite(
+    paths: Vec<PathBuf>,
+    transform: &impl Fn(Vec<u8>) -> Result<Option<Vec<u8>>, String>,
+) -> Result<usize, String> {
+    let mut count = 0;
+    for path in paths {
+        let data = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let Some(data) = transform(data)? else {
+            continue;
+        };
+        // Through a temporary file, so an interruption never leaves half a chat
+        let partial = path.with_extension("json.part");
+        fs::write(&partial, data)
+            .and_then(|_| fs::rename(&partial, &path))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn encrypt_all(app: &tauri::AppHandle, key: &[u8; KEY_LEN]) -> Result<usize, String> {
+    rewrite_chats(app, |data| if is_sealed(&data) { Ok(None) } else { seal_with(key, &data).map(Some) })
+}
+
+// Turns encryption on with a key derived from `passphrase`, or a random key
+// kept in the OS keychain when there's none, and encrypts existing chats.
+// Returns the number of chats encrypted.
+#[tauri::command]
+pub async fn enable_encryption(app: tauri::AppHandle, passphrase: Option<String>) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        // No chat is saved with the old key, or without one, halfway through
+        let _guard = chats::WRITE_LOCK.lock().map_err(|e| e.to_string())?;
+        if enabled(&app) {
+            return Err("Chat encryption is already on".to_string());
+        }
+        let salt = random(SALT_LEN)?;
+        let (key, key_source) = match passphrase.filter(|p| !p.is_empty()) {
+            Some(passphrase) => (derive(&passphrase, &salt, ITERATIONS), KeySource::Passphrase),
+            None => {
+                let key: [u8; KEY_LEN] = random(KEY_LEN)?.try_into().unwrap();
+                keychain::store(KEYCHAIN_ACCOUNT, &STANDARD.encode(key))?;
+                (key, KeySource::Keychain)
+            }
+        };
+        let config = Config {
+            key_source,
+            salt: STANDARD.encode(&salt),
+            iterations: ITERATIONS,
+            verifier: STANDARD.encode(seal_with(&key, VERIFIER)?),
+        };
+        let content = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+        fs::write(config_path(&app)?, content).map_err(|e| format!("Failed to write encryption settings: {}", e))?;
+        set_key(Some(key));
+        // Chat titles don't show in system search once the store is encrypted
+        crate::os_search::configure(&app);
+        encrypt_all(&app, &key)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn unlock_chat_store(app: tauri::AppHandle, passphrase: String) -> Result<(), String> {
+    let config = config(&app)?.ok_or("Chat encryption is off")?;
+    let key = tauri::async_runtime::spawn_blocking(move || {
+        let salt = STANDARD.decode(&config.salt).map_err(|e| e.to_string())?;
+        let key = derive(&passphrase, &salt, config.iterations);
+        verify(&config, &key).map(|_| key)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    set_key(Some(key));
+    crate::app_lock::touch();
+    Ok(())
+}
+
+// Encrypts chats that are still plaintext, e.g. ones imported or written by
+// an older version. Returns the number encrypted.
+#[tauri::command]
+pub async fn encrypt_existing_chats(app: tauri::AppHandle) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if !enabled(&app) {
+        return Err("Chat encryption is off".to_string());
+    }
+    let key = key().ok_or(LOCKED)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        // A chat saved meanwhile would be overwritten with what was read before
+        let _guard = chats::WRITE_LOCK.lock().map_err(|e| e.to_string())?;
+        encrypt_all(&app, &key)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Decrypts every chat and turns encryption off. Needs the store unlocked.
+#[tauri::command]
+pub async fn disable_encryption(app: tauri::AppHandle) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = chats::WRITE_LOCK.lock().map_err(|e| e.to_string())?;
+        let config = config(&app)?.ok_or("Chat encryption is off")?;
+        let key = key().ok_or(LOCKED)?;
+        let decrypt = |data: Vec<u8>| if is_sealed(&data) { open_with(&key, &data).map(Some) } else { Ok(None) };
+        let count = rewrite_chats(&app, decrypt)?;
+        fs::remove_file(config_path(&app)?).map_err(|e| format!("Failed to remove encryption settings: {}", e))?;
+        set_key(None);
+        crate::os_search::configure(&app);
+        if config.key_source == KeySource::Keychain {
+            if let Err(e) = keychain::delete(KEYCHAIN_ACCOUNT) {
+                log::warn!("Failed to remove the chat store key from the keychain: {}", e);
+            }
+        }
+        Ok(count)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn encryption_status(app: tauri::AppHandle) -> Result<EncryptionStatus, String> {
+    let config = config(&app)?;
+    let mut plaintext_chats = 0;
+    for path in chats::chat_files(&app)? {
+        let mut magic = [0u8; MAGIC.len()];
+        let sealed = fs::File::open(&path).and_then(|mut f| std::io::Read::read_exact(&mut f, &mut magic)).is_ok()
+            && is_sealed(&magic);
+        if !sealed {
+            plaintext_chats += 1;
+        }
+    }
+    Ok(EncryptionStatus {
+        enabled: config.is_some(),
+        key_source: config.map(|c| c.key_source),
+        unlocked: key().is_some(),
+        plaintext_chats,
+    })
+}
@@ -0,0 +1,569 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::attachments;
+use crate::sealed;
+
+pub const ARCHIVE_VERSION: u32 = 1;
+// Bigger files are listed in HTML exports instead of being inlined
+const MAX_EMBED_BYTES: u64 = 25 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    // Zip of the session and its attachment blobs, for re-importing
+    Anchorchat,
+    // Zip of a Markdown transcript with the files next to it
+    Zip,
+    // One self-contained page with images inlined
+    Html,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportReport {
+    pub path: String,
+    pub format: ExportFormat,
+    // Password-protected, see sealed.rs
+    pub encrypted: bool,
+    pub attachments: usize,
+    // References whose file no longer exists
+    pub missing: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PublishOptions {
+    pub hide_timestamps: bool,
+    pub hide_models: bool,
+    // A folder with index.html and the files beside it, instead of one page
+    // with everything inlined
+    pub folder: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishReport {
+    pub path: String,
+    pub folder: bool,
+    pub attachments: usize,
+    pub missing: Vec<String>,
+}
+
+// A file a chat points at, resolved to where it lives on disk
+struct Bundled {
+    id: String,
+    name: String,
+    mime: String,
+    size: u64,
+    source: PathBuf,
+}
+
+struct Bundle {
+    session: Value,
+    files: Vec<Bundled>,
+    missing: Vec<String>,
+}
+
+fn stored(app: &tauri::AppHandle, id: &str) -> Option<Bundled> {
+    let attachment = attachments::get(app, id).ok()?;
+    Some(Bundled {
+        source: attachments::path(app, id).ok()?,
+        id: attachment.id,
+        name: attachment.name,
+        mime: attachment.mime,
+        size: attachment.size,
+    })
+}
+
+// Resolves every attachment reference in the session. Images that still point
+// at a file path are hashed like stored attachments and rewritten to an id, so
+// the exported session only ever refers to bundled files.
+fn bundle(app: &tauri::AppHandle, mut session: Value) -> Result<Bundle, String> {
+    let mut files: Vec<Bundled> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut missing = Vec::new();
+    let mut stored_ids = Vec::new();
+
+    for message in session["messages"].as_array_mut().into_iter().flatten() {
+        for image in message["images"].as_array_mut().into_iter().flatten() {
+            if let Some(id) = image["attachmentId"].as_str() {
+                stored_ids.push(id.to_string());
+                continue;
+            }
+            let Some(path) = image["path"].as_str().map(PathBuf::from) else {
+                continue;
+            };
+            let Ok((id, size)) = attachments::hash_file(&path) else {
+                missing.push(path.to_string_lossy().to_string());
+                continue;
+            };
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| id.clone());
+            if let Some(image) = image.as_object_mut() {
+                image.remove("path");
+                image.insert("attachmentId".to_string(), Value::String(id.clone()));
+            }
+            if seen.insert(id.clone()) {
+                let mime = attachments::mime_for(&name).to_string();
+                files.push(Bundled { id, name, mime, size, source: path });
+            }
+        }
+        let others = message["attachments"].as_array().into_iter().flatten().filter_map(Value::as_str);
+        stored_ids.extend(others.map(str::to_string));
+    }
+
+    for id in stored_ids {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        match stored(app, &id) {
+            Some(file) => files.push(file),
+            None => missing.push(id),
+        }
+    }
+    Ok(Bundle { session, files, missing })
+}
+
+// Human-readable archive names, made unique with a short id prefix on clashes
+fn file_names(files: &[Bundled]) -> HashMap<String, String> {
+    let mut taken = HashSet::new();
+    let mut names = HashMap::new();
+    for file in files {
+        let clean: String = file
+            .name
+            .chars()
+            .map(|c| if c.is_alphanumeric() || " ._-".contains(c) { c } else { '_' })
+            .collect();
+        let mut name = clean.trim().to_string();
+        if name.is_empty() || !taken.insert(name.clone()) {
+            name = format!("{}-{}", &file.id[..8.min(file.id.len())], name);
+            taken.insert(name.clone());
+        }
+        names.insert(file.id.clone(), name);
+    }
+    names
+}
+
+fn zip_options() -> SimpleFileOptions {
+    SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated)
+}
+
+fn add_file(zip: &mut ZipWriter<File>, name: &str, content: &[u8]) -> Result<(), String> {
+    zip.start_file(name, zip_options()).map_err(|e| e.to_string())?;
+    zip.write_all(content).map_err(|e| format!("Failed to write {}: {}", name, e))
+}
+
+fn add_blob(zip: &mut ZipWriter<File>, name: &str, source: &Path) -> Result<(), String> {
+    zip.start_file(name, zip_options()).map_err(|e| e.to_string())?;
+    let mut file = File::open(source).map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+    std::io::copy(&mut file, zip).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    Ok(())
+}
+
+fn title(session: &Value) -> String {
+    session["title"].as_str().filter(|t| !t.trim().is_empty()).unwrap_or("Untitled chat").to_string()
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "User",
+        "assistant" => "Assistant",
+        "system" => "System",
+        "tool" => "Tool",
+        other => other,
+    }
+}
+
+// Attachment ids of one message, images first, in order
+fn message_files(message: &Value) -> Vec<String> {
+    let images = message["images"].as_array().into_iter().flatten().filter_map(|i| i["attachmentId"].as_str());
+    let others = message["attachments"].as_array().into_iter().flatten().filter_map(Value::as_str);
+    images.chain(others).map(str::to_string).collect()
+}
+
+fn markdown(session: &Value, files: &HashMap<String, &Bundled>, names: &HashMap<String, String>) -> String {
+    let mut out = format!("# {}\n", title(session));
+    for message in session["messages"].as_array().into_iter().flatten() {
+        let role = message["role"].as_str().unwrap_or("user");
+        out.push_str(&format!("\n## {}\n\n{}\n", role_label(role), message["content"].as_str().unwrap_or_default()));
+        for id in message_files(message) {
+            let (Some(file), Some(name)) = (files.get(&id), names.get(&id)) else {
+                continue;
+            };
+            // Spaces would end the link target
+            let link = format!("attachments/{}", name.replace(' ', "%20"));
+            if file.mime.starts_with("image/") {
+                out.push_str(&format!("\n![{}]({})\n", file.name, link));
+            } else {
+                out.push_str(&format!("\n[{}]({})\n", file.name, link));
+            }
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn data_url(file: &Bundled) -> Result<String, String> {
+    let bytes = fs::read(&file.source).map_err(|e| format!("Failed to read {}: {}", file.name, e))?;
+    Ok(format!("data:{};base64,{}", file.mime, base64::engine::general_purpose::STANDARD.encode(bytes)))
+}
+
+fn html(session: &Value, files: &HashMap<String, &Bundled>) -> Result<String, String> {
+    let title = escape_html(&title(session));
+    let mut body = String::new();
+    for message in session["messages"].as_array().into_iter().flatten() {
+        let role = message["role"].as_str().unwrap_or("user");
+        body.push_str(&format!(
+            "<section class=\"message {}\"><h2>{}</h2><div class=\"content\">{}</div>",
+            escape_html(role),
+            escape_html(role_label(role)),
+            escape_html(message["content"].as_str().unwrap_or_default())
+        ));
+        for id in message_files(message) {
+            let Some(file) = files.get(&id) else {
+                continue;
+            };
+            let name = escape_html(&file.name);
+            if file.size > MAX_EMBED_BYTES {
+                body.push_str(&format!("<p class=\"file\">{} (too large to include)</p>", name));
+            } else if file.mime.starts_with("image/") {
+                body.push_str(&format!("<img src=\"{}\" alt=\"{}\">", data_url(file)?, name));
+            } else {
+                let link = format!("<a download=\"{0}\" href=\"{1}\">{0}</a>", name, data_url(file)?);
+                body.push_str(&format!("<p class=\"file\">{}</p>", link));
+            }
+        }
+        body.push_str("</section>\n");
+    }
+
+    // Light or dark with the reader's system, in the accent of the exporter's
+    Ok(format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title><style>\
+         :root{{color-scheme:light dark;--accent:{accent};--muted:#666;--line:#ddd}}\
+         @media(prefers-color-scheme:dark){{:root{{--muted:#a1a1a6;--line:#333}}}}\
+         body{{font-family:system-ui,sans-serif;max-width:48rem;margin:2rem auto;padding:0 1rem;line-height:1.5}}\
+         .message{{border-top:1px solid var(--line);padding:.5rem 0}}\
+         h2{{font-size:.9rem;color:var(--muted);margin:.5rem 0}}.message.user h2{{color:var(--accent)}}\
+         .content{{white-space:pre-wrap}}img{{max-width:100%;border-radius:6px;margin:.5rem 0}}a{{color:var(--accent)}}\
+         </style></head><body><h1>{title}</h1>\n{body}</body></html>\n",
+        accent = crate::appearance::accent()
+    ))
+}
+
+// The chat as one self-contained page, for sharing without writing a file
+pub fn render_html(app: &tauri::AppHandle, chat_id: &str) -> Result<String, String> {
+    let Bundle { session, files, .. } = bundle(app, crate::chats::read(app, chat_id)?)?;
+    let by_id: HashMap<String, &Bundled> = files.iter().map(|f| (f.id.clone(), f)).collect();
+    html(&session, &by_id)
+}
+
+// The chat as Markdown with its title, for places that only take text, so
+// attachments are left out
+pub fn render_markdown(app: &tauri::AppHandle, chat_id: &str) -> Result<(String, String), String> {
+    let session = crate::chats::read(app, chat_id)?;
+    Ok((title(&session), markdown(&session, &HashMap::new(), &HashMap::new())))
+}
+
+// Prose as pre-wrapped text, fenced code as code blocks
+fn published_content(text: &str) -> String {
+    let mut out = String::new();
+    for (i, part) in text.split("```").enumerate() {
+        if i % 2 == 0 {
+            if !part.trim().is_empty() {
+                out.push_str(&format!("<div class=\"text\">{}</div>", escape_html(part.trim_matches('\n'))));
+            }
+            continue;
+        }
+        let (language, code) = part.split_once('\n').unwrap_or(("", part));
+        let language = language.trim();
+        let class = match language {
+            "" => String::new(),
+            language => format!(" class=\"language-{}\"", escape_html(language)),
+        };
+        out.push_str(&format!("<pre><code{}>{}</code></pre>", class, escape_html(code.trim_end_matches('\n'))));
+    }
+    out
+}
+
+fn published_time(millis: Option<f64>, format: &str) -> Option<String> {
+    let time = chrono::DateTime::from_timestamp_millis(millis? as i64)?;
+    Some(time.with_timezone(&chrono::Local).format(format).to_string())
+}
+
+// A page meant for a static host. With `names`, files are linked from the
+// assets folder rather than inlined.
+fn published_page(
+    session: &Value,
+    files: &HashMap<String, &Bundled>,
+    names: Option<&HashMap<String, String>>,
+    options: &PublishOptions,
+) -> Result<String, String> {
+    let title = escape_html(&title(session));
+    let messages = session["messages"].as_array().cloned().unwrap_or_default();
+    let mut meta = vec![format!("{} messages", messages.len())];
+    if !options.hide_timestamps {
+        meta.extend(published_time(session["timestamp"].as_f64(), "%B %-d, %Y"));
+    }
+
+    let mut body = String::new();
+    for message in &messages {
+        let role = message["role"].as_str().unwrap_or("user");
+        let mut details = vec![format!("<span class=\"role\">{}</span>", escape_html(role_label(role)))];
+        if !options.hide_models {
+            if let Some(model) = message["model"].as_str().filter(|m| !m.is_empty()) {
+                details.push(format!("<span class=\"model\">{}</span>", escape_html(model)));
+            }
+        }
+        if !options.hide_timestamps {
+            if let Some(time) = published_time(message["timestamp"].as_f64(), "%Y-%m-%d %H:%M") {
+                details.push(format!("<time>{}</time>", time));
+            }
+        }
+        body.push_str(&format!(
+            "<article class=\"message {}\"><header>{}</header>{}",
+            escape_html(role),
+            details.join(""),
+            published_content(message["content"].as_str().unwrap_or_default())
+        ));
+        for id in message_files(message) {
+            let Some(file) = files.get(&id) else {
+                continue;
+            };
+            let name = escape_html(&file.name);
+            let src = match names.and_then(|names| names.get(&id)) {
+                Some(asset) => format!("assets/{}", escape_html(&asset.replace(' ', "%20"))),
+                None if file.size > MAX_EMBED_BYTES => {
+                    body.push_str(&format!("<p class=\"file\">{} (too large to include)</p>", name));
+                    continue;
+                }
+                None => data_url(file)?,
+            };
+            if file.mime.starts_with("image/") {
+                body.push_str(&format!("<img src=\"{}\" alt=\"{}\" loading=\"lazy\">", src, name));
+            } else {
+                body.push_str(&format!("<p class=\"file\"><a download=\"{0}\" href=\"{1}\">{0}</a></p>", name, src));
+            }
+        }
+        body.push_str("</article>\n");
+    }
+
+    Ok(format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <meta name=\"generator\" content=\"Anchor\"><title>{title}</title><style>\
+         :root{{color-scheme:light dark;--fg:#1d1d1f;--muted:#6e6e73;--bg:#fff;--card:#f5f5f7;--line:#e5e5ea;\
+         --accent:{accent}}}\
+         @media(prefers-color-scheme:dark){{:root{{--fg:#f5f5f7;--muted:#a1a1a6;--bg:#111;--card:#1c1c1e;\
+         --line:#2c2c2e}}}}\
+         *{{box-sizing:border-box}}body{{margin:0;background:var(--bg);color:var(--fg);\
+         font:16px/1.6 system-ui,-apple-system,\"Segoe UI\",sans-serif}}\
+         main{{max-width:46rem;margin:0 auto;padding:3rem 1.25rem}}h1{{font-size:1.75rem;line-height:1.25;margin:0}}\
+         .meta{{color:var(--muted);font-size:.875rem;margin:.5rem 0 2rem}}\
+         .message{{padding:1rem 1.25rem;border-radius:14px;margin:0 0 1rem;border:1px solid var(--line)}}\
+         .message.user{{background:var(--card)}}.message header{{display:flex;flex-wrap:wrap;gap:.75rem;\
+         font-size:.8rem;color:var(--muted);margin-bottom:.25rem}}.role{{font-weight:600;color:var(--fg)}}\
+         .message.user .role{{color:var(--accent)}}\
+         .text{{white-space:pre-wrap;overflow-wrap:anywhere}}pre{{background:var(--card);border:1px solid var(--line);\
+         border-radius:8px;padding:.75rem 1rem;overflow-x:auto;font-size:.875rem}}\
+         code{{font-family:ui-monospace,SFMono-Regular,Menlo,monospace}}\
+         img{{max-width:100%;border-radius:10px;margin:.5rem 0}}.file a{{color:inherit}}\
+         footer{{color:var(--muted);font-size:.8rem;text-align:center;margin-top:3rem}}\
+         </style></head><body><main><h1>{title}</h1><p class=\"meta\">{meta}</p>\n{body}\
+         <footer>Shared from Anchor</footer></main></body></html>\n",
+        meta = meta.join(" · "),
+        accent = crate::appearance::accent()
+    ))
+}
+
+// Writes the page, or the folder, next to the destination and moves it into
+// place once it's complete
+pub fn publish(
+    app: &tauri::AppHandle,
+    chat_id: &str,
+    destination: &Path,
+    options: &PublishOptions,
+) -> Result<PublishReport, String> {
+    if destination.exists() && !(options.folder && destination.read_dir().is_ok_and(|mut d| d.next().is_none())) {
+        return Err(format!("{} already exists", destination.display()));
+    }
+    let Bundle { session, files, missing } = bundle(app, crate::chats::read(app, chat_id)?)?;
+    let by_id: HashMap<String, &Bundled> = files.iter().map(|f| (f.id.clone(), f)).collect();
+    let partial = destination.with_extension("part");
+
+    let result = if options.folder {
+        let names = file_names(&files);
+        let assets = partial.join("assets");
+        fs::create_dir_all(&assets).map_err(|e| format!("Failed to create {}: {}", assets.display(), e))?;
+        published_page(&session, &by_id, Some(&names), options)
+            .and_then(|page| fs::write(partial.join("index.html"), page).map_err(|e| e.to_string()))
+            .and_then(|_| {
+                for file in &files {
+                    fs::copy(&file.source, assets.join(&names[&file.id]))
+                        .map_err(|e| format!("Failed to copy {}: {}", file.name, e))?;
+                }
+                Ok(())
+            })
+    } else {
+        published_page(&session, &by_id, None, options)
+            .and_then(|page| fs::write(&partial, page).map_err(|e| e.to_string()))
+    };
+    if let Err(e) = result {
+        let _ = if options.folder { fs::remove_dir_all(&partial) } else { fs::remove_file(&partial) };
+        return Err(e);
+    }
+    if options.folder && destination.exists() {
+        fs::remove_dir(destination).map_err(|e| e.to_string())?;
+    }
+    fs::rename(&partial, destination).map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+
+    Ok(PublishReport {
+        path: destination.to_string_lossy().to_string(),
+        folder: options.folder,
+        attachments: files.len(),
+        missing,
+    })
+}
+
+fn write_archive(path: &Path, entries: impl FnOnce(&mut ZipWriter<File>) -> Result<(), String>) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    entries(&mut zip)?;
+    zip.finish().map_err(|e| format!("Failed to finish archive: {}", e))?;
+    Ok(())
+}
+
+pub fn export(
+    app: &tauri::AppHandle,
+    chat_id: &str,
+    format: ExportFormat,
+    destination: &Path,
+    password: Option<&str>,
+) -> Result<ExportReport, String> {
+    let Bundle { session, files, missing } = bundle(app, crate::chats::read(app, chat_id)?)?;
+    let by_id: HashMap<String, &Bundled> = files.iter().map(|f| (f.id.clone(), f)).collect();
+    let chat_json = serde_json::to_string_pretty(&session).map_err(|e| e.to_string())?;
+
+    // Written next to the destination first so a failed export never leaves
+    // half a file behind. One that's going to be password-protected is
+    // staged in the app's own directory instead, so the plaintext never sits
+    // in a folder that may be synced or shared.
+    let partial = match password {
+        Some(_) => {
+            let name = format!("export_{}.part", crate::share::hex(&crate::encryption::random(8)?));
+            crate::app_data_dir(app)?.join(name)
+        }
+        None => destination.with_extension("part"),
+    };
+    let result = match format {
+        ExportFormat::Anchorchat => write_archive(&partial, |zip| {
+            let manifest = json!({
+                "format": "anchorchat",
+                "version": ARCHIVE_VERSION,
+                "exportedAt": chrono::Local::now().to_rfc3339(),
+                "attachments": files
+                    .iter()
+                    .map(|f| json!({ "id": f.id, "name": f.name, "mime": f.mime, "size": f.size }))
+                    .collect::<Vec<_>>(),
+            });
+            let manifest = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+            add_file(zip, "manifest.json", manifest.as_bytes())?;
+            add_file(zip, "chat.json", chat_json.as_bytes())?;
+            for file in &files {
+                add_blob(zip, &format!("attachments/{}", file.id), &file.source)?;
+            }
+            Ok(())
+        }),
+        ExportFormat::Zip => write_archive(&partial, |zip| {
+            let names = file_names(&files);
+            add_file(zip, "chat.md", markdown(&session, &by_id, &names).as_bytes())?;
+            add_file(zip, "chat.json", chat_json.as_bytes())?;
+            for file in &files {
+                add_blob(zip, &format!("attachments/{}", names[&file.id]), &file.source)?;
+            }
+            Ok(())
+        }),
+        ExportFormat::Html => {
+            html(&session, &by_id).and_then(|page| fs::write(&partial, page).map_err(|e| e.to_string()))
+        }
+    };
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    if let Some(password) = password {
+        let sealed = destination.with_extension("sealed.part");
+        let result = sealed::encrypt_file(&partial, &sealed, password);
+        let _ = crate::shred::remove_file(app, &partial);
+        if let Err(e) = result {
+            let _ = fs::remove_file(&sealed);
+            return Err(e);
+        }
+        fs::rename(&sealed, destination).map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+    } else {
+        fs::rename(&partial, destination).map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+    }
+
+    Ok(ExportReport {
+        path: destination.to_string_lossy().to_string(),
+        format,
+        encrypted: password.is_some(),
+        attachments: files.len(),
+        missing,
+    })
+}
+
+#[tauri::command]
+pub async fn export_chat(
+    app: tauri::AppHandle,
+    chat_id: String,
+    format: ExportFormat,
+    destination: String,
+    password: Option<String>,
+) -> Result<ExportReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let detail = format!("{} as {:?} to {}", chat_id, format, destination);
+    let password = password.filter(|p| !p.is_empty());
+    let handle = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        export(&handle, &chat_id, format, Path::new(&destination), password.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    let report = crate::analytics::track(&app, "export_chat", result)?;
+    crate::audit::record("export", &detail);
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn publish_chat(
+    app: tauri::AppHandle,
+    chat_id: String,
+    destination: String,
+    options: Option<PublishOptions>,
+) -> Result<PublishReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let detail = format!("{} as a web page to {}", chat_id, destination);
+    let options = options.unwrap_or_default();
+    let handle = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        publish(&handle, &chat_id, Path::new(&destination), &options)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    let report = crate::analytics::track(&app, "publish_chat", result)?;
+    crate::audit::record("export", &detail);
+    Ok(report)
+}
@@ -0,0 +1,250 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::Emitter;
+
+use crate::attachments;
+use crate::code::{self, CodeChunk, CodeLanguage};
+use crate::completion::CompletionRequest;
+use crate::ocr;
+use crate::tables;
+use crate::office::{self, OfficeKind};
+use crate::transcription::{self, TranscriptionProgress};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Section {
+    // Slide or sheet name, where the format has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // 1-based page (or slide/sheet) number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub attachment_id: String,
+    pub name: String,
+    pub format: String,
+    pub sections: Vec<Section>,
+}
+
+impl Document {
+    // The whole document as one prompt-ready string
+    pub fn text(&self) -> String {
+        self.sections
+            .iter()
+            .map(|section| match (&section.title, section.page) {
+                (Some(title), _) => format!("## {}\n\n{}", title, section.text),
+                (None, Some(page)) => format!("--- Page {} ---\n{}", page, section.text),
+                (None, None) => section.text.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Pdf,
+    Office(OfficeKind),
+    // Text recognized with OCR
+    Image,
+    // Transcribed with whisper
+    Audio,
+    // CSV/TSV, summarized as schema and samples
+    Table,
+    // Source files, split along syntax boundaries
+    Code(CodeLanguage),
+    Text,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Pdf => "pdf",
+            Format::Office(kind) => kind.name(),
+            Format::Image => "image",
+            Format::Audio => "audio",
+            Format::Table => "table",
+            Format::Code(_) => "code",
+            Format::Text => "text",
+        }
+    }
+}
+
+// Attachments are stored without extensions, so go by the recorded name and
+// fall back to the file's magic bytes.
+fn detect(path: &Path, name: &str, mime: &str) -> Result<Format, String> {
+    let ext = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    let mut magic = [0u8; 16];
+    let read = fs::File::open(path)
+        .and_then(|mut file| file.read(&mut magic))
+        .map_err(|e| e.to_string())?;
+
+    if ext == "pdf" || magic[..read].starts_with(b"%PDF-") {
+        Ok(Format::Pdf)
+    } else if let Some(kind) = magic[..read].starts_with(b"PK").then(|| office::detect(path)).flatten() {
+        Ok(Format::Office(kind))
+    } else if mime.starts_with("image/") || image::guess_format(&magic[..read]).is_ok() {
+        Ok(Format::Image)
+    } else if mime.starts_with("audio/") || is_audio(&magic[..read]) {
+        Ok(Format::Audio)
+    } else if tables::is_table(name, mime) {
+        Ok(Format::Table)
+    } else if let Some(language) = CodeLanguage::from_name(name) {
+        Ok(Format::Code(language))
+    } else if mime.starts_with("text/") || matches!(ext.as_str(), "md" | "txt" | "json" | "log") {
+        Ok(Format::Text)
+    } else {
+        Err(format!("Can't extract text from {}", name))
+    }
+}
+
+fn is_audio(magic: &[u8]) -> bool {
+    let riff_wave = magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"WAVE");
+    let mp4 = magic.get(4..8) == Some(b"ftyp");
+    let mp3_frame = magic.len() > 1 && magic[0] == 0xFF && magic[1] & 0xE0 == 0xE0;
+    riff_wave || mp4 || mp3_frame || [&b"ID3"[..], b"fLaC", b"OggS"].iter().any(|m| magic.starts_with(m))
+}
+
+// Keeps line structure but drops the trailing spaces and runs of blank lines
+// positional PDF output leaves behind.
+fn tidy(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_run = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim().to_string()
+}
+
+fn extract_pdf(path: &Path) -> Result<Vec<Section>, String> {
+    // pdf-extract panics on some malformed files instead of returning an error
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_by_pages(path))
+        .map_err(|_| "PDF parser crashed on this file".to_string())?
+        .map_err(|e| format!("Failed to read PDF: {}", e))?;
+
+    Ok(pages
+        .iter()
+        .enumerate()
+        .map(|(i, text)| Section { title: None, page: Some(i as u32 + 1), text: tidy(text) })
+        .collect())
+}
+
+fn extract_plain(path: &Path) -> Result<Vec<Section>, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    Ok(vec![Section { title: None, page: None, text: String::from_utf8_lossy(&bytes).to_string() }])
+}
+
+fn cache_path(app: &tauri::AppHandle, id: &str) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("extracted");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create extraction cache: {}", e))?;
+    }
+    Ok(dir.join(format!("{}.json", id)))
+}
+
+// Extracted once per attachment; the content never changes under an id.
+pub fn extract(app: &tauri::AppHandle, id: &str) -> Result<Document, String> {
+    extract_with(app, id, true)
+}
+
+// Incognito chats pass `keep: false` so no extracted text is left on disk
+fn extract_with(app: &tauri::AppHandle, id: &str, keep: bool) -> Result<Document, String> {
+    let path = attachments::path(app, id)?;
+    let cache = cache_path(app, id)?;
+    if let Some(document) = fs::read_to_string(&cache).ok().and_then(|c| serde_json::from_str(&c).ok()) {
+        return Ok(document);
+    }
+
+    let attachment = attachments::get(app, id)?;
+    let format = detect(&path, &attachment.name, &attachment.mime)?;
+    let sections = match format {
+        Format::Pdf => extract_pdf(&path)?,
+        Format::Office(kind) => office::extract(kind, &path)?,
+        Format::Image => vec![Section { title: None, page: None, text: ocr::recognize_attachment(app, &path)? }],
+        Format::Audio => {
+            let transcript = transcription::transcribe_file(app, &path, None, None, |percent| {
+                let progress = TranscriptionProgress { attachment_id: Some(id.to_string()), percent };
+                let _ = app.emit("transcription-progress", progress);
+            })?;
+            vec![Section { title: None, page: None, text: transcript.text }]
+        }
+        Format::Table => vec![Section { title: None, page: None, text: tables::summarize(app, id)?.markdown() }],
+        Format::Code(_) => {
+            let chunks = code::chunk_attachment(app, id, code::DEFAULT_MAX_LINES)?;
+            chunks.iter().map(CodeChunk::section).collect()
+        }
+        Format::Text => extract_plain(&path)?,
+    };
+
+    let document = Document {
+        attachment_id: id.to_string(),
+        name: attachment.name,
+        format: format.name().to_string(),
+        sections,
+    };
+    if let Some(content) = serde_json::to_string(&document).ok().filter(|_| keep) {
+        if let Err(e) = fs::write(&cache, content) {
+            log::warn!("Failed to cache extracted text for {}: {}", id, e);
+        }
+    }
+    Ok(document)
+}
+
+pub fn format_of(app: &tauri::AppHandle, id: &str) -> Result<&'static str, String> {
+    let attachment = attachments::get(app, id)?;
+    Ok(detect(&attachments::path(app, id)?, &attachment.name, &attachment.mime)?.name())
+}
+
+pub fn remove_cached(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    let cache = cache_path(app, id)?;
+    if cache.exists() {
+        crate::shred::remove_file(app, &cache).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Appends the text of documents attached to a message to its content, since
+// chat endpoints only take text and images.
+pub async fn inline_documents(app: &tauri::AppHandle, request: &mut CompletionRequest) -> Result<(), String> {
+    let private = request.chat_id.as_deref().is_some_and(crate::incognito::is_incognito);
+    for message in request.messages.iter_mut().filter(|m| !m.attachments.is_empty()) {
+        for id in std::mem::take(&mut message.attachments) {
+            let app = app.clone();
+            let document = tauri::async_runtime::spawn_blocking(move || extract_with(&app, &id, !private))
+                .await
+                .map_err(|e| e.to_string())??;
+            message.content.push_str(&format!(
+                "\n\n<document name=\"{}\">\n{}\n</document>",
+                document.name,
+                document.text()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn extract_text(app: tauri::AppHandle, attachment_id: String) -> Result<Document, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || extract(&app, &attachment_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,70 @@
+use serde::Serialize;
+use tauri::{DragDropEvent, Emitter, Manager, WindowEvent};
+
+use crate::attachments::{self, Attachment, AttachmentSource};
+use crate::imports;
+
+// Files dragged onto the main window. Chat exports are imported as chats,
+// see imports.rs, and everything else is stored as an attachment for the
+// chat in front; the UI learns what came of the drop from one event.
+
+const DROPPED_EVENT: &str = "files-dropped";
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DroppedFiles {
+    pub attachments: Vec<Attachment>,
+    // Chats imported from the dropped files
+    pub chat_ids: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+fn receive(app: &tauri::AppHandle, paths: Vec<std::path::PathBuf>) -> DroppedFiles {
+    let mut dropped = DroppedFiles::default();
+    if let Err(e) = crate::app_lock::ensure_unlocked() {
+        dropped.errors.push(e);
+        return dropped;
+    }
+    for path in paths {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        if !path.is_file() {
+            dropped.errors.push(format!("{}: only files can be dropped", name));
+            continue;
+        }
+        match imports::import(app, &path, None) {
+            Ok(Some(report)) => {
+                dropped.chat_ids.extend(report.chat_ids);
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                dropped.errors.push(format!("{}: {}", name, e));
+                continue;
+            }
+        }
+        let source = AttachmentSource::Path(path.to_string_lossy().into_owned());
+        match attachments::save(app, source, Some(name.clone())) {
+            Ok(attachment) => {
+                crate::transcription::schedule_auto(app, &attachment.id);
+                dropped.attachments.push(attachment);
+            }
+            Err(e) => dropped.errors.push(format!("{}: {}", name, e)),
+        }
+    }
+    dropped
+}
+
+pub fn on_window_event(window: &tauri::Window, event: &WindowEvent) {
+    let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event else {
+        return;
+    };
+    if window.label() != "main" || paths.is_empty() {
+        return;
+    }
+    let app = window.app_handle().clone();
+    let paths = paths.clone();
+    std::thread::spawn(move || {
+        let dropped = receive(&app, paths);
+        let _ = app.emit(DROPPED_EVENT, dropped);
+    });
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tauri_plugin_http::reqwest::{Method, StatusCode};
+
+use crate::{chats, http, keychain};
+
+// Publishes a chat as a GitHub Gist of its Markdown transcript, with a
+// personal access token (gist scope) the user keeps in the keychain. The
+// gist is remembered on the session, so publishing again updates it in place
+// instead of making another one.
+
+const API: &str = "https://api.github.com/gists";
+const TOKEN_ACCOUNT: &str = "github-gist";
+const API_VERSION: &str = "2022-11-28";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GistVisibility {
+    Public,
+    // Unlisted: anyone with the link can read it
+    Secret,
+}
+
+// What a session stores under `gist`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishedGist {
+    pub id: String,
+    pub url: String,
+    pub public: bool,
+    // The transcript's file name in the gist
+    pub file: String,
+    pub published_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GistResponse {
+    id: String,
+    html_url: String,
+    public: bool,
+}
+
+fn file_name(title: &str) -> String {
+    let clean: String = title.chars().map(|c| if c.is_alphanumeric() || "-_".contains(c) { c } else { '-' }).collect();
+    let clean = clean.trim_matches('-');
+    format!("{}.md", if clean.is_empty() { "chat" } else { clean })
+}
+
+// An empty token removes the stored one
+#[tauri::command]
+pub fn set_gist_token(token: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    if token.trim().is_empty() {
+        return keychain::delete(TOKEN_ACCOUNT);
+    }
+    keychain::store(TOKEN_ACCOUNT, token.trim())
+}
+
+async fn send(token: &str, method: Method, url: &str, body: Value) -> Result<Option<GistResponse>, String> {
+    let request = http::client()
+        .request(method, url)
+        .bearer_auth(token)
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", API_VERSION)
+        .json(&body);
+    let response = http::send(request).await.map_err(|e| format!("Failed to reach GitHub: {}", e))?;
+    let status = response.status();
+    // Deleted on GitHub since it was published
+    if status == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err("GitHub turned down the token; it needs the gist scope".to_string());
+    }
+    if !status.is_success() {
+        let message = serde_json::from_slice::<Value>(&body).ok();
+        let message = message.as_ref().and_then(|v| v["message"].as_str()).unwrap_or_default();
+        return Err(format!("GitHub answered {}: {}", status, message));
+    }
+    serde_json::from_slice(&body).map(Some).map_err(|e| format!("Unexpected answer from GitHub: {}", e))
+}
+
+async fn publish(app: &tauri::AppHandle, id: &str, visibility: GistVisibility) -> Result<PublishedGist, String> {
+    let token = keychain::load(TOKEN_ACCOUNT).map_err(|_| "Add a GitHub token first".to_string())?;
+    let (title, content) = crate::exports::render_markdown(app, id)?;
+    let file = file_name(&title);
+    let public = visibility == GistVisibility::Public;
+    let previous: Option<PublishedGist> = serde_json::from_value(chats::read(app, id)?["gist"].clone()).ok();
+
+    let mut published = None;
+    // GitHub can't change a gist between public and secret, so that makes a
+    // new one
+    if let Some(previous) = previous.filter(|p| p.public == public) {
+        let mut files = Map::new();
+        files.insert(previous.file.clone(), json!({ "filename": file, "content": content }));
+        let body = json!({ "description": title, "files": files });
+        let url = format!("{}/{}", API, previous.id);
+        published = send(&token, Method::PATCH, &url, body).await?;
+    }
+    let gist = match published {
+        Some(gist) => gist,
+        None => {
+            let files = json!({ file.clone(): { "content": content } });
+            let body = json!({ "description": title, "public": public, "files": files });
+            send(&token, Method::POST, API, body).await?.ok_or("GitHub couldn't find the gists API")?
+        }
+    };
+
+    let record = PublishedGist {
+        id: gist.id,
+        url: gist.html_url,
+        public: gist.public,
+        file,
+        published_at: chats::now_millis() as u64,
+    };
+    let mut session = chats::read(app, id)?;
+    session["gist"] = serde_json::to_value(&record).map_err(|e| e.to_string())?;
+    chats::write(app, session)?;
+    let scope = if record.public { "public" } else { "secret" };
+    crate::audit::record("share", &format!("{} as a {} gist at {}", id, scope, record.url));
+    Ok(record)
+}
+
+#[tauri::command]
+pub async fn publish_to_gist(
+    app: tauri::AppHandle,
+    id: String,
+    visibility: GistVisibility,
+) -> Result<PublishedGist, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let result = publish(&app, &id, visibility).await;
+    crate::analytics::track(&app, "publish_to_gist", result)
+}
@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::Emitter;
+use tauri_plugin_http::reqwest::Url;
+
+use crate::chats;
+use crate::encryption;
+use crate::settings;
+use crate::transcription::find_in_path;
+
+// Keeps the chats directory in a git repository: saves are committed in
+// batches, and with a remote configured each batch is pulled and pushed too.
+// Deleted chats stay in the history, so it won't run while secure deletion
+// is on. Chats the sync rules exclude are never staged.
+
+// Saves come in bursts while a reply streams, so commits wait this long
+const COMMIT_INTERVAL: Duration = Duration::from_secs(30);
+// The repository's own identity, so a missing global git config isn't an error
+const AUTHOR: [&str; 4] = ["-c", "user.name=Anchor", "-c", "user.email=anchor@localhost"];
+const SYNCED_EVENT: &str = "chats-synced";
+// Paths per git add or rm, to stay under command line limits
+const STAGE_BATCH: usize = 100;
+
+// Chat id -> what happened to it since the last commit
+static PENDING: Mutex<BTreeMap<String, Change>> = Mutex::new(BTreeMap::new());
+// Serialises git runs between the background loop and the commands
+static RUNNING: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Change {
+    Saved(String),
+    Deleted(String),
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub hash: String,
+    // Seconds since the epoch
+    pub timestamp: u64,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub committed: bool,
+    pub pulled: bool,
+    pub pushed: bool,
+}
+
+fn enabled(app: &tauri::AppHandle) -> bool {
+    settings::load(app).is_ok_and(|s| s.git_sync.enabled)
+}
+
+// Titles are left out of commit messages when chats are encrypted, since
+// the messages themselves are plain text
+fn label(app: &tauri::AppHandle, chat_id: &str, session: &serde_json::Value) -> String {
+    match session["title"].as_str().filter(|t| !t.is_empty() && !encryption::enabled(app)) {
+        Some(title) => format!("{} ({})", title, chat_id),
+        None => chat_id.to_string(),
+    }
+}
+
+pub fn record_save(app: &tauri::AppHandle, chat_id: &str, session: &serde_json::Value) {
+    if enabled(app) {
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.insert(chat_id.to_string(), Change::Saved(label(app, chat_id, session)));
+        }
+    }
+}
+
+pub fn record_delete(app: &tauri::AppHandle, chat_id: &str, session: &serde_json::Value) {
+    if enabled(app) {
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.insert(chat_id.to_string(), Change::Deleted(label(app, chat_id, session)));
+        }
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let git = find_in_path("git").ok_or("Git sync needs git installed")?;
+    let output = Command::new(git)
+        .current_dir(dir)
+        .args(AUTHOR)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", args.first().unwrap_or(&""), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn ensure_repo(dir: &Path, branch: &str) -> Result<(), String> {
+    if dir.join(".git").exists() {
+        return Ok(());
+    }
+    git(dir, &["init", "--quiet", "--initial-branch", branch])?;
+    Ok(())
+}
+
+// A subject saying what changed, and one line per chat in the body
+fn commit_message(changes: &BTreeMap<String, Change>) -> String {
+    let (mut saved, mut deleted) = (Vec::new(), Vec::new());
+    for change in changes.values() {
+        match change {
+            Change::Saved(label) => saved.push(label.as_str()),
+            Change::Deleted(label) => deleted.push(label.as_str()),
+        }
+    }
+    let subject = match (saved.as_slice(), deleted.as_slice()) {
+        ([one], []) => format!("Update {}", one),
+        ([], [one]) => format!("Delete {}", one),
+        (saved, []) => format!("Update {} chats", saved.len()),
+        ([], deleted) => format!("Delete {} chats", deleted.len()),
+        (saved, deleted) => format!("Update {} chats, delete {}", saved.len(), deleted.len()),
+    };
+    let mut body: Vec<String> = saved.iter().map(|l| format!("- Update {}", l)).collect();
+    body.extend(deleted.iter().map(|l| format!("- Delete {}", l)));
+    if changes.len() > 1 {
+        format!("{}\n\n{}", subject, body.join("\n"))
+    } else {
+        subject
+    }
+}
+
+// The host of a URL or scp-style ("git@host:path") remote; None for a path
+// on this machine
+fn remote_host(remote: &str) -> Option<String> {
+    let remote = remote.trim();
+    if let Ok(url) = Url::parse(remote) {
+        return url.host_str().map(str::to_string);
+    }
+    let (authority, _) = remote.split_once(':')?;
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    (!host.is_empty() && !host.contains('/')).then(|| host.to_string())
+}
+
+// Stages the chats the sync rules let through, and the removal of ones that
+// were deleted or are excluded now. Excluded chats are never added, so
+// their content doesn't reach the repository's objects at all.
+fn stage(app: &tauri::AppHandle, dir: &Path) -> Result<(), String> {
+    let rules = settings::load(app)?.sync.rules;
+    let mut included = BTreeSet::new();
+    for path in chats::chat_files(app)? {
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let data = fs::read(&path).map_err(|e| e.to_string())?;
+        let session: serde_json::Value = String::from_utf8(encryption::open(data)?)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        if !crate::sync::is_excluded(&rules, &session) {
+            included.insert(name);
+        }
+    }
+    let tracked = git(dir, &["ls-files", "-z"])?;
+    let dropped: Vec<&str> =
+        tracked.split('\0').filter(|name| name.ends_with(".json") && !included.contains(*name)).collect();
+    for batch in dropped.chunks(STAGE_BATCH) {
+        git(dir, &[&["rm", "--cached", "--quiet", "--ignore-unmatch", "--"], batch].concat())?;
+    }
+    let included: Vec<&str> = included.iter().map(String::as_str).collect();
+    for batch in included.chunks(STAGE_BATCH) {
+        git(dir, &[&["add", "--"], batch].concat())?;
+    }
+    Ok(())
+}
+
+// Saves and deletions not committed yet
+pub fn pending() -> usize {
+    PENDING.lock().map(|p| p.len()).unwrap_or(0)
+}
+
+fn sync(app: &tauri::AppHandle) -> Result<SyncReport, String> {
+    crate::sync::started(app, "git");
+    let result = commit_and_push(app);
+    crate::sync::finished(app, "git", result.as_ref().map(|_| ()).map_err(String::as_str));
+    result
+}
+
+// Commits what's pending, then pulls and pushes when a remote is set
+fn commit_and_push(app: &tauri::AppHandle) -> Result<SyncReport, String> {
+    let settings = settings::load(app)?;
+    if settings.secure_delete {
+        return Err("Git sync keeps deleted chats in its history, so it's off while secure deletion is on".to_string());
+    }
+    let settings = settings.git_sync;
+    let _running = RUNNING.lock().map_err(|e| e.to_string())?;
+    let dir = chats::get_chats_dir(app)?;
+    ensure_repo(&dir, &settings.branch)?;
+
+    let changes = std::mem::take(&mut *PENDING.lock().map_err(|e| e.to_string())?);
+    stage(app, &dir)?;
+    let dirty = git(&dir, &["diff", "--cached", "--quiet"]).is_err();
+    let mut report = SyncReport { committed: false, pulled: false, pushed: false };
+    if dirty {
+        // Changes made outside Anchor, or before sync was turned on
+        let message = if changes.is_empty() { "Update chats".to_string() } else { commit_message(&changes) };
+        git(&dir, &["commit", "--quiet", "-m", &message])?;
+        report.committed = true;
+    }
+
+    let Some(remote) = settings.remote.as_deref().filter(|r| !r.trim().is_empty()) else {
+        return Ok(report);
+    };
+    if let Some(host) = remote_host(remote) {
+        crate::http::check_host(&host).map_err(|e| e.to_string())?;
+        crate::audit::record("network", &format!("git sync {}", host));
+    }
+    match git(&dir, &["remote", "get-url", "origin"]) {
+        Ok(url) if url.trim() == remote.trim() => {}
+        Ok(_) => {
+            git(&dir, &["remote", "set-url", "origin", remote.trim()])?;
+        }
+        Err(_) => {
+            git(&dir, &["remote", "add", "origin", remote.trim()])?;
+        }
+    }
+
+    let before = git(&dir, &["rev-parse", "HEAD"]).unwrap_or_default();
+    // The remote branch may not exist yet on a first push
+    if git(&dir, &["ls-remote", "--exit-code", "--heads", "origin", &settings.branch]).is_ok() {
+        if let Err(e) = git(&dir, &["pull", "--rebase", "--quiet", "origin", &settings.branch]) {
+            let _ = git(&dir, &["rebase", "--abort"]);
+            return Err(format!("The chats changed on both sides and couldn't be merged: {}", e));
+        }
+        report.pulled = git(&dir, &["rev-parse", "HEAD"]).unwrap_or_default() != before;
+    }
+    git(&dir, &["push", "--quiet", "origin", &format!("HEAD:{}", settings.branch)])?;
+    report.pushed = true;
+
+    if report.pulled {
+        let _ = app.emit(SYNCED_EVENT, ());
+    }
+    Ok(report)
+}
+
+pub fn schedule(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(COMMIT_INTERVAL);
+        let idle = PENDING.lock().map(|p| p.is_empty()).unwrap_or(true);
+        // sync would only refuse again while secure deletion is on
+        if idle || !enabled(&app) || crate::shred::enabled(&app) {
+            continue;
+        }
+        if let Err(e) = sync(&app) {
+            log::warn!("Failed to sync chats: {}", e);
+        }
+    });
+}
+
+fn chat_file(chat_id: &str) -> Result<String, String> {
+    if chat_id.is_empty() || chat_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid chat id: {}", chat_id));
+    }
+    Ok(format!("{}.json", chat_id))
+}
+
+#[tauri::command]
+pub async fn sync_chats(app: tauri::AppHandle) -> Result<SyncReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if !enabled(&app) {
+        return Err("Git sync is turned off".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || sync(&app)).await.map_err(|e| e.to_string())?
+}
+
+// Commits that touched the chat, newest first
+#[tauri::command]
+pub fn chat_history(app: tauri::AppHandle, chat_id: String, limit: Option<usize>) -> Result<Vec<Revision>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let dir = chats::get_chats_dir(&app)?;
+    if !dir.join(".git").exists() {
+        return Ok(Vec::new());
+    }
+    let file = chat_file(&chat_id)?;
+    let count = format!("--max-count={}", limit.unwrap_or(100));
+    let log = git(&dir, &["log", &count, "--format=%H%x1f%ct%x1f%s", "--", &file])?;
+    Ok(log
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\x1f');
+            Some(Revision {
+                hash: fields.next()?.to_string(),
+                timestamp: fields.next()?.parse().ok()?,
+                message: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
+}
+
+// The chat as it was at `revision`, without changing anything
+#[tauri::command]
+pub fn chat_at_revision(app: tauri::AppHandle, chat_id: String, revision: String) -> Result<serde_json::Value, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if !revision.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid revision: {}", revision));
+    }
+    let dir = chats::get_chats_dir(&app)?;
+    let git_path = find_in_path("git").ok_or("Git sync needs git installed")?;
+    let output = Command::new(git_path)
+        .current_dir(&dir)
+        .arg("show")
+        .arg(format!("{}:{}", revision, chat_file(&chat_id)?))
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("The chat isn't in revision {}", revision));
+    }
+    let content = String::from_utf8(encryption::open(output.stdout)?).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+// Brings back an earlier version as a new save, so history moves forward
+#[tauri::command]
+pub fn restore_chat_revision(app: tauri::AppHandle, chat_id: String, revision: String) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let session = chat_at_revision(app.clone(), chat_id, revision)?;
+    chats::write(&app, session)
+}
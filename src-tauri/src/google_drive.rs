@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+use tauri_plugin_http::reqwest::{RequestBuilder, StatusCode, Url};
+
+use crate::http;
+use crate::settings::SyncBackend;
+
+// Google Drive's appDataFolder: a hidden folder only this app can see, which
+// doesn't count against the files the user browses. Drive addresses files by
+// id and allows several with one name, so prepare() lists the folder once
+// and later calls look names up there. Signing in is in oauth.rs.
+
+const FILES: &str = "https://www.googleapis.com/drive/v3/files";
+const UPLOAD: &str = "https://www.googleapis.com/upload/drive/v3/files";
+const FOLDER: &str = "appDataFolder";
+
+pub struct GoogleDrive {
+    token: String,
+    // File name -> Drive file id
+    ids: Mutex<HashMap<String, String>>,
+}
+
+impl GoogleDrive {
+    pub fn new(token: String) -> Self {
+        Self { token, ids: Mutex::new(HashMap::new()) }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<(StatusCode, Vec<u8>), String> {
+        let response = http::send(request.bearer_auth(&self.token))
+            .await
+            .map_err(|e| format!("Failed to reach Google Drive: {}", e))?;
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED {
+            crate::oauth::forget_token(SyncBackend::GoogleDrive);
+            return Err("Google Drive turned down the sign-in; trying again later".to_string());
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err("Google Drive is limiting requests; trying again later".to_string());
+        }
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        Ok((status, body.to_vec()))
+    }
+
+    fn id(&self, name: &str) -> Option<String> {
+        self.ids.lock().ok()?.get(name).cloned()
+    }
+
+    pub async fn prepare(&self) -> Result<(), String> {
+        let mut ids = HashMap::new();
+        let mut page: Option<String> = None;
+        loop {
+            let mut url = Url::parse_with_params(
+                FILES,
+                [
+                    ("spaces", FOLDER),
+                    ("fields", "nextPageToken,files(id,name)"),
+                    ("pageSize", "1000"),
+                    // Newest first, so that's the one kept for a repeated name
+                    ("orderBy", "modifiedTime desc"),
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            if let Some(page) = &page {
+                url.query_pairs_mut().append_pair("pageToken", page);
+            }
+            let (status, body) = self.send(http::client().get(url)).await?;
+            if !status.is_success() {
+                return Err(format!("Failed to list the Google Drive folder: {}", status));
+            }
+            let listing: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+            for file in listing["files"].as_array().into_iter().flatten() {
+                if let (Some(id), Some(name)) = (file["id"].as_str(), file["name"].as_str()) {
+                    ids.entry(name.to_string()).or_insert_with(|| id.to_string());
+                }
+            }
+            match listing["nextPageToken"].as_str() {
+                Some(next) => page = Some(next.to_string()),
+                None => break,
+            }
+        }
+        *self.ids.lock().map_err(|e| e.to_string())? = ids;
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(id) = self.id(name) else {
+            return Ok(None);
+        };
+        let (status, body) = self.send(http::client().get(format!("{}/{}?alt=media", FILES, id))).await?;
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(body)),
+            status => Err(format!("Failed to download {}: {}", name, status)),
+        }
+    }
+
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        if let Some(id) = self.id(name) {
+            let request = http::client()
+                .patch(format!("{}/{}?uploadType=media", UPLOAD, id))
+                .header("Content-Type", "application/octet-stream")
+                .body(data);
+            let (status, _) = self.send(request).await?;
+            if !status.is_success() {
+                return Err(format!("Failed to upload {}: {}", name, status));
+            }
+            return Ok(());
+        }
+
+        // Metadata and content in one request
+        let boundary = format!("anchor-{}", crate::share::hex(&crate::encryption::random(16)?));
+        let metadata = json!({ "name": name, "parents": [FOLDER] });
+        let mut body = format!(
+            "--{0}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{1}\r\n\
+             --{0}\r\nContent-Type: application/octet-stream\r\n\r\n",
+            boundary, metadata
+        )
+        .into_bytes();
+        body.extend_from_slice(&data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        let request = http::client()
+            .post(format!("{}?uploadType=multipart&fields=id", UPLOAD))
+            .header("Content-Type", format!("multipart/related; boundary={}", boundary))
+            .body(body);
+        let (status, body) = self.send(request).await?;
+        if !status.is_success() {
+            return Err(format!("Failed to upload {}: {}", name, status));
+        }
+        let created: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+        let id = created["id"].as_str().ok_or("Google Drive didn't return the new file's id")?;
+        self.ids.lock().map_err(|e| e.to_string())?.insert(name.to_string(), id.to_string());
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        let Some(id) = self.id(name) else {
+            return Ok(());
+        };
+        let (status, _) = self.send(http::client().delete(format!("{}/{}", FILES, id))).await?;
+        if !(status.is_success() || status == StatusCode::NOT_FOUND) {
+            return Err(format!("Failed to delete {}: {}", name, status));
+        }
+        self.ids.lock().map_err(|e| e.to_string())?.remove(name);
+        Ok(())
+    }
+}
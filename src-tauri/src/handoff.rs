@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::Emitter;
+use tauri_plugin_http::reqwest::Url;
+
+use crate::{attachments, chats, encryption, imports, settings, share};
+
+// Moves a chat from the desktop to the mobile app. The desktop shows a QR
+// code holding a one-off LAN address and key; the phone scans it and fetches
+// the chat from there once. What goes over the network is encrypted with the
+// key, which only ever travels in the QR code, and the address stops
+// answering after one download or a few minutes.
+
+const SCHEME: &str = "anchor";
+const HOST: &str = "handoff";
+const KEY_LEN: usize = 32;
+const ID_LEN: usize = 12;
+const EXPIRY_MILLIS: u64 = 5 * 60 * 1000;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+// Attachments beyond this much in total stay on the desktop
+const MAX_ATTACHMENT_BYTES: u64 = 50 * 1024 * 1024;
+
+static ACTIVE: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Handoff {
+    pub id: String,
+    pub chat_id: String,
+    // What the QR code says, for showing as a link too
+    pub code: String,
+    pub qr_svg: String,
+    pub expires_at: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Finished {
+    id: String,
+    delivered: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    session: Value,
+    attachments: Vec<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct File {
+    id: String,
+    name: String,
+    // Base64
+    data: String,
+}
+
+fn cipher(key: &[u8]) -> Result<LessSafeKey, String> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key).map_err(|_| "Invalid handoff key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn payload(app: &tauri::AppHandle, chat_id: &str) -> Result<Vec<u8>, String> {
+    let session = chats::read(app, chat_id)?;
+    let mut files = Vec::new();
+    let mut total = 0;
+    let mut ids = attachments::referenced_by(&session);
+    ids.sort();
+    ids.dedup();
+    for id in ids {
+        let (Ok(attachment), Ok(path)) = (attachments::get(app, &id), attachments::path(app, &id)) else {
+            continue;
+        };
+        // Smaller ones after a skipped one can still fit
+        if total + attachment.size > MAX_ATTACHMENT_BYTES {
+            continue;
+        }
+        total += attachment.size;
+        let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", attachment.name, e))?;
+        files.push(File { id, name: attachment.name, data: STANDARD.encode(data) });
+    }
+    serde_json::to_vec(&Payload { session, attachments: files }).map_err(|e| e.to_string())
+}
+
+fn seal(key: &[u8], id: &str, mut data: Vec<u8>) -> Result<Vec<u8>, String> {
+    let nonce: [u8; NONCE_LEN] = encryption::random(NONCE_LEN)?.try_into().unwrap();
+    cipher(key)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(id.as_bytes()), &mut data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok([&nonce[..], &data].concat())
+}
+
+fn open(key: &[u8], id: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < NONCE_LEN {
+        return Err("The handoff was cut off".to_string());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce".to_string())?;
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = cipher(key)?
+        .open_in_place(nonce, Aad::from(id.as_bytes()), &mut ciphertext)
+        .map_err(|_| "The handoff couldn't be decrypted; scan the code again".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+// Answers until one download succeeds, it expires or it's cancelled
+fn serve(app: tauri::AppHandle, listener: TcpListener, id: String, body: Vec<u8>, expires_at: u64) {
+    let Some(stop) = ACTIVE.lock().ok().and_then(|active| active.as_ref()?.get(&id).cloned()) else {
+        return;
+    };
+    let mut delivered = false;
+    while !delivered && !stop.load(Ordering::SeqCst) && (chats::now_millis() as u64) < expires_at {
+        match listener.accept() {
+            Ok((stream, from)) => {
+                crate::audit::record("network", &format!("chat handoff to {}", from.ip()));
+                match share::respond(stream, &id, "application/octet-stream", &body) {
+                    Ok(served) => delivered = served,
+                    Err(e) => log::debug!("Failed to answer a handoff request from {}: {}", from, e),
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::warn!("The handoff server stopped: {}", e);
+                break;
+            }
+        }
+    }
+    if let Ok(mut active) = ACTIVE.lock() {
+        if let Some(active) = active.as_mut() {
+            active.remove(&id);
+        }
+    }
+    let _ = app.emit("chat-handoff-finished", Finished { id, delivered });
+}
+
+#[tauri::command]
+pub async fn start_handoff(app: tauri::AppHandle, chat_id: String) -> Result<Handoff, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if settings::load(&app)?.offline_mode {
+        return Err("Offline mode is on, so chats can't be sent to another device".to_string());
+    }
+    let handle = app.clone();
+    let chat = chat_id.clone();
+    let plaintext = tauri::async_runtime::spawn_blocking(move || payload(&handle, &chat))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let key = encryption::random(KEY_LEN)?;
+    let id = share::hex(&encryption::random(ID_LEN)?);
+    let body = seal(&key, &id, plaintext)?;
+    let host = share::lan_address()?;
+    let listener =
+        TcpListener::bind(SocketAddr::new(host, 0)).map_err(|e| format!("Failed to start the handoff: {}", e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+
+    let mut code = Url::parse(&format!("{}://{}", SCHEME, HOST)).map_err(|e| e.to_string())?;
+    code.query_pairs_mut()
+        .append_pair("addr", &addr.to_string())
+        .append_pair("id", &id)
+        .append_pair("key", &URL_SAFE_NO_PAD.encode(&key));
+    let code = code.to_string();
+    let handoff = Handoff {
+        id: id.clone(),
+        chat_id,
+        qr_svg: crate::qr::svg(&code)?,
+        code,
+        expires_at: chats::now_millis() as u64 + EXPIRY_MILLIS,
+    };
+
+    ACTIVE
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get_or_insert_with(HashMap::new)
+        .insert(id.clone(), Arc::new(AtomicBool::new(false)));
+    let (server_app, expires_at) = (app.clone(), handoff.expires_at);
+    std::thread::spawn(move || serve(server_app, listener, id, body, expires_at));
+    crate::audit::record("share", &format!("{} offered for handoff on {}", handoff.chat_id, addr));
+    Ok(handoff)
+}
+
+#[tauri::command]
+pub fn cancel_handoff(handoff_id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut active = ACTIVE.lock().map_err(|e| e.to_string())?;
+    let stop = active.as_mut().and_then(|a| a.remove(&handoff_id)).ok_or("That handoff has already ended")?;
+    stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+// The receiving side: takes the scanned code, fetches the chat and saves it
+// along with its attachments. Returns the chat's id.
+#[tauri::command]
+pub async fn receive_handoff(app: tauri::AppHandle, code: String) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let code = Url::parse(code.trim()).map_err(|_| "That isn't an Anchor handoff code".to_string())?;
+    if code.scheme() != SCHEME || code.host_str() != Some(HOST) {
+        return Err("That isn't an Anchor handoff code".to_string());
+    }
+    let query: HashMap<String, String> = code.query_pairs().into_owned().collect();
+    let (Some(addr), Some(id), Some(key)) = (query.get("addr"), query.get("id"), query.get("key")) else {
+        return Err("The handoff code is incomplete".to_string());
+    };
+    let addr: SocketAddr = addr.parse().map_err(|_| "The handoff code is incomplete".to_string())?;
+    let key = URL_SAFE_NO_PAD.decode(key).map_err(|_| "The handoff code is incomplete".to_string())?;
+
+    let request = crate::http::client().get(format!("http://{}/{}", addr, id)).timeout(FETCH_TIMEOUT);
+    let response = crate::http::send(request)
+        .await
+        .map_err(|e| format!("Failed to reach the other device: {}", e))?;
+    if !response.status().is_success() {
+        return Err("The handoff has expired or was already used".to_string());
+    }
+    let body = response.bytes().await.map_err(|e| format!("Failed to download the chat: {}", e))?;
+    let payload: Payload = serde_json::from_slice(&open(&key, id, &body)?).map_err(|e| e.to_string())?;
+
+    tauri::async_runtime::spawn_blocking(move || save(&app, payload))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn save(app: &tauri::AppHandle, payload: Payload) -> Result<String, String> {
+    let Payload { session, attachments: files } = payload;
+    // Landed like an import: under an id of its own, so it never overwrites
+    // a chat here, and without the other device's sync and gist state
+    let mut session = imports::clean(session).ok_or("The handed-off chat is damaged")?;
+    let mut count: HashMap<String, usize> = HashMap::new();
+    for id in attachments::referenced_by(&session) {
+        *count.entry(id).or_default() += 1;
+    }
+    let mut renamed = HashMap::new();
+    for file in files {
+        let data = STANDARD.decode(&file.data).map_err(|e| e.to_string())?;
+        // One reference per message that points at it
+        for _ in 0..count.get(&file.id).copied().unwrap_or(0) {
+            let source = attachments::AttachmentSource::Bytes(data.clone());
+            let saved = attachments::save(app, source, Some(file.name.clone()))?;
+            // Stripping metadata on this side changes the content, and the id
+            if saved.id != file.id {
+                renamed.insert(file.id.clone(), saved.id);
+            }
+        }
+    }
+    for message in session["messages"].as_array_mut().into_iter().flatten() {
+        for image in message["images"].as_array_mut().into_iter().flatten() {
+            if let Some(new) = image["attachmentId"].as_str().and_then(|id| renamed.get(id)) {
+                image["attachmentId"] = Value::String(new.clone());
+            }
+        }
+        for id in message["attachments"].as_array_mut().into_iter().flatten() {
+            if let Some(new) = id.as_str().and_then(|old| renamed.get(old)) {
+                *id = Value::String(new.clone());
+            }
+        }
+    }
+    session["id"] = Value::String(imports::fresh_id(app)?);
+    chats::write(app, session)
+}
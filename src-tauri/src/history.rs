@@ -0,0 +1,308 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tauri::{Manager, State};
+
+use crate::chats;
+use crate::completion::{ChatMessage, CompletionRequest};
+use crate::embeddings::{self, EmbeddingState};
+use crate::incognito;
+use crate::retrieval::escape_attribute;
+use crate::settings::{self, Settings};
+use crate::vectors::{self, VectorRecord};
+
+// Past exchanges recalled per turn
+const RECALL_K: usize = 3;
+// Less similar exchanges are rarely about the same thing
+const MIN_SIMILARITY: f32 = 0.4;
+// Longer exchanges are cut; the start usually says what it was about
+const MAX_EXCHANGE_CHARS: usize = 1500;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recollection {
+    pub chat_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // Millis since the epoch, when the chat has a timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    pub similarity: f32,
+    pub text: String,
+}
+
+// One collection per embedding model, since vectors from different models
+// don't compare. Switching models starts over; see reindex_chat_history.
+fn collection_name(settings: &Settings) -> String {
+    let digest = Sha256::digest(settings.embedding_model.as_bytes());
+    let hex: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("history_{}", hex)
+}
+
+// Chats are remembered unless turned off for them with set_chat_history_memory
+fn remembered(session: &Value) -> bool {
+    session["historyMemory"].as_bool().unwrap_or(true)
+}
+
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+// Each user message with the reply that ended its turn, skipping injected
+// system messages and tool traffic
+fn exchanges(messages: &[ChatMessage]) -> Vec<String> {
+    let mut exchanges = Vec::new();
+    let mut question: Option<&str> = None;
+    let mut answer: Option<&str> = None;
+    for message in messages {
+        match message.role.as_str() {
+            "user" => {
+                if let (Some(q), Some(a)) = (question, answer) {
+                    exchanges.push(format!("User: {}\nAssistant: {}", q.trim(), a.trim()));
+                }
+                question = Some(&message.content);
+                answer = None;
+            }
+            "assistant" if !message.content.trim().is_empty() => answer = Some(&message.content),
+            _ => {}
+        }
+    }
+    if let (Some(q), Some(a)) = (question, answer) {
+        exchanges.push(format!("User: {}\nAssistant: {}", q.trim(), a.trim()));
+    }
+    exchanges.into_iter().map(|e| truncate(&e, MAX_EXCHANGE_CHARS).to_string()).collect()
+}
+
+// Same text, same id, so exchanges already indexed are skipped
+fn exchange_id(chat_id: &str, text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}_{}", chat_id, hex)
+}
+
+fn chat_filter(chat_id: &str) -> Map<String, Value> {
+    let mut filter = Map::new();
+    filter.insert("chatId".to_string(), Value::String(chat_id.to_string()));
+    filter
+}
+
+// Brings the chat's vectors in line with its transcript: new exchanges are
+// embedded and ones that were edited away or regenerated are dropped.
+// Returns the number of exchanges embedded.
+async fn index(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    settings: &Settings,
+    chat_id: &str,
+    messages: &[ChatMessage],
+) -> Result<usize, String> {
+    let collection = collection_name(settings);
+    let session = chats::read(app, chat_id).unwrap_or(Value::Null);
+    if !remembered(&session) || incognito::is_incognito(chat_id) {
+        return Ok(0);
+    }
+    let exchanges: Vec<(String, String)> =
+        exchanges(messages).into_iter().map(|text| (exchange_id(chat_id, &text), text)).collect();
+
+    let indexed = vectors::ids(app, &collection, &chat_filter(chat_id))?;
+    let stale: Vec<String> = indexed.iter().filter(|id| !exchanges.iter().any(|(e, _)| e == *id)).cloned().collect();
+    let new: Vec<(String, String)> = exchanges.into_iter().filter(|(id, _)| !indexed.contains(id)).collect();
+    if !stale.is_empty() {
+        vectors::delete(app, &collection, &stale)?;
+    }
+    if new.is_empty() {
+        return Ok(0);
+    }
+
+    let texts = new.iter().map(|(_, text)| text.clone()).collect();
+    let embedded = embeddings::embed_texts(app, state, texts, Some(settings.embedding_model.clone())).await?;
+    let records = new
+        .into_iter()
+        .zip(embedded.vectors)
+        .map(|((id, text), vector)| {
+            let mut metadata = chat_filter(chat_id);
+            if let Some(title) = session["title"].as_str() {
+                metadata.insert("title".to_string(), json!(title));
+            }
+            if let Some(timestamp) = session["timestamp"].as_f64() {
+                metadata.insert("timestamp".to_string(), json!(timestamp as u64));
+            }
+            metadata.insert("text".to_string(), Value::String(text));
+            VectorRecord { id, vector, metadata }
+        })
+        .collect::<Vec<_>>();
+    let count = records.len();
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || vectors::upsert(&app_handle, &collection, records))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(count)
+}
+
+// Indexes the finished turn in the background when history memory is on
+pub fn schedule(app: &tauri::AppHandle, settings: &Settings, chat_id: String, messages: Vec<ChatMessage>) {
+    if !settings.chat_history_memory {
+        return;
+    }
+    let (app, settings) = (app.clone(), settings.clone());
+    tauri::async_runtime::spawn(async move {
+        crate::power::wait_for_indexing().await;
+        let state = app.state::<EmbeddingState>();
+        if let Err(e) = index(&app, &state, &settings, &chat_id, &messages).await {
+            log::warn!("Failed to index {} for history memory: {}", chat_id, e);
+        }
+    });
+}
+
+// Drops the chat's exchanges from the index, for deleted or opted-out chats.
+// Every model's collection, not just the current one, since switching back
+// would otherwise bring them back.
+pub fn forget(app: &tauri::AppHandle, chat_id: &str) {
+    let names = match vectors::collections(app) {
+        Ok(names) => names,
+        Err(e) => {
+            log::warn!("Failed to forget {} in history memory: {}", chat_id, e);
+            return;
+        }
+    };
+    for name in names.iter().filter(|name| name.starts_with("history_")) {
+        if let Err(e) = vectors::delete_matching(app, name, &chat_filter(chat_id)) {
+            log::warn!("Failed to forget {} in history memory: {}", chat_id, e);
+        }
+    }
+}
+
+fn recall_message(recollections: &[Recollection]) -> ChatMessage {
+    let mut content = String::from(
+        "Excerpts from earlier conversations with the user that may relate to the next message. Refer to \
+         them when relevant, e.g. to stay consistent with what was decided before.",
+    );
+    for recollection in recollections {
+        let title = recollection.title.as_deref().unwrap_or("Untitled chat");
+        let date = recollection
+            .timestamp
+            .and_then(|t| chrono::DateTime::from_timestamp_millis(t as i64))
+            .map(|d| format!(" date=\"{}\"", d.format("%Y-%m-%d")))
+            .unwrap_or_default();
+        let attributes = format!("title=\"{}\"{}", escape_attribute(title), date);
+        content.push_str(&format!("\n\n<conversation {}>\n{}\n</conversation>", attributes, recollection.text));
+    }
+    ChatMessage::new("system", content)
+}
+
+// Recalls exchanges from other chats related to the latest user message and
+// puts them in a system message right before it. Problems are logged rather
+// than failing the turn.
+pub async fn apply(app: &tauri::AppHandle, settings: &Settings, request: &mut CompletionRequest) -> Vec<Recollection> {
+    if !settings.chat_history_memory {
+        return Vec::new();
+    }
+    let chat_id = request.chat_id.clone().unwrap_or_default();
+    if chats::read(app, &chat_id).is_ok_and(|session| !remembered(&session)) {
+        return Vec::new();
+    }
+    let Some(position) = request.messages.iter().rposition(|m| m.role == "user") else {
+        return Vec::new();
+    };
+
+    let query = request.messages[position].content.clone();
+    match recall(app, settings, &chat_id, &query).await {
+        Ok(recollections) if !recollections.is_empty() => {
+            request.messages.insert(position, recall_message(&recollections));
+            recollections
+        }
+        Ok(recollections) => recollections,
+        Err(e) => {
+            log::warn!("History memory recall failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+async fn recall(
+    app: &tauri::AppHandle,
+    settings: &Settings,
+    chat_id: &str,
+    query: &str,
+) -> Result<Vec<Recollection>, String> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let state = app.state::<EmbeddingState>();
+    let texts = vec![query.to_string()];
+    let embedded = embeddings::embed_texts(app, &state, texts, Some(settings.embedding_model.clone())).await?;
+    let vector = embedded.vectors.into_iter().next().ok_or("Embedding returned no vector")?;
+
+    let (app_handle, collection) = (app.clone(), collection_name(settings));
+    // Extra candidates make up for hits from the current chat
+    let hits = tauri::async_runtime::spawn_blocking(move || {
+        vectors::search(&app_handle, &collection, vector, RECALL_K * 4, &Map::new())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .unwrap_or_default();
+    Ok(hits
+        .into_iter()
+        .filter(|hit| hit.score >= MIN_SIMILARITY && hit.metadata["chatId"].as_str() != Some(chat_id))
+        .take(RECALL_K)
+        .map(|hit| Recollection {
+            chat_id: hit.metadata["chatId"].as_str().unwrap_or_default().to_string(),
+            title: hit.metadata["title"].as_str().map(str::to_string),
+            timestamp: hit.metadata["timestamp"].as_u64(),
+            similarity: hit.score,
+            text: hit.metadata["text"].as_str().unwrap_or_default().to_string(),
+        })
+        .collect())
+}
+
+// Opts a chat out of history memory (or back in). Opted-out chats are
+// neither indexed nor given recollections of others.
+#[tauri::command]
+pub async fn set_chat_history_memory(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    chat_id: String,
+    enabled: bool,
+) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut session = chats::read(&app, &chat_id)?;
+    session["historyMemory"] = json!(enabled);
+    let messages = chats::messages(&session);
+    chats::write(&app, session)?;
+    if !enabled {
+        forget(&app, &chat_id);
+        return Ok(());
+    }
+    let settings = settings::load(&app)?;
+    if settings.chat_history_memory {
+        index(&app, &state, &settings, &chat_id, &messages).await?;
+    }
+    Ok(())
+}
+
+// Rebuilds the index from every stored chat, e.g. after turning history
+// memory on or switching embedding models. Returns the exchanges indexed.
+#[tauri::command]
+pub async fn reindex_chat_history(app: tauri::AppHandle, state: State<'_, EmbeddingState>) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let settings = settings::load(&app)?;
+    vectors::drop_collection(&app, &collection_name(&settings))?;
+    let mut count = 0;
+    for session in chats::list_chats(app.clone())? {
+        let Some(chat_id) = session["id"].as_str() else {
+            continue;
+        };
+        match index(&app, &state, &settings, chat_id, &chats::messages(&session)).await {
+            Ok(indexed) => count += indexed,
+            Err(e) => log::warn!("Failed to index {} for history memory: {}", chat_id, e),
+        }
+    }
+    Ok(count)
+}
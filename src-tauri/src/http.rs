@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri_plugin_http::reqwest::{self, redirect, Url};
+
+use crate::settings::Settings;
+
+// Some gateways reject unknown clients, so keep the UA the webview fetches used.
+const USER_AGENT: &str = "curl/8.7.1";
+const MAX_REDIRECTS: usize = 10;
+
+// Hosts requests may go to, None when the allowlist is off. Kept up to date
+// by configure, since most callers have no settings at hand.
+static ALLOWED_HOSTS: RwLock<Option<Vec<String>>> = RwLock::new(None);
+// Offline mode: nothing but loopback
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HttpError {
+    // The host isn't on the network allowlist, so nothing was sent
+    Blocked { host: String },
+    // Offline mode is on and the host isn't on this machine
+    Offline { host: String },
+    Request { message: String },
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::Blocked { host } => write!(f, "{} is not on the network allowlist", host),
+            HttpError::Offline { host } => write!(f, "Offline mode is on, so {} can't be reached", host),
+            HttpError::Request { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(e: reqwest::Error) -> Self {
+        HttpError::Request { message: e.to_string() }
+    }
+}
+
+// Called at startup and whenever settings are saved. With the allowlist on,
+// the enabled providers' hosts are allowed along with the listed ones.
+pub fn configure(settings: &Settings) {
+    OFFLINE.store(settings.offline_mode, Ordering::Relaxed);
+    let allowlist = &settings.network_allowlist;
+    let hosts = allowlist.enabled.then(|| {
+        let providers = settings.providers.iter().filter(|p| p.enabled);
+        let provider_hosts = providers.filter_map(|p| Url::parse(&p.api_base()).ok()?.host_str().map(str::to_string));
+        let listed = allowlist.hosts.iter().map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty());
+        listed.chain(provider_hosts).collect()
+    });
+    if let Ok(mut allowed) = ALLOWED_HOSTS.write() {
+        *allowed = hosts;
+    }
+}
+
+fn host(url: &Url) -> String {
+    url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_lowercase()
+}
+
+fn is_loopback_host(host: &str) -> bool {
+    host == "localhost" || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+// Loopback is always reachable, even offline; nothing sent there leaves the
+// machine
+pub fn is_loopback(url: &Url) -> bool {
+    is_loopback_host(&host(url))
+}
+
+// For traffic that doesn't go through this client, like git. "example.com"
+// also covers its subdomains.
+pub fn check_host(host: &str) -> Result<(), HttpError> {
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+    if is_loopback_host(&host) {
+        return Ok(());
+    }
+    if OFFLINE.load(Ordering::Relaxed) {
+        return Err(HttpError::Offline { host });
+    }
+    let allowed = ALLOWED_HOSTS.read().map_err(|e| HttpError::Request { message: e.to_string() })?;
+    let Some(hosts) = allowed.as_ref() else {
+        return Ok(());
+    };
+    if hosts.iter().any(|h| host == *h || host.ends_with(&format!(".{}", h))) {
+        Ok(())
+    } else {
+        Err(HttpError::Blocked { host })
+    }
+}
+
+fn check(url: &Url) -> Result<(), HttpError> {
+    check_host(&host(url))
+}
+
+pub fn client() -> reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .connect_timeout(Duration::from_secs(10))
+                // Redirects are held to the allowlist too
+                .redirect(redirect::Policy::custom(|attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        return attempt.error("too many redirects");
+                    }
+                    match check(attempt.url()) {
+                        Ok(()) => attempt.follow(),
+                        Err(e) => attempt.error(e.to_string()),
+                    }
+                }))
+                .build()
+                .expect("failed to build http client")
+        })
+        .clone()
+}
+
+// Every outgoing request goes through here so it ends up in the audit log
+// and can't get past the allowlist
+pub async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, HttpError> {
+    send_audited(request, true).await
+}
+
+// Incognito chats' tool calls are checked all the same, but not recorded
+pub async fn send_audited(request: reqwest::RequestBuilder, audited: bool) -> Result<reqwest::Response, HttpError> {
+    let request = request.build()?;
+    if let Err(e) = check(request.url()) {
+        if audited {
+            crate::audit::record("network", &format!("Blocked {} {}", request.method(), e));
+        }
+        return Err(e);
+    }
+    if audited {
+        crate::audit::network(request.method().as_str(), request.url());
+    }
+    Ok(client().execute(request).await?)
+}
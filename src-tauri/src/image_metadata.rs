@@ -0,0 +1,137 @@
+use std::io::Cursor;
+
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+
+use crate::vision;
+
+// JPEG marker segments that carry EXIF/XMP (APP1), IPTC (APP13), comments and
+// vendor data. JFIF (APP0), ICC profiles (APP2) and Adobe's colour transform
+// (APP14) are kept since they change how the pixels look.
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..2]);
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        // Fill bytes before a marker
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        // Entropy-coded data follows the start of scan; copy the rest as-is
+        if marker == 0xDA || marker == 0xD9 {
+            out.extend_from_slice(&bytes[pos..]);
+            return Some(out);
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            out.extend_from_slice(&bytes[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > bytes.len() {
+            return None;
+        }
+        let payload = &bytes[pos + 4..end];
+        let keep = match marker {
+            0xE2 => payload.starts_with(b"ICC_PROFILE\0"),
+            0xE0 | 0xEE => true,
+            0xE1..=0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+    None
+}
+
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    const DROPPED: &[&[u8; 4]] = &[b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..8]);
+    let mut pos = 8;
+    while pos + 12 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().ok()?) as usize;
+        let end = pos.checked_add(12 + length).filter(|end| *end <= bytes.len())?;
+        let kind = &bytes[pos + 4..pos + 8];
+        if !DROPPED.iter().any(|d| &d[..] == kind) {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+        if kind == b"IEND" {
+            return Some(out);
+        }
+    }
+    None
+}
+
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    // VP8X feature flags for the chunks we drop
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..12]);
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind = &bytes[pos..pos + 4];
+        let length = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().ok()?) as usize;
+        // Chunks are padded to an even size
+        let end = pos.checked_add(8 + length + (length & 1))?.min(bytes.len());
+        if kind != b"EXIF" && kind != b"XMP " {
+            let start = out.len();
+            out.extend_from_slice(&bytes[pos..end]);
+            if kind == b"VP8X" && out.len() > start + 8 {
+                out[start + 8] &= !(EXIF_FLAG | XMP_FLAG);
+            }
+        }
+        pos = end;
+    }
+    let riff_size = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
+
+fn orientation(bytes: &[u8]) -> Orientation {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.orientation().ok())
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+// Removes GPS, camera and other embedded metadata without re-encoding. When
+// the EXIF data rotates the image, the rotation is applied to the pixels
+// first (which does re-encode) so the picture doesn't end up sideways.
+pub fn strip(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    let Ok(format) = image::guess_format(&bytes) else {
+        return Ok(bytes);
+    };
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP) {
+        return Ok(bytes);
+    }
+
+    let orientation = orientation(&bytes);
+    if orientation != Orientation::NoTransforms {
+        let mut img: DynamicImage = image::load_from_memory_with_format(&bytes, format)
+            .map_err(|e| format!("Failed to decode image: {}", e))?;
+        img.apply_orientation(orientation);
+        return Ok(vision::encode(&img)?.data);
+    }
+
+    let stripped = match format {
+        ImageFormat::Jpeg => strip_jpeg(&bytes),
+        ImageFormat::Png => strip_png(&bytes),
+        _ => strip_webp(&bytes),
+    };
+    // Files we can't walk are passed on untouched rather than refused
+    Ok(stripped.unwrap_or(bytes))
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use zip::ZipArchive;
+
+use crate::attachments::{self, AttachmentSource};
+use crate::{chats, sealed};
+
+// Reads chats back in: .anchorchat archives from exports.rs (attachments
+// included), a saved session's JSON, Markdown transcripts in the layout
+// exports.rs writes, and ChatGPT's conversations.json. Every chat comes in
+// as a new one, so importing never overwrites what's already here.
+
+// What a copy of a chat shouldn't carry over from where it was exported
+const DROPPED_KEYS: &[&str] = &[
+    "gist",
+    "conflictOf",
+    "conflictDevice",
+    "createdBy",
+    "updatedBy",
+    "crdtClock",
+    "deletedMessages",
+    // From before grants were kept outside the chat, see permissions.rs
+    "allowedTools",
+];
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub chat_ids: Vec<String>,
+    pub attachments: usize,
+}
+
+pub fn fresh_id(app: &tauri::AppHandle) -> Result<String, String> {
+    let dir = chats::get_chats_dir(app)?;
+    let mut millis = chats::now_millis();
+    while dir.join(format!("chat_{}.json", millis)).exists() {
+        millis += 1;
+    }
+    Ok(format!("chat_{}", millis))
+}
+
+pub fn clean(mut session: Value) -> Option<Value> {
+    let obj = session.as_object_mut()?;
+    obj.get("messages")?.as_array()?;
+    for key in DROPPED_KEYS {
+        obj.remove(*key);
+    }
+    for message in obj.get_mut("messages").and_then(Value::as_array_mut).into_iter().flatten() {
+        if let Some(message) = message.as_object_mut() {
+            message.remove("crdt");
+        }
+    }
+    Some(session)
+}
+
+// Points the session at attachments stored under a new id, which happens
+// when metadata is stripped from an image on the way in
+fn rename_attachments(session: &mut Value, renamed: &HashMap<String, String>) {
+    for message in session["messages"].as_array_mut().into_iter().flatten() {
+        for image in message["images"].as_array_mut().into_iter().flatten() {
+            if let Some(new) = image["attachmentId"].as_str().and_then(|id| renamed.get(id)) {
+                image["attachmentId"] = Value::String(new.clone());
+            }
+        }
+        for id in message["attachments"].as_array_mut().into_iter().flatten() {
+            if let Some(new) = id.as_str().and_then(|id| renamed.get(id)) {
+                *id = Value::String(new.clone());
+            }
+        }
+    }
+}
+
+fn archive(app: &tauri::AppHandle, path: &Path, report: &mut ImportReport) -> Result<Vec<Value>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut zip = ZipArchive::new(file)
+        .map_err(|_| "Not an Anchor archive; if it's password-protected, enter its password".to_string())?;
+    let mut read = |name: &str| -> Result<Vec<u8>, String> {
+        let mut entry = zip.by_name(name).map_err(|_| format!("The archive has no {}", name))?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        Ok(data)
+    };
+    let manifest: Value = serde_json::from_slice(&read("manifest.json")?).map_err(|e| e.to_string())?;
+    if manifest["format"] != "anchorchat" {
+        return Err("Not an Anchor archive".to_string());
+    }
+    let version = manifest["version"].as_u64().unwrap_or(0);
+    if version > crate::exports::ARCHIVE_VERSION as u64 {
+        return Err("The archive is from a newer version of Anchor".to_string());
+    }
+    let session: Value = serde_json::from_slice(&read("chat.json")?).map_err(|e| e.to_string())?;
+    let mut session = clean(session).ok_or("The archive's chat is damaged")?;
+
+    let files = manifest["attachments"].as_array().cloned().unwrap_or_default();
+    let names: HashMap<&str, &str> =
+        files.iter().filter_map(|f| Some((f["id"].as_str()?, f["name"].as_str()?))).collect();
+    let mut renamed = HashMap::new();
+    // One stored reference per place the chat points at a file
+    for id in attachments::referenced_by(&session) {
+        let data = read(&format!("attachments/{}", id))?;
+        let name = names.get(id.as_str()).map(|n| n.to_string());
+        let attachment = attachments::save(app, AttachmentSource::Bytes(data), name)?;
+        report.attachments += 1;
+        if attachment.id != id {
+            renamed.insert(id, attachment.id);
+        }
+    }
+    rename_attachments(&mut session, &renamed);
+    Ok(vec![session])
+}
+
+fn chatgpt_text(message: &Value) -> String {
+    let content = &message["content"];
+    let parts = content["parts"].as_array().into_iter().flatten().filter_map(Value::as_str);
+    let text = parts.collect::<Vec<_>>().join("\n");
+    let text = if text.trim().is_empty() { content["text"].as_str().unwrap_or_default().to_string() } else { text };
+    text.trim().to_string()
+}
+
+// One conversation, following the branch that was last shown
+fn chatgpt(conversation: &Value) -> Option<Value> {
+    let mapping = conversation["mapping"].as_object()?;
+    let leaf = conversation["current_node"].as_str().map(str::to_string).or_else(|| {
+        let leaves = mapping.iter().filter(|(_, n)| n["children"].as_array().map_or(true, |c| c.is_empty()));
+        let created = |node: &Value| node["message"]["create_time"].as_f64().unwrap_or(0.0);
+        leaves.max_by(|a, b| created(a.1).total_cmp(&created(b.1))).map(|(id, _)| id.clone())
+    });
+    let mut chain = Vec::new();
+    let mut next = leaf;
+    while let Some(id) = next.filter(|_| chain.len() <= mapping.len()) {
+        let Some(node) = mapping.get(&id) else {
+            break;
+        };
+        chain.push(node);
+        next = node["parent"].as_str().map(str::to_string);
+    }
+    chain.reverse();
+
+    let millis = |seconds: &Value| seconds.as_f64().map(|s| (s * 1000.0) as u64);
+    let mut messages = Vec::new();
+    for node in chain {
+        let message = &node["message"];
+        let Some(role) = message["author"]["role"].as_str().filter(|r| matches!(*r, "user" | "assistant")) else {
+            continue;
+        };
+        let text = chatgpt_text(message);
+        if text.is_empty() || message["metadata"]["is_visually_hidden_from_conversation"] == true {
+            continue;
+        }
+        let mut entry = Map::new();
+        entry.insert("role".to_string(), json!(role));
+        entry.insert("content".to_string(), json!(text));
+        if let Some(time) = millis(&message["create_time"]) {
+            entry.insert("timestamp".to_string(), json!(time));
+        }
+        if let Some(model) = message["metadata"]["model_slug"].as_str() {
+            entry.insert("model".to_string(), json!(model));
+        }
+        messages.push(Value::Object(entry));
+    }
+    if messages.is_empty() {
+        return None;
+    }
+    let title = conversation["title"].as_str().filter(|t| !t.trim().is_empty()).unwrap_or("ChatGPT conversation");
+    Some(json!({
+        "title": title,
+        "timestamp": millis(&conversation["create_time"]).unwrap_or(chats::now_millis() as u64),
+        "messages": messages,
+        "importedFrom": "chatgpt",
+    }))
+}
+
+// A transcript with a "# Title" and a "## User" / "## Assistant" heading over
+// each message
+fn markdown(text: &str) -> Option<Value> {
+    let mut title = None;
+    let mut messages: Vec<(String, Vec<&str>)> = Vec::new();
+    let mut fenced = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        if !fenced {
+            if let Some(heading) = line.strip_prefix("# ").filter(|_| title.is_none() && messages.is_empty()) {
+                title = Some(heading.trim().to_string());
+                continue;
+            }
+            let role = match line.trim_end() {
+                "## User" => Some("user"),
+                "## Assistant" => Some("assistant"),
+                "## System" => Some("system"),
+                "## Tool" => Some("tool"),
+                _ => None,
+            };
+            if let Some(role) = role {
+                messages.push((role.to_string(), Vec::new()));
+                continue;
+            }
+        }
+        if let Some((_, lines)) = messages.last_mut() {
+            lines.push(line);
+        }
+    }
+    if messages.is_empty() {
+        return None;
+    }
+    let now = chats::now_millis() as u64;
+    let messages: Vec<Value> = messages
+        .into_iter()
+        .map(|(role, lines)| json!({ "role": role, "content": lines.join("\n").trim(), "timestamp": now }))
+        .collect();
+    Some(json!({ "title": title.unwrap_or_default(), "timestamp": now, "messages": messages }))
+}
+
+// The chats in `path`, or None when it isn't a chat export
+fn sessions(app: &tauri::AppHandle, path: &Path, report: &mut ImportReport) -> Result<Option<Vec<Value>>, String> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "anchorchat" => archive(app, path, report).map(Some),
+        "json" => {
+            let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let Ok(value) = serde_json::from_str::<Value>(&content) else {
+                return Ok(None);
+            };
+            // ChatGPT exports a list of conversations; one on its own also works
+            if let Some(list) = value.as_array().filter(|l| l.first().is_some_and(|c| c["mapping"].is_object())) {
+                return Ok(Some(list.iter().filter_map(chatgpt).collect()));
+            }
+            if value["mapping"].is_object() {
+                return Ok(chatgpt(&value).map(|session| vec![session]));
+            }
+            Ok(clean(value).map(|session| vec![session]))
+        }
+        "md" | "markdown" => {
+            let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            Ok(markdown(&content).map(|session| vec![session]))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Imports the chats in `path`; None when it holds none Anchor can read.
+// Password-protected exports are opened with `password` first.
+pub fn import(app: &tauri::AppHandle, path: &Path, password: Option<&str>) -> Result<Option<ImportReport>, String> {
+    let mut report = ImportReport::default();
+    let found = match password {
+        Some(password) => {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let opened = crate::app_data_dir(app)?.join(format!("import_{}_{}", chats::now_millis(), name));
+            let result = sealed::decrypt_file(path, &opened, password)
+                .and_then(|_| sessions(app, &opened, &mut report));
+            let _ = fs::remove_file(&opened);
+            result?
+        }
+        None => sessions(app, path, &mut report)?,
+    };
+    let Some(found) = found else {
+        return Ok(None);
+    };
+    for mut session in found {
+        session["id"] = Value::String(fresh_id(app)?);
+        report.chat_ids.push(chats::write(app, session)?);
+    }
+    crate::audit::record("import", &format!("{} chats from {}", report.chat_ids.len(), path.display()));
+    Ok(Some(report))
+}
+
+#[tauri::command]
+pub async fn import_chats(
+    app: tauri::AppHandle,
+    path: String,
+    password: Option<String>,
+) -> Result<ImportReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let password = password.filter(|p| !p.is_empty());
+    let handle = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let report = import(&handle, Path::new(&path), password.as_deref())?;
+        report.ok_or_else(|| "No chats Anchor can read in that file".to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    crate::analytics::track(&app, "import_chats", result)
+}
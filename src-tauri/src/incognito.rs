@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_json::Value;
+use tauri::Manager;
+
+use crate::chats;
+
+// Chat ids with this prefix live only in memory; chats::read and
+// chats::write route them here instead of the chats directory
+const PREFIX: &str = "incognito_";
+// Ids of the attachments open incognito chats hold, so the ones left behind
+// when the app didn't get to close them are let go on the next start
+const HELD_FILE: &str = "incognito_attachments.json";
+
+static SESSIONS: Mutex<Option<HashMap<String, Value>>> = Mutex::new(None);
+
+pub fn is_incognito(chat_id: &str) -> bool {
+    chat_id.starts_with(PREFIX)
+}
+
+fn held_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join(HELD_FILE))
+}
+
+// Brings the held list on disk in line with the open chats
+fn save_held(app: &tauri::AppHandle) {
+    let held: Vec<String> = sessions().iter().flat_map(crate::attachments::referenced_by).collect();
+    let result = held_path(app).and_then(|path| {
+        if held.is_empty() {
+            return if path.exists() { fs::remove_file(&path).map_err(|e| e.to_string()) } else { Ok(()) };
+        }
+        let content = serde_json::to_string(&held).map_err(|e| e.to_string())?;
+        fs::write(&path, content).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        log::warn!("Failed to note the attachments of incognito chats: {}", e);
+    }
+}
+
+pub fn store(app: &tauri::AppHandle, chat_id: &str, session: Value) -> Result<(), String> {
+    let mut sessions = SESSIONS.lock().map_err(|e| e.to_string())?;
+    sessions.get_or_insert_with(HashMap::new).insert(chat_id.to_string(), session);
+    drop(sessions);
+    save_held(app);
+    Ok(())
+}
+
+// Before the first save there's nothing stored, so it reads as an empty chat
+pub fn read(chat_id: &str) -> Result<Value, String> {
+    let sessions = SESSIONS.lock().map_err(|e| e.to_string())?;
+    Ok(sessions
+        .as_ref()
+        .and_then(|s| s.get(chat_id))
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({ "id": chat_id, "messages": [] })))
+}
+
+fn take(chat_id: &str) -> Option<Value> {
+    SESSIONS.lock().ok()?.as_mut()?.remove(chat_id)
+}
+
+// Attachments are stored like any other, so the references the chat held
+// are dropped with it. Once nothing else holds them, the file and its
+// thumbnails and extracted text go too, see attachments::release.
+fn release_attachments(app: &tauri::AppHandle, ids: Vec<String>) {
+    for id in ids {
+        if let Err(e) = crate::attachments::release(app, &id) {
+            log::warn!("Failed to release attachment {}: {}", id, e);
+        }
+    }
+}
+
+pub fn remove(app: &tauri::AppHandle, chat_id: &str) {
+    if let Some(session) = take(chat_id) {
+        release_attachments(app, crate::attachments::referenced_by(&session));
+        save_held(app);
+    }
+}
+
+// Open incognito chats, so attachment GC leaves what they reference alone
+pub fn sessions() -> Vec<Value> {
+    SESSIONS.lock().map(|s| s.as_ref().map(|s| s.values().cloned().collect()).unwrap_or_default()).unwrap_or_default()
+}
+
+pub fn wipe(app: &tauri::AppHandle) {
+    let sessions = SESSIONS.lock().ok().and_then(|mut sessions| sessions.take()).unwrap_or_default();
+    release_attachments(app, sessions.values().flat_map(crate::attachments::referenced_by).collect());
+    save_held(app);
+}
+
+// Lets go of what incognito chats held when the app last quit without
+// closing them, e.g. after a crash
+pub fn init(app: &tauri::AppHandle) {
+    let Ok(path) = held_path(app) else {
+        return;
+    };
+    let Some(held) = fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str::<Vec<String>>(&c).ok()) else {
+        return;
+    };
+    release_attachments(app, held);
+    let _ = fs::remove_file(&path);
+}
+
+// Incognito chats don't outlive the main window; chat windows come and go
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if window.label() != "main" {
+        return;
+    }
+    if matches!(event, tauri::WindowEvent::CloseRequested { .. } | tauri::WindowEvent::Destroyed) {
+        wipe(window.app_handle());
+    }
+}
+
+// Starts a chat that's kept in memory only: streaming, tools and retrieval
+// work as usual, but it's never written to disk, indexed for history memory
+// or mined for memories, its tool calls aren't audited, and its attachments
+// go when it ends. Returns its id.
+#[tauri::command]
+pub fn start_incognito_chat() -> String {
+    format!("{}{}", PREFIX, chats::now_millis())
+}
+
+#[tauri::command]
+pub fn end_incognito_chat(app: tauri::AppHandle, chat_id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    if !is_incognito(&chat_id) {
+        return Err(format!("Not an incognito chat: {}", chat_id));
+    }
+    remove(&app, &chat_id);
+    Ok(())
+}
+
+// Saves an incognito chat as a regular one under a new id. It's marked
+// `fromIncognito`, which the sync rules can keep off other devices.
+#[tauri::command]
+pub fn keep_incognito_chat(app: tauri::AppHandle, chat_id: String) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if !is_incognito(&chat_id) {
+        return Err(format!("Not an incognito chat: {}", chat_id));
+    }
+    let mut session = read(&chat_id)?;
+    let obj = session.as_object_mut().ok_or("Invalid session format")?;
+    obj.insert("id".to_string(), Value::String(format!("chat_{}", chats::now_millis())));
+    obj.insert("fromIncognito".to_string(), Value::Bool(true));
+    let id = chats::write(&app, session)?;
+    // The saved chat holds the attachment references now
+    take(&chat_id);
+    save_held(&app);
+    Ok(id)
+}
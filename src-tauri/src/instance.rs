@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+// Only one Anchor runs at a time, so two processes never write the same chats
+// directory. Launching it again brings the running one to the front and
+// hands it what the new launch was given: files to open (a chat dropped on
+// the icon, say) and any other arguments. anchor:// links among them are
+// left to deep_link.rs. Arguments the app was started with wait in
+// take_launch_arguments until the UI has loaded.
+//
+// Anchor is also what opens .anchorchat files (and, when picked, exported
+// .json chats): those are imported and the window opens at the chat. While
+// the app is locked they're passed on to the UI instead, to import once it's
+// unlocked.
+//
+// The running app leaves its process id in the data directory, so the
+// command line (see cli.rs) can tell it's there and not write alongside it.
+
+const ARGUMENTS_EVENT: &str = "launch-arguments";
+const PID_FILE: &str = "instance.pid";
+// The files Anchor is registered for, see bundle.fileAssociations
+const CHAT_EXTENSIONS: &[&str] = &["anchorchat", "json"];
+
+static PENDING: Mutex<Option<LaunchArguments>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchArguments {
+    // Absolute paths of existing files
+    pub files: Vec<String>,
+    pub args: Vec<String>,
+}
+
+impl LaunchArguments {
+    fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.args.is_empty()
+    }
+}
+
+// Sorts a launch's arguments, without the program itself, into files and the
+// rest; relative paths are taken from the directory it was started in
+fn arguments(argv: &[String], cwd: &Path) -> LaunchArguments {
+    let mut launch = LaunchArguments::default();
+    for arg in argv.iter().skip(1) {
+        if arg.starts_with(&format!("{}:", crate::deep_link::SCHEME)) || arg == crate::autostart::HIDDEN_ARG {
+            continue;
+        }
+        let path = PathBuf::from(arg);
+        let path = if path.is_absolute() { path } else { cwd.join(path) };
+        if !arg.starts_with('-') && path.is_file() {
+            launch.files.push(path.to_string_lossy().into_owned());
+        } else {
+            launch.args.push(arg.clone());
+        }
+    }
+    launch
+}
+
+// Imports the chat files among `launch.files` and opens the last of them
+fn open_chats(app: &tauri::AppHandle, launch: &mut LaunchArguments, emit: bool) {
+    if crate::app_lock::is_locked() || crate::encryption::is_locked(app) {
+        return;
+    }
+    let mut opened = None;
+    launch.files.retain(|file| {
+        let path = Path::new(file);
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if !CHAT_EXTENSIONS.contains(&extension.as_str()) {
+            return true;
+        }
+        match crate::imports::import(app, path, None) {
+            Ok(Some(report)) => {
+                if let Some(id) = report.chat_ids.last() {
+                    opened = Some(id.clone());
+                }
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                log::warn!("Failed to open {}: {}", file, e);
+                true
+            }
+        }
+    });
+    if let Some(chat_id) = opened {
+        crate::deep_link::navigate(app, crate::deep_link::Navigation::OpenChat { chat_id }, emit);
+    }
+}
+
+fn hand_over(app: &tauri::AppHandle, mut launch: LaunchArguments, emit: bool) {
+    open_chats(app, &mut launch, emit);
+    if launch.is_empty() {
+        return;
+    }
+    if emit && app.get_webview_window("main").is_some() {
+        let _ = app.emit(ARGUMENTS_EVENT, launch);
+        return;
+    }
+    if let Ok(mut pending) = PENDING.lock() {
+        let waiting = pending.get_or_insert_with(LaunchArguments::default);
+        waiting.files.extend(launch.files);
+        waiting.args.extend(launch.args);
+    }
+}
+
+fn pid_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join(PID_FILE))
+}
+
+// Whether `pid` is a live process of this same program, so a stale file
+// whose id went to something else doesn't count
+fn is_anchor(pid: u32) -> bool {
+    let exe = std::env::current_exe().ok();
+    let Some(program) = exe.as_ref().and_then(|exe| exe.file_stem()).map(|s| s.to_string_lossy().to_lowercase())
+    else {
+        return false;
+    };
+    let pid = pid.to_string();
+    let filter = format!("PID eq {}", pid);
+    let (tool, args) = if cfg!(windows) {
+        ("tasklist", vec!["/FI", filter.as_str(), "/FO", "CSV", "/NH"])
+    } else {
+        ("ps", vec!["-p", pid.as_str(), "-o", "comm="])
+    };
+    let output = crate::transcription::find_in_path(tool).and_then(|tool| Command::new(tool).args(args).output().ok());
+    let Some(output) = output else {
+        return false;
+    };
+    let listed = String::from_utf8_lossy(&output.stdout).to_lowercase();
+    if cfg!(windows) {
+        // "anchor.exe","1234",...
+        return listed.starts_with(&format!("\"{}.exe\"", program));
+    }
+    // Linux cuts the name at 15 characters; macOS gives the whole path
+    let name = listed.trim().rsplit('/').next().unwrap_or_default().to_string();
+    output.status.success() && !name.is_empty() && program.starts_with(&name)
+}
+
+// Whether the app is running, for the command line
+pub fn running(app: &tauri::AppHandle) -> bool {
+    let pid = pid_path(app).ok().and_then(|path| fs::read_to_string(path).ok()).and_then(|pid| pid.trim().parse().ok());
+    pid.is_some_and(|pid| pid != std::process::id() && is_anchor(pid))
+}
+
+// What this launch was started with
+pub fn init(app: &tauri::AppHandle) {
+    match pid_path(app) {
+        Ok(path) => {
+            if let Err(e) = fs::write(path, std::process::id().to_string()) {
+                log::warn!("Failed to record the process id: {}", e);
+            }
+        }
+        Err(e) => log::warn!("Failed to record the process id: {}", e),
+    }
+    let argv: Vec<String> = std::env::args().collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    hand_over(app, arguments(&argv, &cwd), false);
+}
+
+// Another launch, which exits once this returns
+pub fn on_second_instance(app: &tauri::AppHandle, argv: Vec<String>, cwd: String) {
+    let open = app.get_webview_window("main").is_some();
+    if let Err(e) = crate::tray::show_window(app) {
+        log::warn!("{}", e);
+    }
+    hand_over(app, arguments(&argv, Path::new(&cwd)), open);
+}
+
+// macOS hands files opened with Anchor to the running app instead of
+// starting it with them
+pub fn on_run_event(app: &tauri::AppHandle, event: &tauri::RunEvent) {
+    if let tauri::RunEvent::Exit = event {
+        if let Ok(path) = pid_path(app) {
+            let _ = fs::remove_file(path);
+        }
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let tauri::RunEvent::Opened { urls } = event {
+        let files = urls.iter().filter(|url| url.scheme() == "file").filter_map(|url| url.to_file_path().ok());
+        let files = files.map(|path| path.to_string_lossy().into_owned()).collect();
+        let open = app.get_webview_window("main").is_some();
+        if let Err(e) = crate::tray::show_window(app) {
+            log::warn!("{}", e);
+        }
+        hand_over(app, LaunchArguments { files, args: Vec::new() }, open);
+    }
+}
+
+#[tauri::command]
+pub fn take_launch_arguments() -> Option<LaunchArguments> {
+    PENDING.lock().ok().and_then(|mut pending| pending.take())
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use tauri::window::{ProgressBarState, ProgressBarStatus};
+use tauri::Manager;
+
+// Work that outlives a click, kept track of so the dock or taskbar icon can
+// show it: a badge with how many chats have a reply on the way, and a
+// progress bar while a longer job runs (downloading the embedding model or
+// an update, re-embedding a knowledge base, indexing chats for system
+// search). Both are guards that take themselves off when dropped, so an
+// early return or an error never leaves a stale badge. Windows has no badge
+// count, only the progress bar; Linux shows both where libunity is around.
+
+const MAIN_WINDOW: &str = "main";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Shown {
+    // Tracked on Windows too, though there is no badge to show it on
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    badge: usize,
+    // 0-100, or None for no bar; jobs without a size yet show as busy
+    progress: Option<Option<u64>>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+// Chat id -> replies being generated for it
+static GENERATING: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+// Job id -> (done, total)
+static JOBS: Mutex<Option<HashMap<u64, (u64, u64)>>> = Mutex::new(None);
+static SHOWN: Mutex<Option<Shown>> = Mutex::new(None);
+
+pub struct Generation {
+    app: tauri::AppHandle,
+    chat_id: Option<String>,
+}
+
+pub struct Job {
+    app: tauri::AppHandle,
+    id: u64,
+}
+
+fn wanted() -> Shown {
+    let badge = GENERATING.lock().ok().and_then(|g| g.as_ref().map(|g| g.len())).unwrap_or(0);
+    let jobs: Vec<(u64, u64)> =
+        JOBS.lock().ok().and_then(|j| j.as_ref().map(|j| j.values().copied().collect())).unwrap_or_default();
+    let progress = (!jobs.is_empty()).then(|| {
+        // All the jobs as one bar
+        let (done, total) = jobs.iter().fold((0, 0), |(d, t), (done, total)| (d + done.min(total), t + total));
+        (total > 0).then(|| done * 100 / total)
+    });
+    Shown { badge, progress }
+}
+
+fn apply(app: &tauri::AppHandle, shown: Shown) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    #[cfg(not(target_os = "windows"))]
+    if let Err(e) = window.set_badge_count(Some(shown.badge as i64).filter(|&n| n > 0)) {
+        log::debug!("Failed to set the badge: {}", e);
+    }
+    let (status, progress) = match shown.progress {
+        None => (ProgressBarStatus::None, None),
+        Some(None) => (ProgressBarStatus::Indeterminate, None),
+        Some(Some(percent)) => (ProgressBarStatus::Normal, Some(percent)),
+    };
+    let state = ProgressBarState { status: Some(status), progress };
+    if let Err(e) = window.set_progress_bar(state) {
+        log::debug!("Failed to set the progress bar: {}", e);
+    }
+}
+
+// Only when something the icon shows moved, since downloads report often
+fn refresh(app: &tauri::AppHandle) {
+    let shown = wanted();
+    let changed = SHOWN.lock().is_ok_and(|mut last| last.replace(shown) != Some(shown));
+    if changed {
+        apply(app, shown);
+    }
+}
+
+// Chatless prompts (quick prompts, one-off completions) aren't counted
+pub fn generating(app: &tauri::AppHandle, chat_id: Option<&str>) -> Generation {
+    let chat_id = chat_id.filter(|id| !id.is_empty()).map(str::to_string);
+    if let Some(id) = &chat_id {
+        if let Ok(mut generating) = GENERATING.lock() {
+            *generating.get_or_insert_with(HashMap::new).entry(id.clone()).or_default() += 1;
+        }
+        refresh(app);
+    }
+    Generation { app: app.clone(), chat_id }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        let Some(id) = &self.chat_id else {
+            return;
+        };
+        if let Ok(mut generating) = GENERATING.lock() {
+            if let Some(generating) = generating.as_mut() {
+                if let Some(count) = generating.get_mut(id) {
+                    *count -= 1;
+                    if *count == 0 {
+                        generating.remove(id);
+                    }
+                }
+            }
+        }
+        refresh(&self.app);
+    }
+}
+
+// A job whose size isn't known yet, until progress says otherwise
+pub fn start(app: &tauri::AppHandle) -> Job {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mut jobs) = JOBS.lock() {
+        jobs.get_or_insert_with(HashMap::new).insert(id, (0, 0));
+    }
+    refresh(app);
+    Job { app: app.clone(), id }
+}
+
+impl Job {
+    pub fn progress(&self, done: u64, total: u64) {
+        if let Ok(mut jobs) = JOBS.lock() {
+            if let Some(job) = jobs.as_mut().and_then(|jobs| jobs.get_mut(&self.id)) {
+                *job = (done, total);
+            }
+        }
+        refresh(&self.app);
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if let Ok(mut jobs) = JOBS.lock() {
+            if let Some(jobs) = jobs.as_mut() {
+                jobs.remove(&self.id);
+            }
+        }
+        refresh(&self.app);
+    }
+}
+
+// The main window is made anew when it's reopened from the tray, without
+// what was set on the old one
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if window.label() == MAIN_WINDOW && matches!(event, tauri::WindowEvent::Focused(true)) {
+        apply(window.app_handle(), wanted());
+    }
+}
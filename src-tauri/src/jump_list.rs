@@ -0,0 +1,197 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// The recent chats, the same ones the tray lists, in the Dock icon's menu on
+// macOS and the taskbar jump list on Windows. Picking one opens the chat
+// through deep_link.rs: the jump list starts Anchor with anchor://chat/<id>,
+// which a running app receives from the new launch. The lists are rebuilt
+// shortly after the chats change and left empty while the app lock is on,
+// since Windows keeps a jump list around after the app quits.
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+
+// Set when the chats change, so the lists are rebuilt
+static STALE: AtomicBool = AtomicBool::new(true);
+
+pub fn chats_changed() {
+    STALE.store(true, Ordering::SeqCst);
+}
+
+#[cfg(target_os = "macos")]
+mod dock {
+    use std::cell::RefCell;
+    use std::sync::{Mutex, OnceLock};
+
+    use objc2::rc::Retained;
+    use objc2::runtime::{AnyObject, Imp, Sel};
+    use objc2::{define_class, ffi, msg_send, sel, MainThreadMarker, MainThreadOnly};
+    use objc2_app_kit::{NSApplication, NSMenu, NSMenuItem};
+    use objc2_foundation::{NSObject, NSString};
+
+    use crate::deep_link::{self, Navigation};
+
+    static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+    // The chat behind each item, by the item's tag
+    static IDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    thread_local! {
+        static MENU: RefCell<Option<Retained<NSMenu>>> = const { RefCell::new(None) };
+        // Items only hold their target weakly
+        static TARGET: RefCell<Option<Retained<DockTarget>>> = const { RefCell::new(None) };
+    }
+
+    define_class!(
+        #[unsafe(super(NSObject))]
+        #[thread_kind = MainThreadOnly]
+        #[name = "AnchorDockTarget"]
+        struct DockTarget;
+
+        impl DockTarget {
+            #[unsafe(method(openChat:))]
+            fn open_chat(&self, sender: &NSMenuItem) {
+                let id = IDS.lock().ok().and_then(|ids| ids.get(sender.tag() as usize).cloned());
+                if let (Some(chat_id), Some(app)) = (id, APP.get()) {
+                    deep_link::navigate(app, Navigation::OpenChat { chat_id }, true);
+                }
+            }
+        }
+    );
+
+    impl DockTarget {
+        fn new(mtm: MainThreadMarker) -> Retained<Self> {
+            let this = Self::alloc(mtm).set_ivars(());
+            unsafe { msg_send![super(this), init] }
+        }
+    }
+
+    // -[NSApplicationDelegate applicationDockMenu:], which the app's delegate
+    // doesn't answer on its own
+    extern "C-unwind" fn dock_menu(_this: &AnyObject, _cmd: Sel, _sender: &AnyObject) -> *mut NSMenu {
+        MENU.with(|menu| menu.borrow().as_ref().map_or(std::ptr::null_mut(), |m| Retained::as_ptr(m) as *mut NSMenu))
+    }
+
+    // On the main thread, once the app's delegate is set
+    pub fn install(app: &tauri::AppHandle) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            return;
+        };
+        let _ = APP.set(app.clone());
+        let Some(delegate) = NSApplication::sharedApplication(mtm).delegate() else {
+            log::warn!("No app delegate to add the Dock menu to");
+            return;
+        };
+        let object: &AnyObject = delegate.as_ref();
+        let dock_menu: extern "C-unwind" fn(&AnyObject, Sel, &AnyObject) -> *mut NSMenu = dock_menu;
+        let class = object.class() as *const _ as *mut _;
+        let added = unsafe {
+            let imp: Imp = std::mem::transmute(dock_menu);
+            ffi::class_addMethod(class, sel!(applicationDockMenu:), imp, c"@@:@".as_ptr())
+        };
+        if !added.as_bool() {
+            log::warn!("The app delegate already has a Dock menu");
+        }
+        TARGET.with(|target| *target.borrow_mut() = Some(DockTarget::new(mtm)));
+    }
+
+    // On the main thread
+    pub fn set(chats: &[(String, String)]) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            return;
+        };
+        let menu = NSMenu::new(mtm);
+        TARGET.with(|target| {
+            let target = target.borrow();
+            for (i, (_, title)) in chats.iter().enumerate() {
+                let item = unsafe {
+                    NSMenuItem::initWithTitle_action_keyEquivalent(
+                        NSMenuItem::alloc(mtm),
+                        &NSString::from_str(title),
+                        Some(sel!(openChat:)),
+                        &NSString::from_str(""),
+                    )
+                };
+                unsafe { item.setTarget(target.as_deref().map(|t| t as &AnyObject)) };
+                item.setTag(i as isize);
+                menu.addItem(&item);
+            }
+        });
+        if let Ok(mut ids) = IDS.lock() {
+            *ids = chats.iter().map(|(id, _)| id.clone()).collect();
+        }
+        MENU.with(|m| *m.borrow_mut() = Some(menu));
+    }
+}
+
+#[cfg(windows)]
+fn set_jump_list(chats: &[(String, String)]) -> Result<(), String> {
+    use windows::core::{Interface, HSTRING};
+    use windows::Win32::Storage::EnhancedStorage::PKEY_Title;
+    use windows::Win32::System::Com::StructuredStorage::PROPVARIANT;
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED};
+    use windows::Win32::UI::Shell::Common::{IObjectArray, IObjectCollection};
+    use windows::Win32::UI::Shell::PropertiesSystem::IPropertyStore;
+    use windows::Win32::UI::Shell::{
+        DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW, ShellLink,
+    };
+
+    let exe = HSTRING::from(std::env::current_exe().map_err(|e| e.to_string())?.as_os_str());
+    let e = |e: windows::core::Error| format!("Failed to update the jump list: {}", e);
+    unsafe {
+        // Already set up on this thread is fine too
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let list: ICustomDestinationList = CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER).map_err(e)?;
+        let mut slots = 0u32;
+        let _removed: IObjectArray = list.BeginList(&mut slots).map_err(e)?;
+        let items: IObjectCollection =
+            CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER).map_err(e)?;
+        for (id, title) in chats.iter().take(slots as usize) {
+            let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER).map_err(e)?;
+            link.SetPath(&exe).map_err(e)?;
+            link.SetArguments(&HSTRING::from(format!("{}://chat/{}", crate::deep_link::SCHEME, id))).map_err(e)?;
+            link.SetIconLocation(&exe, 0).map_err(e)?;
+            let properties: IPropertyStore = link.cast().map_err(e)?;
+            properties.SetValue(&PKEY_Title, &PROPVARIANT::from(title.as_str())).map_err(e)?;
+            properties.Commit().map_err(e)?;
+            items.AddObject(&link).map_err(e)?;
+        }
+        if !chats.is_empty() {
+            let items: IObjectArray = items.cast().map_err(e)?;
+            list.AppendCategory(&HSTRING::from("Recent Chats"), &items).map_err(e)?;
+        }
+        list.CommitList().map_err(e)
+    }
+}
+
+fn refresh(app: &tauri::AppHandle) {
+    let chats = if crate::app_lock::enabled(app) {
+        Vec::new()
+    } else {
+        crate::tray::recent_chats(app).unwrap_or_default()
+    };
+    #[cfg(target_os = "macos")]
+    if let Err(e) = app.run_on_main_thread(move || dock::set(&chats)) {
+        log::warn!("Failed to update the Dock menu: {}", e);
+    }
+    #[cfg(windows)]
+    if let Err(e) = set_jump_list(&chats) {
+        log::warn!("{}", e);
+    }
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let _ = chats;
+}
+
+// Called from setup, on the main thread
+pub fn init(app: &tauri::AppHandle) {
+    if !cfg!(any(target_os = "macos", windows)) {
+        return;
+    }
+    #[cfg(target_os = "macos")]
+    dock::install(app);
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        if STALE.swap(false, Ordering::SeqCst) {
+            refresh(&app);
+        }
+        std::thread::sleep(REFRESH_INTERVAL);
+    });
+}
@@ -0,0 +1,85 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Secrets live under this service name in the OS credential store
+const SERVICE: &str = "anchor";
+
+fn run(mut cmd: Command, input: Option<&str>) -> Result<String, String> {
+    cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to reach the OS keychain: {}", e))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("OS keychain refused: {}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The service and account reach the script as $env:ANCHOR_SERVICE and
+// $env:ANCHOR_ACCOUNT rather than being pasted into it
+fn powershell(script: &str, account: &str) -> Command {
+    let vault = "[void][Windows.Security.Credentials.PasswordVault,Windows.Security.Credentials,\
+                 ContentType=WindowsRuntime]; $v = New-Object Windows.Security.Credentials.PasswordVault;";
+    let mut cmd = Command::new("powershell");
+    cmd.args(["-NoProfile", "-NonInteractive", "-Command", &format!("{} {}", vault, script)]);
+    cmd.env("ANCHOR_SERVICE", SERVICE).env("ANCHOR_ACCOUNT", account);
+    cmd
+}
+
+// Uses the macOS keychain, the Windows credential vault, or the Secret
+// Service (via secret-tool) elsewhere. Secrets go through stdin where the
+// tool allows it, so they don't show up in the process list.
+pub fn store(account: &str, secret: &str) -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        // A trailing -w makes `security` ask for the secret, and again to
+        // confirm, instead of taking it as an argument
+        let mut cmd = Command::new("security");
+        cmd.args(["add-generic-password", "-U", "-s", SERVICE, "-a", account, "-w"]);
+        run(cmd, Some(&format!("{}\n{}\n", secret, secret))).map(|_| ())
+    } else if cfg!(windows) {
+        let script = "$s = [Console]::In.ReadToEnd(); $v.Add((New-Object \
+                      Windows.Security.Credentials.PasswordCredential($env:ANCHOR_SERVICE, $env:ANCHOR_ACCOUNT, $s)))";
+        run(powershell(script, account), Some(secret)).map(|_| ())
+    } else {
+        let mut cmd = Command::new("secret-tool");
+        cmd.args(["store", "--label", &format!("Anchor {}", account), "service", SERVICE, "account", account]);
+        run(cmd, Some(secret)).map(|_| ())
+    }
+}
+
+pub fn load(account: &str) -> Result<String, String> {
+    let secret = if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("security");
+        cmd.args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"]);
+        run(cmd, None)?
+    } else if cfg!(windows) {
+        let script = "$c = $v.Retrieve($env:ANCHOR_SERVICE, $env:ANCHOR_ACCOUNT); $c.RetrievePassword(); $c.Password";
+        run(powershell(script, account), None)?
+    } else {
+        let mut cmd = Command::new("secret-tool");
+        cmd.args(["lookup", "service", SERVICE, "account", account]);
+        run(cmd, None)?
+    };
+    if secret.is_empty() {
+        return Err(format!("No {} in the OS keychain", account));
+    }
+    Ok(secret)
+}
+
+pub fn delete(account: &str) -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("security");
+        cmd.args(["delete-generic-password", "-s", SERVICE, "-a", account]);
+        run(cmd, None).map(|_| ())
+    } else if cfg!(windows) {
+        let script = "$v.Remove($v.Retrieve($env:ANCHOR_SERVICE, $env:ANCHOR_ACCOUNT))";
+        run(powershell(script, account), None).map(|_| ())
+    } else {
+        let mut cmd = Command::new("secret-tool");
+        cmd.args(["clear", "service", SERVICE, "account", account]);
+        run(cmd, None).map(|_| ())
+    }
+}
@@ -0,0 +1,1137 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tauri::{Emitter, Manager, State};
+
+use crate::attachments::{self, AttachmentSource};
+use crate::chats::now_millis;
+use crate::code::CodeLanguage;
+use crate::crawler::{self, CrawlOptions, PageStatus};
+use crate::embeddings::{self, EmbeddingState};
+use crate::extraction::{self, Document};
+use crate::vectors::{self, VectorRecord};
+
+// Guards the kb.json/chunks.json read-modify-write cycles. Vectors live in
+// the collection named after the knowledge base id, see vectors.rs.
+static KB_LOCK: Mutex<()> = Mutex::new(());
+// Files queued or being indexed, per knowledge base
+static PENDING: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+// Knowledge bases whose vectors are being recomputed for a new model; other
+// ingestion waits for that, since it would embed with the old one
+static REEMBEDDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Roughly the 256 tokens the local embedding model reads per text
+const MAX_CHUNK_CHARS: usize = 1000;
+const CHUNK_OVERLAP_CHARS: usize = 150;
+// Texts per embedding call; provider APIs cap the inputs per request
+const EMBED_BATCH: usize = 128;
+// Picked up when walking a folder; single files may be anything extraction reads
+const FOLDER_EXTENSIONS: &[&str] = &["pdf", "docx", "xlsx", "pptx", "md", "markdown", "txt", "rst", "json", "log"];
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "dist", "build", "__pycache__", "venv"];
+// How often watched folders are checked for new, changed and deleted files
+const WATCH_INTERVAL: Duration = Duration::from_secs(30);
+pub const INGEST_EVENT: &str = "knowledge-ingest";
+pub const CRAWL_EVENT: &str = "knowledge-crawl";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    File,
+    Folder,
+    Website,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceDocument {
+    pub attachment_id: String,
+    pub name: String,
+    pub path: String,
+    pub chunks: usize,
+    // File state when it was indexed, to spot changes in watched folders
+    #[serde(default)]
+    pub modified: u64,
+    #[serde(default)]
+    pub size: u64,
+}
+
+// A file that couldn't be read; retried once the file changes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceError {
+    pub path: String,
+    pub error: String,
+    #[serde(default)]
+    pub modified: u64,
+    #[serde(default)]
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    pub id: String,
+    pub kind: SourceKind,
+    pub path: String,
+    pub documents: Vec<SourceDocument>,
+    #[serde(default)]
+    pub errors: Vec<SourceError>,
+    // Folders re-scanned in the background, see schedule_watch
+    #[serde(default)]
+    pub watch: bool,
+    // How a website source was crawled, so a reindex crawls it the same way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crawl: Option<CrawlOptions>,
+    pub added_at: u64,
+}
+
+// A file or fetched page to index. `path` is the file path or URL.
+struct Input {
+    path: String,
+    content: AttachmentSource,
+    name: Option<String>,
+    modified: u64,
+    size: u64,
+}
+
+impl Input {
+    fn file(path: &Path) -> Self {
+        let (modified, size) = file_stamp(path);
+        let path = path.to_string_lossy().to_string();
+        Input { content: AttachmentSource::Path(path.clone()), path, name: None, modified, size }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnowledgeBase {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    // "local:<name>" or "<provider id>:<model>". Vectors from different models
+    // don't compare, so changing it re-embeds every chunk, see reembed.
+    pub embedding_model: String,
+    #[serde(default)]
+    pub dimensions: usize,
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    pub id: String,
+    pub source_id: String,
+    pub attachment_id: String,
+    pub name: String,
+    // The file the chunk was cut from
+    #[serde(default)]
+    pub path: String,
+    // Of the embedded text, see chunk_hash
+    #[serde(default)]
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestProgress {
+    pub kb_id: String,
+    pub source_id: String,
+    pub path: String,
+    // "indexing", "indexed", "removed" or "failed" per file, or
+    // "reembedding" with chunk counts while switching embedding models
+    pub status: &'static str,
+    pub done: usize,
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrawlProgress {
+    pub kb_id: String,
+    pub source_id: String,
+    #[serde(flatten)]
+    pub page: PageStatus,
+    pub fetched: usize,
+    pub max_pages: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrawlReport {
+    pub source: Source,
+    pub pages: Vec<PageStatus>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KbStatus {
+    pub id: String,
+    pub name: String,
+    pub embedding_model: String,
+    pub dimensions: usize,
+    pub sources: usize,
+    pub watched: usize,
+    pub documents: usize,
+    pub chunks: usize,
+    // Differs from `chunks` after an interrupted ingestion; reindex repairs it
+    pub vectors: usize,
+    pub failed: usize,
+    pub pending: usize,
+}
+
+fn timestamp() -> u64 {
+    now_millis() as u64
+}
+
+// Modification time in millis and size, or zeros when the file is gone
+fn file_stamp(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return (0, 0);
+    };
+    let modified = metadata.modified().ok().and_then(|m| m.duration_since(UNIX_EPOCH).ok());
+    (modified.map_or(0, |m| m.as_millis() as u64), metadata.len())
+}
+
+fn knowledge_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("knowledge");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create knowledge directory: {}", e))?;
+    }
+    Ok(dir)
+}
+
+fn kb_dir(app: &tauri::AppHandle, id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("Invalid knowledge base id: {}", id));
+    }
+    Ok(knowledge_dir(app)?.join(id))
+}
+
+pub fn get(app: &tauri::AppHandle, id: &str) -> Result<KnowledgeBase, String> {
+    let path = kb_dir(app, id)?.join("kb.json");
+    let content = fs::read_to_string(&path).map_err(|_| format!("Knowledge base not found: {}", id))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid knowledge base {}: {}", id, e))
+}
+
+fn write(app: &tauri::AppHandle, kb: &KnowledgeBase) -> Result<(), String> {
+    let dir = kb_dir(app, &kb.id)?;
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create knowledge base: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(kb).map_err(|e| e.to_string())?;
+    fs::write(dir.join("kb.json"), content).map_err(|e| format!("Failed to write knowledge base: {}", e))
+}
+
+pub fn load(app: &tauri::AppHandle) -> Result<Vec<KnowledgeBase>, String> {
+    let mut kbs = Vec::new();
+    for entry in fs::read_dir(knowledge_dir(app)?).map_err(|e| e.to_string())?.flatten() {
+        let id = entry.file_name().to_string_lossy().to_string();
+        match get(app, &id) {
+            Ok(kb) => kbs.push(kb),
+            Err(e) => log::warn!("Skipping knowledge base {}: {}", id, e),
+        }
+    }
+    kbs.sort_by_key(|kb| kb.created_at);
+    Ok(kbs)
+}
+
+pub fn chunks(app: &tauri::AppHandle, id: &str) -> Result<Vec<Chunk>, String> {
+    let path = kb_dir(app, id)?.join("chunks.json");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read(&path).map_err(|e| format!("Failed to read chunks: {}", e))?;
+    let content = crate::encryption::open(content)?;
+    serde_json::from_slice(&content).map_err(|e| format!("Invalid chunks file for {}: {}", id, e))
+}
+
+// Sealed while chat encryption is on, since chunks are document text
+fn write_chunks(app: &tauri::AppHandle, id: &str, chunks: &[Chunk]) -> Result<(), String> {
+    let content = serde_json::to_vec(chunks).map_err(|e| e.to_string())?;
+    let content = crate::encryption::seal(app, content)?;
+    // Through a temporary file, so an interruption never leaves half of them
+    let path = kb_dir(app, id)?.join("chunks.json");
+    let partial = path.with_extension("json.part");
+    fs::write(&partial, content)
+        .and_then(|_| crate::shred::replace(app, &partial, &path))
+        .map_err(|e| format!("Failed to write chunks: {}", e))?;
+    crate::retrieval::forget_index(id);
+    Ok(())
+}
+
+// Attachment id -> knowledge base id, once per document holding a reference
+pub fn references(app: &tauri::AppHandle) -> Result<Vec<(String, String)>, String> {
+    Ok(load(app)?
+        .into_iter()
+        .flat_map(|kb| {
+            let documents: Vec<String> =
+                kb.sources.iter().flat_map(|s| &s.documents).map(|d| d.attachment_id.clone()).collect();
+            documents.into_iter().map(move |id| (id, kb.id.clone()))
+        })
+        .collect())
+}
+
+fn hidden(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'))
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if hidden(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            let skipped = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| SKIPPED_DIRS.contains(&n));
+            if !skipped {
+                collect_files(&path, files)?;
+            }
+            continue;
+        }
+        let name = path.to_string_lossy();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        if FOLDER_EXTENSIONS.contains(&ext.as_str())
+            || CodeLanguage::from_name(&name).is_some()
+            || crate::tables::is_table(&name, attachments::mime_for(&name))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Splits on paragraphs, packing them up to MAX_CHUNK_CHARS. Paragraphs that
+// are too long on their own are cut at whitespace, and consecutive chunks
+// overlap a little so a sentence on the boundary is found from either side.
+pub fn split_text(text: &str) -> Vec<String> {
+    let mut pieces: Vec<&str> = Vec::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let mut rest = paragraph;
+        while rest.len() > MAX_CHUNK_CHARS {
+            let mut cut = MAX_CHUNK_CHARS;
+            while !rest.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let cut = rest[..cut].rfind(char::is_whitespace).filter(|c| *c > 0).unwrap_or(cut);
+            pieces.push(rest[..cut].trim());
+            rest = rest[cut..].trim_start();
+        }
+        pieces.push(rest);
+    }
+
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        if !current.is_empty() && current.len() + piece.len() + 2 > MAX_CHUNK_CHARS {
+            let overlap = overlap_tail(&current);
+            // Only when it still leaves room for the next piece
+            let overlap = if overlap.len() + piece.len() + 2 > MAX_CHUNK_CHARS { "" } else { overlap }.to_string();
+            chunks.push(std::mem::replace(&mut current, overlap));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(piece);
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn overlap_tail(text: &str) -> &str {
+    if text.len() <= CHUNK_OVERLAP_CHARS {
+        return "";
+    }
+    let mut start = text.len() - CHUNK_OVERLAP_CHARS;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    // Start on a word
+    match text[start..].find(char::is_whitespace) {
+        Some(space) => text[start + space..].trim_start(),
+        None => "",
+    }
+}
+
+fn hex_digest(input: &str) -> String {
+    Sha256::digest(input.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Changes whenever the text that gets embedded does
+fn chunk_hash(chunk: &Chunk) -> String {
+    hex_digest(&embedding_input(chunk))
+}
+
+// Derived from the file and the chunk's content (the occurrence tells repeated
+// text apart), so a chunk that survives an edit keeps its id and vector
+fn chunk_id(source_id: &str, path: &str, hash: &str, occurrence: usize) -> String {
+    format!("{}_{}", source_id, hex_digest(&format!("{}:{}:{}", path, hash, occurrence)))
+}
+
+// Code sections come out of the syntax-aware chunker already, so they're
+// kept whole; everything else is split into overlapping pieces.
+fn document_chunks(source_id: &str, path: &str, document: &Document) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for section in &document.sections {
+        let texts = if document.format == "code" { vec![section.text.clone()] } else { split_text(&section.text) };
+        for text in texts {
+            let mut chunk = Chunk {
+                id: String::new(),
+                source_id: source_id.to_string(),
+                attachment_id: document.attachment_id.clone(),
+                name: document.name.clone(),
+                path: path.to_string(),
+                hash: String::new(),
+                title: section.title.clone(),
+                page: section.page,
+                text,
+            };
+            chunk.hash = chunk_hash(&chunk);
+            let occurrence = occurrences.entry(chunk.hash.clone()).or_default();
+            chunk.id = chunk_id(source_id, path, &chunk.hash, *occurrence);
+            *occurrence += 1;
+            chunks.push(chunk);
+        }
+    }
+    chunks
+}
+
+// Stored with each vector so searches can be narrowed down
+fn chunk_metadata(chunk: &Chunk) -> Map<String, Value> {
+    let mut metadata = Map::new();
+    metadata.insert("sourceId".to_string(), Value::String(chunk.source_id.clone()));
+    metadata.insert("attachmentId".to_string(), Value::String(chunk.attachment_id.clone()));
+    metadata.insert("path".to_string(), Value::String(chunk.path.clone()));
+    metadata
+}
+
+// What the embedding model sees: the text with where it came from
+fn embedding_input(chunk: &Chunk) -> String {
+    match &chunk.title {
+        Some(title) => format!("{} - {}\n{}", chunk.name, title, chunk.text),
+        None => format!("{}\n{}", chunk.name, chunk.text),
+    }
+}
+
+pub fn create(
+    app: &tauri::AppHandle,
+    name: &str,
+    description: Option<String>,
+    embedding_model: Option<String>,
+) -> Result<KnowledgeBase, String> {
+    if name.trim().is_empty() {
+        return Err("Knowledge base name is required".to_string());
+    }
+    let settings = crate::settings::load(app)?;
+    let embedding_model = embedding_model.unwrap_or(settings.embedding_model.clone());
+    embeddings::validate_model(&settings, &embedding_model)?;
+    let now = timestamp();
+    let kb = KnowledgeBase {
+        id: format!("kb_{}", now_millis()),
+        name: name.trim().to_string(),
+        description: description.unwrap_or_default(),
+        embedding_model,
+        dimensions: 0,
+        sources: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    };
+    let _guard = KB_LOCK.lock().map_err(|e| e.to_string())?;
+    write(app, &kb)?;
+    Ok(kb)
+}
+
+fn emit(app: &tauri::AppHandle, progress: IngestProgress) {
+    let _ = app.emit(INGEST_EVENT, progress);
+}
+
+// Adds to (or with a negative `delta`, takes from) the pending file count
+fn track_pending(kb_id: &str, delta: isize) {
+    let Ok(mut pending) = PENDING.lock() else {
+        return;
+    };
+    let pending = pending.get_or_insert_with(HashMap::new);
+    let count = pending.entry(kb_id.to_string()).or_default();
+    *count = count.saturating_add_signed(delta);
+    if *count == 0 {
+        pending.remove(kb_id);
+    }
+}
+
+fn reembedding(kb_id: &str) -> bool {
+    REEMBEDDING.lock().is_ok_and(|ids| ids.iter().any(|id| id == kb_id))
+}
+
+fn pending(kb_id: &str) -> usize {
+    PENDING.lock().ok().and_then(|p| p.as_ref().and_then(|p| p.get(kb_id).copied())).unwrap_or(0)
+}
+
+// Copies a file or page into the attachment store, extracts and chunks it,
+// and writes the chunks' vectors to the knowledge base's collection. Also
+// returns the embedding size, 0 when there was no text.
+async fn ingest(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb: &KnowledgeBase,
+    source_id: &str,
+    input: Input,
+    indexed: &HashSet<String>,
+) -> Result<(SourceDocument, Vec<Chunk>, usize), String> {
+    let Input { path, content, name, modified, size } = input;
+    let app_handle = app.clone();
+    let document = tauri::async_runtime::spawn_blocking(move || {
+        let attachment = attachments::save(&app_handle, content, name)?;
+        extraction::extract(&app_handle, &attachment.id).inspect_err(|_| {
+            let _ = attachments::release(&app_handle, &attachment.id);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let chunks = document_chunks(source_id, &path, &document);
+    let dimensions = match embed_chunks(app, state, kb, &chunks, indexed).await {
+        Ok(dimensions) => dimensions,
+        Err(e) => {
+            let _ = attachments::release(app, &document.attachment_id);
+            return Err(e);
+        }
+    };
+    let source_document = SourceDocument {
+        attachment_id: document.attachment_id,
+        name: document.name,
+        path,
+        chunks: chunks.len(),
+        modified,
+        size,
+    };
+    Ok((source_document, chunks, dimensions))
+}
+
+// Embeds the chunks that don't have a vector yet. Ones already `indexed`
+// under the same id have the same text, so only their metadata is updated.
+async fn embed_chunks(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb: &KnowledgeBase,
+    chunks: &[Chunk],
+    indexed: &HashSet<String>,
+) -> Result<usize, String> {
+    let (kept, new): (Vec<&Chunk>, Vec<&Chunk>) = chunks.iter().partition(|c| indexed.contains(&c.id));
+    if !kept.is_empty() {
+        let entries = kept.iter().map(|c| (c.id.clone(), chunk_metadata(c))).collect();
+        let (app_handle, collection) = (app.clone(), kb.id.clone());
+        tauri::async_runtime::spawn_blocking(move || vectors::set_metadata(&app_handle, &collection, entries))
+            .await
+            .map_err(|e| e.to_string())??;
+    }
+    let mut records = Vec::with_capacity(new.len());
+    for batch in new.chunks(EMBED_BATCH) {
+        let inputs = batch.iter().map(|c| embedding_input(c)).collect();
+        let embedded = embeddings::embed_texts(app, state, inputs, Some(kb.embedding_model.clone())).await?;
+        for (chunk, vector) in batch.iter().zip(embedded.vectors) {
+            records.push(VectorRecord { id: chunk.id.clone(), vector, metadata: chunk_metadata(chunk) });
+        }
+    }
+    if !kept.is_empty() {
+        log::info!("{}: reused {} of {} chunk vectors", kb.name, kept.len(), chunks.len());
+    }
+    let Some(dimensions) = records.first().map(|r| r.vector.len()) else {
+        return Ok(0);
+    };
+    let (app_handle, collection) = (app.clone(), kb.id.clone());
+    tauri::async_runtime::spawn_blocking(move || vectors::upsert(&app_handle, &collection, records))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(dimensions)
+}
+
+// (Re-)indexes `changed` files of a source and drops `removed` ones, then
+// records the result. A file that fails keeps its previous version, if any.
+// The source is added to the knowledge base when it isn't part of it yet.
+async fn sync_source(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb_id: &str,
+    mut source: Source,
+    changed: Vec<Input>,
+    removed: Vec<String>,
+) -> Result<Source, String> {
+    if reembedding(kb_id) {
+        return Err("The knowledge base is switching embedding models, try again when it's done".to_string());
+    }
+    let kb = get(app, kb_id)?;
+    let total = changed.len();
+    let progress = |path: &str, status, done, error| IngestProgress {
+        kb_id: kb_id.to_string(),
+        source_id: source.id.clone(),
+        path: path.to_string(),
+        status,
+        done,
+        total,
+        error,
+    };
+
+    let mut dropped: HashSet<String> = HashSet::new();
+    let mut released = Vec::new();
+    let mut new_chunks = Vec::new();
+    let mut dimensions = 0;
+    let mut documents = std::mem::take(&mut source.documents);
+    let mut errors = std::mem::take(&mut source.errors);
+    for path in &removed {
+        emit(app, progress(path, "removed", 0, None));
+        dropped.insert(path.clone());
+    }
+    // Chunks of this source that already have vectors, from the index itself
+    // rather than chunks.json, which may list chunks whose vectors never
+    // made it in
+    let mut filter = Map::new();
+    filter.insert("sourceId".to_string(), Value::String(source.id.clone()));
+    let indexed: HashSet<String> = vectors::ids(app, kb_id, &filter)?.into_iter().collect();
+    track_pending(kb_id, total as isize);
+    for (done, input) in changed.into_iter().enumerate() {
+        let (path, modified, size) = (input.path.clone(), input.modified, input.size);
+        emit(app, progress(&path, "indexing", done, None));
+        errors.retain(|e| e.path != path);
+        let ingested = ingest(app, state, &kb, &source.id, input, &indexed).await;
+        track_pending(kb_id, -1);
+        match ingested {
+            Ok((document, chunks, embedded)) => {
+                dimensions = dimensions.max(embedded);
+                dropped.insert(path.clone());
+                new_chunks.extend(chunks);
+                documents.push(document);
+                emit(app, progress(&path, "indexed", done + 1, None));
+            }
+            Err(error) => {
+                emit(app, progress(&path, "failed", done + 1, Some(error.clone())));
+                errors.push(SourceError { path, error, modified, size });
+            }
+        }
+    }
+
+    // The newest entry per path wins; older ones are what was replaced
+    let mut latest: HashMap<String, usize> = HashMap::new();
+    for (index, document) in documents.iter().enumerate() {
+        latest.insert(document.path.clone(), index);
+    }
+    for (index, document) in documents.into_iter().enumerate() {
+        let replaced = latest.get(&document.path) != Some(&index);
+        if replaced || removed.contains(&document.path) {
+            released.push(document.attachment_id);
+        } else {
+            source.documents.push(document);
+        }
+    }
+    errors.retain(|e| !removed.contains(&e.path));
+    source.errors = errors;
+
+    let new_ids: HashSet<String> = new_chunks.iter().map(|c| c.id.clone()).collect();
+    let stale: Vec<String> = {
+        let _guard = KB_LOCK.lock().map_err(|e| e.to_string())?;
+        // Re-read in case other sources changed meanwhile
+        let mut kb = get(app, kb_id)?;
+        let mut stored = chunks(app, kb_id)?;
+        let is_dropped = |c: &Chunk| c.source_id == source.id && dropped.contains(&c.path);
+        let stale = stored.iter().filter(|c| is_dropped(c) && !new_ids.contains(&c.id)).map(|c| c.id.clone()).collect();
+        stored.retain(|c| !is_dropped(c));
+        stored.extend(new_chunks);
+        write_chunks(app, kb_id, &stored)?;
+
+        if kb.dimensions == 0 {
+            kb.dimensions = dimensions;
+        }
+        match kb.sources.iter_mut().find(|s| s.id == source.id) {
+            Some(existing) => *existing = source.clone(),
+            None => kb.sources.push(source.clone()),
+        }
+        kb.updated_at = timestamp();
+        write(app, &kb)?;
+        stale
+    };
+
+    if let Err(e) = vectors::delete(app, kb_id, &stale) {
+        log::warn!("Failed to delete old vectors from {}: {}", kb_id, e);
+    }
+    for id in released {
+        if let Err(e) = attachments::release(app, &id) {
+            log::warn!("Failed to release attachment {}: {}", id, e);
+        }
+    }
+    Ok(source)
+}
+
+// Copies the file or every supported file under the folder into the
+// attachment store, extracts, chunks and embeds them, and records the result.
+// Files that fail are listed on the source instead of failing the whole add.
+pub async fn add(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb_id: &str,
+    path: &str,
+    watch: bool,
+) -> Result<Source, String> {
+    let kb = get(app, kb_id)?;
+    let root = PathBuf::from(path);
+    if kb.sources.iter().any(|s| Path::new(&s.path) == root) {
+        return Err(format!("{} is already in {}", path, kb.name));
+    }
+    let kind = if root.is_dir() {
+        SourceKind::Folder
+    } else if root.is_file() {
+        SourceKind::File
+    } else {
+        return Err(format!("Not a file or folder: {}", path));
+    };
+    if watch && kind != SourceKind::Folder {
+        return Err("Only folders can be watched".to_string());
+    }
+    let files = tauri::async_runtime::spawn_blocking(move || {
+        let mut files = Vec::new();
+        match kind {
+            SourceKind::Folder => collect_files(&root, &mut files)?,
+            _ => files.push(root),
+        }
+        Ok::<_, String>(files.iter().map(|f| Input::file(f)).collect())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let source = Source {
+        id: format!("source_{}", now_millis()),
+        kind,
+        path: path.to_string(),
+        documents: Vec::new(),
+        errors: Vec::new(),
+        watch,
+        crawl: None,
+        added_at: timestamp(),
+    };
+    sync_source(app, state, kb_id, source, files, Vec::new()).await
+}
+
+// Crawls the site into the source and indexes the pages fetched. Pages that
+// were indexed before but aren't found again are dropped, unless fetching
+// them failed this time.
+async fn crawl_source(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb_id: &str,
+    mut source: Source,
+) -> Result<CrawlReport, String> {
+    let options = source.crawl.clone().unwrap_or_default();
+    let (crawled, pages) = crawler::crawl(&source.path, &options, |page, fetched| {
+        let progress = CrawlProgress {
+            kb_id: kb_id.to_string(),
+            source_id: source.id.clone(),
+            page: page.clone(),
+            fetched,
+            max_pages: options.max_pages,
+        };
+        let _ = app.emit(CRAWL_EVENT, progress);
+    })
+    .await?;
+
+    let fetched_at = timestamp();
+    let inputs: Vec<Input> = crawled
+        .into_iter()
+        .filter(|page| !page.markdown.trim().is_empty())
+        .map(|page| {
+            let content = format!("# {}\n\n{}", page.title, page.markdown);
+            let name = format!("{}.md", page.title.replace(['/', '\\'], "-"));
+            Input {
+                path: page.url,
+                size: content.len() as u64,
+                content: AttachmentSource::Bytes(content.into_bytes()),
+                name: Some(name),
+                modified: fetched_at,
+            }
+        })
+        .collect();
+    let seen: HashSet<&str> = inputs
+        .iter()
+        .map(|i| i.path.as_str())
+        .chain(pages.iter().filter(|p| p.status == "failed").map(|p| p.url.as_str()))
+        .collect();
+    let removed = source.documents.iter().map(|d| d.path.clone()).filter(|p| !seen.contains(p.as_str())).collect();
+
+    // Errors are from this crawl only
+    source.errors = pages
+        .iter()
+        .filter(|p| p.status == "failed")
+        .map(|p| SourceError {
+            path: p.url.clone(),
+            error: p.error.clone().unwrap_or_default(),
+            modified: fetched_at,
+            size: 0,
+        })
+        .collect();
+    let source = sync_source(app, state, kb_id, source, inputs, removed).await?;
+    Ok(CrawlReport { source, pages })
+}
+
+pub async fn add_website(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb_id: &str,
+    url: &str,
+    options: CrawlOptions,
+) -> Result<CrawlReport, String> {
+    let kb = get(app, kb_id)?;
+    let url = url.trim();
+    if kb.sources.iter().any(|s| s.path == url) {
+        return Err(format!("{} is already in {}", url, kb.name));
+    }
+    let source = Source {
+        id: format!("source_{}", now_millis()),
+        kind: SourceKind::Website,
+        path: url.to_string(),
+        documents: Vec::new(),
+        errors: Vec::new(),
+        watch: false,
+        crawl: Some(options),
+        added_at: timestamp(),
+    };
+    crawl_source(app, state, kb_id, source).await
+}
+
+// Files under a watched folder that are new or differ from when they were
+// indexed (or last failed), and indexed paths that no longer exist
+fn changes(source: &Source) -> Result<(Vec<Input>, Vec<String>), String> {
+    let mut files = Vec::new();
+    collect_files(Path::new(&source.path), &mut files)?;
+    let known: HashMap<&str, (u64, u64)> = source
+        .documents
+        .iter()
+        .map(|d| (d.path.as_str(), (d.modified, d.size)))
+        .chain(source.errors.iter().map(|e| (e.path.as_str(), (e.modified, e.size))))
+        .collect();
+
+    let present: HashSet<String> = files.iter().map(|f| f.to_string_lossy().to_string()).collect();
+    let changed = files
+        .iter()
+        .map(|file| Input::file(file))
+        .filter(|input| known.get(input.path.as_str()) != Some(&(input.modified, input.size)))
+        .collect();
+    let removed = known.keys().filter(|path| !present.contains(**path)).map(|p| p.to_string()).collect();
+    Ok((changed, removed))
+}
+
+fn scan_watched(app: &tauri::AppHandle) -> Result<(), String> {
+    let state = app.state::<EmbeddingState>();
+    for kb in load(app)? {
+        if reembedding(&kb.id) {
+            continue;
+        }
+        for source in kb.sources.into_iter().filter(|s| s.watch) {
+            if !Path::new(&source.path).is_dir() {
+                log::warn!("Watched folder {} is missing", source.path);
+                continue;
+            }
+            let (changed, removed) = changes(&source)?;
+            if changed.is_empty() && removed.is_empty() {
+                continue;
+            }
+            log::info!("{}: {} changed and {} removed files", source.path, changed.len(), removed.len());
+            let synced = tauri::async_runtime::block_on(sync_source(app, &state, &kb.id, source, changed, removed));
+            if let Err(e) = synced {
+                log::warn!("Failed to update {}: {}", kb.name, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks watched folders right away, which catches edits made while the app
+// was closed, and then every WATCH_INTERVAL unless power.rs holds indexing back.
+pub fn schedule_watch(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        if crate::power::indexing_paused() {
+            log::debug!("Watched folder scan waits to spare the battery");
+        } else if let Err(e) = scan_watched(&app) {
+            log::warn!("Watched folder scan failed: {}", e);
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    });
+}
+
+// Extracts and embeds every file of every source again, picking up edits to
+// the originals and chunking changes, and crawls websites again. Indexed
+// files that no longer exist are dropped.
+pub async fn reindex(app: &tauri::AppHandle, state: &EmbeddingState, kb_id: &str) -> Result<KbStatus, String> {
+    let kb = get(app, kb_id)?;
+    for source in kb.sources {
+        if source.kind == SourceKind::Website {
+            crawl_source(app, state, kb_id, source).await?;
+            continue;
+        }
+        let scanned = source.clone();
+        let files = tauri::async_runtime::spawn_blocking(move || {
+            let mut files = Vec::new();
+            match scanned.kind {
+                SourceKind::Folder => collect_files(Path::new(&scanned.path), &mut files)?,
+                _ => files.extend(Some(PathBuf::from(&scanned.path)).filter(|p| p.is_file())),
+            }
+            Ok::<Vec<Input>, String>(files.iter().map(|f| Input::file(f)).collect())
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        let files = match files {
+            Ok(files) => files,
+            Err(e) => {
+                log::warn!("Skipping {} while reindexing {}: {}", source.path, kb.name, e);
+                continue;
+            }
+        };
+        let present: HashSet<String> = files.iter().map(|f| f.path.clone()).collect();
+        let removed = source.documents.iter().map(|d| d.path.clone()).filter(|p| !present.contains(p)).collect();
+        sync_source(app, state, kb_id, source, files, removed).await?;
+    }
+    status(app, kb_id)
+}
+
+// Switches the knowledge base to another embedding model. Every stored
+// chunk is embedded with it first, so a failure part way leaves the old
+// vectors in place; then a collection of the new vectors is swapped in.
+pub async fn reembed(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    kb_id: &str,
+    model: &str,
+) -> Result<KnowledgeBase, String> {
+    let settings = crate::settings::load(app)?;
+    embeddings::validate_model(&settings, model)?;
+    let kb = get(app, kb_id)?;
+    if kb.embedding_model == model {
+        return Ok(kb);
+    }
+    {
+        let mut ids = REEMBEDDING.lock().map_err(|e| e.to_string())?;
+        if ids.iter().any(|id| id == kb_id) {
+            return Err(format!("{} is already switching embedding models", kb.name));
+        }
+        if pending(kb_id) > 0 {
+            return Err(format!("{} is still indexing files", kb.name));
+        }
+        ids.push(kb_id.to_string());
+    }
+    let result = reembed_chunks(app, state, kb, model).await;
+    if let Ok(mut ids) = REEMBEDDING.lock() {
+        ids.retain(|id| id != kb_id);
+    }
+    result
+}
+
+async fn reembed_chunks(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    mut kb: KnowledgeBase,
+    model: &str,
+) -> Result<KnowledgeBase, String> {
+    let chunks = chunks(app, &kb.id)?;
+    let progress = |done| IngestProgress {
+        kb_id: kb.id.clone(),
+        source_id: String::new(),
+        path: String::new(),
+        status: "reembedding",
+        done,
+        total: chunks.len(),
+        error: None,
+    };
+    let mut records = Vec::with_capacity(chunks.len());
+    let job = crate::jobs::start(app);
+    for batch in chunks.chunks(EMBED_BATCH) {
+        emit(app, progress(records.len()));
+        job.progress(records.len() as u64, chunks.len() as u64);
+        let inputs = batch.iter().map(embedding_input).collect();
+        let embedded = embeddings::embed_texts(app, state, inputs, Some(model.to_string())).await?;
+        for (chunk, vector) in batch.iter().zip(embedded.vectors) {
+            records.push(VectorRecord { id: chunk.id.clone(), vector, metadata: chunk_metadata(chunk) });
+        }
+    }
+    emit(app, progress(records.len()));
+
+    let dimensions = records.first().map_or(0, |r| r.vector.len());
+    let (app_handle, collection) = (app.clone(), kb.id.clone());
+    tauri::async_runtime::spawn_blocking(move || vectors::replace(&app_handle, &collection, records))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let _guard = KB_LOCK.lock().map_err(|e| e.to_string())?;
+    // Sources can't have changed meanwhile, but the name or description may have
+    kb = get(app, &kb.id)?;
+    log::info!("Re-embedded {} chunks of {} with {}", chunks.len(), kb.name, model);
+    kb.embedding_model = model.to_string();
+    kb.dimensions = dimensions;
+    kb.updated_at = timestamp();
+    write(app, &kb)?;
+    Ok(kb)
+}
+
+pub fn status(app: &tauri::AppHandle, kb_id: &str) -> Result<KbStatus, String> {
+    let kb = get(app, kb_id)?;
+    let chunks = chunks(app, kb_id)?.len();
+    let vectors = vectors::count(app, kb_id)?;
+    Ok(KbStatus {
+        sources: kb.sources.len(),
+        watched: kb.sources.iter().filter(|s| s.watch).count(),
+        documents: kb.sources.iter().map(|s| s.documents.len()).sum(),
+        failed: kb.sources.iter().map(|s| s.errors.len()).sum(),
+        pending: pending(kb_id),
+        chunks,
+        vectors,
+        id: kb.id,
+        name: kb.name,
+        embedding_model: kb.embedding_model,
+        dimensions: kb.dimensions,
+    })
+}
+
+pub fn delete(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    let _guard = KB_LOCK.lock().map_err(|e| e.to_string())?;
+    let kb = get(app, id)?;
+    for document in kb.sources.iter().flat_map(|s| &s.documents) {
+        if let Err(e) = attachments::release(app, &document.attachment_id) {
+            log::warn!("Failed to release {} from {}: {}", document.name, kb.name, e);
+        }
+    }
+    vectors::drop_collection(app, id)?;
+    crate::retrieval::forget_index(id);
+    fs::remove_dir_all(kb_dir(app, id)?).map_err(|e| format!("Failed to delete knowledge base: {}", e))
+}
+
+#[tauri::command]
+pub fn create_kb(
+    app: tauri::AppHandle,
+    name: String,
+    description: Option<String>,
+    embedding_model: Option<String>,
+) -> Result<KnowledgeBase, String> {
+    crate::app_lock::ensure_unlocked()?;
+    create(&app, &name, description, embedding_model)
+}
+
+#[tauri::command]
+pub async fn add_source(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    kb_id: String,
+    path: String,
+    watch: Option<bool>,
+) -> Result<Source, String> {
+    crate::app_lock::ensure_unlocked()?;
+    add(&app, &state, &kb_id, &path, watch.unwrap_or(false)).await
+}
+
+// Turns watching on or off for a folder source. Turning it on catches up
+// with changes right away.
+#[tauri::command]
+pub async fn watch_folder(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    kb_id: String,
+    source_id: String,
+    watch: bool,
+) -> Result<Source, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let source = {
+        let _guard = KB_LOCK.lock().map_err(|e| e.to_string())?;
+        let mut kb = get(&app, &kb_id)?;
+        let source = kb
+            .sources
+            .iter_mut()
+            .find(|s| s.id == source_id)
+            .ok_or_else(|| format!("Unknown source: {}", source_id))?;
+        if source.kind != SourceKind::Folder {
+            return Err("Only folders can be watched".to_string());
+        }
+        source.watch = watch;
+        let source = source.clone();
+        write(&app, &kb)?;
+        source
+    };
+    if !watch {
+        return Ok(source);
+    }
+    let scanned = source.clone();
+    let (changed, removed) = tauri::async_runtime::spawn_blocking(move || changes(&scanned))
+        .await
+        .map_err(|e| e.to_string())??;
+    sync_source(&app, &state, &kb_id, source, changed, removed).await
+}
+
+// Crawls a documentation site (same origin, bounded depth and page count,
+// following robots.txt) into the knowledge base
+#[tauri::command]
+pub async fn add_website_source(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    kb_id: String,
+    url: String,
+    options: Option<CrawlOptions>,
+) -> Result<CrawlReport, String> {
+    crate::app_lock::ensure_unlocked()?;
+    add_website(&app, &state, &kb_id, &url, options.unwrap_or_default()).await
+}
+
+#[tauri::command]
+pub fn list_kbs(app: tauri::AppHandle) -> Result<Vec<KnowledgeBase>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    load(&app)
+}
+
+#[tauri::command]
+pub async fn reindex_kb(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    id: String,
+) -> Result<KbStatus, String> {
+    crate::app_lock::ensure_unlocked()?;
+    reindex(&app, &state, &id).await
+}
+
+#[tauri::command]
+pub async fn set_kb_embedding_model(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    id: String,
+    model: String,
+) -> Result<KnowledgeBase, String> {
+    crate::app_lock::ensure_unlocked()?;
+    reembed(&app, &state, &id, &model).await
+}
+
+#[tauri::command]
+pub async fn kb_status(app: tauri::AppHandle, id: String) -> Result<KbStatus, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || status(&app, &id)).await.map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn delete_kb(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    delete(&app, &id)
+}
@@ -0,0 +1,732 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::hkdf;
+use ring::rand::SystemRandom;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::Emitter;
+
+use crate::chats;
+use crate::devices;
+use crate::e2e;
+use crate::keychain;
+use crate::mdns;
+use crate::settings;
+use crate::settings_sync;
+use crate::sync::{self, SyncStatus};
+
+// Device-to-device sync on the local network, without any cloud account.
+// Devices find each other with mDNS and pair once: both screens show a code
+// derived from an X25519 exchange, and when the user confirms they match,
+// both keep a shared key in the keychain. Every later connection mixes that
+// key into a fresh exchange, so only paired devices can read the traffic.
+// A sync then runs the regular sync engine with the peer serving its own
+// library in place of a remote store.
+
+const SERVICE_TYPE: &str = "_anchor-sync._tcp.local";
+const MAX_FRAME: usize = 64 * 1024 * 1024;
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// How long each side waits for its user to compare the pairing codes
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(120);
+// How long answers to a discovery query are collected
+const DISCOVERY_WAIT: Duration = Duration::from_millis(1500);
+// A peer that hasn't answered for this long is listed as offline
+const PEER_TTL_MILLIS: u64 = 5 * 60 * 1000;
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const PAIRING_REQUESTED_EVENT: &str = "lan-pairing-requested";
+const PAIRING_FINISHED_EVENT: &str = "lan-pairing-finished";
+const SYNCED_EVENT: &str = "chats-synced";
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+static SOCKET: OnceLock<UdpSocket> = OnceLock::new();
+// Peers that answered discovery, by device id
+static SEEN: Mutex<BTreeMap<String, Seen>> = Mutex::new(BTreeMap::new());
+// Pairings waiting for this device's user to compare codes, by peer id
+static PENDING: Mutex<Option<HashMap<String, mpsc::Sender<bool>>>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PairedPeer {
+    id: String,
+    name: String,
+    paired_at: u64,
+}
+
+struct Seen {
+    name: String,
+    addr: SocketAddr,
+    last_seen: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanPeer {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    pub paired: bool,
+    pub online: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PairingRequest {
+    peer_id: String,
+    name: String,
+    code: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PairingFinished {
+    peer_id: String,
+    paired: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Purpose {
+    Pair,
+    Sync,
+}
+
+// Sent in the clear to open a connection
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Hello {
+    id: String,
+    name: String,
+    public_key: String,
+    purpose: Purpose,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Request {
+    Confirm { accept: bool },
+    // End-to-end sync keys, see e2e.rs
+    Keys { keys: BTreeMap<String, String> },
+    Get { name: String },
+    Put { name: String, data: String },
+    Delete { name: String },
+    Done,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Reply {
+    Ok,
+    Data { data: Option<String> },
+    Keys { keys: BTreeMap<String, String> },
+    Error { message: String },
+}
+
+fn active(app: &tauri::AppHandle) -> bool {
+    settings::load(app).is_ok_and(|s| s.lan_sync.enabled && !s.offline_mode)
+}
+
+fn peers_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("lan_peers.json"))
+}
+
+fn paired_peers(app: &tauri::AppHandle) -> Vec<PairedPeer> {
+    peers_path(app)
+        .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn paired_ids(app: &tauri::AppHandle) -> Vec<String> {
+    paired_peers(app).into_iter().map(|peer| peer.id).collect()
+}
+
+// Id and name of each paired device
+pub fn paired_names(app: &tauri::AppHandle) -> Vec<(String, String)> {
+    paired_peers(app).into_iter().map(|peer| (peer.id, peer.name)).collect()
+}
+
+fn save_paired_peers(app: &tauri::AppHandle, peers: &[PairedPeer]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(peers).map_err(|e| e.to_string())?;
+    fs::write(peers_path(app)?, content).map_err(|e| format!("Failed to save paired devices: {}", e))
+}
+
+fn key_account(peer_id: &str) -> Result<String, String> {
+    if !devices::valid_id(peer_id) {
+        return Err("Invalid device id".to_string());
+    }
+    Ok(format!("lan-peer-{}", peer_id))
+}
+
+fn pair_key(peer_id: &str) -> Option<Vec<u8>> {
+    keychain::load(&key_account(peer_id).ok()?).ok().and_then(|key| STANDARD.decode(key).ok())
+}
+
+fn write_frame(stream: &mut TcpStream, data: &[u8]) -> Result<(), String> {
+    stream.write_all(&(data.len() as u32).to_be_bytes()).map_err(|e| e.to_string())?;
+    stream.write_all(data).map_err(|e| e.to_string())
+}
+
+fn read_frame(stream: &mut TcpStream) -> Result<Vec<u8>, String> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).map_err(|e| format!("The peer stopped responding: {}", e))?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err("The peer sent a message that's too large".to_string());
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).map_err(|e| format!("The peer stopped responding: {}", e))?;
+    Ok(data)
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn derive(salt: &[u8], secret: &[u8], info: &[&[u8]], out: &mut [u8]) -> Result<(), String> {
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+        .extract(secret)
+        .expand(info, Len(out.len()))
+        .and_then(|okm| okm.fill(out))
+        .map_err(|_| "Failed to derive the connection keys".to_string())
+}
+
+fn cipher(salt: &[u8], secret: &[u8], transcript: &[u8], direction: &[u8]) -> Result<LessSafeKey, String> {
+    let mut key = [0u8; 32];
+    derive(salt, secret, &[b"anchor-lan key", transcript, direction], &mut key)?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| "Failed to set up encryption".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut bytes = [0u8; NONCE_LEN];
+    bytes[NONCE_LEN - 8..].copy_from_slice(&counter.to_be_bytes());
+    Nonce::assume_unique_for_key(bytes)
+}
+
+// Messages after the handshake; each direction has its own key and counter
+struct Channel {
+    stream: TcpStream,
+    seal: LessSafeKey,
+    open: LessSafeKey,
+    sent: u64,
+    received: u64,
+}
+
+impl Channel {
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<(), String> {
+        let mut data = serde_json::to_vec(message).map_err(|e| e.to_string())?;
+        self.seal
+            .seal_in_place_append_tag(nonce(self.sent), Aad::empty(), &mut data)
+            .map_err(|_| "Failed to encrypt a message".to_string())?;
+        self.sent += 1;
+        write_frame(&mut self.stream, &data)
+    }
+
+    fn recv<T: DeserializeOwned>(&mut self) -> Result<T, String> {
+        let mut data = read_frame(&mut self.stream)?;
+        let plaintext = self
+            .open
+            .open_in_place(nonce(self.received), Aad::empty(), &mut data)
+            .map_err(|_| "The peer's messages can't be decrypted; try pairing the devices again".to_string())?;
+        self.received += 1;
+        serde_json::from_slice(plaintext).map_err(|e| format!("Invalid message from the peer: {}", e))
+    }
+}
+
+struct Session {
+    channel: Channel,
+    peer: Hello,
+    // Shown on both devices while pairing
+    code: String,
+    // Kept by both devices once pairing is confirmed
+    pair_key: [u8; 32],
+}
+
+// `purpose` is Some on the connecting side; the other side answers in kind
+fn handshake(app: &tauri::AppHandle, mut stream: TcpStream, purpose: Option<Purpose>) -> Result<Session, String> {
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    let rng = SystemRandom::new();
+    let private = EphemeralPrivateKey::generate(&X25519, &rng).map_err(|_| "Failed to make a key".to_string())?;
+    let public = private.compute_public_key().map_err(|_| "Failed to make a key".to_string())?;
+    let hello = |purpose| -> Result<Hello, String> {
+        let public_key = STANDARD.encode(public.as_ref());
+        Ok(Hello { id: devices::id(app)?, name: devices::name(app), public_key, purpose })
+    };
+    let parse = |data: Vec<u8>| serde_json::from_slice::<Hello>(&data).map_err(|e| format!("Invalid hello: {}", e));
+    let (ours, theirs) = match purpose {
+        Some(purpose) => {
+            let ours = hello(purpose)?;
+            write_frame(&mut stream, &serde_json::to_vec(&ours).map_err(|e| e.to_string())?)?;
+            (ours, parse(read_frame(&mut stream)?)?)
+        }
+        None => {
+            let theirs = parse(read_frame(&mut stream)?)?;
+            let ours = hello(theirs.purpose)?;
+            write_frame(&mut stream, &serde_json::to_vec(&ours).map_err(|e| e.to_string())?)?;
+            (ours, theirs)
+        }
+    };
+    // The id comes from an unauthenticated frame
+    if !devices::valid_id(&theirs.id) {
+        return Err("The peer sent an invalid device id".to_string());
+    }
+    if theirs.purpose != ours.purpose || theirs.id == ours.id {
+        return Err("The peer answered unexpectedly".to_string());
+    }
+
+    let their_key = STANDARD.decode(&theirs.public_key).map_err(|e| e.to_string())?;
+    let shared = agreement::agree_ephemeral(private, &UnparsedPublicKey::new(&X25519, &their_key), |s| s.to_vec())
+        .map_err(|_| "The peer sent an invalid key".to_string())?;
+    let initiator_first = purpose.is_some();
+    let (initiator_key, responder_key) =
+        if initiator_first { (public.as_ref().to_vec(), their_key) } else { (their_key, public.as_ref().to_vec()) };
+    let transcript = Sha256::digest([initiator_key, responder_key].concat()).to_vec();
+
+    // Paired devices mix in their shared key, so a stranger can't finish the handshake
+    let salt = match ours.purpose {
+        Purpose::Pair => transcript.clone(),
+        Purpose::Sync => pair_key(&theirs.id).ok_or("That device isn't paired with this one")?,
+    };
+    let to_responder = cipher(&salt, &shared, &transcript, b"initiator")?;
+    let to_initiator = cipher(&salt, &shared, &transcript, b"responder")?;
+    let (seal, open) = if initiator_first { (to_responder, to_initiator) } else { (to_initiator, to_responder) };
+
+    let mut code = [0u8; 4];
+    derive(&transcript, &shared, &[b"anchor-lan code"], &mut code)?;
+    let mut pair_key = [0u8; 32];
+    derive(&transcript, &shared, &[b"anchor-lan pair key"], &mut pair_key)?;
+    Ok(Session {
+        channel: Channel { stream, seal, open, sent: 0, received: 0 },
+        peer: theirs,
+        code: format!("{:06}", u32::from_be_bytes(code) % 1_000_000),
+        pair_key,
+    })
+}
+
+fn forget_pending(peer_id: &str) {
+    if let Ok(mut pending) = PENDING.lock() {
+        if let Some(pending) = pending.as_mut() {
+            pending.remove(peer_id);
+        }
+    }
+}
+
+// Waits for this device's user, then trades the decision with the peer;
+// both have to accept
+fn finish_pairing(app: &tauri::AppHandle, mut session: Session) {
+    let peer_id = session.peer.id.clone();
+    let (sender, receiver) = mpsc::channel();
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.get_or_insert_with(HashMap::new).insert(peer_id.clone(), sender);
+    }
+    let accept = receiver.recv_timeout(PAIRING_TIMEOUT).unwrap_or(false);
+    forget_pending(&peer_id);
+
+    let result = (|| -> Result<bool, String> {
+        // The peer's user may still be deciding
+        session.channel.stream.set_read_timeout(Some(PAIRING_TIMEOUT * 2)).map_err(|e| e.to_string())?;
+        session.channel.send(&Request::Confirm { accept })?;
+        let Request::Confirm { accept: theirs } = session.channel.recv()? else {
+            return Err("The peer answered unexpectedly".to_string());
+        };
+        if !(accept && theirs) {
+            return Ok(false);
+        }
+        // Each can now read what the other syncs through a backend
+        session.channel.send(&Request::Keys { keys: e2e::export_keys(app)? })?;
+        let Request::Keys { keys } = session.channel.recv()? else {
+            return Err("The peer answered unexpectedly".to_string());
+        };
+        e2e::import_keys(app, keys)?;
+        keychain::store(&key_account(&peer_id)?, &STANDARD.encode(session.pair_key))?;
+        let mut peers = paired_peers(app);
+        peers.retain(|p| p.id != peer_id);
+        peers.push(PairedPeer {
+            id: peer_id.clone(),
+            name: session.peer.name.clone(),
+            paired_at: chats::now_millis() as u64,
+        });
+        save_paired_peers(app, &peers)?;
+        devices::seen(app, &peer_id, &session.peer.name);
+        Ok(true)
+    })();
+    if let Err(e) = &result {
+        log::warn!("Failed to pair with {}: {}", session.peer.name, e);
+    }
+    let finished = PairingFinished { peer_id, paired: result.unwrap_or(false) };
+    let _ = app.emit(PAIRING_FINISHED_EVENT, finished);
+}
+
+fn chat_id(name: &str) -> Result<&str, String> {
+    name.strip_suffix(".json")
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || "_-".contains(c)))
+        .ok_or_else(|| format!("Invalid chat file name: {}", name))
+}
+
+// What one sync request does to this device's library. `excluded` are the
+// chats the sync rules keep here; changes to them are ignored.
+fn handle(
+    app: &tauri::AppHandle,
+    peer_id: &str,
+    dir: &Path,
+    excluded: &BTreeSet<String>,
+    request: Request,
+    changed: &mut bool,
+) -> Result<Reply, String> {
+    match request {
+        Request::Get { name } if name == sync::MANIFEST => {
+            Ok(Reply::Data { data: Some(STANDARD.encode(sync::local_manifest(app, peer_id)?)) })
+        }
+        Request::Get { name } if settings_sync::kind(&name).is_some() => {
+            let data = settings_sync::serve(app, settings_sync::kind(&name).unwrap_or_default())?;
+            Ok(Reply::Data { data: data.map(|d| STANDARD.encode(d)) })
+        }
+        Request::Put { name, data } if settings_sync::kind(&name).is_some() => {
+            let data = STANDARD.decode(data).map_err(|e| e.to_string())?;
+            let scope = format!("peer_{}", peer_id);
+            settings_sync::receive(app, &scope, settings_sync::kind(&name).unwrap_or_default(), &data)?;
+            Ok(Reply::Ok)
+        }
+        Request::Put { name, .. } | Request::Delete { name }
+            if excluded.contains(chat_id(&name).unwrap_or_default()) =>
+        {
+            Ok(Reply::Ok)
+        }
+        Request::Get { name } => {
+            let path = dir.join(format!("{}.json", chat_id(&name)?));
+            if !path.exists() {
+                return Ok(Reply::Data { data: None });
+            }
+            Ok(Reply::Data { data: Some(STANDARD.encode(sync::read_chat(&path)?)) })
+        }
+        Request::Put { name, data } => {
+            let data = STANDARD.decode(data).map_err(|e| e.to_string())?;
+            if name == sync::MANIFEST {
+                sync::save_peer_state(app, peer_id, &data)?;
+                return Ok(Reply::Ok);
+            }
+            sync::store_chat(app, dir, chat_id(&name)?, &data)?;
+            *changed = true;
+            Ok(Reply::Ok)
+        }
+        Request::Delete { name } => {
+            let id = chat_id(&name)?;
+            if dir.join(&name).exists() {
+                chats::remove(app.clone(), id.to_string())?;
+                *changed = true;
+            }
+            Ok(Reply::Ok)
+        }
+        Request::Keys { keys } => {
+            e2e::import_keys(app, keys)?;
+            Ok(Reply::Keys { keys: e2e::export_keys(app)? })
+        }
+        Request::Confirm { .. } | Request::Done => Err("Unexpected request".to_string()),
+    }
+}
+
+// Answers a peer's sync requests against this device's library
+fn serve_sync(app: &tauri::AppHandle, mut session: Session) -> Result<(), String> {
+    let peer_id = session.peer.id.clone();
+    let dir = chats::get_chats_dir(app)?;
+    let excluded = sync::excluded_chats(app)?;
+    let mut changed = false;
+    loop {
+        let reply = match session.channel.recv::<Request>()? {
+            Request::Done => break,
+            request => {
+                let reply = handle(app, &peer_id, &dir, &excluded, request, &mut changed);
+                reply.unwrap_or_else(|message| Reply::Error { message })
+            }
+        };
+        session.channel.send(&reply)?;
+    }
+    devices::seen(app, &peer_id, &session.peer.name);
+    if changed {
+        let _ = app.emit(SYNCED_EVENT, ());
+    }
+    Ok(())
+}
+
+fn serve(app: &tauri::AppHandle, stream: TcpStream) -> Result<(), String> {
+    if !active(app) {
+        return Ok(());
+    }
+    let addr = stream.peer_addr().map_err(|e| e.to_string())?;
+    let session = handshake(app, stream, None)?;
+    crate::audit::record("network", &format!("lan sync from {}", addr.ip()));
+    match session.peer.purpose {
+        Purpose::Pair => {
+            let request = PairingRequest {
+                peer_id: session.peer.id.clone(),
+                name: session.peer.name.clone(),
+                code: session.code.clone(),
+            };
+            let _ = app.emit(PAIRING_REQUESTED_EVENT, request);
+            finish_pairing(app, session);
+            Ok(())
+        }
+        Purpose::Sync => sync::while_idle(|| serve_sync(app, session)),
+    }
+}
+
+fn service(app: &tauri::AppHandle, port: u16) -> Result<mdns::Service, String> {
+    let id = devices::id(app)?;
+    Ok(mdns::Service {
+        instance: format!("{}.{}", id, SERVICE_TYPE),
+        port,
+        txt: vec![format!("id={}", id), format!("name={}", devices::name(app))],
+    })
+}
+
+fn remember(app: &tauri::AppHandle, found: mdns::Found) {
+    let field = |key: &str| found.txt.iter().find_map(|t| t.strip_prefix(&format!("{}=", key)).map(str::to_string));
+    let Some(id) = field("id") else {
+        return;
+    };
+    if devices::id(app).is_ok_and(|me| me == id) {
+        return;
+    }
+    let name = field("name").unwrap_or_else(|| id.clone());
+    if let Ok(mut seen) = SEEN.lock() {
+        seen.insert(id, Seen { name, addr: found.addr, last_seen: chats::now_millis() as u64 });
+    }
+}
+
+// Answers discovery queries and collects other devices' answers
+fn listen_mdns(app: tauri::AppHandle, socket: UdpSocket, port: u16) {
+    let group = SocketAddr::V4(SocketAddrV4::new(mdns::GROUP, mdns::PORT));
+    if let Ok(service) = service(&app, port) {
+        let _ = socket.send_to(&mdns::response(SERVICE_TYPE, &service), group);
+    }
+    let mut buf = [0u8; 9000];
+    loop {
+        let Ok((len, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        if !active(&app) {
+            continue;
+        }
+        let packet = &buf[..len];
+        if mdns::asks_for(packet, SERVICE_TYPE) {
+            if let Ok(service) = service(&app, port) {
+                let _ = socket.send_to(&mdns::response(SERVICE_TYPE, &service), group);
+            }
+        } else {
+            for found in mdns::answers(packet, SERVICE_TYPE, from.ip()) {
+                remember(&app, found);
+            }
+        }
+    }
+}
+
+fn start(app: &tauri::AppHandle) -> Result<(), String> {
+    let port = settings::load(app)?.lan_sync.port;
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).map_err(|e| format!("Failed to listen: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let socket = mdns::socket()?;
+    let _ = SOCKET.set(socket.try_clone().map_err(|e| e.to_string())?);
+
+    let accept_app = app.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let app = accept_app.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = serve(&app, stream) {
+                    log::warn!("LAN sync with a peer failed: {}", e);
+                }
+            });
+        }
+    });
+    let mdns_app = app.clone();
+    std::thread::spawn(move || listen_mdns(mdns_app, socket, port));
+    log::info!("LAN sync listening on port {}", port);
+    Ok(())
+}
+
+// Starts listening once LAN sync is turned on; it stays bound afterwards
+// but ignores peers while turned off
+pub fn schedule(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        if !STARTED.load(Ordering::SeqCst) && active(&app) {
+            match start(&app) {
+                Ok(()) => STARTED.store(true, Ordering::SeqCst),
+                Err(e) => log::warn!("Failed to start LAN sync: {}", e),
+            }
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn connect(app: &tauri::AppHandle, peer_id: &str, purpose: Purpose) -> Result<Session, String> {
+    if !active(app) {
+        return Err("LAN sync is turned off".to_string());
+    }
+    let addr = SEEN
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(peer_id)
+        .map(|seen| seen.addr)
+        .ok_or("That device isn't on the network right now")?;
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .map_err(|e| format!("Failed to reach the device: {}", e))?;
+    crate::audit::record("network", &format!("lan sync to {}", addr.ip()));
+    let session = handshake(app, stream, Some(purpose))?;
+    if session.peer.id != peer_id {
+        return Err("A different device answered at that address".to_string());
+    }
+    Ok(session)
+}
+
+// The other side of a sync, used by the sync engine as its remote
+pub struct Peer {
+    channel: Mutex<Channel>,
+}
+
+impl Peer {
+    fn call(&self, request: &Request) -> Result<Reply, String> {
+        let mut channel = self.channel.lock().map_err(|e| e.to_string())?;
+        channel.send(request)?;
+        match channel.recv()? {
+            Reply::Error { message } => Err(message),
+            reply => Ok(reply),
+        }
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        match self.call(&Request::Get { name: name.to_string() })? {
+            Reply::Data { data } => data.map(|d| STANDARD.decode(d).map_err(|e| e.to_string())).transpose(),
+            _ => Err("The peer answered unexpectedly".to_string()),
+        }
+    }
+
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        self.call(&Request::Put { name: name.to_string(), data: STANDARD.encode(data) }).map(|_| ())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        self.call(&Request::Delete { name: name.to_string() }).map(|_| ())
+    }
+
+    // Trades end-to-end sync keys, so keys from devices paired with only one
+    // of the two spread to the other
+    fn exchange_keys(&self, app: &tauri::AppHandle) -> Result<(), String> {
+        match self.call(&Request::Keys { keys: e2e::export_keys(app)? })? {
+            Reply::Keys { keys } => e2e::import_keys(app, keys),
+            _ => Err("The peer answered unexpectedly".to_string()),
+        }
+    }
+
+    pub async fn finish(&self) -> Result<(), String> {
+        self.channel.lock().map_err(|e| e.to_string())?.send(&Request::Done)
+    }
+}
+
+// Asks the network for other devices and lists them with the paired ones
+#[tauri::command]
+pub async fn lan_peers(app: tauri::AppHandle) -> Result<Vec<LanPeer>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if !active(&app) {
+        return Err("LAN sync is turned off".to_string());
+    }
+    if let Some(socket) = SOCKET.get() {
+        let group = SocketAddr::V4(SocketAddrV4::new(mdns::GROUP, mdns::PORT));
+        socket.send_to(&mdns::query(SERVICE_TYPE), group).map_err(|e| e.to_string())?;
+        tauri::async_runtime::spawn_blocking(|| std::thread::sleep(DISCOVERY_WAIT)).await.map_err(|e| e.to_string())?;
+    }
+
+    let now = chats::now_millis() as u64;
+    let seen = SEEN.lock().map_err(|e| e.to_string())?;
+    let online = |id: &str| seen.get(id).filter(|s| now.saturating_sub(s.last_seen) < PEER_TTL_MILLIS);
+    let paired = paired_peers(&app);
+    let mut peers: Vec<LanPeer> = paired
+        .iter()
+        .map(|p| LanPeer {
+            id: p.id.clone(),
+            name: online(&p.id).map(|s| s.name.clone()).unwrap_or_else(|| p.name.clone()),
+            address: online(&p.id).map(|s| s.addr.to_string()),
+            paired: true,
+            online: online(&p.id).is_some(),
+        })
+        .collect();
+    for (id, s) in seen.iter() {
+        if online(id).is_some() && !paired.iter().any(|p| &p.id == id) {
+            let address = Some(s.addr.to_string());
+            peers.push(LanPeer { id: id.clone(), name: s.name.clone(), address, paired: false, online: true });
+        }
+    }
+    Ok(peers)
+}
+
+// Starts pairing and returns the code to compare with the other screen;
+// confirm_lan_pairing finishes it on each device
+#[tauri::command]
+pub async fn pair_lan_peer(app: tauri::AppHandle, peer_id: String) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let session = connect(&app, &peer_id, Purpose::Pair)?;
+        let code = session.code.clone();
+        std::thread::spawn(move || finish_pairing(&app, session));
+        Ok(code)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn confirm_lan_pairing(peer_id: String, accept: bool) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let sender = PENDING
+        .lock()
+        .map_err(|e| e.to_string())?
+        .as_mut()
+        .and_then(|pending| pending.remove(&peer_id))
+        .ok_or("No pairing with that device is waiting")?;
+    sender.send(accept).map_err(|_| "The pairing already timed out".to_string())
+}
+
+#[tauri::command]
+pub fn unpair_lan_peer(app: tauri::AppHandle, peer_id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    if let Ok(account) = key_account(&peer_id) {
+        let _ = keychain::delete(&account);
+    }
+    let mut peers = paired_peers(&app);
+    peers.retain(|p| p.id != peer_id);
+    save_paired_peers(&app, &peers)
+}
+
+#[tauri::command]
+pub async fn sync_lan_peer(app: tauri::AppHandle, peer_id: String) -> Result<SyncStatus, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let session = connect(&app, &peer_id, Purpose::Sync)?;
+        let name = session.peer.name.clone();
+        let peer = Box::new(Peer { channel: Mutex::new(session.channel) });
+        peer.exchange_keys(&app)?;
+        let report = tauri::async_runtime::block_on(sync::sync_peer(&app, peer, &peer_id))?;
+        devices::seen(&app, &peer_id, &name);
+        Ok(report)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -2,118 +2,368 @@ use std::fs;
 use std::path::PathBuf;
 use tauri::Manager;
 
-fn get_chats_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+mod analytics;
+mod anonymize;
+mod app_lock;
+mod appearance;
+mod asset_protocol;
+mod assistants;
+mod attachments;
+mod audit;
+mod audio;
+mod autostart;
+mod chat_windows;
+mod chats;
+mod cli;
+mod clipboard;
+mod clipboard_watch;
+mod cloud_folder;
+mod code;
+mod compare;
+mod completion;
+mod conflicts;
+mod crdt;
+mod context;
+mod crawler;
+mod deep_link;
+mod device_status;
+mod devices;
+mod dropbox;
+mod e2e;
+mod embeddings;
+mod encryption;
+mod exports;
+mod extraction;
+mod file_drop;
+mod gist;
+mod git_sync;
+mod google_drive;
+mod handoff;
+mod history;
+mod http;
+mod image_metadata;
+mod imports;
+mod incognito;
+mod instance;
+mod jobs;
+mod jump_list;
+mod keychain;
+mod knowledge;
+mod lan_sync;
+mod mdns;
+mod memory;
+mod mini_chat;
+mod models;
+mod notifications;
+mod ocr;
+mod oauth;
+mod office;
+mod os_search;
+mod permissions;
+mod power;
+mod presets;
+mod prompts;
+mod qr;
+mod quick_prompt;
+mod recording;
+mod retrieval;
+mod s3;
+mod sandbox;
+mod screenshot;
+mod sealed;
+mod secrets;
+mod settings;
+mod settings_sync;
+mod share;
+mod share_service;
+mod shred;
+mod structured;
+mod summary;
+mod sync;
+mod tables;
+mod templates;
+mod thumbnails;
+mod titles;
+mod transcription;
+mod tools;
+mod tray;
+mod tts;
+mod vectors;
+mod vision;
+mod web;
+mod webdav;
+mod whisper;
+mod window_state;
+mod youtube;
+
+pub(crate) fn app_data_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
     // Use Tauri's app data directory for production builds
     let app_data_dir = app_handle
         .path()
         .app_data_dir()
         .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    
-    let chats_dir = app_data_dir.join("chats");
-    
-    if !chats_dir.exists() {
-        fs::create_dir_all(&chats_dir)
-            .map_err(|e| format!("Failed to create chats directory: {}", e))?;
-    }
-    
-    Ok(chats_dir)
-}
-
-#[tauri::command]
-fn save_chat(app: tauri::AppHandle, session: serde_json::Value) -> Result<String, String> {
-    let id = session["id"].as_str().unwrap_or_default().to_string();
-    let id = if id.is_empty() {
-        format!("chat_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis())
-    } else {
-        id
-    };
-
-    let filename = format!("{}.json", id);
-    let path = get_chats_dir(&app)?.join(&filename);
-
-    // Ensure the session has the ID
-    let mut session_obj = session.as_object().ok_or("Invalid session format")?.clone();
-    session_obj.insert("id".to_string(), serde_json::Value::String(id.clone()));
-    
-    let content = serde_json::to_string_pretty(&session_obj).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
-
-    Ok(id)
-}
-
-#[tauri::command]
-fn list_chats(app: tauri::AppHandle) -> Result<Vec<serde_json::Value>, String> {
-    let dir = get_chats_dir(&app)?;
-    let mut sessions = Vec::new();
-
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                    if let Ok(content) = fs::read_to_string(&path) {
-                        if let Ok(mut data) = serde_json::from_str::<serde_json::Value>(&content) {
-                            // Add filename/timestamp if missing for sorting
-                            if let Some(obj) = data.as_object_mut() {
-                                // Force ID to match filename to ensure deletion works
-                                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                                    obj.insert("id".to_string(), serde_json::Value::String(stem.to_string()));
-                                }
 
-                                if !obj.contains_key("timestamp") {
-                                    if let Ok(metadata) = fs::metadata(&path) {
-                                        if let Ok(created) = metadata.created() {
-                                            if let Ok(duration) = created.duration_since(std::time::UNIX_EPOCH) {
-                                                obj.insert("timestamp".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(duration.as_secs_f64() * 1000.0).unwrap()));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            sessions.push(data);
-                        }
-                    }
-                }
-            }
-        }
+    if !app_data_dir.exists() {
+        fs::create_dir_all(&app_data_dir)
+            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
     }
 
-    // Sort by timestamp descending
-    sessions.sort_by(|a, b| {
-        let t_a = a["timestamp"].as_f64().unwrap_or(0.0);
-        let t_b = b["timestamp"].as_f64().unwrap_or(0.0);
-        t_b.partial_cmp(&t_a).unwrap_or(std::cmp::Ordering::Equal)
-    });
-
-    Ok(sessions)
-}
-
-#[tauri::command]
-fn load_chat(app: tauri::AppHandle, id: String) -> Result<serde_json::Value, String> {
-    let filename = format!("{}.json", id);
-    let path = get_chats_dir(&app)?.join(filename);
-    
-    let content = fs::read_to_string(path).map_err(|_| "Chat not found".to_string())?;
-    let data = serde_json::from_str(&content).map_err(|e| e.to_string())?;
-    
-    Ok(data)
+    Ok(app_data_dir)
 }
 
-#[tauri::command]
-fn delete_chat(app: tauri::AppHandle, id: String) -> Result<(), String> {
-    let filename = format!("{}.json", id);
-    let path = get_chats_dir(&app)?.join(filename);
-    
-    fs::remove_file(path).map_err(|e| e.to_string())?;
-    Ok(())
+// State the backend looks up through the app handle
+fn managed(builder: tauri::Builder<tauri::Wry>) -> tauri::Builder<tauri::Wry> {
+  builder
+    .manage(models::ModelCache::default())
+    .manage(tts::SpeechState::default())
+    .manage(embeddings::EmbeddingState::default())
+    .manage(recording::RecordingState::default())
+    .manage(vectors::VectorState::default())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-  tauri::Builder::default()
+  let mut context = tauri::generate_context!();
+  let argv: Vec<String> = std::env::args().collect();
+  if let Some(command) = cli::parse(&argv) {
+    // Run from a terminal: no window, plugins or background work, see cli.rs
+    context.config_mut().app.windows.clear();
+    let app = managed(tauri::Builder::default()).build(context).expect("error while building tauri application");
+    std::process::exit(cli::run(app.handle(), command));
+  }
+  // Launched at login: the window is only shown if settings say so
+  let hidden = autostart::hidden_launch(&argv);
+  if hidden {
+    for window in context.config_mut().app.windows.iter_mut() {
+      window.visible = false;
+    }
+  }
+
+  managed(tauri::Builder::default()
+    // Has to come first, so a second launch exits before setting anything up
+    .plugin(tauri_plugin_single_instance::init(instance::on_second_instance))
     .plugin(tauri_plugin_log::Builder::default().build())
     .plugin(tauri_plugin_http::init())
-    .invoke_handler(tauri::generate_handler![save_chat, list_chats, load_chat, delete_chat])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .plugin(tauri_plugin_deep_link::init())
+    .plugin(tauri_plugin_notification::init())
+    .plugin(autostart::plugin())
+    // One handler for every shortcut; each module checks it's one of its own
+    .plugin(
+      tauri_plugin_global_shortcut::Builder::new()
+        .with_handler(|app, shortcut, event| {
+          quick_prompt::on_shortcut(app, shortcut, event);
+          clipboard_watch::on_shortcut(app, shortcut, event);
+        })
+        .build(),
+    ))
+    .setup(move |app| {
+      if let Some(window) = app.get_webview_window("main") {
+        window_state::restore(&window);
+      }
+      audit::init(app.handle());
+      match settings::load(app.handle()) {
+        Ok(settings) => {
+          http::configure(&settings);
+          secrets::configure(&settings);
+        }
+        Err(e) => log::warn!("Failed to load the network allowlist and secret scan setting: {}", e),
+      }
+      app_lock::init(app.handle());
+      encryption::init(app.handle());
+      incognito::init(app.handle());
+      attachments::schedule_gc(app.handle().clone());
+      knowledge::schedule_watch(app.handle().clone());
+      app_lock::schedule_idle_lock(app.handle().clone());
+      analytics::schedule_flush(app.handle().clone());
+      git_sync::schedule(app.handle().clone());
+      sync::schedule(app.handle().clone());
+      lan_sync::schedule(app.handle().clone());
+      tray::init(app.handle());
+      jump_list::init(app.handle());
+      appearance::init(app.handle());
+      power::init(app.handle());
+      quick_prompt::configure(app.handle());
+      deep_link::init(app.handle());
+      instance::init(app.handle());
+      os_search::configure(app.handle());
+      share_service::configure(app.handle());
+      autostart::configure(app.handle());
+      autostart::on_setup(app.handle(), hidden);
+      clipboard_watch::configure(app.handle());
+      Ok(())
+    })
+    .on_window_event(|window, event| {
+      app_lock::on_window_event(window, event);
+      incognito::on_window_event(window, event);
+      sync::on_window_event(event);
+      quick_prompt::on_window_event(window, event);
+      mini_chat::on_window_event(window, event);
+      notifications::on_window_event(window, event);
+      file_drop::on_window_event(window, event);
+      window_state::on_window_event(window, event);
+      appearance::on_window_event(window, event);
+      jobs::on_window_event(window, event);
+      chat_windows::on_window_event(window, event);
+    })
+    .register_asynchronous_uri_scheme_protocol(asset_protocol::SCHEME, asset_protocol::handle)
+    .invoke_handler(analytics::counted(tauri::generate_handler![
+      chats::save_chat,
+      chats::list_chats,
+      chats::load_chat,
+      chats::delete_chat,
+      chats::set_chat_folder,
+      git_sync::sync_chats,
+      git_sync::chat_history,
+      git_sync::chat_at_revision,
+      git_sync::restore_chat_revision,
+      sync::sync_now,
+      sync::sync_status,
+      sync::get_sync_status,
+      sync::set_sync_secret,
+      oauth::start_sync_auth,
+      oauth::finish_sync_auth,
+      oauth::sign_out_sync,
+      sync::sync_rules,
+      sync::set_sync_rules,
+      conflicts::list_conflicts,
+      conflicts::resolve_conflict,
+      e2e::sync_keys,
+      e2e::export_sync_keys,
+      e2e::import_sync_keys,
+      lan_sync::lan_peers,
+      lan_sync::pair_lan_peer,
+      lan_sync::confirm_lan_pairing,
+      lan_sync::unpair_lan_peer,
+      lan_sync::sync_lan_peer,
+      devices::list_devices,
+      settings::get_settings,
+      settings::save_settings,
+      settings::set_offline_mode,
+      models::list_models,
+      completion::stream_completion,
+      transcription::list_whisper_models,
+      transcription::download_whisper_model,
+      transcription::delete_whisper_model,
+      transcription::transcribe_audio,
+      tts::list_voices,
+      tts::speak,
+      tts::stop_speaking,
+      embeddings::embed,
+      structured::structured_completion,
+      compare::compare_models,
+      compare::select_candidate,
+      summary::summarize_chat,
+      presets::list_presets,
+      presets::save_preset,
+      presets::delete_preset,
+      presets::set_default_preset,
+      presets::default_preset,
+      permissions::respond_tool_permission,
+      permissions::tool_permissions,
+      permissions::revoke_tool_permission,
+      prompts::list_prompts,
+      prompts::create_prompt,
+      prompts::update_prompt,
+      prompts::delete_prompt,
+      prompts::assign_prompt,
+      templates::render_template,
+      templates::template_variables,
+      assistants::list_assistants,
+      assistants::save_assistant,
+      assistants::delete_assistant,
+      assistants::assign_assistant,
+      attachments::save_attachment,
+      attachments::get_attachment_path,
+      attachments::release_attachment,
+      attachments::clean_up_attachments,
+      attachments::collect_attachment_garbage,
+      thumbnails::get_thumbnail,
+      extraction::extract_text,
+      ocr::ocr_attachment,
+      recording::start_recording,
+      recording::stop_recording,
+      tables::describe_table,
+      tables::query_table,
+      web::fetch_url,
+      youtube::youtube_transcript,
+      code::chunk_code,
+      screenshot::capture_screenshot,
+      clipboard::paste_clipboard_image,
+      exports::export_chat,
+      imports::import_chats,
+      exports::publish_chat,
+      gist::set_gist_token,
+      gist::publish_to_gist,
+      share::share_chat,
+      share::list_shares,
+      share::stop_share,
+      handoff::start_handoff,
+      handoff::cancel_handoff,
+      handoff::receive_handoff,
+      sealed::decrypt_export,
+      knowledge::create_kb,
+      knowledge::add_source,
+      knowledge::add_website_source,
+      knowledge::list_kbs,
+      knowledge::delete_kb,
+      knowledge::watch_folder,
+      knowledge::reindex_kb,
+      knowledge::set_kb_embedding_model,
+      knowledge::kb_status,
+      vectors::upsert_vectors,
+      vectors::delete_vectors,
+      vectors::search_vectors,
+      retrieval::retrieve_context,
+      retrieval::attach_kb,
+      retrieval::detach_kb,
+      retrieval::get_chat_knowledge,
+      history::set_chat_history_memory,
+      history::reindex_chat_history,
+      memory::list_memories,
+      memory::add_memory,
+      memory::update_memory,
+      memory::delete_memory,
+      memory::clear_memories,
+      encryption::enable_encryption,
+      encryption::unlock_chat_store,
+      encryption::encrypt_existing_chats,
+      encryption::disable_encryption,
+      encryption::encryption_status,
+      app_lock::set_app_passphrase,
+      app_lock::lock_app,
+      app_lock::unlock,
+      app_lock::unlock_with_biometrics,
+      app_lock::record_activity,
+      app_lock::app_lock_status,
+      incognito::start_incognito_chat,
+      incognito::end_incognito_chat,
+      incognito::keep_incognito_chat,
+      audit::audit_log,
+      audit::export_audit_log,
+      analytics::analytics_report,
+      analytics::reset_analytics,
+      tray::take_tray_action,
+      quick_prompt::send_quick_prompt,
+      quick_prompt::hide_quick_prompt,
+      deep_link::take_deep_link,
+      instance::take_launch_arguments,
+      window_state::set_window_chat,
+      window_state::last_window_chat,
+      chat_windows::open_chat_window,
+      chat_windows::chat_window_scope,
+      os_search::reindex_os_search,
+      mini_chat::send_mini_prompt,
+      mini_chat::mini_chat_messages,
+      mini_chat::clear_mini_chat,
+      mini_chat::save_mini_chat,
+      mini_chat::hide_mini_chat,
+      appearance::system_appearance,
+      power::power_state
+    ]))
+    .build(context)
+    .expect("error while running tauri application")
+    .run(|app, event| {
+      tray::on_run_event(app, &event);
+      instance::on_run_event(app, &event);
+    });
 }
@@ -0,0 +1,208 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+// Just enough multicast DNS service discovery (RFC 6762/6763) to find other
+// Anchor devices on the local network: PTR queries for one service type, and
+// answers with PTR, SRV and TXT records. Peers are reached at the address
+// the answer came from, so no A records are needed.
+
+pub const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const PORT: u16 = 5353;
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// Set on records only we answer for, so caches replace older copies
+const CACHE_FLUSH: u16 = 0x8000;
+const TTL: u32 = 120;
+
+pub struct Service {
+    // "<instance>.<service type>"
+    pub instance: String,
+    pub port: u16,
+    pub txt: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Found {
+    pub addr: SocketAddr,
+    pub txt: Vec<String>,
+}
+
+// Shared with any other responder on the machine (Bonjour, Avahi)
+pub fn socket() -> Result<UdpSocket, String> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(|e| e.to_string())?;
+    socket.set_reuse_address(true).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true).map_err(|e| e.to_string())?;
+    let bind = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT);
+    socket.bind(&SocketAddr::V4(bind).into()).map_err(|e| format!("Failed to listen for mDNS: {}", e))?;
+    let socket: UdpSocket = socket.into();
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED).map_err(|e| e.to_string())?;
+    socket.set_multicast_loop_v4(true).map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+fn push_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label);
+    }
+    packet.push(0);
+}
+
+fn push_record(packet: &mut Vec<u8>, name: &str, kind: u16, class: u16, rdata: &[u8]) {
+    push_name(packet, name);
+    packet.extend_from_slice(&kind.to_be_bytes());
+    packet.extend_from_slice(&class.to_be_bytes());
+    packet.extend_from_slice(&TTL.to_be_bytes());
+    packet.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    packet.extend_from_slice(rdata);
+}
+
+fn header(flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(512);
+    for field in [0, flags, questions, answers, 0, 0] {
+        packet.extend_from_slice(&field.to_be_bytes());
+    }
+    packet
+}
+
+pub fn query(service_type: &str) -> Vec<u8> {
+    let mut packet = header(0, 1, 0);
+    push_name(&mut packet, service_type);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+pub fn response(service_type: &str, service: &Service) -> Vec<u8> {
+    // Authoritative answer
+    let mut packet = header(0x8400, 0, 3);
+    let mut ptr = Vec::new();
+    push_name(&mut ptr, &service.instance);
+    push_record(&mut packet, service_type, TYPE_PTR, CLASS_IN, &ptr);
+
+    let mut srv = Vec::new();
+    srv.extend_from_slice(&[0, 0, 0, 0]);
+    srv.extend_from_slice(&service.port.to_be_bytes());
+    push_name(&mut srv, &format!("{}.local", service.instance.split('.').next().unwrap_or_default()));
+    push_record(&mut packet, &service.instance, TYPE_SRV, CLASS_IN | CACHE_FLUSH, &srv);
+
+    let mut txt = Vec::new();
+    for entry in &service.txt {
+        let entry = &entry.as_bytes()[..entry.len().min(255)];
+        txt.push(entry.len() as u8);
+        txt.extend_from_slice(entry);
+    }
+    push_record(&mut packet, &service.instance, TYPE_TXT, CLASS_IN | CACHE_FLUSH, &txt);
+    packet
+}
+
+// Reads a possibly compressed name at `pos`; returns it and the position
+// after it in the original (uncompressed) stream
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops in malformed packets
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xC0 == 0xC0 {
+            let target = ((len & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = target;
+            continue;
+        }
+        let label = packet.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        pos += 1 + len;
+    }
+    None
+}
+
+fn u16_at(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*packet.get(pos)?, *packet.get(pos + 1)?]))
+}
+
+// Whether `packet` is a query asking for `service_type`
+pub fn asks_for(packet: &[u8], service_type: &str) -> bool {
+    let (Some(flags), Some(questions)) = (u16_at(packet, 2), u16_at(packet, 4)) else {
+        return false;
+    };
+    if flags & 0x8000 != 0 {
+        return false;
+    }
+    let mut pos = 12;
+    for _ in 0..questions {
+        let Some((name, next)) = read_name(packet, pos) else {
+            return false;
+        };
+        if name.eq_ignore_ascii_case(service_type.trim_end_matches('.')) {
+            return true;
+        }
+        pos = next + 4;
+    }
+    false
+}
+
+// The instances of `service_type` a response announces
+pub fn answers(packet: &[u8], service_type: &str, from: IpAddr) -> Vec<Found> {
+    let mut found = Vec::new();
+    let parsed = (|| {
+        let flags = u16_at(packet, 2)?;
+        if flags & 0x8000 == 0 {
+            return None;
+        }
+        let questions = u16_at(packet, 4)?;
+        let records = u16_at(packet, 6)? as usize + u16_at(packet, 8)? as usize + u16_at(packet, 10)? as usize;
+        let mut pos = 12;
+        for _ in 0..questions {
+            pos = read_name(packet, pos)?.1 + 4;
+        }
+        let mut instances = Vec::new();
+        let mut ports = Vec::new();
+        let mut txts = Vec::new();
+        for _ in 0..records {
+            let (name, next) = read_name(packet, pos)?;
+            let kind = u16_at(packet, next)?;
+            let len = u16_at(packet, next + 8)? as usize;
+            let rdata = next + 10;
+            packet.get(rdata..rdata + len)?;
+            match kind {
+                TYPE_PTR if name.eq_ignore_ascii_case(service_type) => instances.push(read_name(packet, rdata)?.0),
+                TYPE_SRV => ports.push((name, u16_at(packet, rdata + 4)?)),
+                TYPE_TXT => {
+                    let mut entries = Vec::new();
+                    let mut at = rdata;
+                    while at < rdata + len {
+                        let size = packet[at] as usize;
+                        let entry = packet.get(at + 1..(at + 1 + size).min(rdata + len))?;
+                        entries.push(String::from_utf8_lossy(entry).to_string());
+                        at += 1 + size;
+                    }
+                    txts.push((name, entries));
+                }
+                _ => {}
+            }
+            pos = rdata + len;
+        }
+        for instance in instances {
+            let Some((_, port)) = ports.iter().find(|(name, _)| name.eq_ignore_ascii_case(&instance)) else {
+                continue;
+            };
+            let txt = txts.iter().find(|(name, _)| name.eq_ignore_ascii_case(&instance)).map(|(_, t)| t.clone());
+            found.push(Found { addr: SocketAddr::new(from, *port), txt: txt.unwrap_or_default() });
+        }
+        Some(())
+    })();
+    if parsed.is_none() {
+        log::debug!("Ignoring a malformed mDNS packet from {}", from);
+    }
+    found
+}
@@ -0,0 +1,299 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::Emitter;
+
+use crate::anonymize::{self, Scope};
+use crate::chats;
+use crate::completion::{ChatMessage, CompletionRequest};
+use crate::context;
+use crate::incognito;
+use crate::settings::{ProviderConfig, Settings};
+use crate::structured::{self, OutputSchema};
+
+// Guards the memories.json read-modify-write cycle; extraction runs in the
+// background alongside edits from the UI
+static MEMORY_LOCK: Mutex<()> = Mutex::new(());
+
+// Facts saved from a single turn, at most
+const MAX_FACTS_PER_TURN: usize = 5;
+const MAX_FACT_CHARS: usize = 200;
+// Enough of each message to tell what was said
+const MAX_MESSAGE_CHARS: usize = 4000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Memory {
+    pub id: String,
+    pub text: String,
+    // The chat it was extracted from; None for memories added by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_id: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Deserialize)]
+struct Extraction {
+    facts: Vec<String>,
+}
+
+fn memories_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("memories.json"))
+}
+
+pub fn load(app: &tauri::AppHandle) -> Result<Vec<Memory>, String> {
+    let path = memories_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read(&path).map_err(|e| format!("Failed to read memories: {}", e))?;
+    // Sealed like the chats they come from while chat encryption is on
+    let content = crate::encryption::open(content)?;
+    serde_json::from_slice(&content).map_err(|e| format!("Invalid memories file: {}", e))
+}
+
+fn save(app: &tauri::AppHandle, memories: &[Memory]) -> Result<(), String> {
+    let content = serde_json::to_vec_pretty(memories).map_err(|e| e.to_string())?;
+    let content = crate::encryption::seal(app, content)?;
+    fs::write(memories_path(app)?, content).map_err(|e| format!("Failed to write memories: {}", e))
+}
+
+fn now() -> u64 {
+    chats::now_millis() as u64
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn same_fact(a: &str, b: &str) -> bool {
+    normalize(a).trim_end_matches('.').eq_ignore_ascii_case(normalize(b).trim_end_matches('.'))
+}
+
+fn schema() -> OutputSchema {
+    OutputSchema {
+        name: "user_facts".to_string(),
+        schema: json!({
+            "type": "object",
+            "properties": {
+                "facts": {
+                    "type": "array",
+                    "items": { "type": "string", "maxLength": MAX_FACT_CHARS },
+                    "maxItems": MAX_FACTS_PER_TURN
+                }
+            },
+            "required": ["facts"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+fn clip(content: &str) -> String {
+    match content.char_indices().nth(MAX_MESSAGE_CHARS) {
+        Some((end, _)) => format!("{}…", &content[..end]),
+        None => content.to_string(),
+    }
+}
+
+// Asks the model for durable facts in the latest exchange that aren't
+// known yet. Only the last turn is read, so each turn is looked at once.
+async fn extract(
+    provider: &ProviderConfig,
+    model: &str,
+    known: &[Memory],
+    messages: &[ChatMessage],
+    anonymize: Option<Scope>,
+) -> Result<Vec<String>, String> {
+    let Some(start) = messages.iter().rposition(|m| m.role == "user") else {
+        return Ok(Vec::new());
+    };
+    let turn: Vec<ChatMessage> = messages[start..]
+        .iter()
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .map(|m| ChatMessage::new(&m.role, clip(&m.content)))
+        .collect();
+
+    let mut instructions = String::from(
+        "Extract durable facts about the user from the conversation below: preferences, tools they use, \
+         their setup, role, location or timezone, and similar things likely to still hold in future \
+         conversations. Write each as a short third-person statement, e.g. \"Uses pnpm\" or \"Timezone is \
+         Asia/Taipei\". Leave out one-off requests, anything about the assistant, and secrets such as \
+         passwords or keys. Return an empty list when there is nothing worth keeping.",
+    );
+    if !known.is_empty() {
+        instructions.push_str("\n\nAlready known, don't repeat these:");
+        for memory in known {
+            instructions.push_str(&format!("\n- {}", memory.text));
+        }
+    }
+
+    let request = CompletionRequest {
+        provider_id: Some(provider.id.clone()),
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage::new("system", instructions),
+            ChatMessage::new("user", context::transcript(None, &turn)),
+        ],
+        anonymize,
+        ..Default::default()
+    };
+    let result = structured::complete_structured(provider, request, &schema(), 1).await?;
+    let extraction: Extraction = serde_json::from_value(result.value).map_err(|e| e.to_string())?;
+    Ok(extraction.facts)
+}
+
+// Adds the facts that aren't stored yet and returns the new memories
+fn remember(app: &tauri::AppHandle, facts: Vec<String>, chat_id: Option<&str>) -> Result<Vec<Memory>, String> {
+    let _guard = MEMORY_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut memories = load(app)?;
+    let mut added = Vec::new();
+    for fact in facts.iter().map(|f| normalize(f)).filter(|f| !f.is_empty()) {
+        if memories.iter().chain(&added).any(|m: &Memory| same_fact(&m.text, &fact)) {
+            continue;
+        }
+        let now = now();
+        added.push(Memory {
+            id: format!("memory_{}_{}", chats::now_millis(), added.len()),
+            text: fact.chars().take(MAX_FACT_CHARS).collect(),
+            chat_id: chat_id.map(str::to_string),
+            created_at: now,
+            updated_at: now,
+        });
+    }
+    if !added.is_empty() {
+        memories.extend(added.iter().cloned());
+        save(app, &memories)?;
+    }
+    Ok(added)
+}
+
+// Runs the extraction pass for the finished turn in the background when
+// it's turned on in settings
+pub fn schedule(
+    app: &tauri::AppHandle,
+    settings: &Settings,
+    provider: &ProviderConfig,
+    model: &str,
+    chat_id: String,
+    messages: Vec<ChatMessage>,
+) {
+    if !settings.extract_memories || incognito::is_incognito(&chat_id) {
+        return;
+    }
+    let anonymize = anonymize::key(settings, provider, Some(&chat_id));
+    let app = app.clone();
+    let provider = provider.clone();
+    let model = model.to_string();
+    tauri::async_runtime::spawn(async move {
+        let extracted = match load(&app) {
+            Ok(known) => extract(&provider, &model, &known, &messages, anonymize).await,
+            Err(e) => Err(e),
+        };
+        match extracted.and_then(|facts| remember(&app, facts, Some(&chat_id))) {
+            Ok(added) if !added.is_empty() => {
+                let _ = app.emit("memories-added", added);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to extract memories from {}: {}", chat_id, e),
+        }
+    });
+}
+
+// Adds what's known about the user to the system prompt, creating one when
+// the request has none. Runs after the chat's own prompt is in place.
+pub fn apply(app: &tauri::AppHandle, settings: &Settings, request: &mut CompletionRequest) {
+    if !settings.inject_memories {
+        return;
+    }
+    let memories = load(app).unwrap_or_else(|e| {
+        log::warn!("Failed to load memories: {}", e);
+        Vec::new()
+    });
+    if memories.is_empty() {
+        return;
+    }
+
+    let mut block = String::from("What you know about the user from earlier conversations:");
+    for memory in &memories {
+        block.push_str(&format!("\n- {}", memory.text));
+    }
+    match request.messages.first_mut().filter(|m| m.role == "system") {
+        Some(system) => system.content = format!("{}\n\n{}", system.content, block),
+        None => request.messages.insert(0, ChatMessage::new("system", block)),
+    }
+}
+
+// Drops the memories extracted from a deleted chat, when secure deletion is
+// on; otherwise they're kept, like ones added by hand
+pub fn forget_chat(app: &tauri::AppHandle, chat_id: &str) -> Result<(), String> {
+    if !crate::shred::enabled(app) {
+        return Ok(());
+    }
+    let _guard = MEMORY_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut memories = load(app)?;
+    let before = memories.len();
+    memories.retain(|m| m.chat_id.as_deref() != Some(chat_id));
+    if memories.len() == before {
+        return Ok(());
+    }
+    save(app, &memories)
+}
+
+#[tauri::command]
+pub fn list_memories(app: tauri::AppHandle) -> Result<Vec<Memory>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    load(&app)
+}
+
+#[tauri::command]
+pub fn add_memory(app: tauri::AppHandle, text: String) -> Result<Memory, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if text.trim().is_empty() {
+        return Err("Memory text is required".to_string());
+    }
+    remember(&app, vec![text.clone()], None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Already remembered: {}", text.trim()))
+}
+
+#[tauri::command]
+pub fn update_memory(app: tauri::AppHandle, id: String, text: String) -> Result<Memory, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let text = normalize(&text);
+    if text.is_empty() {
+        return Err("Memory text is required".to_string());
+    }
+    let _guard = MEMORY_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut memories = load(&app)?;
+    let memory = memories.iter_mut().find(|m| m.id == id).ok_or_else(|| format!("Unknown memory: {}", id))?;
+    memory.text = text;
+    memory.updated_at = now();
+    let memory = memory.clone();
+    save(&app, &memories)?;
+    Ok(memory)
+}
+
+#[tauri::command]
+pub fn delete_memory(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let _guard = MEMORY_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut memories = load(&app)?;
+    let before = memories.len();
+    memories.retain(|m| m.id != id);
+    if memories.len() == before {
+        return Err(format!("Unknown memory: {}", id));
+    }
+    save(&app, &memories)
+}
+
+#[tauri::command]
+pub fn clear_memories(app: tauri::AppHandle) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let _guard = MEMORY_LOCK.lock().map_err(|e| e.to_string())?;
+    save(&app, &[])
+}
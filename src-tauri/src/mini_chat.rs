@@ -0,0 +1,203 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tauri::ipc::Channel;
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconEvent};
+use tauri::{Manager, PhysicalPosition, Rect, WebviewUrl, WebviewWindowBuilder};
+
+use crate::chats;
+use crate::completion::{self, CompletionRequest, StreamEvent};
+use crate::settings;
+
+// A small chat that drops down from the menu bar icon (the tray icon
+// elsewhere) when it's clicked, for a quick question without bringing up
+// the main window; the icon's menu moves to a right click. The conversation
+// lives in memory until it's cleared or saved as a regular chat, and uses
+// the model picked for quick prompts, see quick_prompt.rs.
+
+pub const LABEL: &str = "mini";
+const URL: &str = "mini";
+const WIDTH: f64 = 380.0;
+const HEIGHT: f64 = 520.0;
+// Between the icon and the window
+const GAP: i32 = 4;
+// Clicking the icon while the window is open first takes the focus from it,
+// which hides it; a click that soon after is that same click
+const REOPEN_GUARD: Duration = Duration::from_millis(300);
+
+static MESSAGES: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+static HIDDEN_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn enabled(app: &tauri::AppHandle) -> bool {
+    settings::load(app).is_ok_and(|s| s.menu_bar_chat)
+}
+
+// Left clicks open the chat while it's on, so the menu is for right clicks
+pub fn configure(app: &tauri::AppHandle) {
+    if let Some(tray) = app.tray_by_id(crate::tray::TRAY_ID) {
+        if let Err(e) = tray.set_show_menu_on_left_click(!enabled(app)) {
+            log::warn!("Failed to update the tray icon: {}", e);
+        }
+    }
+}
+
+// Under the icon when it's at the top of the screen, above it otherwise,
+// and kept on the monitor it's on
+fn position(window: &tauri::WebviewWindow, rect: &Rect) -> Option<PhysicalPosition<i32>> {
+    let scale = window.scale_factor().unwrap_or(1.0);
+    let icon = rect.position.to_physical::<f64>(scale);
+    let icon_size = rect.size.to_physical::<f64>(scale);
+    let size = window.outer_size().ok()?;
+    let monitor = window.monitor_from_point(icon.x, icon.y).ok().flatten()?;
+    let (origin, area) = (monitor.position(), monitor.size());
+    let below = icon.y < origin.y as f64 + area.height as f64 / 2.0;
+    let x = (icon.x + icon_size.width / 2.0 - size.width as f64 / 2.0) as i32;
+    let y = if below {
+        (icon.y + icon_size.height) as i32 + GAP
+    } else {
+        icon.y as i32 - size.height as i32 - GAP
+    };
+    let max_x = origin.x + area.width as i32 - size.width as i32;
+    let max_y = origin.y + area.height as i32 - size.height as i32;
+    Some(PhysicalPosition::new(x.clamp(origin.x, max_x.max(origin.x)), y.clamp(origin.y, max_y.max(origin.y))))
+}
+
+fn toggle(app: &tauri::AppHandle, rect: &Rect) -> Result<(), String> {
+    let window = match app.get_webview_window(LABEL) {
+        Some(window) => {
+            if window.is_visible().unwrap_or(false) {
+                return window.hide().map_err(|e| e.to_string());
+            }
+            let just_hidden = HIDDEN_AT.lock().ok().and_then(|at| *at).is_some_and(|at| at.elapsed() < REOPEN_GUARD);
+            if just_hidden {
+                return Ok(());
+            }
+            window
+        }
+        None => WebviewWindowBuilder::new(app, LABEL, WebviewUrl::App(URL.into()))
+            .title("Anchor")
+            .inner_size(WIDTH, HEIGHT)
+            .resizable(false)
+            .decorations(false)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .visible_on_all_workspaces(true)
+            .visible(false)
+            .build()
+            .map_err(|e| format!("Failed to open the menu bar chat: {}", e))?,
+    };
+    if let Some(position) = position(&window, rect) {
+        let _ = window.set_position(position);
+    }
+    let _ = window.show();
+    window.set_focus().map_err(|e| e.to_string())
+}
+
+pub fn on_tray_event(app: &tauri::AppHandle, event: TrayIconEvent) {
+    let TrayIconEvent::Click { rect, button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event else {
+        return;
+    };
+    if !enabled(app) {
+        return;
+    }
+    if let Err(e) = toggle(app, &rect) {
+        log::warn!("{}", e);
+    }
+}
+
+// Tucks the window away once the user clicks elsewhere
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if window.label() == LABEL && matches!(event, tauri::WindowEvent::Focused(false)) {
+        let _ = window.hide();
+        if let Ok(mut at) = HIDDEN_AT.lock() {
+            *at = Some(Instant::now());
+        }
+    }
+}
+
+fn session(messages: Vec<Value>) -> Value {
+    json!({ "id": "", "title": "", "timestamp": chats::now_millis() as u64, "messages": messages })
+}
+
+// Streams the reply over on_event and returns it
+#[tauri::command]
+pub async fn send_mini_prompt(
+    app: tauri::AppHandle,
+    prompt: String,
+    on_event: Channel<StreamEvent>,
+) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err("Type a prompt first".to_string());
+    }
+    let quick = settings::load(&app)?.quick_prompt;
+    let model = Some(quick.model.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .ok_or("Pick a model for quick prompts in settings")?;
+    let messages = {
+        let mut messages = MESSAGES.lock().map_err(|e| e.to_string())?;
+        messages.push(json!({ "role": "user", "content": prompt, "timestamp": chats::now_millis() as u64 }));
+        messages.clone()
+    };
+    let request = CompletionRequest {
+        provider_id: quick.provider_id,
+        model: model.clone(),
+        messages: chats::messages(&session(messages)),
+        ..Default::default()
+    };
+    let result = completion::stream_completion(app.clone(), request, on_event).await;
+    let mut messages = MESSAGES.lock().map_err(|e| e.to_string())?;
+    match &result {
+        Ok(reply) => messages.push(json!({
+            "role": "assistant",
+            "content": reply,
+            "model": model,
+            "timestamp": chats::now_millis() as u64,
+        })),
+        // Left out, so the prompt can be sent again
+        Err(_) => {
+            messages.pop();
+        }
+    }
+    crate::analytics::track(&app, "send_mini_prompt", result)
+}
+
+// The conversation so far, for a window that has only just opened
+#[tauri::command]
+pub fn mini_chat_messages() -> Result<Vec<Value>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    MESSAGES.lock().map(|m| m.clone()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn clear_mini_chat() -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    MESSAGES.lock().map(|mut m| m.clear()).map_err(|e| e.to_string())
+}
+
+// Keeps the conversation as a regular chat, starts a fresh one and returns
+// the chat's id
+#[tauri::command]
+pub fn save_mini_chat(app: tauri::AppHandle) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut messages = MESSAGES.lock().map_err(|e| e.to_string())?;
+    if messages.is_empty() {
+        return Err("There's nothing to save yet".to_string());
+    }
+    let mut session = session(messages.clone());
+    let title = messages[0]["content"].as_str().unwrap_or_default().lines().next().unwrap_or_default();
+    session["title"] = json!(title.chars().take(60).collect::<String>());
+    let id = chats::write(&app, session)?;
+    messages.clear();
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn hide_mini_chat(app: tauri::AppHandle) -> Result<(), String> {
+    match app.get_webview_window(LABEL) {
+        Some(window) => window.hide().map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
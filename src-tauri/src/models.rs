@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::settings::{self, ProviderConfig, ProviderKind};
+
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+const LOCAL_TIMEOUT: Duration = Duration::from_secs(3);
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    pub provider_id: String,
+    pub provider_name: String,
+    pub owned_by: Option<String>,
+}
+
+struct CachedModels {
+    // Base URL and key the list was fetched with, so edits to a provider invalidate it
+    config_key: String,
+    fetched_at: Instant,
+    models: Vec<ModelInfo>,
+}
+
+#[derive(Default)]
+pub struct ModelCache(Mutex<HashMap<String, CachedModels>>);
+
+fn config_key(provider: &ProviderConfig) -> String {
+    format!("{}|{}", provider.api_base(), provider.api_key)
+}
+
+async fn fetch_models(provider: &ProviderConfig) -> Result<Vec<ModelInfo>, String> {
+    let base = provider.api_base();
+    let url = match provider.kind {
+        // Ollama's native listing lives outside the OpenAI-compatible /v1 prefix
+        ProviderKind::Ollama => format!("{}/api/tags", base.strip_suffix("/v1").unwrap_or(&base)),
+        ProviderKind::OpenAi | ProviderKind::LlamaCpp => format!("{}/models", base),
+    };
+
+    let mut request = crate::http::client()
+        .get(&url)
+        .timeout(if provider.is_local() { LOCAL_TIMEOUT } else { REMOTE_TIMEOUT });
+    if !provider.api_key.trim().is_empty() {
+        request = request.bearer_auth(provider.api_key.trim());
+    }
+
+    let response = crate::http::send(request)
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", provider.name, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{} returned {} for {}", provider.name, response.status(), url));
+    }
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid model list from {}: {}", provider.name, e))?;
+
+    let (entries, id_field) = match provider.kind {
+        ProviderKind::Ollama => (&body["models"], "name"),
+        ProviderKind::OpenAi | ProviderKind::LlamaCpp => (&body["data"], "id"),
+    };
+
+    let mut models: Vec<ModelInfo> = entries
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    Some(ModelInfo {
+                        id: item[id_field].as_str()?.to_string(),
+                        provider_id: provider.id.clone(),
+                        provider_name: provider.name.clone(),
+                        owned_by: item["owned_by"].as_str().map(str::to_string),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(models)
+}
+
+#[tauri::command]
+pub async fn list_models(
+    app: tauri::AppHandle,
+    cache: State<'_, ModelCache>,
+    refresh: Option<bool>,
+) -> Result<Vec<ModelInfo>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let settings = settings::load(&app)?;
+    let providers: Vec<ProviderConfig> = settings.providers.into_iter().filter(|p| p.enabled).collect();
+    let refresh = refresh.unwrap_or(false);
+
+    // Query stale providers concurrently so one unreachable local engine doesn't stall the rest
+    let mut pending = Vec::new();
+    {
+        let cached = cache.0.lock().map_err(|e| e.to_string())?;
+        for provider in &providers {
+            let fresh = cached.get(&provider.id).is_some_and(|entry| {
+                entry.config_key == config_key(provider) && entry.fetched_at.elapsed() < CACHE_TTL
+            });
+            if refresh || !fresh {
+                let provider = provider.clone();
+                pending.push(tauri::async_runtime::spawn(async move {
+                    let result = fetch_models(&provider).await;
+                    (provider, result)
+                }));
+            }
+        }
+    }
+
+    for handle in pending {
+        let (provider, result) = handle.await.map_err(|e| e.to_string())?;
+        match result {
+            Ok(models) => {
+                cache.0.lock().map_err(|e| e.to_string())?.insert(
+                    provider.id.clone(),
+                    CachedModels {
+                        config_key: config_key(&provider),
+                        fetched_at: Instant::now(),
+                        models,
+                    },
+                );
+            }
+            // Keep serving whatever we fetched last time rather than emptying the picker
+            Err(e) => log::warn!("Model listing failed: {}", e),
+        }
+    }
+
+    let cached = cache.0.lock().map_err(|e| e.to_string())?;
+    Ok(providers
+        .iter()
+        .filter_map(|p| cached.get(&p.id).filter(|entry| entry.config_key == config_key(p)))
+        .flat_map(|entry| entry.models.iter().cloned())
+        .collect())
+}
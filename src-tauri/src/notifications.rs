@@ -0,0 +1,75 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri::Manager;
+use tauri_plugin_notification::NotificationExt;
+
+use crate::deep_link::{self, Navigation};
+use crate::{chats, incognito, settings};
+
+// A system notification when an answer, tool steps included, finishes while
+// the window is in the background. Desktop notifications don't tell the app
+// they were clicked, but clicking one brings Anchor to the front: a window
+// focused soon after a notification opens the chat it was about.
+
+const MAX_SNIPPET_CHARS: usize = 140;
+// How long after a notification focusing the window still counts as a click
+const CLICK_WINDOW: Duration = Duration::from_secs(120);
+
+static LAST: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+fn in_front(app: &tauri::AppHandle) -> bool {
+    app.webview_windows().values().any(|w| w.is_focused().unwrap_or(false))
+}
+
+fn snippet(content: &str) -> String {
+    let text = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= MAX_SNIPPET_CHARS {
+        return text;
+    }
+    format!("{}…", text.chars().take(MAX_SNIPPET_CHARS - 1).collect::<String>())
+}
+
+// A streamed answer for `chat_id` finished after `elapsed`
+pub fn finished(app: &tauri::AppHandle, chat_id: Option<&str>, content: &str, elapsed: Duration) {
+    // Not set up for command-line runs
+    if app.try_state::<tauri_plugin_notification::Notification<tauri::Wry>>().is_none() {
+        return;
+    }
+    let Ok(settings) = settings::load(app) else {
+        return;
+    };
+    let wanted = &settings.notifications;
+    if !wanted.enabled || elapsed < Duration::from_secs(wanted.min_seconds) || in_front(app) {
+        return;
+    }
+    let session = chat_id.and_then(|id| chats::read(app, id).ok());
+    let title = session.as_ref().and_then(|s| s["title"].as_str()).filter(|t| !t.trim().is_empty());
+    let private = crate::app_lock::enabled(app) || chat_id.is_some_and(incognito::is_incognito);
+    let body = if wanted.show_snippet && !private && !content.trim().is_empty() {
+        snippet(content)
+    } else {
+        "The answer is ready".to_string()
+    };
+    let title = match title {
+        Some(title) if !private => title.to_string(),
+        _ => "Anchor".to_string(),
+    };
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        log::warn!("Failed to show a notification: {}", e);
+        return;
+    }
+    if let (Some(id), Ok(mut last)) = (chat_id, LAST.lock()) {
+        *last = Some((id.to_string(), Instant::now()));
+    }
+}
+
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if window.label() != "main" || !matches!(event, tauri::WindowEvent::Focused(true)) {
+        return;
+    }
+    let last = LAST.lock().ok().and_then(|mut last| last.take());
+    if let Some((chat_id, _)) = last.filter(|(_, at)| at.elapsed() < CLICK_WINDOW) {
+        deep_link::navigate(window.app_handle(), Navigation::OpenChat { chat_id }, true);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::Emitter;
+use tauri_plugin_http::reqwest::Url;
+
+use crate::settings::{self, OAuthAppSettings, SyncBackend};
+use crate::sync::secret_account;
+use crate::{chats, http, keychain};
+
+// Signing in to Dropbox and Google Drive for sync. Google Drive uses the
+// device flow: the user enters a short code on Google's page while this polls
+// for the outcome. Dropbox has no device flow, so it uses PKCE without a
+// redirect instead: the user approves in the browser and pastes back the code
+// Dropbox shows. Only the refresh token is kept, in the keychain; access
+// tokens stay in memory until just before they expire.
+
+const GOOGLE_DEVICE_URL: &str = "https://oauth2.googleapis.com/device/code";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+// The app's own hidden folder, and nothing else in the user's Drive
+const GOOGLE_SCOPE: &str = "https://www.googleapis.com/auth/drive.appdata";
+const DEVICE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const DROPBOX_AUTHORIZE_URL: &str = "https://www.dropbox.com/oauth2/authorize";
+const DROPBOX_TOKEN_URL: &str = "https://api.dropboxapi.com/oauth2/token";
+const FINISHED_EVENT: &str = "sync-auth-finished";
+// Access tokens are refreshed this long before they run out
+const EXPIRY_MARGIN_MILLIS: u64 = 60 * 1000;
+
+// Backend -> access token and when it expires
+static TOKENS: Mutex<Option<HashMap<SyncBackend, (String, u64)>>> = Mutex::new(None);
+// The PKCE verifier of a Dropbox sign-in waiting for its code
+static VERIFIER: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthPrompt {
+    // Where the user approves access
+    pub url: String,
+    // Entered at `url`; None when the provider shows a code to paste back
+    // into finish_sync_auth instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Finished {
+    backend: SyncBackend,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TokenResponse {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+fn label(backend: SyncBackend) -> &'static str {
+    match backend {
+        SyncBackend::Dropbox => "Dropbox",
+        _ => "Google Drive",
+    }
+}
+
+fn app_settings(app: &tauri::AppHandle, backend: SyncBackend) -> Result<OAuthAppSettings, String> {
+    let sync = settings::load(app)?.sync;
+    let oauth = match backend {
+        SyncBackend::Dropbox => sync.dropbox,
+        SyncBackend::GoogleDrive => sync.google_drive,
+        _ => return Err("That sync backend doesn't sign in with an account".to_string()),
+    };
+    if oauth.client_id.trim().is_empty() {
+        return Err(format!("Set the {} app's client id first", label(backend)));
+    }
+    Ok(oauth)
+}
+
+async fn post_form(url: &str, form: &[(&str, &str)]) -> Result<TokenResponse, String> {
+    let request = http::client().post(url).form(form);
+    let response = http::send(request).await.map_err(|e| e.to_string())?;
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    serde_json::from_slice(&body).map_err(|e| format!("Unexpected sign-in response: {}", e))
+}
+
+fn describe(response: &TokenResponse) -> String {
+    let error = response.error.as_deref().unwrap_or("unknown error");
+    match &response.error_description {
+        Some(description) => format!("{} ({})", description, error),
+        None => error.to_string(),
+    }
+}
+
+// Keeps the tokens a sign-in or refresh returned
+fn keep(backend: SyncBackend, response: TokenResponse) -> Result<String, String> {
+    let access_token = response.access_token.clone().ok_or_else(|| describe(&response))?;
+    if let Some(refresh_token) = &response.refresh_token {
+        keychain::store(&secret_account(backend), refresh_token)?;
+    }
+    let expires_at = chats::now_millis() as u64 + response.expires_in.unwrap_or(3600) * 1000;
+    if let Ok(mut tokens) = TOKENS.lock() {
+        tokens.get_or_insert_with(HashMap::new).insert(backend, (access_token.clone(), expires_at));
+    }
+    Ok(access_token)
+}
+
+// After the provider turned a token down, so the next call refreshes it
+pub fn forget_token(backend: SyncBackend) {
+    if let Ok(mut tokens) = TOKENS.lock() {
+        if let Some(tokens) = tokens.as_mut() {
+            tokens.remove(&backend);
+        }
+    }
+}
+
+pub async fn access_token(app: &tauri::AppHandle, backend: SyncBackend) -> Result<String, String> {
+    let now = chats::now_millis() as u64;
+    let cached = TOKENS.lock().ok().and_then(|tokens| tokens.as_ref()?.get(&backend).cloned());
+    if let Some((token, _)) = cached.filter(|(_, expires_at)| *expires_at > now + EXPIRY_MARGIN_MILLIS) {
+        return Ok(token);
+    }
+    let oauth = app_settings(app, backend)?;
+    let refresh_token =
+        keychain::load(&secret_account(backend)).map_err(|_| format!("Sign in to {} first", label(backend)))?;
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token.as_str()),
+        ("client_id", oauth.client_id.trim()),
+    ];
+    if !oauth.client_secret.is_empty() {
+        form.push(("client_secret", oauth.client_secret.trim()));
+    }
+    let url = if backend == SyncBackend::Dropbox { DROPBOX_TOKEN_URL } else { GOOGLE_TOKEN_URL };
+    let response = post_form(url, &form).await?;
+    if response.error.as_deref() == Some("invalid_grant") {
+        return Err(format!("{} signed Anchor out; sign in again", label(backend)));
+    }
+    keep(backend, response)
+}
+
+// Polls until the user approves or declines on Google's page
+fn poll_device(oauth: &OAuthAppSettings, device: DeviceCode) -> Result<(), String> {
+    let mut interval = device.interval.unwrap_or(5);
+    let deadline = chats::now_millis() as u64 + device.expires_in * 1000;
+    while (chats::now_millis() as u64) < deadline {
+        std::thread::sleep(Duration::from_secs(interval));
+        let form = [
+            ("client_id", oauth.client_id.trim()),
+            ("client_secret", oauth.client_secret.trim()),
+            ("device_code", device.device_code.as_str()),
+            ("grant_type", DEVICE_GRANT),
+        ];
+        let response = tauri::async_runtime::block_on(post_form(GOOGLE_TOKEN_URL, &form))?;
+        match response.error.as_deref() {
+            None => return keep(SyncBackend::GoogleDrive, response).map(|_| ()),
+            Some("authorization_pending") => {}
+            Some("slow_down") => interval += 5,
+            Some("access_denied") => return Err("Access to Google Drive was declined".to_string()),
+            Some(_) => return Err(describe(&response)),
+        }
+    }
+    Err("The sign-in code expired; start again".to_string())
+}
+
+#[tauri::command]
+pub async fn start_sync_auth(app: tauri::AppHandle, backend: SyncBackend) -> Result<AuthPrompt, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let oauth = app_settings(&app, backend)?;
+    if backend == SyncBackend::Dropbox {
+        let verifier = URL_SAFE_NO_PAD.encode(crate::encryption::random(32)?);
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        let url = Url::parse_with_params(
+            DROPBOX_AUTHORIZE_URL,
+            [
+                ("client_id", oauth.client_id.trim()),
+                ("response_type", "code"),
+                ("token_access_type", "offline"),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        *VERIFIER.lock().map_err(|e| e.to_string())? = Some(verifier);
+        return Ok(AuthPrompt { url: url.to_string(), user_code: None, expires_at: None });
+    }
+
+    let form = [("client_id", oauth.client_id.trim()), ("scope", GOOGLE_SCOPE)];
+    let response = http::send(http::client().post(GOOGLE_DEVICE_URL).form(&form)).await.map_err(|e| e.to_string())?;
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    let device: DeviceCode = serde_json::from_slice(&body).map_err(|_| {
+        let error: TokenResponse = serde_json::from_slice(&body).unwrap_or_default();
+        format!("Google didn't start the sign-in: {}", describe(&error))
+    })?;
+    let prompt = AuthPrompt {
+        url: device.verification_url.clone(),
+        user_code: Some(device.user_code.clone()),
+        expires_at: Some(chats::now_millis() as u64 + device.expires_in * 1000),
+    };
+    std::thread::spawn(move || {
+        let result = poll_device(&oauth, device);
+        if let Err(e) = &result {
+            log::warn!("Google Drive sign-in failed: {}", e);
+        }
+        let _ = app.emit(FINISHED_EVENT, Finished { backend, error: result.err() });
+    });
+    Ok(prompt)
+}
+
+// Takes the code Dropbox showed after the user approved access
+#[tauri::command]
+pub async fn finish_sync_auth(app: tauri::AppHandle, backend: SyncBackend, code: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    if backend != SyncBackend::Dropbox {
+        return Err(format!("{} finishes signing in by itself", label(backend)));
+    }
+    let oauth = app_settings(&app, backend)?;
+    let verifier = VERIFIER.lock().map_err(|e| e.to_string())?.take().ok_or("Start signing in to Dropbox first")?;
+    let form = [
+        ("code", code.trim()),
+        ("grant_type", "authorization_code"),
+        ("client_id", oauth.client_id.trim()),
+        ("code_verifier", verifier.as_str()),
+    ];
+    let result = post_form(DROPBOX_TOKEN_URL, &form).await.and_then(|response| keep(backend, response)).map(|_| ());
+    let _ = app.emit(FINISHED_EVENT, Finished { backend, error: result.clone().err() });
+    result
+}
+
+#[tauri::command]
+pub fn sign_out_sync(backend: SyncBackend) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    forget_token(backend);
+    keychain::delete(&secret_account(backend))
+}
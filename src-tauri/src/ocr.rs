@@ -0,0 +1,95 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ocrs::{ImageSource, OcrEngine, OcrEngineParams};
+
+use crate::extraction::{self, Document};
+
+// Text recognition runs in-process with ocrs, a pure-Rust engine, so nothing
+// needs installing. Its models read Latin script only. They're fetched on
+// first use and the engine is kept for the rest of the session.
+
+const MODEL_BASE_URL: &str = "https://ocrs-models.s3-accelerate.amazonaws.com";
+const DETECTION_MODEL: &str = "text-detection.rten";
+const RECOGNITION_MODEL: &str = "text-recognition.rten";
+
+static ENGINE: Mutex<Option<Arc<OcrEngine>>> = Mutex::new(None);
+
+fn models_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("ocr");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create OCR directory: {}", e))?;
+    }
+    Ok(dir)
+}
+
+async fn download(path: &Path, file_name: &str) -> Result<(), String> {
+    log::info!("Downloading OCR model {}", file_name);
+    let request = crate::http::client().get(format!("{}/{}", MODEL_BASE_URL, file_name));
+    let response = crate::http::send(request)
+        .await
+        .map_err(|e| format!("Failed to download the OCR model: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("OCR model download returned {}", response.status()));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+
+    let partial = path.with_extension("part");
+    fs::File::create(&partial).and_then(|mut file| file.write_all(&bytes)).map_err(|e| e.to_string())?;
+    fs::rename(&partial, path).map_err(|e| e.to_string())
+}
+
+fn load_model(app: &tauri::AppHandle, file_name: &str) -> Result<rten::Model, String> {
+    let path = models_dir(app)?.join(file_name);
+    if !path.exists() {
+        tauri::async_runtime::block_on(download(&path, file_name))?;
+    }
+    rten::Model::load_file(&path).map_err(|e| format!("Failed to load the OCR model: {}", e))
+}
+
+fn engine(app: &tauri::AppHandle) -> Result<Arc<OcrEngine>, String> {
+    let mut engine = ENGINE.lock().map_err(|e| e.to_string())?;
+    if let Some(engine) = engine.as_ref() {
+        return Ok(engine.clone());
+    }
+    let loaded = OcrEngine::new(OcrEngineParams {
+        detection_model: Some(load_model(app, DETECTION_MODEL)?),
+        recognition_model: Some(load_model(app, RECOGNITION_MODEL)?),
+        ..Default::default()
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(engine.insert(Arc::new(loaded)).clone())
+}
+
+// Blocking; the image crate sniffs the format itself, so extensionless
+// attachment blobs are fine.
+pub fn recognize_attachment(app: &tauri::AppHandle, path: &Path) -> Result<String, String> {
+    let image = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| format!("Failed to read the image: {}", e))?
+        .decode()
+        .map_err(|e| format!("Failed to decode the image: {}", e))?
+        .into_rgb8();
+    let engine = engine(app)?;
+    let source = ImageSource::from_bytes(image.as_raw(), image.dimensions()).map_err(|e| e.to_string())?;
+    let input = engine.prepare_input(source).map_err(|e| e.to_string())?;
+    let text = engine.get_text(&input).map_err(|e| format!("Text recognition failed: {}", e))?;
+    Ok(text.trim().to_string())
+}
+
+// Same as extract_text, but only for images so the UI can offer it explicitly.
+#[tauri::command]
+pub async fn ocr_attachment(app: tauri::AppHandle, attachment_id: String) -> Result<Document, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let document = extraction::extract(&app, &attachment_id)?;
+        if document.format != "image" {
+            return Err(format!("{} is not an image", document.name));
+        }
+        Ok(document)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,296 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use calamine::{Reader as _, Xlsx};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::ZipArchive;
+
+use crate::extraction::Section;
+
+// Big sheets are cut off so a single attachment can't swamp the prompt
+const MAX_SHEET_ROWS: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OfficeKind {
+    Docx,
+    Pptx,
+    Xlsx,
+}
+
+impl OfficeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            OfficeKind::Docx => "docx",
+            OfficeKind::Pptx => "pptx",
+            OfficeKind::Xlsx => "xlsx",
+        }
+    }
+}
+
+// OOXML files are zips; the part names tell them apart.
+pub fn detect(path: &Path) -> Option<OfficeKind> {
+    let archive = ZipArchive::new(File::open(path).ok()?).ok()?;
+    let has = |name: &str| archive.file_names().any(|n| n == name);
+    if has("word/document.xml") {
+        Some(OfficeKind::Docx)
+    } else if has("ppt/presentation.xml") {
+        Some(OfficeKind::Pptx)
+    } else if has("xl/workbook.xml") {
+        Some(OfficeKind::Xlsx)
+    } else {
+        None
+    }
+}
+
+pub fn extract(kind: OfficeKind, path: &Path) -> Result<Vec<Section>, String> {
+    match kind {
+        OfficeKind::Docx => docx(path),
+        OfficeKind::Pptx => pptx(path),
+        OfficeKind::Xlsx => xlsx(path),
+    }
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.trim().replace('|', "\\|").replace('\n', "<br>")
+}
+
+// First row becomes the header, as that's almost always what it is
+pub(crate) fn markdown_table(rows: &[Vec<String>]) -> String {
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if width == 0 {
+        return String::new();
+    }
+    let line = |row: &Vec<String>| {
+        let cells: Vec<String> = (0..width).map(|i| markdown_cell(row.get(i).map(String::as_str).unwrap_or(""))).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut out = vec![line(&rows[0]), format!("|{}", " --- |".repeat(width))];
+    out.extend(rows[1..].iter().map(line));
+    out.join("\n")
+}
+
+fn attr(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .map(|a| String::from_utf8_lossy(&a.value).to_string())
+}
+
+fn zip_part(archive: &mut ZipArchive<File>, name: &str) -> Result<String, String> {
+    let mut part = archive.by_name(name).map_err(|e| format!("Missing {}: {}", name, e))?;
+    let mut xml = String::new();
+    part.read_to_string(&mut xml).map_err(|e| e.to_string())?;
+    Ok(xml)
+}
+
+// Collects table cells while inside <tbl>; nested tables are flattened into
+// the enclosing cell's text.
+#[derive(Default)]
+struct TableBuilder {
+    depth: usize,
+    rows: Vec<Vec<String>>,
+    cell: String,
+}
+
+impl TableBuilder {
+    fn active(&self) -> bool {
+        self.depth > 0
+    }
+}
+
+fn docx(path: &Path) -> Result<Vec<Section>, String> {
+    let mut archive = ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let xml = zip_part(&mut archive, "word/document.xml")?;
+    let mut reader = Reader::from_str(&xml);
+
+    let mut blocks: Vec<String> = Vec::new();
+    let mut paragraph = String::new();
+    let mut prefix = String::new();
+    let mut table = TableBuilder::default();
+    // Only <w:t> holds text; whitespace between elements is formatting
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event().map_err(|e| format!("Invalid document XML: {}", e))? {
+            Event::Start(e) if e.local_name().as_ref() == b"t" => in_text = true,
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"tbl" => table.depth += 1,
+                b"tr" if table.depth == 1 => table.rows.push(Vec::new()),
+                b"pStyle" => {
+                    let style = attr(&e, b"val").unwrap_or_default();
+                    if let Some(level) = style.strip_prefix("Heading").and_then(|l| l.parse::<usize>().ok()) {
+                        prefix = format!("{} ", "#".repeat(level.clamp(1, 6)));
+                    } else if style.eq_ignore_ascii_case("Title") {
+                        prefix = "# ".to_string();
+                    }
+                }
+                b"numPr" if prefix.is_empty() => prefix = "- ".to_string(),
+                b"tab" => paragraph.push('\t'),
+                b"br" => paragraph.push('\n'),
+                _ => {}
+            },
+            Event::Text(text) if in_text => {
+                paragraph.push_str(&text.unescape().map_err(|e| e.to_string())?);
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    let text = std::mem::take(&mut paragraph);
+                    let prefix = std::mem::take(&mut prefix);
+                    if table.active() {
+                        if !table.cell.is_empty() && !text.trim().is_empty() {
+                            table.cell.push(' ');
+                        }
+                        table.cell.push_str(text.trim());
+                    } else if !text.trim().is_empty() {
+                        blocks.push(format!("{}{}", prefix, text.trim()));
+                    }
+                }
+                b"tc" if table.depth == 1 => {
+                    let cell = std::mem::take(&mut table.cell);
+                    if let Some(row) = table.rows.last_mut() {
+                        row.push(cell);
+                    }
+                }
+                b"tbl" => {
+                    table.depth -= 1;
+                    if table.depth == 0 {
+                        blocks.push(markdown_table(&std::mem::take(&mut table.rows)));
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(vec![Section { title: None, page: None, text: blocks.join("\n\n") }])
+}
+
+// "ppt/slides/slide12.xml" -> 12, so slides sort in presentation order
+fn slide_number(name: &str) -> Option<u32> {
+    name.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?.parse().ok()
+}
+
+fn pptx(path: &Path) -> Result<Vec<Section>, String> {
+    let mut archive = ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let mut slides: Vec<(u32, String)> = archive
+        .file_names()
+        .filter_map(|name| Some((slide_number(name)?, name.to_string())))
+        .collect();
+    slides.sort();
+
+    let mut sections = Vec::new();
+    for (number, name) in slides {
+        let xml = zip_part(&mut archive, &name)?;
+        let mut reader = Reader::from_str(&xml);
+
+        let mut title: Option<String> = None;
+        let mut body: Vec<String> = Vec::new();
+        let mut shape_text: Vec<String> = Vec::new();
+        let mut paragraph = String::new();
+        let mut is_title = false;
+        let mut table = TableBuilder::default();
+        let mut in_text = false;
+
+        loop {
+            match reader.read_event().map_err(|e| format!("Invalid slide XML: {}", e))? {
+                Event::Start(e) if e.local_name().as_ref() == b"t" => in_text = true,
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"sp" => {
+                        is_title = false;
+                        shape_text.clear();
+                    }
+                    b"ph" => {
+                        is_title = matches!(attr(&e, b"type").as_deref(), Some("title" | "ctrTitle"));
+                    }
+                    b"tbl" => table.depth += 1,
+                    b"tr" if table.depth == 1 => table.rows.push(Vec::new()),
+                    b"br" => paragraph.push('\n'),
+                    _ => {}
+                },
+                Event::Text(text) if in_text => paragraph.push_str(&text.unescape().map_err(|e| e.to_string())?),
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"t" => in_text = false,
+                    b"p" => {
+                        let text = std::mem::take(&mut paragraph);
+                        if text.trim().is_empty() {
+                            continue;
+                        }
+                        if table.active() {
+                            if !table.cell.is_empty() {
+                                table.cell.push(' ');
+                            }
+                            table.cell.push_str(text.trim());
+                        } else {
+                            shape_text.push(text.trim().to_string());
+                        }
+                    }
+                    b"sp" => {
+                        let text = std::mem::take(&mut shape_text);
+                        if is_title && title.is_none() {
+                            title = Some(text.join(" "));
+                        } else {
+                            body.extend(text);
+                        }
+                    }
+                    b"tc" if table.depth == 1 => {
+                        let cell = std::mem::take(&mut table.cell);
+                        if let Some(row) = table.rows.last_mut() {
+                            row.push(cell);
+                        }
+                    }
+                    b"tbl" => {
+                        table.depth -= 1;
+                        if table.depth == 0 {
+                            body.push(markdown_table(&std::mem::take(&mut table.rows)));
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        sections.push(Section {
+            title: Some(title.unwrap_or_else(|| format!("Slide {}", number))),
+            page: Some(number),
+            text: body.join("\n"),
+        });
+    }
+    Ok(sections)
+}
+
+fn xlsx(path: &Path) -> Result<Vec<Section>, String> {
+    let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let mut workbook: Xlsx<_> = Xlsx::new(file).map_err(|e| format!("Failed to open workbook: {}", e))?;
+
+    let mut sections = Vec::new();
+    for (index, name) in workbook.sheet_names().into_iter().enumerate() {
+        let range = workbook
+            .worksheet_range(&name)
+            .map_err(|e| format!("Failed to read sheet {}: {}", name, e))?;
+
+        let mut rows: Vec<Vec<String>> = range
+            .rows()
+            .filter(|row| row.iter().any(|cell| !cell.to_string().trim().is_empty()))
+            .take(MAX_SHEET_ROWS + 1)
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect();
+        let truncated = rows.len() > MAX_SHEET_ROWS;
+        rows.truncate(MAX_SHEET_ROWS);
+
+        let mut text = markdown_table(&rows);
+        if truncated {
+            text.push_str(&format!("\n\n[{} rows total, first {} shown]", range.height(), MAX_SHEET_ROWS));
+        }
+        sections.push(Section { title: Some(name), page: Some(index as u32 + 1), text });
+    }
+    Ok(sections)
+}
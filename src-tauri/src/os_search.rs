@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_json::Value;
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+use tauri::Manager;
+
+use crate::{chats, settings};
+
+// A small file per chat where the system search looks, so a conversation
+// can be found from Spotlight or the Start menu: a .webloc in the folder
+// macOS keeps for app metadata, a .url under the Start menu's programs on
+// Windows. Each is named after the chat and opens anchor://chat/<id>, see
+// deep_link.rs. Those formats are indexed by name, so titles are all that
+// leave the chat store. Off by default, and nothing is written while the
+// app lock or chat encryption is on, since the titles would show outside
+// either. Incognito chats and sync conflict copies are never indexed.
+
+const INDEX_FILE: &str = "os_search.json";
+const MAX_NAME_CHARS: usize = 80;
+
+// Chat id -> the stub's file name
+static INDEX: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+#[cfg(target_os = "macos")]
+const EXTENSION: &str = "webloc";
+#[cfg(not(target_os = "macos"))]
+const EXTENSION: &str = "url";
+
+#[cfg(target_os = "macos")]
+fn stub_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let home = app.path().home_dir().map_err(|e| e.to_string())?;
+    Ok(home.join("Library/Caches/Metadata").join(&app.config().identifier))
+}
+
+#[cfg(target_os = "windows")]
+fn stub_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let roaming = app.path().data_dir().map_err(|e| e.to_string())?;
+    Ok(roaming.join("Microsoft/Windows/Start Menu/Programs/Anchor Chats"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn stub_dir(_app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Err("System search only indexes chats on macOS and Windows".to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn stub(link: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n\t<key>URL</key>\n\t<string>{}</string>\n</dict>\n</plist>\n",
+        link
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn stub(link: &str) -> String {
+    format!("[InternetShortcut]\r\nURL={}\r\n", link)
+}
+
+fn active(app: &tauri::AppHandle) -> bool {
+    settings::load(app).is_ok_and(|s| s.os_search)
+        && !crate::app_lock::enabled(app)
+        && !crate::encryption::enabled(app)
+}
+
+fn index_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join(INDEX_FILE))
+}
+
+fn with_index<T>(app: &tauri::AppHandle, f: impl FnOnce(&mut HashMap<String, String>) -> T) -> Result<T, String> {
+    let mut guard = INDEX.lock().map_err(|e| e.to_string())?;
+    let index = guard.get_or_insert_with(|| {
+        index_path(app)
+            .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    });
+    Ok(f(index))
+}
+
+fn save_index(app: &tauri::AppHandle) -> Result<(), String> {
+    let content = with_index(app, |index| serde_json::to_string_pretty(index))?.map_err(|e| e.to_string())?;
+    fs::write(index_path(app)?, content).map_err(|e| format!("Failed to save the search index: {}", e))
+}
+
+// The chat's title as a file name every system accepts
+fn file_stem(session: &Value) -> String {
+    let title = session["title"].as_str().unwrap_or_default();
+    let cleaned: String = title
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { ' ' } else { c })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let cleaned: String = cleaned.chars().take(MAX_NAME_CHARS).collect();
+    // Windows drops trailing dots and spaces, and hides leading dots elsewhere
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c == ' ');
+    if cleaned.is_empty() {
+        "Untitled chat".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+// A name no other chat's stub has, so two chats with the same title both show
+fn unique_name(stem: &str, taken: &HashSet<String>) -> String {
+    let mut name = format!("{}.{}", stem, EXTENSION);
+    let mut n = 2;
+    while taken.contains(&name.to_lowercase()) {
+        name = format!("{} ({}).{}", stem, n, EXTENSION);
+        n += 1;
+    }
+    name
+}
+
+fn indexed(session: &Value) -> Option<&str> {
+    let id = session["id"].as_str().filter(|id| !id.is_empty())?;
+    (session["conflictOf"].is_null() && !crate::incognito::is_incognito(id)).then_some(id)
+}
+
+fn write_stub(dir: &std::path::Path, name: &str, id: &str) -> Result<(), String> {
+    let link = format!("{}://chat/{}", crate::deep_link::SCHEME, id);
+    fs::write(dir.join(name), stub(&link)).map_err(|e| format!("Failed to write {}: {}", name, e))
+}
+
+fn remove_stub(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    let Some(name) = with_index(app, |index| index.remove(id))? else {
+        return Ok(());
+    };
+    save_index(app)?;
+    match fs::remove_file(stub_dir(app)?.join(&name)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove {}: {}", name, e)),
+        _ => Ok(()),
+    }
+}
+
+fn update(app: &tauri::AppHandle, session: &Value) -> Result<(), String> {
+    let Some(id) = indexed(session) else {
+        return match session["id"].as_str() {
+            Some(id) => remove_stub(app, id),
+            None => Ok(()),
+        };
+    };
+    let dir = stub_dir(app)?;
+    let stem = file_stem(session);
+    let current = with_index(app, |index| index.get(id).cloned())?;
+    // Still named after the same title
+    let unchanged = current.as_ref().is_some_and(|name| {
+        let base = name.strip_suffix(&format!(".{}", EXTENSION)).unwrap_or(name);
+        let numbered = base.strip_prefix(&stem).is_some_and(|rest| rest.starts_with(" ("));
+        (base == stem || numbered) && dir.join(name).exists()
+    });
+    if unchanged {
+        return Ok(());
+    }
+    remove_stub(app, id)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let taken = with_index(app, |index| index.values().map(|n| n.to_lowercase()).collect::<HashSet<_>>())?;
+    let name = unique_name(&stem, &taken);
+    write_stub(&dir, &name, id)?;
+    with_index(app, |index| index.insert(id.to_string(), name))?;
+    save_index(app)
+}
+
+// After a chat is saved
+pub fn chat_changed(app: &tauri::AppHandle, session: &Value) {
+    if !active(app) {
+        return;
+    }
+    if let Err(e) = update(app, session) {
+        log::warn!("{}", e);
+    }
+}
+
+// After a chat is deleted
+pub fn chat_deleted(app: &tauri::AppHandle, id: &str) {
+    if let Err(e) = remove_stub(app, id) {
+        log::warn!("{}", e);
+    }
+}
+
+// Removes every stub and, when indexing is on, writes them all again;
+// returns how many chats are indexed
+pub fn rebuild(app: &tauri::AppHandle) -> Result<usize, String> {
+    let dir = stub_dir(app);
+    if let Ok(dir) = &dir {
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == EXTENSION) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+    with_index(app, |index| index.clear())?;
+    save_index(app)?;
+    if !active(app) {
+        if let Ok(dir) = &dir {
+            // Only goes when it's empty
+            let _ = fs::remove_dir(dir);
+        }
+        return Ok(0);
+    }
+    let dir = dir?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let mut taken = HashSet::new();
+    let mut names = HashMap::new();
+    let job = crate::jobs::start(app);
+    let sessions = chats::list_chats(app.clone())?;
+    for (done, session) in sessions.iter().enumerate() {
+        job.progress(done as u64, sessions.len() as u64);
+        let Some(id) = indexed(session) else {
+            continue;
+        };
+        let name = unique_name(&file_stem(session), &taken);
+        write_stub(&dir, &name, id)?;
+        taken.insert(name.to_lowercase());
+        names.insert(id.to_string(), name);
+    }
+    let count = names.len();
+    with_index(app, |index| *index = names)?;
+    save_index(app)?;
+    Ok(count)
+}
+
+// At launch and when settings change, in the background
+pub fn configure(app: &tauri::AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if !active(&app) && with_index(&app, |index| index.is_empty()).unwrap_or(true) {
+            return;
+        }
+        if let Err(e) = rebuild(&app) {
+            log::warn!("Failed to index chats for system search: {}", e);
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn reindex_os_search(app: tauri::AppHandle) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let handle = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || rebuild(&handle))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+    crate::analytics::track(&app, "reindex_os_search", result)
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+
+use crate::completion::{StreamEvent, ToolCall};
+use crate::tools;
+
+// Tools that touch the shell, the filesystem or the web ask before every
+// call. The answer comes back through respond_tool_permission; "always"
+// grants are kept per chat in the app's data, not in the chat file, so a
+// chat that arrives by import, handoff or sync can't bring grants along.
+// Incognito chats' grants are only held in memory.
+
+// A prompt nobody answers counts as a denial after this long
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(300);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+// Prompts waiting for an answer, by request id
+static PENDING: Mutex<Option<HashMap<String, mpsc::Sender<Grant>>>> = Mutex::new(None);
+// Chat id -> tools granted "always"
+static GRANTS: Mutex<Option<HashMap<String, Vec<String>>>> = Mutex::new(None);
+
+const GRANTS_FILE: &str = "tool_grants.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Grant {
+    Deny,
+    // Just this call
+    Once,
+    // This tool, in this chat, from now on
+    Always,
+}
+
+fn grants_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join(GRANTS_FILE))
+}
+
+// Runs `f` on every chat's grants, loading them the first time, and saves
+// them when `f` says they changed
+fn with_grants<T>(
+    app: &tauri::AppHandle,
+    f: impl FnOnce(&mut HashMap<String, Vec<String>>) -> (T, bool),
+) -> Result<T, String> {
+    let mut guard = GRANTS.lock().map_err(|e| e.to_string())?;
+    let grants = guard.get_or_insert_with(|| {
+        grants_path(app)
+            .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    });
+    let (value, changed) = f(grants);
+    if changed {
+        let kept: HashMap<&String, &Vec<String>> = grants
+            .iter()
+            .filter(|(id, tools)| !tools.is_empty() && !crate::incognito::is_incognito(id))
+            .collect();
+        let content = serde_json::to_string_pretty(&kept).map_err(|e| e.to_string())?;
+        fs::write(grants_path(app)?, content).map_err(|e| format!("Failed to save tool permissions: {}", e))?;
+    }
+    Ok(value)
+}
+
+fn allowed_tools(app: &tauri::AppHandle, chat_id: &str) -> Result<Vec<String>, String> {
+    with_grants(app, |grants| (grants.get(chat_id).cloned().unwrap_or_default(), false))
+}
+
+fn set_allowed_tools(app: &tauri::AppHandle, chat_id: &str, edit: impl FnOnce(&mut Vec<String>)) -> Result<(), String> {
+    with_grants(app, |grants| {
+        let allowed = grants.entry(chat_id.to_string()).or_default();
+        let before = allowed.clone();
+        edit(allowed);
+        let changed = *allowed != before;
+        if allowed.is_empty() {
+            grants.remove(chat_id);
+        }
+        ((), changed)
+    })
+}
+
+fn is_allowed(app: &tauri::AppHandle, chat_id: Option<&str>, tool: &str) -> bool {
+    chat_id.and_then(|id| allowed_tools(app, id).ok()).is_some_and(|allowed| allowed.iter().any(|t| t == tool))
+}
+
+// After a chat is deleted
+pub fn forget_chat(app: &tauri::AppHandle, chat_id: &str) {
+    if let Err(e) = with_grants(app, |grants| ((), grants.remove(chat_id).is_some())) {
+        log::warn!("{}", e);
+    }
+}
+
+fn forget(request_id: &str) {
+    if let Ok(mut pending) = PENDING.lock() {
+        if let Some(pending) = pending.as_mut() {
+            pending.remove(request_id);
+        }
+    }
+}
+
+// Returns once the user allows the call, or an error for the model when
+// they don't. Tools that don't need a grant pass straight through.
+pub async fn request(
+    app: &tauri::AppHandle,
+    chat_id: Option<&str>,
+    call: &ToolCall,
+    on_event: &Channel<StreamEvent>,
+) -> Result<(), String> {
+    let needs_grant = tools::find(&call.name).is_some_and(|tool| tool.needs_permission);
+    if !needs_grant || is_allowed(app, chat_id, &call.name) {
+        return Ok(());
+    }
+
+    let request_id = format!("permission_{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let (sender, receiver) = mpsc::channel();
+    PENDING.lock().map_err(|e| e.to_string())?.get_or_insert_with(HashMap::new).insert(request_id.clone(), sender);
+
+    let event = StreamEvent::PermissionRequested { request_id: request_id.clone(), call: call.clone() };
+    // Nobody to ask when the window has gone away
+    let grant = if on_event.send(event).is_ok() {
+        tauri::async_runtime::spawn_blocking(move || receiver.recv_timeout(PROMPT_TIMEOUT).unwrap_or(Grant::Deny))
+            .await
+            .unwrap_or(Grant::Deny)
+    } else {
+        Grant::Deny
+    };
+    forget(&request_id);
+
+    match grant {
+        Grant::Deny => Err(format!("The user did not allow {} to run", call.name)),
+        Grant::Once => Ok(()),
+        Grant::Always => {
+            // A chat that hasn't been saved yet only gets this call
+            if let Some(chat_id) = chat_id {
+                let tool = call.name.clone();
+                let result = set_allowed_tools(app, chat_id, |allowed| {
+                    if !allowed.contains(&tool) {
+                        allowed.push(tool);
+                    }
+                });
+                if let Err(e) = result {
+                    log::warn!("Failed to remember the grant for {} in {}: {}", call.name, chat_id, e);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[tauri::command]
+pub fn respond_tool_permission(request_id: String, grant: Grant) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let sender = PENDING
+        .lock()
+        .map_err(|e| e.to_string())?
+        .as_mut()
+        .and_then(|pending| pending.remove(&request_id))
+        .ok_or_else(|| format!("No pending permission request: {}", request_id))?;
+    sender.send(grant).map_err(|_| "The tool call is no longer waiting".to_string())
+}
+
+// Tools the chat may run without asking
+#[tauri::command]
+pub fn tool_permissions(app: tauri::AppHandle, chat_id: String) -> Result<Vec<String>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    allowed_tools(&app, &chat_id)
+}
+
+// Revokes one tool's "always" grant, or all of them when no tool is given
+#[tauri::command]
+pub fn revoke_tool_permission(app: tauri::AppHandle, chat_id: String, tool: Option<String>) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    set_allowed_tools(&app, &chat_id, |allowed| match tool {
+        Some(tool) => allowed.retain(|t| *t != tool),
+        None => allowed.clear(),
+    })
+}
@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::device_status;
+use crate::settings::{self, PowerPolicy, PowerSettings};
+
+// Eases off local inference on a laptop that's on battery, in low power mode
+// or running hot, as the power settings say: the local embedding model runs
+// on fewer threads, and background indexing (watched knowledge folders,
+// history memory) waits until it's over. Ollama and llama.cpp run in their
+// own process with their own thread settings, so they're left alone. The UI
+// hears about each change to show why indexing is behind.
+
+const CHANGED_EVENT: &str = "power-state-changed";
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+static THROTTLED: AtomicBool = AtomicBool::new(false);
+static INDEXING_PAUSED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<Option<PowerState>> = Mutex::new(None);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerState {
+    pub on_battery: bool,
+    // Percent, while on battery
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charge: Option<u8>,
+    pub low_power: bool,
+    pub hot: bool,
+    pub throttled: bool,
+    pub indexing_paused: bool,
+}
+
+fn detect(settings: &PowerSettings) -> PowerState {
+    let charge = device_status::battery_discharging();
+    let low_power = device_status::low_power_mode() == Some(true);
+    let hot = settings.when_hot && device_status::thermal_throttled() == Some(true);
+    let throttled = hot
+        || match settings.policy {
+            PowerPolicy::Never => false,
+            PowerPolicy::LowPower => low_power,
+            PowerPolicy::OnBattery => low_power || charge.is_some(),
+        };
+    PowerState {
+        on_battery: charge.is_some(),
+        charge,
+        low_power,
+        hot,
+        throttled,
+        indexing_paused: throttled && settings.pause_indexing,
+    }
+}
+
+fn refresh(app: &tauri::AppHandle) -> PowerState {
+    let settings = settings::load(app).map(|s| s.power).unwrap_or_default();
+    let state = detect(&settings);
+    THROTTLED.store(state.throttled, Ordering::SeqCst);
+    INDEXING_PAUSED.store(state.indexing_paused, Ordering::SeqCst);
+    let changed = STATE.lock().is_ok_and(|mut last| last.replace(state.clone()).as_ref() != Some(&state));
+    if changed {
+        if state.throttled {
+            log::info!("Easing off local inference: {:?}", state);
+        }
+        let _ = app.emit(CHANGED_EVENT, &state);
+    }
+    state
+}
+
+pub fn init(app: &tauri::AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        refresh(&app);
+        std::thread::sleep(POLL_INTERVAL);
+    });
+}
+
+// When the settings are saved
+pub fn configure(app: &tauri::AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || refresh(&app));
+}
+
+pub fn indexing_paused() -> bool {
+    INDEXING_PAUSED.load(Ordering::SeqCst)
+}
+
+pub async fn wait_for_indexing() {
+    while indexing_paused() {
+        let _ = tauri::async_runtime::spawn_blocking(|| std::thread::sleep(POLL_INTERVAL)).await;
+    }
+}
+
+// How many threads local inference may use right now; None when it's not
+// held back
+pub fn threads(app: &tauri::AppHandle) -> Option<usize> {
+    if !THROTTLED.load(Ordering::SeqCst) {
+        return None;
+    }
+    let wanted = settings::load(app).map(|s| s.power.threads).unwrap_or_default();
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    Some(if wanted == 0 { cores / 2 } else { wanted }.clamp(1, cores))
+}
+
+#[tauri::command]
+pub async fn power_state(app: tauri::AppHandle) -> Result<PowerState, String> {
+    tauri::async_runtime::spawn_blocking(move || refresh(&app)).await.map_err(|e| e.to_string())
+}
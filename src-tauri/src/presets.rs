@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::chats;
+use crate::completion::CompletionRequest;
+use crate::settings::{self, Settings};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+}
+
+impl PresetParams {
+    // Sets the payload options that aren't already set
+    pub fn fill(&self, options: &mut Map<String, Value>) {
+        let params = [
+            ("temperature", self.temperature.map(|v| json!(v))),
+            ("top_p", self.top_p.map(|v| json!(v))),
+            ("max_tokens", self.max_tokens.map(|v| json!(v))),
+        ];
+        for (key, value) in params {
+            if let Some(value) = value {
+                options.entry(key.to_string()).or_insert(value);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub params: PresetParams,
+}
+
+pub fn builtin() -> Vec<Preset> {
+    vec![
+        Preset {
+            id: "creative".to_string(),
+            name: "Creative".to_string(),
+            params: PresetParams { temperature: Some(1.0), top_p: Some(0.95), max_tokens: None },
+        },
+        Preset {
+            id: "precise".to_string(),
+            name: "Precise".to_string(),
+            params: PresetParams { temperature: Some(0.2), top_p: Some(0.9), max_tokens: None },
+        },
+    ]
+}
+
+// Defaults are keyed "<provider id>:<model>", or just "<provider id>" for the whole provider
+fn default_key(provider_id: &str, model: Option<&str>) -> String {
+    match model {
+        Some(model) => format!("{}:{}", provider_id, model),
+        None => provider_id.to_string(),
+    }
+}
+
+pub fn default_for<'a>(settings: &'a Settings, provider_id: &str, model: &str) -> Option<&'a Preset> {
+    let id = settings
+        .default_presets
+        .get(&default_key(provider_id, Some(model)))
+        .or_else(|| settings.default_presets.get(provider_id))?;
+    settings.presets.iter().find(|p| &p.id == id)
+}
+
+// Fills sampling options from the chosen preset: the request's own preset,
+// then the chat's, then the provider/model default. Explicit options win.
+pub fn apply(app: &tauri::AppHandle, settings: &Settings, provider_id: &str, request: &mut CompletionRequest) {
+    let chat_preset = request
+        .chat_id
+        .as_deref()
+        .and_then(|id| chats::read(app, id).ok())
+        .and_then(|session| session["presetId"].as_str().map(str::to_string));
+
+    let preset = match request.preset_id.clone().or(chat_preset) {
+        Some(id) => settings.presets.iter().find(|p| p.id == id),
+        None => default_for(settings, provider_id, &request.model),
+    };
+    let Some(preset) = preset else {
+        return;
+    };
+
+    preset.params.fill(&mut request.options);
+}
+
+#[tauri::command]
+pub fn list_presets(app: tauri::AppHandle) -> Result<Vec<Preset>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    Ok(settings::load(&app)?.presets)
+}
+
+#[tauri::command]
+pub fn save_preset(app: tauri::AppHandle, mut preset: Preset) -> Result<Preset, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if preset.name.trim().is_empty() {
+        return Err("Preset name is required".to_string());
+    }
+    if preset.id.is_empty() {
+        preset.id = format!("preset_{}", chats::now_millis());
+    }
+
+    let mut settings = settings::load(&app)?;
+    match settings.presets.iter_mut().find(|p| p.id == preset.id) {
+        Some(existing) => *existing = preset.clone(),
+        None => settings.presets.push(preset.clone()),
+    }
+    settings::save(&app, &settings)?;
+    Ok(preset)
+}
+
+#[tauri::command]
+pub fn delete_preset(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut settings = settings::load(&app)?;
+    settings.presets.retain(|p| p.id != id);
+    settings.default_presets.retain(|_, preset_id| *preset_id != id);
+    settings::save(&app, &settings)
+}
+
+#[tauri::command]
+pub fn set_default_preset(
+    app: tauri::AppHandle,
+    provider_id: String,
+    model: Option<String>,
+    preset_id: Option<String>,
+) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut settings = settings::load(&app)?;
+    let key = default_key(&provider_id, model.as_deref());
+    match preset_id {
+        Some(id) => {
+            if !settings.presets.iter().any(|p| p.id == id) {
+                return Err(format!("Unknown preset: {}", id));
+            }
+            settings.default_presets.insert(key, id);
+        }
+        None => {
+            settings.default_presets.remove(&key);
+        }
+    }
+    settings::save(&app, &settings)
+}
+
+#[tauri::command]
+pub fn default_preset(
+    app: tauri::AppHandle,
+    provider_id: String,
+    model: String,
+) -> Result<Option<Preset>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let settings = settings::load(&app)?;
+    Ok(default_for(&settings, &provider_id, &model).cloned())
+}
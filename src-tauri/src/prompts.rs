@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chats;
+use crate::completion::{ChatMessage, CompletionRequest};
+use crate::templates;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptVersion {
+    pub version: u32,
+    pub content: String,
+    pub created_at: u64,
+}
+
+// Edits append a version instead of rewriting, so chats pinned to an older
+// version keep the exact prompt they were run with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Prompt {
+    pub id: String,
+    pub name: String,
+    pub versions: Vec<PromptVersion>,
+}
+
+impl Prompt {
+    pub fn latest(&self) -> Option<&PromptVersion> {
+        self.versions.last()
+    }
+
+    pub fn version(&self, version: u32) -> Option<&PromptVersion> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+}
+
+// What a chat stores under `systemPrompt`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptAssignment {
+    pub prompt_id: String,
+    pub version: u32,
+}
+
+fn prompts_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("prompts.json"))
+}
+
+pub fn load(app: &tauri::AppHandle) -> Result<Vec<Prompt>, String> {
+    let path = prompts_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read prompts: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid prompts file: {}", e))
+}
+
+pub fn save(app: &tauri::AppHandle, prompts: &[Prompt]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(prompts).map_err(|e| e.to_string())?;
+    fs::write(prompts_path(app)?, content).map_err(|e| format!("Failed to write prompts: {}", e))?;
+    crate::sync::record_change();
+    Ok(())
+}
+
+pub fn assignment(session: &serde_json::Value) -> Option<PromptAssignment> {
+    serde_json::from_value(session["systemPrompt"].clone()).ok()
+}
+
+// Prepends the chat's assigned prompt unless the request already carries a system message.
+pub fn apply(app: &tauri::AppHandle, request: &mut CompletionRequest) -> Result<(), String> {
+    if request.messages.iter().any(|m| m.role == "system") {
+        return Ok(());
+    }
+    let Some(session) = request.chat_id.as_deref().and_then(|id| chats::read(app, id).ok()) else {
+        return Ok(());
+    };
+    let Some(assigned) = assignment(&session) else {
+        return Ok(());
+    };
+
+    let prompts = load(app)?;
+    let content = prompts
+        .iter()
+        .find(|p| p.id == assigned.prompt_id)
+        .and_then(|p| p.version(assigned.version))
+        .map(|v| v.content.clone());
+    match content {
+        Some(content) => {
+            // Builtins like {{date}} are filled in; anything else is sent as written
+            let content = templates::render(app, &content, &HashMap::new()).unwrap_or_else(|e| {
+                log::warn!("Failed to render chat prompt {}: {}", assigned.prompt_id, e);
+                content
+            });
+            request.messages.insert(0, ChatMessage::new("system", content))
+        }
+        None => log::warn!("Chat prompt {} v{} no longer exists", assigned.prompt_id, assigned.version),
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    chats::now_millis() as u64
+}
+
+#[tauri::command]
+pub fn list_prompts(app: tauri::AppHandle) -> Result<Vec<Prompt>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    load(&app)
+}
+
+#[tauri::command]
+pub fn create_prompt(app: tauri::AppHandle, name: String, content: String) -> Result<Prompt, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if name.trim().is_empty() {
+        return Err("Prompt name is required".to_string());
+    }
+    let prompt = Prompt {
+        id: format!("prompt_{}", chats::now_millis()),
+        name,
+        versions: vec![PromptVersion { version: 1, content, created_at: now() }],
+    };
+
+    let mut prompts = load(&app)?;
+    prompts.push(prompt.clone());
+    save(&app, &prompts)?;
+    Ok(prompt)
+}
+
+#[tauri::command]
+pub fn update_prompt(
+    app: tauri::AppHandle,
+    id: String,
+    name: Option<String>,
+    content: Option<String>,
+) -> Result<Prompt, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut prompts = load(&app)?;
+    let prompt = prompts
+        .iter_mut()
+        .find(|p| p.id == id)
+        .ok_or_else(|| format!("Unknown prompt: {}", id))?;
+
+    if let Some(name) = name.filter(|n| !n.trim().is_empty()) {
+        prompt.name = name;
+    }
+    if let Some(content) = content {
+        let latest = prompt.latest().map(|v| (v.version, v.content.as_str()));
+        if latest.map(|(_, c)| c) != Some(content.as_str()) {
+            let version = latest.map(|(v, _)| v + 1).unwrap_or(1);
+            prompt.versions.push(PromptVersion { version, content, created_at: now() });
+        }
+    }
+
+    let prompt = prompt.clone();
+    save(&app, &prompts)?;
+    Ok(prompt)
+}
+
+#[tauri::command]
+pub fn delete_prompt(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut prompts = load(&app)?;
+    prompts.retain(|p| p.id != id);
+    save(&app, &prompts)
+}
+
+// Pins the chat to the prompt's current version; None clears the assignment.
+#[tauri::command]
+pub fn assign_prompt(
+    app: tauri::AppHandle,
+    chat_id: String,
+    prompt_id: Option<String>,
+) -> Result<Option<PromptAssignment>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut session = chats::read(&app, &chat_id)?;
+
+    let assigned = match prompt_id {
+        Some(prompt_id) => {
+            let prompts = load(&app)?;
+            let prompt = prompts
+                .iter()
+                .find(|p| p.id == prompt_id)
+                .ok_or_else(|| format!("Unknown prompt: {}", prompt_id))?;
+            let version = prompt.latest().map(|v| v.version).ok_or("Prompt has no content")?;
+            Some(PromptAssignment { prompt_id, version })
+        }
+        None => None,
+    };
+
+    session["systemPrompt"] = json!(assigned);
+    chats::write(&app, session)?;
+    Ok(assigned)
+}
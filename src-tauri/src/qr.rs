@@ -0,0 +1,350 @@
+// A QR code encoder (ISO/IEC 18004) for short texts like handoff links:
+// byte mode at error correction level M, versions 1 to 10, which holds up to
+// 213 bytes. Rendered as SVG so the UI can show it at any size.
+
+// Per version at level M: error correction codewords per block, then the
+// blocks as (count, data codewords)
+const BLOCKS: [(usize, [(usize, usize); 2]); 10] = [
+    (10, [(1, 16), (0, 0)]),
+    (16, [(1, 28), (0, 0)]),
+    (26, [(1, 44), (0, 0)]),
+    (18, [(2, 32), (0, 0)]),
+    (24, [(2, 43), (0, 0)]),
+    (16, [(4, 27), (0, 0)]),
+    (18, [(4, 31), (0, 0)]),
+    (22, [(2, 38), (2, 39)]),
+    (22, [(3, 36), (2, 37)]),
+    (26, [(4, 43), (1, 44)]),
+];
+const ALIGNMENT: [&[usize]; 10] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+    &[6, 28, 50],
+];
+// Light modules around the code that scanners need to find it
+const QUIET_ZONE: usize = 4;
+
+struct Grid {
+    size: usize,
+    dark: Vec<bool>,
+    // Finder, timing and format modules, which masks and data skip
+    function: Vec<bool>,
+}
+
+impl Grid {
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.dark[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.dark[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+}
+
+fn data_capacity(version: usize) -> usize {
+    BLOCKS[version - 1].1.iter().map(|(count, len)| count * len).sum()
+}
+
+fn count_bits(version: usize) -> usize {
+    if version < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+// Multiplication in GF(256) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    result
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+// Mode, length, data and padding, split into blocks with their error
+// correction and interleaved
+fn codewords(data: &[u8], version: usize) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    let mut push = |value: usize, len: usize| bits.extend((0..len).rev().map(|i| (value >> i) & 1 == 1));
+    push(0b0100, 4);
+    push(data.len(), count_bits(version));
+    for &byte in data {
+        push(byte as usize, 8);
+    }
+    let capacity = data_capacity(version) * 8;
+    let terminator = (capacity - bits.len()).min(4);
+    bits.extend(std::iter::repeat(false).take(terminator));
+    bits.extend(std::iter::repeat(false).take((8 - bits.len() % 8) % 8));
+    let mut bytes: Vec<u8> = bits.chunks(8).map(|c| c.iter().fold(0, |b, &bit| (b << 1) | bit as u8)).collect();
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if bytes.len() >= data_capacity(version) {
+            break;
+        }
+        bytes.push(pad);
+    }
+
+    let (ec_len, groups) = BLOCKS[version - 1];
+    let divisor = rs_divisor(ec_len);
+    let mut blocks = Vec::new();
+    let mut rest = bytes.as_slice();
+    for (count, len) in groups {
+        for _ in 0..count {
+            let (block, tail) = rest.split_at(len);
+            blocks.push((block, rs_remainder(block, &divisor)));
+            rest = tail;
+        }
+    }
+    let longest = blocks.iter().map(|(block, _)| block.len()).max().unwrap_or(0);
+    let mut out = Vec::new();
+    for i in 0..longest {
+        out.extend(blocks.iter().filter_map(|(block, _)| block.get(i)));
+    }
+    for i in 0..ec_len {
+        out.extend(blocks.iter().map(|(_, ec)| ec[i]));
+    }
+    out
+}
+
+fn draw_function_patterns(grid: &mut Grid, version: usize) {
+    let size = grid.size;
+    for i in 0..size {
+        grid.set_function(6, i, i % 2 == 0);
+        grid.set_function(i, 6, i % 2 == 0);
+    }
+    for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                if x < 0 || y < 0 || x >= size as i32 || y >= size as i32 {
+                    continue;
+                }
+                let distance = dx.abs().max(dy.abs());
+                grid.set_function(x as usize, y as usize, distance != 2 && distance != 4);
+            }
+        }
+    }
+    let positions = ALIGNMENT[version - 1];
+    let last = positions.len().saturating_sub(1);
+    for (i, &cx) in positions.iter().enumerate() {
+        for (j, &cy) in positions.iter().enumerate() {
+            // These overlap the finder patterns
+            if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                continue;
+            }
+            for dy in -2i32..=2 {
+                for dx in -2i32..=2 {
+                    let dark = dx.abs().max(dy.abs()) != 1;
+                    grid.set_function((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dark);
+                }
+            }
+        }
+    }
+    // Reserved now, written for real once the mask is chosen
+    draw_format(grid, 0);
+    if version >= 7 {
+        let mut rem = version;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = (version << 12) | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 == 1;
+            let (a, b) = (size - 11 + i % 3, i / 3);
+            grid.set_function(a, b, dark);
+            grid.set_function(b, a, dark);
+        }
+    }
+}
+
+fn draw_format(grid: &mut Grid, mask: usize) {
+    // Level M is 00
+    let data = mask;
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    let bits = ((data << 10) | rem) ^ 0x5412;
+    let bit = |i: usize| (bits >> i) & 1 == 1;
+    let size = grid.size;
+    for i in 0..=5 {
+        grid.set_function(8, i, bit(i));
+    }
+    grid.set_function(8, 7, bit(6));
+    grid.set_function(8, 8, bit(7));
+    grid.set_function(7, 8, bit(8));
+    for i in 9..15 {
+        grid.set_function(14 - i, 8, bit(i));
+    }
+    for i in 0..8 {
+        grid.set_function(size - 1 - i, 8, bit(i));
+    }
+    for i in 8..15 {
+        grid.set_function(8, size - 15 + i, bit(i));
+    }
+    grid.set_function(8, size - 8, true);
+}
+
+// Zigzags up and down two columns at a time from the bottom right
+fn draw_codewords(grid: &mut Grid, data: &[u8]) {
+    let size = grid.size;
+    let mut i = 0;
+    let mut right = size as i32 - 1;
+    while right >= 1 {
+        if right == 6 {
+            right = 5;
+        }
+        for vert in 0..size {
+            for j in 0..2 {
+                let x = (right - j) as usize;
+                let upward = (right + 1) & 2 == 0;
+                let y = if upward { size - 1 - vert } else { vert };
+                if !grid.function[y * size + x] && i < data.len() * 8 {
+                    grid.dark[y * size + x] = (data[i >> 3] >> (7 - (i & 7))) & 1 == 1;
+                    i += 1;
+                }
+            }
+        }
+        right -= 2;
+    }
+}
+
+fn apply_mask(grid: &mut Grid, mask: usize) {
+    let size = grid.size;
+    for y in 0..size {
+        for x in 0..size {
+            let invert = match mask {
+                0 => (x + y) % 2 == 0,
+                1 => y % 2 == 0,
+                2 => x % 3 == 0,
+                3 => (x + y) % 3 == 0,
+                4 => (x / 3 + y / 2) % 2 == 0,
+                5 => x * y % 2 + x * y % 3 == 0,
+                6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+            };
+            if invert && !grid.function[y * size + x] {
+                grid.dark[y * size + x] ^= true;
+            }
+        }
+    }
+}
+
+// The spec's penalty score; the mask with the lowest is the easiest to scan
+fn penalty(grid: &Grid) -> usize {
+    let size = grid.size;
+    let mut score = 0;
+    let finder_like = [true, false, true, true, true, false, true, false, false, false, false];
+    for horizontal in [true, false] {
+        let at = |a: usize, b: usize| if horizontal { grid.get(b, a) } else { grid.get(a, b) };
+        for a in 0..size {
+            let mut run = 1;
+            for b in 1..size {
+                if at(a, b) == at(a, b - 1) {
+                    run += 1;
+                    if run == 5 {
+                        score += 3;
+                    } else if run > 5 {
+                        score += 1;
+                    }
+                } else {
+                    run = 1;
+                }
+            }
+            let line: Vec<bool> = (0..size).map(|b| at(a, b)).collect();
+            for window in line.windows(finder_like.len()) {
+                if window == finder_like || window.iter().rev().eq(finder_like.iter()) {
+                    score += 40;
+                }
+            }
+        }
+    }
+    for y in 0..size - 1 {
+        for x in 0..size - 1 {
+            let color = grid.get(x, y);
+            if color == grid.get(x + 1, y) && color == grid.get(x, y + 1) && color == grid.get(x + 1, y + 1) {
+                score += 3;
+            }
+        }
+    }
+    let dark = grid.dark.iter().filter(|&&d| d).count();
+    let total = size * size;
+    score + (dark * 20).abs_diff(total * 10) / total * 10
+}
+
+fn encode(data: &[u8]) -> Result<Grid, String> {
+    let version = (1..=BLOCKS.len())
+        .find(|&v| 4 + count_bits(v) + data.len() * 8 <= data_capacity(v) * 8)
+        .ok_or("Too much data for a QR code")?;
+    let size = version * 4 + 17;
+    let mut grid = Grid { size, dark: vec![false; size * size], function: vec![false; size * size] };
+    draw_function_patterns(&mut grid, version);
+    draw_codewords(&mut grid, &codewords(data, version));
+
+    let mut best = (usize::MAX, 0);
+    for mask in 0..8 {
+        apply_mask(&mut grid, mask);
+        draw_format(&mut grid, mask);
+        best = best.min((penalty(&grid), mask));
+        // Masks are their own inverse
+        apply_mask(&mut grid, mask);
+    }
+    apply_mask(&mut grid, best.1);
+    draw_format(&mut grid, best.1);
+    Ok(grid)
+}
+
+pub fn svg(text: &str) -> Result<String, String> {
+    let grid = encode(text.as_bytes())?;
+    let full = grid.size + QUIET_ZONE * 2;
+    let mut path = String::new();
+    for y in 0..grid.size {
+        for x in 0..grid.size {
+            if grid.get(x, y) {
+                path.push_str(&format!("M{},{}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE));
+            }
+        }
+    }
+    Ok(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {0} {0}\" shape-rendering=\"crispEdges\">\
+         <rect width=\"{0}\" height=\"{0}\" fill=\"#fff\"/><path d=\"{1}\" fill=\"#000\"/></svg>",
+        full, path
+    ))
+}
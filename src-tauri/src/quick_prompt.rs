@@ -0,0 +1,185 @@
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+use tauri::ipc::Channel;
+use tauri::{Emitter, Manager, WebviewUrl, WebviewWindowBuilder};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::chats;
+use crate::completion::{self, CompletionRequest, StreamEvent};
+use crate::settings;
+
+// A global shortcut that opens a small prompt window over whatever the user
+// is doing. Prompts typed there go to a "quick" chat the backend keeps: the
+// latest one while it's recent, otherwise a new one, so the reply can be
+// picked up later in the main window like any other chat.
+
+pub const LABEL: &str = "quick";
+const URL: &str = "quick";
+const QUICK_KEY: &str = "quick";
+const SAVED_EVENT: &str = "quick-prompt-saved";
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 360.0;
+
+// The shortcut registered now, so a changed one replaces it
+static REGISTERED: Mutex<Option<Shortcut>> = Mutex::new(None);
+
+// Registers the shortcut from settings; called at startup and when the
+// settings are saved
+pub fn configure(app: &tauri::AppHandle) {
+    let wanted = settings::load(app)
+        .ok()
+        .map(|s| s.quick_prompt)
+        .filter(|quick| quick.enabled)
+        .and_then(|quick| match quick.shortcut.parse::<Shortcut>() {
+            Ok(shortcut) => Some(shortcut),
+            Err(e) => {
+                log::warn!("Invalid quick prompt shortcut {}: {}", quick.shortcut, e);
+                None
+            }
+        });
+    let Ok(mut registered) = REGISTERED.lock() else {
+        return;
+    };
+    if *registered == wanted {
+        return;
+    }
+    if let Some(previous) = registered.take() {
+        if let Err(e) = app.global_shortcut().unregister(previous) {
+            log::warn!("Failed to release the quick prompt shortcut: {}", e);
+        }
+    }
+    if let Some(shortcut) = wanted {
+        match app.global_shortcut().register(shortcut) {
+            Ok(()) => *registered = Some(shortcut),
+            // Usually taken by another app
+            Err(e) => log::warn!("Failed to register the quick prompt shortcut: {}", e),
+        }
+    }
+}
+
+pub fn on_shortcut(app: &tauri::AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state != ShortcutState::Pressed || REGISTERED.lock().map_or(true, |r| r.as_ref() != Some(shortcut)) {
+        return;
+    }
+    if let Err(e) = toggle(app) {
+        log::warn!("{}", e);
+    }
+}
+
+// Shows the window, or hides it when it's already in front
+fn toggle(app: &tauri::AppHandle) -> Result<(), String> {
+    if let Some(window) = app.get_webview_window(LABEL) {
+        if window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false) {
+            return window.hide().map_err(|e| e.to_string());
+        }
+        let _ = window.center();
+        let _ = window.show();
+        return window.set_focus().map_err(|e| e.to_string());
+    }
+    WebviewWindowBuilder::new(app, LABEL, WebviewUrl::App(URL.into()))
+        .title("Quick Prompt")
+        .inner_size(WIDTH, HEIGHT)
+        .resizable(false)
+        .decorations(false)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .center()
+        .focused(true)
+        .build()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open the quick prompt window: {}", e))
+}
+
+// Tucks the window away once the user clicks elsewhere
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    if window.label() == LABEL && matches!(event, tauri::WindowEvent::Focused(false)) {
+        let _ = window.hide();
+    }
+}
+
+// The quick chat to continue, or a new one when the last was too long ago
+fn session(app: &tauri::AppHandle, new_chat_after_minutes: u64) -> Result<Value, String> {
+    let cutoff = chats::now_millis().saturating_sub(new_chat_after_minutes as u128 * 60_000) as u64;
+    let latest = chats::list_chats(app.clone())?
+        .into_iter()
+        .filter(|s| s[QUICK_KEY].as_bool() == Some(true) && s["conflictOf"].is_null())
+        .filter_map(|s| Some((s["updatedAt"].as_u64()?, s["id"].as_str()?.to_string())))
+        .max();
+    if let Some((_, id)) = latest.filter(|(updated, _)| *updated >= cutoff) {
+        return chats::read(app, &id);
+    }
+    Ok(json!({
+        "id": "",
+        "title": "Quick prompt",
+        QUICK_KEY: true,
+        "timestamp": chats::now_millis() as u64,
+        "messages": [],
+    }))
+}
+
+fn last_model(session: &Value) -> Option<String> {
+    let messages = session["messages"].as_array()?;
+    let model = messages.iter().rev().find_map(|m| m["model"].as_str().filter(|m| !m.is_empty()));
+    model.map(str::to_string)
+}
+
+async fn send(app: &tauri::AppHandle, prompt: &str, on_event: Channel<StreamEvent>) -> Result<String, String> {
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err("Type a prompt first".to_string());
+    }
+    let quick = settings::load(app)?.quick_prompt;
+    let mut session = session(app, quick.new_chat_after_minutes)?;
+    let model = Some(quick.model.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .or_else(|| last_model(&session))
+        .ok_or("Pick a model for quick prompts in settings")?;
+
+    let messages = session["messages"].as_array_mut().ok_or("Invalid session format")?;
+    messages.push(json!({ "role": "user", "content": prompt, "timestamp": chats::now_millis() as u64 }));
+    // Saved before the reply, so the prompt isn't lost if the request fails
+    let id = chats::write(app, session)?;
+    let session = chats::read(app, &id)?;
+
+    let request = CompletionRequest {
+        provider_id: quick.provider_id.clone(),
+        chat_id: Some(id.clone()),
+        model: model.clone(),
+        messages: chats::messages(&session),
+        ..Default::default()
+    };
+    let reply = completion::stream_completion(app.clone(), request, on_event).await?;
+
+    // Read again, the completion may have updated the chat meanwhile
+    let mut session = chats::read(app, &id)?;
+    let messages = session["messages"].as_array_mut().ok_or("Invalid session format")?;
+    messages.push(json!({
+        "role": "assistant",
+        "content": reply,
+        "model": model,
+        "timestamp": chats::now_millis() as u64,
+    }));
+    chats::write(app, session)?;
+    let _ = app.emit(SAVED_EVENT, &id);
+    Ok(id)
+}
+
+// Streams the reply over on_event and returns the chat it was saved to
+#[tauri::command]
+pub async fn send_quick_prompt(
+    app: tauri::AppHandle,
+    prompt: String,
+    on_event: Channel<StreamEvent>,
+) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    send(&app, &prompt, on_event).await
+}
+
+#[tauri::command]
+pub fn hide_quick_prompt(app: tauri::AppHandle) -> Result<(), String> {
+    match app.get_webview_window(LABEL) {
+        Some(window) => window.hide().map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+use serde::Serialize;
+use tauri::{Emitter, State};
+
+use crate::attachments::{self, Attachment, AttachmentSource};
+
+const LEVEL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingLevel {
+    // RMS and peak of the last interval, 0.0 to 1.0
+    pub rms: f32,
+    pub peak: f32,
+    pub elapsed_ms: u64,
+}
+
+struct Capture {
+    samples: Mutex<Vec<f32>>,
+    // f32 bits of the running sums since the last level event
+    sum_squares: AtomicU32,
+    peak: AtomicU32,
+    frames: AtomicU32,
+}
+
+impl Capture {
+    fn push(&self, data: &[f32], channels: usize) {
+        let mut sum_squares = f32::from_bits(self.sum_squares.load(Ordering::Relaxed));
+        let mut peak = f32::from_bits(self.peak.load(Ordering::Relaxed));
+        let mut mono = Vec::with_capacity(data.len() / channels.max(1));
+        for frame in data.chunks(channels.max(1)) {
+            let sample = frame.iter().sum::<f32>() / frame.len() as f32;
+            sum_squares += sample * sample;
+            peak = peak.max(sample.abs());
+            mono.push(sample);
+        }
+
+        self.sum_squares.store(sum_squares.to_bits(), Ordering::Relaxed);
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.frames.fetch_add(mono.len() as u32, Ordering::Relaxed);
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.extend(mono);
+        }
+    }
+
+    fn take_level(&self) -> (f32, f32) {
+        let sum_squares = f32::from_bits(self.sum_squares.swap(0, Ordering::Relaxed));
+        let peak = f32::from_bits(self.peak.swap(0, Ordering::Relaxed));
+        let frames = self.frames.swap(0, Ordering::Relaxed);
+        let rms = if frames == 0 { 0.0 } else { (sum_squares / frames as f32).sqrt() };
+        (rms.min(1.0), peak.min(1.0))
+    }
+}
+
+struct Recording {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<(Vec<f32>, u32), String>>,
+}
+
+#[derive(Default)]
+pub struct RecordingState {
+    current: Mutex<Option<Recording>>,
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    capture: Arc<Capture>,
+) -> Result<cpal::Stream, String>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _| {
+                let data: Vec<f32> = data.iter().map(|s| f32::from_sample(*s)).collect();
+                capture.push(&data, channels);
+            },
+            |e| log::error!("Audio input error: {}", e),
+            None,
+        )
+        .map_err(|e| format!("Failed to open microphone: {}", e))
+}
+
+// cpal streams aren't Send on every platform, so the stream lives and dies
+// on this thread; it reports levels until asked to stop.
+fn record(
+    app: tauri::AppHandle,
+    stop: Arc<AtomicBool>,
+    ready: mpsc::Sender<Result<(), String>>,
+) -> Result<(Vec<f32>, u32), String> {
+    let setup = (|| {
+        let device = cpal::default_host().default_input_device().ok_or("No microphone found")?;
+        let supported = device.default_input_config().map_err(|e| format!("Microphone unavailable: {}", e))?;
+        let capture = Arc::new(Capture {
+            samples: Mutex::new(Vec::new()),
+            sum_squares: AtomicU32::new(0),
+            peak: AtomicU32::new(0),
+            frames: AtomicU32::new(0),
+        });
+
+        let config = supported.config();
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, capture.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, capture.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, capture.clone()),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, capture.clone()),
+            format => Err(format!("Unsupported microphone sample format: {}", format)),
+        }?;
+        stream.play().map_err(|e| format!("Failed to start recording: {}", e))?;
+        Ok::<_, String>((stream, capture, config.sample_rate.0))
+    })();
+
+    let (stream, capture, sample_rate) = match setup {
+        Ok(setup) => {
+            let _ = ready.send(Ok(()));
+            setup
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e.clone()));
+            return Err(e);
+        }
+    };
+
+    let started = std::time::Instant::now();
+    while !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(LEVEL_INTERVAL);
+        let (rms, peak) = capture.take_level();
+        let _ = app.emit(
+            "recording-level",
+            RecordingLevel { rms, peak, elapsed_ms: started.elapsed().as_millis() as u64 },
+        );
+    }
+    drop(stream);
+
+    let samples = std::mem::take(&mut *capture.samples.lock().map_err(|e| e.to_string())?);
+    Ok((samples, sample_rate))
+}
+
+#[tauri::command]
+pub fn start_recording(app: tauri::AppHandle, state: State<'_, RecordingState>) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut current = state.current.lock().map_err(|e| e.to_string())?;
+    if current.is_some() {
+        return Err("Already recording".to_string());
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let thread_stop = stop.clone();
+    let handle = std::thread::spawn(move || record(app, thread_stop, ready_tx));
+
+    // Surface microphone errors to the caller instead of at stop time
+    ready_rx.recv().map_err(|e| e.to_string())??;
+    *current = Some(Recording { stop, handle });
+    Ok(())
+}
+
+// Stops recording and stores the audio as a WAV attachment.
+#[tauri::command]
+pub async fn stop_recording(app: tauri::AppHandle, state: State<'_, RecordingState>) -> Result<Attachment, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let recording = state.current.lock().map_err(|e| e.to_string())?.take().ok_or("Not recording")?;
+    recording.stop.store(true, Ordering::SeqCst);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let (samples, sample_rate) = recording.handle.join().map_err(|_| "Recording thread panicked".to_string())??;
+        if samples.is_empty() {
+            return Err("Nothing was recorded".to_string());
+        }
+
+        let path = std::env::temp_dir().join(format!("anchor-recording-{}.wav", crate::chats::now_millis()));
+        crate::audio::write_wav(&path, &samples, sample_rate)?;
+        let name = format!("Recording {}.wav", chrono::Local::now().format("%Y-%m-%d %H.%M.%S"));
+        let result = attachments::save(&app, AttachmentSource::Path(path.to_string_lossy().to_string()), Some(name));
+        let _ = std::fs::remove_file(&path);
+        if let Ok(attachment) = &result {
+            crate::transcription::schedule_auto(&app, &attachment.id);
+        }
+        result
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map};
+use tauri::{Manager, State};
+
+use crate::chats;
+use crate::completion::{ChatMessage, CompletionRequest};
+use crate::embeddings::{self, EmbeddingState};
+use crate::knowledge;
+use crate::vectors;
+
+pub const DEFAULT_TOP_K: usize = 5;
+// Cosine similarity below which a chunk is rarely on topic
+pub const DEFAULT_MIN_SCORE: f32 = 0.25;
+// Each ranking contributes this many candidates per requested chunk
+const CANDIDATES_PER_RESULT: usize = 4;
+// Reciprocal rank fusion damping; 60 is the value from the original paper
+const RRF_K: f32 = 60.0;
+// BM25 term frequency saturation and length normalisation
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+// Keyword matches scoring less than this share of the best the query could
+// score are only kept when the vector search agrees
+const MIN_KEYWORD_SCORE: f32 = 0.3;
+// Too common to say what a query is about
+const STOPWORDS: &[&str] = &[
+    "a", "about", "an", "and", "are", "as", "at", "be", "but", "by", "can", "do", "does", "for", "from", "how",
+    "i", "if", "in", "is", "it", "its", "me", "my", "of", "on", "or", "so", "that", "the", "their", "them",
+    "there", "these", "this", "to", "was", "we", "what", "when", "where", "which", "who", "why", "will", "with",
+    "you", "your",
+];
+
+// Knowledge base id -> its chunks, tokenized once; dropped whenever the
+// chunks are written, see forget_index
+static INDEXES: Mutex<Option<HashMap<String, Arc<KeywordIndex>>>> = Mutex::new(None);
+
+struct KeywordIndex {
+    chunks: Vec<knowledge::Chunk>,
+    // Per chunk, how often each word occurs
+    frequencies: Vec<HashMap<String, u32>>,
+    lengths: Vec<usize>,
+    // Chunks containing each word
+    containing: HashMap<String, usize>,
+    average_length: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetrievedChunk {
+    pub kb_id: String,
+    pub chunk_id: String,
+    // Fused rank score the results are ordered by
+    pub score: f32,
+    // Cosine similarity, when the vector search found the chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>,
+    // BM25 score over the best the query could score, 0-1, when the chunk
+    // contains words from the query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyword_score: Option<f32>,
+    pub source_id: String,
+    pub attachment_id: String,
+    pub name: String,
+    // Where the file was added from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    pub text: String,
+}
+
+// Lowercased words, keeping identifiers such as `ERR_CONN_RESET`, `0x80070005`
+// or `parse_config` whole
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl KeywordIndex {
+    fn new(chunks: Vec<knowledge::Chunk>) -> Self {
+        let mut containing: HashMap<String, usize> = HashMap::new();
+        let mut frequencies = Vec::with_capacity(chunks.len());
+        let mut lengths = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            let title = chunk.title.as_deref().unwrap_or_default();
+            let words = tokenize(&format!("{} {} {}", chunk.name, title, chunk.text));
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for word in &words {
+                *counts.entry(word.clone()).or_default() += 1;
+            }
+            for word in counts.keys() {
+                *containing.entry(word.clone()).or_default() += 1;
+            }
+            lengths.push(words.len());
+            frequencies.push(counts);
+        }
+        let average_length = lengths.iter().sum::<usize>() as f32 / lengths.len().max(1) as f32;
+        Self { chunks, frequencies, lengths, containing, average_length }
+    }
+
+    // Top `limit` chunks by BM25 against the query, as (index, score), with
+    // scores over the most the query's terms could add up to
+    fn ranking(&self, query: &str, limit: usize) -> Vec<(usize, f32)> {
+        let mut terms: Vec<String> = tokenize(query).into_iter().filter(|t| !STOPWORDS.contains(&t.as_str())).collect();
+        terms.sort();
+        terms.dedup();
+        if terms.is_empty() || self.chunks.is_empty() {
+            return Vec::new();
+        }
+
+        let count = self.chunks.len() as f32;
+        let idf: Vec<f32> = terms
+            .iter()
+            .map(|term| {
+                let containing = self.containing.get(term).copied().unwrap_or(0) as f32;
+                ((count - containing + 0.5) / (containing + 0.5) + 1.0).ln()
+            })
+            .collect();
+        let best = idf.iter().sum::<f32>() * (BM25_K1 + 1.0);
+
+        let mut scored: Vec<(usize, f32)> = self
+            .frequencies
+            .iter()
+            .zip(&self.lengths)
+            .enumerate()
+            .filter_map(|(index, (counts, &length))| {
+                let length_norm = 1.0 - BM25_B + BM25_B * length as f32 / self.average_length.max(1.0);
+                let score: f32 = terms
+                    .iter()
+                    .zip(&idf)
+                    .map(|(term, idf)| {
+                        let frequency = counts.get(term).copied().unwrap_or(0) as f32;
+                        idf * frequency * (BM25_K1 + 1.0) / (frequency + BM25_K1 * length_norm)
+                    })
+                    .sum();
+                (score > 0.0).then_some((index, score / best))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+fn keyword_index(app: &tauri::AppHandle, kb_id: &str) -> Result<Arc<KeywordIndex>, String> {
+    if let Some(index) = INDEXES.lock().ok().and_then(|indexes| indexes.as_ref()?.get(kb_id).cloned()) {
+        return Ok(index);
+    }
+    let index = Arc::new(KeywordIndex::new(knowledge::chunks(app, kb_id)?));
+    if let Ok(mut indexes) = INDEXES.lock() {
+        indexes.get_or_insert_with(HashMap::new).insert(kb_id.to_string(), index.clone());
+    }
+    Ok(index)
+}
+
+// Called whenever a knowledge base's chunks change or it's deleted
+pub fn forget_index(kb_id: &str) {
+    if let Ok(mut indexes) = INDEXES.lock() {
+        if let Some(indexes) = indexes.as_mut() {
+            indexes.remove(kb_id);
+        }
+    }
+}
+
+// Top `k` chunks across the knowledge bases, best first. Vector similarity
+// and BM25 keyword rankings are merged with reciprocal rank fusion, so exact
+// identifiers that embeddings blur still surface. The query is embedded once
+// per embedding model in use among the knowledge bases.
+pub async fn retrieve(
+    app: &tauri::AppHandle,
+    state: &EmbeddingState,
+    query: &str,
+    kb_ids: &[String],
+    k: usize,
+) -> Result<Vec<RetrievedChunk>, String> {
+    if query.trim().is_empty() || k == 0 {
+        return Ok(Vec::new());
+    }
+    let kbs = kb_ids.iter().map(|id| knowledge::get(app, id)).collect::<Result<Vec<_>, _>>()?;
+    let candidates = k * CANDIDATES_PER_RESULT;
+
+    let mut queries: HashMap<String, Vec<f32>> = HashMap::new();
+    let mut results = Vec::new();
+    for kb in kbs.into_iter().filter(|kb| kb.dimensions > 0) {
+        if !queries.contains_key(&kb.embedding_model) {
+            let texts = vec![query.to_string()];
+            let embedded = embeddings::embed_texts(app, state, texts, Some(kb.embedding_model.clone())).await?;
+            let vector = embedded.vectors.into_iter().next().ok_or("Embedding returned no vector")?;
+            queries.insert(kb.embedding_model.clone(), vector);
+        }
+        let vector = queries[&kb.embedding_model].clone();
+
+        let (app_handle, kb_id, text) = (app.clone(), kb.id.clone(), query.to_string());
+        let (hits, index, keyword_hits) = tauri::async_runtime::spawn_blocking(move || {
+            let hits = vectors::search(&app_handle, &kb_id, vector, candidates, &Map::new())?;
+            let index = keyword_index(&app_handle, &kb_id)?;
+            let keyword_hits = index.ranking(&text, candidates);
+            Ok::<_, String>((hits, index, keyword_hits))
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        let chunks = &index.chunks;
+
+        let positions: HashMap<&str, usize> = chunks.iter().enumerate().map(|(i, c)| (c.id.as_str(), i)).collect();
+        // chunk index -> (fused score, similarity, keyword score)
+        let mut fused: HashMap<usize, (f32, Option<f32>, Option<f32>)> = HashMap::new();
+        // Vectors whose chunk is gone are leftovers of an interrupted add
+        let vector_hits = hits.iter().filter_map(|hit| positions.get(hit.id.as_str()).map(|&i| (i, hit.score)));
+        for (rank, (index, similarity)) in vector_hits.enumerate() {
+            let entry = fused.entry(index).or_default();
+            entry.0 += 1.0 / (RRF_K + rank as f32 + 1.0);
+            entry.1 = Some(similarity);
+        }
+        for (rank, &(index, keyword_score)) in keyword_hits.iter().enumerate() {
+            let entry = fused.entry(index).or_default();
+            entry.0 += 1.0 / (RRF_K + rank as f32 + 1.0);
+            entry.2 = Some(keyword_score);
+        }
+
+        let paths: HashMap<&str, &str> = kb
+            .sources
+            .iter()
+            .flat_map(|s| &s.documents)
+            .map(|d| (d.attachment_id.as_str(), d.path.as_str()))
+            .collect();
+        for (index, (score, similarity, keyword_score)) in fused {
+            let chunk = &chunks[index];
+            results.push(RetrievedChunk {
+                kb_id: kb.id.clone(),
+                chunk_id: chunk.id.clone(),
+                score,
+                similarity,
+                keyword_score,
+                source_id: chunk.source_id.clone(),
+                attachment_id: chunk.attachment_id.clone(),
+                name: chunk.name.clone(),
+                path: paths.get(chunk.attachment_id.as_str()).map(|p| p.to_string()),
+                title: chunk.title.clone(),
+                page: chunk.page,
+                text: chunk.text.clone(),
+            });
+        }
+    }
+
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results.truncate(k);
+    Ok(results)
+}
+
+pub fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;")
+}
+
+fn context_message(chunks: &[RetrievedChunk]) -> ChatMessage {
+    let mut content = String::from(
+        "Excerpts from the knowledge bases linked to this chat. Use them where they help answer the next \
+         message, and cite the ones you rely on by their index in square brackets, e.g. [2].",
+    );
+    for (index, chunk) in chunks.iter().enumerate() {
+        let mut attributes = format!("index=\"{}\" name=\"{}\"", index + 1, escape_attribute(&chunk.name));
+        if let Some(title) = &chunk.title {
+            attributes.push_str(&format!(" section=\"{}\"", escape_attribute(title)));
+        }
+        if let Some(page) = chunk.page {
+            attributes.push_str(&format!(" page=\"{}\"", page));
+        }
+        content.push_str(&format!("\n\n<source {}>\n{}\n</source>", attributes, chunk.text));
+    }
+    ChatMessage::new("system", content)
+}
+
+// Where an answer's information came from, stored on the assistant message
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Citation {
+    // The `[n]` the model was asked to cite the chunk by
+    pub index: usize,
+    pub kb_id: String,
+    pub chunk_id: String,
+    pub attachment_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    // For code, whose section titles read "path:start-end symbol"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<usize>,
+    pub score: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>,
+    // Whether the answer refers to it with `[index]`
+    #[serde(default)]
+    pub cited: bool,
+}
+
+fn line_range(title: &str) -> Option<(usize, usize)> {
+    let range = title.split_whitespace().next()?.rsplit(':').next()?;
+    let (start, end) = range.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+// Citation metadata for the chunks that went into the prompt, in prompt
+// order. Models don't always cite, so uncited chunks are kept and flagged.
+pub fn citations(chunks: &[RetrievedChunk], answer: &str) -> Vec<Citation> {
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(position, chunk)| {
+            let index = position + 1;
+            let lines = chunk.title.as_deref().and_then(line_range);
+            Citation {
+                index,
+                kb_id: chunk.kb_id.clone(),
+                chunk_id: chunk.chunk_id.clone(),
+                attachment_id: chunk.attachment_id.clone(),
+                name: chunk.name.clone(),
+                path: chunk.path.clone(),
+                title: chunk.title.clone(),
+                page: chunk.page,
+                start_line: lines.map(|l| l.0),
+                end_line: lines.map(|l| l.1),
+                score: chunk.score,
+                similarity: chunk.similarity,
+                cited: answer.contains(&format!("[{}]", index)),
+            }
+        })
+        .collect()
+}
+
+// Stored on the session under `retrieval`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChatRetrieval {
+    pub k: usize,
+    // Chunks less similar are left out, even if that leaves fewer than `k`,
+    // unless they matched the query's keywords well, see MIN_KEYWORD_SCORE
+    pub min_score: f32,
+}
+
+impl Default for ChatRetrieval {
+    fn default() -> Self {
+        Self { k: DEFAULT_TOP_K, min_score: DEFAULT_MIN_SCORE }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatKnowledge {
+    pub knowledge_base_ids: Vec<String>,
+    pub retrieval: ChatRetrieval,
+}
+
+// Knowledge base ids a stored chat is linked to
+pub fn linked_kbs(session: &serde_json::Value) -> Vec<String> {
+    session["knowledgeBaseIds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| id.as_str().map(str::to_string))
+        .collect()
+}
+
+pub fn chat_retrieval(session: &serde_json::Value) -> ChatRetrieval {
+    serde_json::from_value(session["retrieval"].clone()).unwrap_or_default()
+}
+
+fn chat_knowledge(session: &serde_json::Value) -> ChatKnowledge {
+    ChatKnowledge { knowledge_base_ids: linked_kbs(session), retrieval: chat_retrieval(session) }
+}
+
+// For chats linked to knowledge bases, looks up chunks relevant to the latest
+// user message and puts them in a system message right before it. Retrieval
+// problems are logged rather than failing the turn.
+pub async fn apply(app: &tauri::AppHandle, request: &mut CompletionRequest) -> Vec<RetrievedChunk> {
+    let Some(session) = request.chat_id.as_deref().and_then(|id| chats::read(app, id).ok()) else {
+        return Vec::new();
+    };
+    let kb_ids = linked_kbs(&session);
+    if kb_ids.is_empty() {
+        return Vec::new();
+    }
+    let Some(position) = request.messages.iter().rposition(|m| m.role == "user") else {
+        return Vec::new();
+    };
+
+    let settings = chat_retrieval(&session);
+    let query = request.messages[position].content.clone();
+    let state = app.state::<EmbeddingState>();
+    match retrieve(app, &state, &query, &kb_ids, settings.k).await {
+        Ok(mut chunks) => {
+            chunks.retain(|c| {
+                c.keyword_score.is_some_and(|s| s >= MIN_KEYWORD_SCORE)
+                    || c.similarity.is_some_and(|s| s >= settings.min_score)
+            });
+            if chunks.is_empty() {
+                return chunks;
+            }
+            request.messages.insert(position, context_message(&chunks));
+            chunks
+        }
+        Err(e) => {
+            log::warn!("Knowledge base retrieval failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn retrieve_context(
+    app: tauri::AppHandle,
+    state: State<'_, EmbeddingState>,
+    query: String,
+    kb_ids: Vec<String>,
+    k: Option<usize>,
+) -> Result<Vec<RetrievedChunk>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    retrieve(&app, &state, &query, &kb_ids, k.unwrap_or(DEFAULT_TOP_K)).await
+}
+
+// Links the knowledge base to the chat so every turn retrieves from it. `k`
+// and `min_score` update the chat's retrieval settings when given.
+#[tauri::command]
+pub fn attach_kb(
+    app: tauri::AppHandle,
+    chat_id: String,
+    kb_id: String,
+    k: Option<usize>,
+    min_score: Option<f32>,
+) -> Result<ChatKnowledge, String> {
+    crate::app_lock::ensure_unlocked()?;
+    knowledge::get(&app, &kb_id)?;
+    let mut session = chats::read(&app, &chat_id)?;
+    let mut ids = linked_kbs(&session);
+    if !ids.contains(&kb_id) {
+        ids.push(kb_id);
+    }
+    let mut retrieval = chat_retrieval(&session);
+    if let Some(k) = k {
+        retrieval.k = k.max(1);
+    }
+    if let Some(min_score) = min_score {
+        retrieval.min_score = min_score;
+    }
+    session["knowledgeBaseIds"] = json!(ids);
+    session["retrieval"] = json!(retrieval);
+    let knowledge = chat_knowledge(&session);
+    chats::write(&app, session)?;
+    Ok(knowledge)
+}
+
+#[tauri::command]
+pub fn detach_kb(app: tauri::AppHandle, chat_id: String, kb_id: String) -> Result<ChatKnowledge, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut session = chats::read(&app, &chat_id)?;
+    let ids: Vec<String> = linked_kbs(&session).into_iter().filter(|id| *id != kb_id).collect();
+    session["knowledgeBaseIds"] = json!(ids);
+    let knowledge = chat_knowledge(&session);
+    chats::write(&app, session)?;
+    Ok(knowledge)
+}
+
+#[tauri::command]
+pub fn get_chat_knowledge(app: tauri::AppHandle, chat_id: String) -> Result<ChatKnowledge, String> {
+    crate::app_lock::ensure_unlocked()?;
+    Ok(chat_knowledge(&chats::read(&app, &chat_id)?))
+}
@@ -0,0 +1,218 @@
+use ring::hmac;
+use sha2::{Digest, Sha256};
+use tauri_plugin_http::reqwest::{Method, StatusCode, Url};
+
+use crate::http;
+use crate::settings::S3Settings;
+
+// S3 and the services that copy its API (MinIO, Cloudflare R2, Backblaze B2,
+// Wasabi), signed with Signature V4. Objects larger than MULTIPART_THRESHOLD
+// go up in parts so a dropped connection doesn't restart the whole upload.
+
+const MULTIPART_THRESHOLD: usize = 16 * 1024 * 1024;
+// S3 wants at least 5 MiB for every part but the last
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+pub struct S3 {
+    // The bucket root, path-style or virtual-hosted
+    base: Url,
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn sign(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes()).as_ref().to_vec()
+}
+
+// URI encoding as SigV4 defines it: everything but unreserved characters
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn between<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", tag))? + start;
+    Some(&xml[start..end])
+}
+
+impl S3 {
+    pub fn new(settings: &S3Settings, secret_key: String) -> Result<Self, String> {
+        let endpoint = settings.endpoint.trim().trim_end_matches('/');
+        if endpoint.is_empty() || settings.bucket.trim().is_empty() {
+            return Err("Set the S3 endpoint and bucket first".to_string());
+        }
+        let mut base = Url::parse(endpoint).map_err(|e| format!("Invalid S3 endpoint: {}", e))?;
+        if settings.path_style {
+            base.set_path(&format!("/{}/", settings.bucket.trim()));
+        } else {
+            let host = format!("{}.{}", settings.bucket.trim(), base.host_str().unwrap_or_default());
+            base.set_host(Some(&host)).map_err(|e| e.to_string())?;
+            base.set_path("/");
+        }
+        Ok(Self {
+            base,
+            prefix: settings.prefix.trim_matches('/').to_string(),
+            region: match settings.region.trim() {
+                "" => "us-east-1".to_string(),
+                region => region.to_string(),
+            },
+            access_key: settings.access_key_id.clone(),
+            secret_key,
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.prefix, name)
+        }
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        name: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<(StatusCode, Vec<u8>, Option<String>), String> {
+        let key: Vec<String> = self.key(name).split('/').map(encode).collect();
+        let path = format!("{}{}", self.base.path(), key.join("/"));
+        let mut query: Vec<(String, String)> = query.iter().map(|(k, v)| (encode(k), encode(v))).collect();
+        query.sort();
+        let query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+        let mut url = self.base.clone();
+        url.set_path(&path);
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(&body);
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let canonical_hash = sha256_hex(canonical.as_bytes());
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, canonical_hash);
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"]
+            .iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |key, part| sign(&key, part));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            hex(&sign(&key, &to_sign))
+        );
+
+        let request = http::client()
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .body(body);
+        let response = http::send(request).await.map_err(|e| format!("Failed to reach S3: {}", e))?;
+        let status = response.status();
+        let etag = response.headers().get("etag").and_then(|v| v.to_str().ok()).map(str::to_string);
+        let body = response.bytes().await.map_err(|e| e.to_string())?.to_vec();
+        if status == StatusCode::FORBIDDEN {
+            let text = String::from_utf8_lossy(&body);
+            return Err(format!("S3 refused the request: {}", between(&text, "Message").unwrap_or("access denied")));
+        }
+        Ok((status, body, etag))
+    }
+
+    // Buckets already exist; nothing to set up
+    pub async fn prepare(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let (status, body, _) = self.request(Method::GET, name, &[], Vec::new()).await?;
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(body)),
+            status => Err(format!("Failed to download {}: {}", name, status)),
+        }
+    }
+
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        if data.len() > MULTIPART_THRESHOLD {
+            return self.put_multipart(name, data).await;
+        }
+        let (status, body, _) = self.request(Method::PUT, name, &[], data).await?;
+        if !status.is_success() {
+            let text = String::from_utf8_lossy(&body);
+            return Err(format!("Failed to upload {}: {} {}", name, status, between(&text, "Message").unwrap_or("")));
+        }
+        Ok(())
+    }
+
+    async fn put_multipart(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        let (status, body, _) = self.request(Method::POST, name, &[("uploads", "")], Vec::new()).await?;
+        let text = String::from_utf8_lossy(&body);
+        let upload_id = between(&text, "UploadId")
+            .filter(|_| status.is_success())
+            .ok_or_else(|| format!("Failed to start uploading {}: {}", name, status))?
+            .to_string();
+
+        let result = self.upload_parts(name, &upload_id, &data).await;
+        if result.is_err() {
+            let _ = self.request(Method::DELETE, name, &[("uploadId", &upload_id)], Vec::new()).await;
+        }
+        result
+    }
+
+    async fn upload_parts(&self, name: &str, upload_id: &str, data: &[u8]) -> Result<(), String> {
+        let mut parts = String::new();
+        for (index, chunk) in data.chunks(PART_SIZE).enumerate() {
+            let number = (index + 1).to_string();
+            let query = [("partNumber", number.as_str()), ("uploadId", upload_id)];
+            let (status, _, etag) = self.request(Method::PUT, name, &query, chunk.to_vec()).await?;
+            let etag = etag
+                .filter(|_| status.is_success())
+                .ok_or_else(|| format!("Failed to upload part {} of {}: {}", number, name, status))?;
+            parts.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", number, etag));
+        }
+        let complete = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+        let query = [("uploadId", upload_id)];
+        let (status, body, _) = self.request(Method::POST, name, &query, complete.into_bytes()).await?;
+        let text = String::from_utf8_lossy(&body);
+        // Failures can come back as a 200 with an error document
+        if !status.is_success() || text.contains("<Error>") {
+            return Err(format!("Failed to finish uploading {}: {}", name, between(&text, "Message").unwrap_or("")));
+        }
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        let (status, _, _) = self.request(Method::DELETE, name, &[], Vec::new()).await?;
+        if !(status.is_success() || status == StatusCode::NOT_FOUND) {
+            return Err(format!("Failed to delete {}: {}", name, status));
+        }
+        Ok(())
+    }
+}
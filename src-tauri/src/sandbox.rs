@@ -0,0 +1,223 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use serde::Serialize;
+
+use crate::transcription::find_in_path;
+
+// Runs model-written code in a scratch directory of its own, with no network,
+// nothing of the user's home visible and only the scratch directory
+// writable: bubblewrap on Linux, sandbox-exec on macOS. With neither there's
+// no isolation to offer, so run_code refuses rather than running on the host.
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const MAX_TIMEOUT_SECS: u64 = 300;
+const MEMORY_LIMIT_KB: u64 = 1024 * 1024;
+// Largest file the code may write, stdout and stderr included, in ulimit -f
+// blocks: 512 bytes in sh, so 64 MB (1 KB in bash, so 128 MB)
+const FILE_LIMIT_BLOCKS: u64 = 128 * 1024;
+// Output and files beyond these are cut off or left out
+const MAX_OUTPUT_BYTES: usize = 20_000;
+const MAX_FILES: usize = 20;
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputFile {
+    // Relative to the scratch directory
+    pub path: String,
+    pub size: u64,
+    // UTF-8 files come back as text, anything else as base64
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunOutput {
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    // Files the code left in its working directory
+    pub files: Vec<OutputFile>,
+}
+
+// Interpreter and source file name for each supported language
+fn interpreter(language: &str) -> Result<(&'static str, &'static str), String> {
+    match language {
+        "python" => Ok(("python3", "main.py")),
+        "javascript" => Ok(("node", "main.js")),
+        "shell" => Ok(("sh", "main.sh")),
+        _ => Err(format!("Unsupported language: {}", language)),
+    }
+}
+
+// CPU time, memory and file size go through ulimit in a wrapper shell. V8 reserves far
+// more address space than it uses, so node gets no memory limit.
+fn limits(language: &str, timeout: u64) -> String {
+    let memory = if language == "javascript" { String::new() } else { format!("ulimit -v {};", MEMORY_LIMIT_KB) };
+    format!("ulimit -t {}; ulimit -f {}; {} exec \"$0\" \"$@\"", timeout, FILE_LIMIT_BLOCKS, memory)
+}
+
+#[cfg(target_os = "linux")]
+fn isolated(work: &Path, wrapper: &str, program: &Path, source: &str) -> Result<Command, String> {
+    let bwrap = find_in_path("bwrap").ok_or("Code execution needs bubblewrap (bwrap) to sandbox it")?;
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("/home"));
+    let mut cmd = Command::new(bwrap);
+    cmd.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp", "--tmpfs"])
+        .arg(&home)
+        .arg("--bind")
+        .args([work, work])
+        .arg("--chdir")
+        .arg(work)
+        .args(["--unshare-all", "--die-with-parent", "--", "sh", "-c", wrapper])
+        .arg(program)
+        .arg(source);
+    Ok(cmd)
+}
+
+#[cfg(target_os = "macos")]
+fn isolated(work: &Path, wrapper: &str, program: &Path, source: &str) -> Result<Command, String> {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/Users".to_string());
+    // Later rules win, so the scratch directory stays readable inside home
+    let profile = format!(
+        "(version 1)(allow default)(deny network*)(deny file-write*)(deny file-read* (subpath \"{home}\"))\
+         (allow file-read* file-write* (subpath \"{work}\"))(allow file-write* (literal \"/dev/null\"))",
+        home = home,
+        work = work.display()
+    );
+    let mut cmd = Command::new("sandbox-exec");
+    cmd.args(["-p", &profile, "sh", "-c", wrapper]).arg(program).arg(source);
+    Ok(cmd)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn isolated(_work: &Path, _wrapper: &str, _program: &Path, _source: &str) -> Result<Command, String> {
+    Err("Sandboxed code execution isn't available on this platform".to_string())
+}
+
+// Reads no more than the cap, however much the code printed
+fn read_capped(path: &Path) -> String {
+    let mut bytes = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(MAX_OUTPUT_BYTES as u64 + 1).read_to_end(&mut bytes));
+    if read.is_err() {
+        return String::new();
+    }
+    let mut text = String::from_utf8_lossy(&bytes).to_string();
+    if bytes.len() > MAX_OUTPUT_BYTES {
+        let mut end = MAX_OUTPUT_BYTES;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n[output truncated]");
+    }
+    text
+}
+
+fn collect_files(root: &Path, dir: &Path, source: &Path, files: &mut Vec<OutputFile>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        // Not followed: the code could link to anything on the host it
+        // can't read itself, like ~/.ssh
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+        if kind.is_dir() {
+            collect_files(root, &path, source, files);
+            continue;
+        }
+        if !kind.is_file() || path == source {
+            continue;
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string();
+        let mut file = OutputFile { path: relative, size, text: None, base64: None };
+        if size <= MAX_FILE_BYTES {
+            if let Ok(bytes) = fs::read(&path) {
+                match String::from_utf8(bytes) {
+                    Ok(text) => file.text = Some(text),
+                    Err(e) => file.base64 = Some(base64::engine::general_purpose::STANDARD.encode(e.into_bytes())),
+                }
+            }
+        }
+        files.push(file);
+    }
+}
+
+fn run_in(run_dir: &Path, language: &str, code: &str, timeout: u64) -> Result<RunOutput, String> {
+    let (interpreter, source_name) = interpreter(language)?;
+    let program = find_in_path(interpreter).ok_or_else(|| format!("{} not found", interpreter))?;
+    let work = run_dir.join("work");
+    fs::create_dir_all(&work).map_err(|e| format!("Failed to create the sandbox: {}", e))?;
+    let source = work.join(source_name);
+    fs::write(&source, code).map_err(|e| format!("Failed to write the code: {}", e))?;
+
+    // Kept outside the working directory so they don't show up as output files
+    let (stdout_path, stderr_path) = (run_dir.join("stdout"), run_dir.join("stderr"));
+    let stdout = File::create(&stdout_path).map_err(|e| e.to_string())?;
+    let stderr = File::create(&stderr_path).map_err(|e| e.to_string())?;
+
+    let mut cmd = isolated(&work, &limits(language, timeout), &program, source_name)?;
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    cmd.current_dir(&work)
+        .env_clear()
+        .env("PATH", path)
+        .env("HOME", &work)
+        .env("TMPDIR", &work)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to start the sandbox: {}", e))?;
+
+    // ulimit -t only counts CPU time, so a sleeping program is caught here
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let (status, timed_out) = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break (Some(status), false);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break (None, true);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    let mut files = Vec::new();
+    collect_files(&work, &work, &source, &mut files);
+    Ok(RunOutput {
+        exit_code: status.and_then(|s| s.code()),
+        timed_out,
+        stdout: read_capped(&stdout_path),
+        stderr: read_capped(&stderr_path),
+        files,
+    })
+}
+
+pub fn run(app: &tauri::AppHandle, language: &str, code: &str, timeout: Option<u64>) -> Result<RunOutput, String> {
+    let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT_SECS).clamp(1, MAX_TIMEOUT_SECS);
+    let runs = crate::app_data_dir(app)?.join("sandbox");
+    fs::create_dir_all(&runs).map_err(|e| format!("Failed to create the sandbox: {}", e))?;
+    // Created here rather than reused, so two runs never share one
+    let run_dir = runs.join(format!("run_{}", crate::share::hex(&crate::encryption::random(8)?)));
+    fs::create_dir(&run_dir).map_err(|e| format!("Failed to create the sandbox: {}", e))?;
+    let result = run_in(&run_dir, language, code, timeout);
+    if let Err(e) = fs::remove_dir_all(&run_dir) {
+        log::warn!("Failed to clean up {}: {}", run_dir.display(), e);
+    }
+    result
+}
@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tauri::Manager;
+
+use crate::attachments::{self, Attachment, AttachmentSource};
+use crate::transcription::find_in_path;
+
+// Lets the compositor finish hiding our windows before the capture
+const HIDE_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureMode {
+    Screen,
+    // The user picks a window
+    Window,
+    // The user drags out a rectangle
+    Region,
+}
+
+fn run(mut cmd: Command) -> Result<(), String> {
+    let output = cmd.output().map_err(|e| format!("Failed to run screenshot tool: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Screenshot failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+fn command(program: &Path, args: &[&str], output: &Path) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.args(args).arg(output);
+    cmd
+}
+
+// Wayland needs grim (with slurp for regions); X11 desktops usually ship one
+// of the others.
+fn capture_linux(mode: CaptureMode, output: &Path) -> Result<(), String> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    if wayland && mode != CaptureMode::Window {
+        if let Some(grim) = find_in_path("grim") {
+            if mode == CaptureMode::Screen {
+                return run(command(&grim, &[], output));
+            }
+            if let Some(slurp) = find_in_path("slurp") {
+                let selection = Command::new(slurp).output().map_err(|e| e.to_string())?;
+                if !selection.status.success() {
+                    return Err("Screenshot cancelled".to_string());
+                }
+                let geometry = String::from_utf8_lossy(&selection.stdout).trim().to_string();
+                return run(command(&grim, &["-g", &geometry], output));
+            }
+        }
+    }
+
+    let tools: &[(&str, &[&str])] = match mode {
+        CaptureMode::Screen => &[
+            ("gnome-screenshot", &["-f"]),
+            ("spectacle", &["-b", "-n", "-f", "-o"]),
+            ("scrot", &["-o"]),
+            ("import", &["-window", "root"]),
+        ],
+        CaptureMode::Window => &[
+            ("gnome-screenshot", &["-w", "-f"]),
+            ("spectacle", &["-b", "-n", "-a", "-o"]),
+            ("scrot", &["-s", "-o"]),
+            ("import", &[]),
+        ],
+        CaptureMode::Region => &[
+            ("gnome-screenshot", &["-a", "-f"]),
+            ("spectacle", &["-b", "-n", "-r", "-o"]),
+            ("scrot", &["-s", "-o"]),
+            ("import", &[]),
+        ],
+    };
+    let (program, args) = tools
+        .iter()
+        .find_map(|(name, args)| Some((find_in_path(name)?, *args)))
+        .ok_or("No screenshot tool found - install gnome-screenshot, spectacle, scrot, grim or ImageMagick")?;
+    run(command(&program, args, output))
+}
+
+fn capture_windows(mode: CaptureMode, output: &Path) -> Result<(), String> {
+    if mode != CaptureMode::Screen {
+        return Err("Only full-screen captures are supported on Windows".to_string());
+    }
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+         $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+         $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+         $g = [System.Drawing.Graphics]::FromImage($bmp); \
+         $g.CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size); \
+         $bmp.Save('{}', [System.Drawing.Imaging.ImageFormat]::Png)",
+        output.to_string_lossy().replace('\'', "''")
+    );
+    let mut cmd = Command::new("powershell");
+    cmd.args(["-NoProfile", "-Command", &script]);
+    run(cmd)
+}
+
+fn capture_to(mode: CaptureMode, output: &Path) -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        let args: &[&str] = match mode {
+            CaptureMode::Screen => &["-x"],
+            CaptureMode::Window => &["-x", "-i", "-w"],
+            CaptureMode::Region => &["-x", "-i", "-s"],
+        };
+        run(command(Path::new("screencapture"), args, output))
+    } else if cfg!(windows) {
+        capture_windows(mode, output)
+    } else {
+        capture_linux(mode, output)
+    }
+}
+
+fn capture(app: &tauri::AppHandle, mode: CaptureMode, hide_app: bool) -> Result<Attachment, String> {
+    let path: PathBuf = std::env::temp_dir().join(format!("anchor-screenshot-{}.png", crate::chats::now_millis()));
+
+    let hidden: Vec<_> = if hide_app {
+        app.webview_windows().into_values().filter(|w| w.is_visible().unwrap_or(false)).collect()
+    } else {
+        Vec::new()
+    };
+    for window in &hidden {
+        let _ = window.hide();
+    }
+    if !hidden.is_empty() {
+        std::thread::sleep(HIDE_DELAY);
+    }
+    let result = capture_to(mode, &path);
+    for window in &hidden {
+        let _ = window.show();
+    }
+    result?;
+
+    // Interactive tools exit cleanly without writing anything when cancelled
+    if !path.is_file() {
+        return Err("Screenshot cancelled".to_string());
+    }
+    let name = format!("Screenshot {}.png", chrono::Local::now().format("%Y-%m-%d %H.%M.%S"));
+    let saved = attachments::save(app, AttachmentSource::Path(path.to_string_lossy().to_string()), Some(name));
+    let _ = std::fs::remove_file(&path);
+    saved
+}
+
+// Captures the screen, a window or a region and stores it as an attachment.
+// Anchor's own windows are hidden for the capture unless `hide_app` is false.
+#[tauri::command]
+pub async fn capture_screenshot(
+    app: tauri::AppHandle,
+    mode: Option<CaptureMode>,
+    hide_app: Option<bool>,
+) -> Result<Attachment, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        capture(&app, mode.unwrap_or(CaptureMode::Screen), hide_app.unwrap_or(true))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
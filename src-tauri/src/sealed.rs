@@ -0,0 +1,133 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+
+use crate::encryption;
+
+// Password-protected files for exports. There's no AES zip or age support
+// among our dependencies, so this is a small format of its own: MAGIC, the
+// PBKDF2 iteration count and salt, then the file in AES-256-GCM sealed
+// chunks. Anchor opens them again with decrypt_export.
+const MAGIC: &[u8] = b"ANCHORSEALED1";
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+// Plaintext per chunk, so large exports never have to fit in memory
+const CHUNK: usize = 64 * 1024;
+// The count comes from the file, so a crafted one can't tie up a core for
+// hours; well above what encrypt_file has ever written
+const MAX_ITERATIONS: u32 = 10 * encryption::ITERATIONS;
+
+fn key(password: &str, salt: &[u8], iterations: u32) -> LessSafeKey {
+    let mut key = [0u8; KEY_LEN];
+    let iterations = NonZeroU32::new(iterations.max(1)).unwrap();
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.as_bytes(), &mut key);
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("key length matches the algorithm"))
+}
+
+// The chunk counter, with the last byte flagging the final chunk so a
+// truncated file fails to open rather than coming out short. Each file has
+// its own salt and so its own key, which keeps counter nonces unique.
+fn nonce(counter: u64, last: bool) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 9..NONCE_LEN - 1].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+// Reads until `buf` is full or the input ends
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+// Runs `f` over every chunk of `input`, `size` bytes at a time, telling it
+// whether it's the last one
+fn chunks(
+    input: &mut impl Read,
+    size: usize,
+    mut f: impl FnMut(Vec<u8>, u64, bool) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut current = vec![0u8; size];
+    let mut len = read_full(input, &mut current).map_err(|e| e.to_string())?;
+    for counter in 0.. {
+        let mut next = vec![0u8; size];
+        let next_len = if len == size { read_full(input, &mut next).map_err(|e| e.to_string())? } else { 0 };
+        current.truncate(len);
+        f(current, counter, next_len == 0)?;
+        if next_len == 0 {
+            break;
+        }
+        (current, len) = (next, next_len);
+    }
+    Ok(())
+}
+
+pub fn encrypt_file(source: &Path, destination: &Path, password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("A password is required".to_string());
+    }
+    let salt = encryption::random(encryption::SALT_LEN)?;
+    let iterations = encryption::ITERATIONS;
+    let header = [MAGIC, &iterations.to_be_bytes(), &salt].concat();
+    let key = key(password, &salt, iterations);
+
+    let mut input = BufReader::new(File::open(source).map_err(|e| e.to_string())?);
+    let file = File::create(destination).map_err(|e| format!("Failed to create {}: {}", destination.display(), e))?;
+    let mut output = BufWriter::new(file);
+    output.write_all(&header).map_err(|e| e.to_string())?;
+    chunks(&mut input, CHUNK, |mut data, counter, last| {
+        key.seal_in_place_append_tag(nonce(counter, last), Aad::from(&header), &mut data)
+            .map_err(|_| "Encryption failed".to_string())?;
+        output.write_all(&data).map_err(|e| e.to_string())
+    })?;
+    output.flush().map_err(|e| e.to_string())
+}
+
+pub fn decrypt_file(source: &Path, destination: &Path, password: &str) -> Result<(), String> {
+    let mut input = BufReader::new(File::open(source).map_err(|e| e.to_string())?);
+    let mut header = vec![0u8; MAGIC.len() + 4 + encryption::SALT_LEN];
+    input.read_exact(&mut header).map_err(|_| "Not a password-protected export".to_string())?;
+    if !header.starts_with(MAGIC) {
+        return Err("Not a password-protected export".to_string());
+    }
+    let iterations = u32::from_be_bytes(header[MAGIC.len()..MAGIC.len() + 4].try_into().unwrap());
+    if iterations == 0 || iterations > MAX_ITERATIONS {
+        return Err("The export's key settings are out of range; it may be damaged".to_string());
+    }
+    let key = key(password, &header[MAGIC.len() + 4..], iterations);
+
+    let file = File::create(destination).map_err(|e| format!("Failed to create {}: {}", destination.display(), e))?;
+    let mut output = BufWriter::new(file);
+    let result = chunks(&mut input, CHUNK + TAG_LEN, |mut data, counter, last| {
+        let plaintext = key
+            .open_in_place(nonce(counter, last), Aad::from(&header), &mut data)
+            .map_err(|_| "Wrong password or a damaged file".to_string())?;
+        output.write_all(plaintext).map_err(|e| e.to_string())
+    })
+    .and_then(|_| output.flush().map_err(|e| e.to_string()));
+    if result.is_err() {
+        drop(output);
+        let _ = fs::remove_file(destination);
+    }
+    result
+}
+
+// Opens a password-protected export, writing the original file to
+// `destination`
+#[tauri::command]
+pub async fn decrypt_export(source: String, destination: String, password: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || decrypt_file(Path::new(&source), Path::new(&destination), &password))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::completion::ChatMessage;
+use crate::settings::{ProviderConfig, Settings};
+
+// Tokens shorter than this are too short to tell apart from ordinary words
+const MIN_ENTROPY_LEN: usize = 24;
+// Longer runs are usually encoded data (images, archives), not credentials
+const MAX_ENTROPY_LEN: usize = 256;
+// Bits per character; random base64 of this length sits around 4.3-4.6
+const MIN_ENTROPY: f64 = 4.0;
+// Characters of a finding shown in logs and the UI; the rest is masked
+const PREVIEW_CHARS: usize = 4;
+
+// (kind, prefix, minimum length of what follows the prefix). More specific
+// prefixes come first so "sk-ant-" isn't reported as an OpenAI key.
+const PREFIXES: &[(&str, &str, usize)] = &[
+    ("AWS access key", "AKIA", 16),
+    ("AWS access key", "ASIA", 16),
+    ("GitHub token", "github_pat_", 22),
+    ("GitHub token", "ghp_", 36),
+    ("GitHub token", "gho_", 36),
+    ("GitHub token", "ghu_", 36),
+    ("GitHub token", "ghs_", 36),
+    ("GitHub token", "ghr_", 36),
+    ("Anthropic API key", "sk-ant-", 32),
+    ("OpenAI API key", "sk-proj-", 32),
+    ("OpenAI API key", "sk-", 20),
+    ("Slack token", "xoxb-", 10),
+    ("Slack token", "xoxp-", 10),
+    ("Slack token", "xoxa-", 10),
+    ("Slack token", "xoxr-", 10),
+    ("Slack token", "xoxs-", 10),
+    ("Google API key", "AIza", 35),
+    ("Stripe key", "sk_live_", 16),
+    ("Stripe key", "rk_live_", 16),
+    ("Hugging Face token", "hf_", 30),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretScan {
+    Off,
+    // Lets the request through and tells the UI what was found
+    #[default]
+    Warn,
+    Block,
+}
+
+// The setting as last saved. Kept here rather than on each request, so
+// every request that leaves the app is checked against it, including ones
+// built away from the chat path (quick prompts, titles, summaries).
+static MODE: Mutex<SecretScan> = Mutex::new(SecretScan::Warn);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretFinding {
+    pub kind: &'static str,
+    // Index into the request's messages
+    pub message: usize,
+    // The first few characters, enough to recognise it without leaking it
+    pub preview: String,
+}
+
+fn preview(secret: &str) -> String {
+    format!("{}…", secret.chars().take(PREVIEW_CHARS).collect::<String>())
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_+/=.".contains(c)
+}
+
+// Shannon entropy in bits per character
+fn entropy(token: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in token.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = token.chars().count() as f64;
+    counts.values().map(|&n| n as f64 / len).map(|p| -p * p.log2()).sum()
+}
+
+fn is_jwt(token: &str) -> bool {
+    let parts: Vec<&str> = token.split('.').collect();
+    parts.len() == 3
+        && parts[0].starts_with("eyJ")
+        && parts.iter().all(|p| p.len() >= 10 && p.chars().all(|c| c.is_ascii_alphanumeric() || "-_".contains(c)))
+}
+
+// Mixed case and digits rules out words, hex digests and UUIDs
+fn looks_random(token: &str) -> bool {
+    let len = token.len();
+    (MIN_ENTROPY_LEN..=MAX_ENTROPY_LEN).contains(&len)
+        && token.chars().any(|c| c.is_ascii_uppercase())
+        && token.chars().any(|c| c.is_ascii_lowercase())
+        && token.chars().any(|c| c.is_ascii_digit())
+        && !token.contains('.')
+        && entropy(token) >= MIN_ENTROPY
+}
+
+fn classify(token: &str) -> Option<&'static str> {
+    let prefixed = PREFIXES.iter().find(|(_, prefix, min)| {
+        token.strip_prefix(prefix).is_some_and(|rest| {
+            rest.len() >= *min && rest.chars().all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+        })
+    });
+    if let Some((kind, _, _)) = prefixed {
+        return Some(kind);
+    }
+    if is_jwt(token) {
+        return Some("JSON web token");
+    }
+    looks_random(token).then_some("High-entropy string")
+}
+
+fn scan_text(text: &str, message: usize, findings: &mut Vec<SecretFinding>) {
+    if let Some(start) = text.find("-----BEGIN") {
+        let header = text[start..].lines().next().unwrap_or_default();
+        if header.contains("PRIVATE KEY-----") {
+            findings.push(SecretFinding { kind: "Private key", message, preview: header.to_string() });
+        }
+    }
+    for token in text.split(|c| !is_token_char(c)) {
+        let token = token.trim_matches(|c| ".=".contains(c));
+        if let Some(kind) = classify(token) {
+            let finding = SecretFinding { kind, message, preview: preview(token) };
+            if !findings.contains(&finding) {
+                findings.push(finding);
+            }
+        }
+    }
+}
+
+// Looks through message text and tool call arguments by pattern (known key
+// formats, private key blocks, JWTs) and by entropy for anything else that
+// looks like a credential
+fn scan(messages: &[ChatMessage]) -> Vec<SecretFinding> {
+    let mut findings = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        scan_text(&message.content, index, &mut findings);
+        for call in &message.tool_calls {
+            scan_text(&call.arguments, index, &mut findings);
+        }
+    }
+    findings
+}
+
+// Called at startup and whenever settings are saved
+pub fn configure(settings: &Settings) {
+    if let Ok(mut mode) = MODE.lock() {
+        *mode = settings.secret_scan;
+    }
+}
+
+pub fn mode() -> SecretScan {
+    MODE.lock().map(|mode| *mode).unwrap_or_default()
+}
+
+// What the scanner reports for a request to `provider`. Providers on this
+// machine are skipped since nothing leaves it; a local engine on another
+// host is scanned like any other.
+pub fn findings(mode: SecretScan, provider: &ProviderConfig, messages: &[ChatMessage]) -> Vec<SecretFinding> {
+    if mode == SecretScan::Off || provider.is_on_device() {
+        return Vec::new();
+    }
+    scan(messages)
+}
+
+// Logs what the scanner caught and, when blocking, refuses the request
+pub fn check(provider: &ProviderConfig, messages: &[ChatMessage]) -> Result<(), String> {
+    let mode = mode();
+    let findings = findings(mode, provider, messages);
+    if findings.is_empty() {
+        return Ok(());
+    }
+    let caught: Vec<String> = findings.iter().map(|f| format!("{} ({})", f.kind, f.preview)).collect();
+    log::warn!("Possible secrets in a request to {}: {}", provider.name, caught.join(", "));
+    if mode == SecretScan::Block {
+        return Err(format!(
+            "Not sent: the request to {} contains what looks like a secret: {}",
+            provider.name,
+            caught.join(", ")
+        ));
+    }
+    Ok(())
+}
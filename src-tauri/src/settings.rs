@@ -0,0 +1,663 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::Emitter;
+use tauri_plugin_http::reqwest::Url;
+
+use crate::context::ContextStrategy;
+use crate::presets::{self, Preset};
+use crate::secrets::SecretScan;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    OpenAi,
+    Ollama,
+    LlamaCpp,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfig {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub kind: ProviderKind,
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // Whether the endpoint honours `response_format: json_schema`
+    #[serde(default = "default_true")]
+    pub structured_outputs: bool,
+    // Override the built-in image limits for this endpoint, see vision.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_image_edge: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_image_bytes: Option<usize>,
+}
+
+impl ProviderConfig {
+    // Accepts both a bare base URL and a full chat completions endpoint,
+    // since that's what the settings modal has historically stored.
+    pub fn api_base(&self) -> String {
+        let url = self.base_url.trim().trim_end_matches('/');
+        let url = url.strip_suffix("/chat/completions").unwrap_or(url);
+        url.trim_end_matches('/').to_string()
+    }
+
+    // Root of the OpenAI-compatible API; Ollama serves it under /v1
+    pub fn openai_base(&self) -> String {
+        let base = self.api_base();
+        match self.kind {
+            ProviderKind::Ollama if !base.ends_with("/v1") => format!("{}/v1", base),
+            _ => base,
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self.kind, ProviderKind::Ollama | ProviderKind::LlamaCpp)
+    }
+
+    // Whether requests to it stay on this machine, so it works offline
+    pub fn is_on_device(&self) -> bool {
+        Url::parse(&self.api_base()).is_ok_and(|url| crate::http::is_loopback(&url))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpeechSettings {
+    pub whisper_model: String,
+    // "<system|provider id>:<voice>", falls back to the OS default voice
+    pub voice: Option<String>,
+    pub tts_model: String,
+    // Transcribe audio attachments as soon as they're added
+    pub auto_transcribe: bool,
+}
+
+impl Default for SpeechSettings {
+    fn default() -> Self {
+        Self {
+            whisper_model: "base".to_string(),
+            voice: None,
+            tts_model: "tts-1".to_string(),
+            auto_transcribe: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AttachmentLimits {
+    // Largest file the attachment store accepts
+    pub max_file_bytes: u64,
+    // Largest image sent to a provider
+    pub max_image_bytes: u64,
+    // Largest document whose text is inlined into a prompt
+    pub max_document_bytes: u64,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 4 * 1024 * 1024 * 1024,
+            max_image_bytes: 20 * 1024 * 1024,
+            max_document_bytes: 100 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppLockSettings {
+    // Only apply once a lock passphrase is set, see app_lock.rs
+    pub lock_on_startup: bool,
+    pub lock_on_minimize: bool,
+    // Touch ID or Windows Hello as an alternative to the passphrase
+    pub biometric_unlock: bool,
+    // Locks after this many minutes without activity; 0 turns it off
+    pub idle_timeout_minutes: u64,
+}
+
+impl Default for AppLockSettings {
+    fn default() -> Self {
+        Self { lock_on_startup: true, lock_on_minimize: false, biometric_unlock: false, idle_timeout_minutes: 0 }
+    }
+}
+
+// Placeholders for personal details in requests to cloud providers, see
+// anonymize.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnonymizeSettings {
+    pub enabled: bool,
+    // Also treat runs of capitalised words as names
+    pub detect_names: bool,
+    // Names (people, companies, places) to always replace
+    pub names: Vec<String>,
+}
+
+impl Default for AnonymizeSettings {
+    fn default() -> Self {
+        Self { enabled: false, detect_names: true, names: Vec::new() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncBackend {
+    #[default]
+    Off,
+    WebDav,
+    S3,
+    Folder,
+    Dropbox,
+    GoogleDrive,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebDavSettings {
+    pub url: String,
+    pub username: String,
+    // Created under the server URL if missing; the password is in the keychain
+    pub folder: String,
+}
+
+impl Default for WebDavSettings {
+    fn default() -> Self {
+        Self { url: String::new(), username: String::new(), folder: "Anchor".to_string() }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct S3Settings {
+    // e.g. https://s3.us-east-1.amazonaws.com, http://localhost:9000 for
+    // MinIO, https://<account>.r2.cloudflarestorage.com for R2
+    pub endpoint: String,
+    // R2 takes "auto"
+    pub region: String,
+    pub bucket: String,
+    // Object keys start with this; the secret key is in the keychain
+    pub prefix: String,
+    pub access_key_id: String,
+    // bucket in the path rather than the host name, which MinIO needs
+    pub path_style: bool,
+}
+
+impl Default for S3Settings {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            region: "us-east-1".to_string(),
+            bucket: String::new(),
+            prefix: "anchor".to_string(),
+            access_key_id: String::new(),
+            path_style: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FolderSettings {
+    // A folder inside iCloud Drive, OneDrive, Dropbox and the like
+    pub path: String,
+}
+
+// An app registered with Dropbox or Google, for signing in, see oauth.rs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OAuthAppSettings {
+    // Dropbox calls it the app key
+    pub client_id: String,
+    // Google issues one to desktop apps too and doesn't treat it as
+    // confidential; Dropbox doesn't need one
+    pub client_secret: String,
+}
+
+// Chats the sync engine leaves alone, wherever they're synced to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncRules {
+    // A folder excludes its subfolders too
+    pub excluded_folders: Vec<String>,
+    pub excluded_tags: Vec<String>,
+    // Incognito chats that were kept, see incognito::keep_incognito_chat
+    pub exclude_from_incognito: bool,
+}
+
+impl Default for SyncRules {
+    fn default() -> Self {
+        Self { excluded_folders: Vec::new(), excluded_tags: Vec::new(), exclude_from_incognito: true }
+    }
+}
+
+// When background syncs run besides every interval_minutes, see sync::schedule
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncSchedule {
+    // Once saves have stopped for debounce_seconds
+    pub on_save: bool,
+    pub debounce_seconds: u64,
+    // When the window comes back to the front
+    pub on_focus: bool,
+    pub skip_metered: bool,
+    // Only while running on battery
+    pub skip_low_battery: bool,
+    pub low_battery_percent: u8,
+}
+
+impl Default for SyncSchedule {
+    fn default() -> Self {
+        Self {
+            on_save: true,
+            debounce_seconds: 10,
+            on_focus: true,
+            skip_metered: true,
+            skip_low_battery: true,
+            low_battery_percent: 20,
+        }
+    }
+}
+
+// Two-way sync of the chat library with a remote store, see sync.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncSettings {
+    pub backend: SyncBackend,
+    // 0 syncs only when asked to
+    pub interval_minutes: u64,
+    pub schedule: SyncSchedule,
+    // Seal chats and the manifest before they reach the backend, see e2e.rs
+    pub end_to_end: bool,
+    pub rules: SyncRules,
+    // Settings, prompts and assistants go along with the chats, see
+    // settings_sync.rs
+    pub include_settings: bool,
+    pub webdav: WebDavSettings,
+    pub s3: S3Settings,
+    pub folder: FolderSettings,
+    pub dropbox: OAuthAppSettings,
+    pub google_drive: OAuthAppSettings,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        Self {
+            backend: SyncBackend::Off,
+            interval_minutes: 15,
+            schedule: SyncSchedule::default(),
+            end_to_end: true,
+            rules: SyncRules::default(),
+            include_settings: true,
+            webdav: WebDavSettings::default(),
+            s3: S3Settings::default(),
+            folder: FolderSettings::default(),
+            dropbox: OAuthAppSettings::default(),
+            google_drive: OAuthAppSettings::default(),
+        }
+    }
+}
+
+// Versions the chats directory with git, see git_sync.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GitSyncSettings {
+    pub enabled: bool,
+    // Pulled from and pushed to after each commit when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    pub branch: String,
+}
+
+impl Default for GitSyncSettings {
+    fn default() -> Self {
+        Self { enabled: false, remote: None, branch: "main".to_string() }
+    }
+}
+
+// Device-to-device sync on the local network, see lan_sync.rs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LanSyncSettings {
+    pub enabled: bool,
+    // Shown to other devices; the host name when empty
+    pub device_name: String,
+    // 0 picks a free port at startup
+    pub port: u16,
+}
+
+// The small prompt window a global shortcut opens, see quick_prompt.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QuickPromptSettings {
+    pub enabled: bool,
+    // In the form "CommandOrControl+Shift+Space"
+    pub shortcut: String,
+    // The first enabled provider when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    // The model of the last quick reply when empty
+    pub model: String,
+    // Minutes after the last quick prompt before the next one starts a new
+    // chat instead of continuing it
+    pub new_chat_after_minutes: u64,
+}
+
+impl Default for QuickPromptSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            shortcut: "CommandOrControl+Shift+Space".to_string(),
+            provider_id: None,
+            model: String::new(),
+            new_chat_after_minutes: 60,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PowerPolicy {
+    Never,
+    // In low power mode or battery saver
+    LowPower,
+    // Whenever the laptop runs on battery
+    #[default]
+    OnBattery,
+}
+
+// When local inference eases off to spare the battery, see power.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PowerSettings {
+    pub policy: PowerPolicy,
+    // Also while the OS is holding the CPU back to cool down
+    pub when_hot: bool,
+    // Threads the local embedding model may use then; 0 is half the cores
+    pub threads: usize,
+    // Background indexing waits until it's over
+    pub pause_indexing: bool,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self { policy: PowerPolicy::OnBattery, when_hot: true, threads: 0, pause_indexing: true }
+    }
+}
+
+// The theme windows are drawn in; System follows the OS, see appearance.rs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowTheme {
+    System,
+    Light,
+    #[default]
+    Dark,
+}
+
+// A shortcut that starts a prompt from the last copied text, see
+// clipboard_watch.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClipboardWatchSettings {
+    pub enabled: bool,
+    pub shortcut: String,
+    // "{text}" is replaced with the copied text, which is added at the end
+    // when it's missing
+    pub prompt: String,
+    // App names, matched in part and case-insensitively; only these count
+    // when any are given
+    pub allowed_apps: Vec<String>,
+    pub denied_apps: Vec<String>,
+    // Older copies are left alone
+    pub max_age_minutes: u64,
+}
+
+impl Default for ClipboardWatchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shortcut: "CommandOrControl+Shift+E".to_string(),
+            prompt: "Explain this:\n\n```\n{text}\n```".to_string(),
+            allowed_apps: Vec::new(),
+            denied_apps: ["1Password", "Bitwarden", "KeePass", "LastPass", "Dashlane", "Keychain Access"]
+                .iter()
+                .map(|app| app.to_string())
+                .collect(),
+            max_age_minutes: 10,
+        }
+    }
+}
+
+// A system notification when an answer finishes while Anchor is in the
+// background, see notifications.rs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    // Quicker answers don't notify
+    pub min_seconds: u64,
+    // Part of the answer in the notification; never for locked or incognito
+    // chats
+    pub show_snippet: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self { enabled: true, min_seconds: 10, show_snippet: true }
+    }
+}
+
+// When enabled, the backend only contacts the enabled providers and these
+// hosts, see http.rs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkAllowlist {
+    pub enabled: bool,
+    pub hosts: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub providers: Vec<ProviderConfig>,
+    pub speech: SpeechSettings,
+    // "local:<name>" or "<provider id>:<model>", see embeddings.rs
+    pub embedding_model: String,
+    // Overrides for models whose context window we don't know (or get wrong)
+    pub context_windows: HashMap<String, usize>,
+    pub context_strategy: ContextStrategy,
+    // User turns before a title and tags are suggested; 0 turns it off
+    pub auto_title_after_turns: usize,
+    pub presets: Vec<Preset>,
+    // "<provider id>:<model>" or "<provider id>" -> preset id
+    pub default_presets: HashMap<String, String>,
+    // Hours between background attachment GC runs; 0 turns it off
+    pub attachment_gc_interval_hours: u64,
+    pub attachment_limits: AttachmentLimits,
+    // Drops GPS, camera and other EXIF/XMP data from images before they're
+    // stored or sent to a cloud provider
+    pub strip_image_metadata: bool,
+    // Indexes past conversations so new ones can recall them, see history.rs
+    pub chat_history_memory: bool,
+    // Saves lasting facts about the user after each turn, see memory.rs
+    pub extract_memories: bool,
+    // Adds saved facts to the system prompt
+    pub inject_memories: bool,
+    pub app_lock: AppLockSettings,
+    // What to do when an outgoing request to a cloud provider looks like it
+    // holds an API key, token or private key
+    pub secret_scan: SecretScan,
+    // Keeps a hash-chained record of network requests, files read by tools
+    // and exports, see audit.rs
+    pub audit_log: bool,
+    // Overwrites chat and attachment files before deleting them and purges
+    // deleted entries from vector indexes right away, see shred.rs
+    pub secure_delete: bool,
+    // Counts feature use and errors on this machine only, see analytics.rs
+    pub local_analytics: bool,
+    pub network_allowlist: NetworkAllowlist,
+    pub anonymize: AnonymizeSettings,
+    // Local models, embeddings and tools only; cloud providers and every
+    // other outbound request fail right away, see http.rs
+    pub offline_mode: bool,
+    pub git_sync: GitSyncSettings,
+    pub sync: SyncSettings,
+    pub lan_sync: LanSyncSettings,
+    // An icon in the tray or menu bar, which keeps Anchor running after the
+    // window is closed, see tray.rs
+    pub tray_icon: bool,
+    pub quick_prompt: QuickPromptSettings,
+    pub notifications: NotificationSettings,
+    // Chat titles in Spotlight or the Start menu, see os_search.rs
+    pub os_search: bool,
+    // "Send to Anchor" in the macOS Services and share menus, see
+    // share_service.rs
+    pub share_service: bool,
+    // Starts Anchor when the user logs in, see autostart.rs
+    pub launch_at_login: bool,
+    // A launch at login opens to the tray without a window
+    pub start_hidden: bool,
+    pub clipboard_watch: ClipboardWatchSettings,
+    // Clicking the menu bar icon opens a small chat, see mini_chat.rs
+    pub menu_bar_chat: bool,
+    pub window_theme: WindowTheme,
+    pub power: PowerSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            providers: vec![
+                ProviderConfig {
+                    id: "ollama".to_string(),
+                    name: "Ollama".to_string(),
+                    kind: ProviderKind::Ollama,
+                    base_url: "http://localhost:11434".to_string(),
+                    api_key: String::new(),
+                    enabled: true,
+                    structured_outputs: true,
+                    max_image_edge: None,
+                    max_image_bytes: None,
+                },
+                ProviderConfig {
+                    id: "llamacpp".to_string(),
+                    name: "llama.cpp".to_string(),
+                    kind: ProviderKind::LlamaCpp,
+                    base_url: "http://localhost:8080/v1".to_string(),
+                    api_key: String::new(),
+                    enabled: true,
+                    structured_outputs: true,
+                    max_image_edge: None,
+                    max_image_bytes: None,
+                },
+            ],
+            speech: SpeechSettings::default(),
+            embedding_model: "local:all-minilm-l6-v2".to_string(),
+            context_windows: HashMap::new(),
+            context_strategy: ContextStrategy::default(),
+            auto_title_after_turns: 2,
+            presets: presets::builtin(),
+            default_presets: HashMap::new(),
+            attachment_gc_interval_hours: 24,
+            attachment_limits: AttachmentLimits::default(),
+            strip_image_metadata: true,
+            chat_history_memory: false,
+            extract_memories: false,
+            inject_memories: true,
+            app_lock: AppLockSettings::default(),
+            secret_scan: SecretScan::default(),
+            audit_log: false,
+            secure_delete: false,
+            local_analytics: false,
+            network_allowlist: NetworkAllowlist::default(),
+            anonymize: AnonymizeSettings::default(),
+            offline_mode: false,
+            git_sync: GitSyncSettings::default(),
+            sync: SyncSettings::default(),
+            lan_sync: LanSyncSettings::default(),
+            tray_icon: true,
+            quick_prompt: QuickPromptSettings::default(),
+            notifications: NotificationSettings::default(),
+            os_search: false,
+            share_service: true,
+            launch_at_login: false,
+            start_hidden: true,
+            clipboard_watch: ClipboardWatchSettings::default(),
+            menu_bar_chat: cfg!(target_os = "macos"),
+            window_theme: WindowTheme::Dark,
+            power: PowerSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn provider(&self, id: &str) -> Option<&ProviderConfig> {
+        self.providers.iter().find(|p| p.id == id)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn settings_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("settings.json"))
+}
+
+pub fn load(app: &tauri::AppHandle) -> Result<Settings, String> {
+    let path = settings_path(app)?;
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read settings: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid settings file: {}", e))
+}
+
+pub fn save(app: &tauri::AppHandle, settings: &Settings) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(settings_path(app)?, content).map_err(|e| format!("Failed to write settings: {}", e))?;
+    crate::http::configure(settings);
+    crate::secrets::configure(settings);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_settings(app: tauri::AppHandle) -> Result<Settings, String> {
+    crate::app_lock::ensure_unlocked()?;
+    load(&app)
+}
+
+#[tauri::command]
+pub fn save_settings(app: tauri::AppHandle, settings: Settings) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    save(&app, &settings)?;
+    crate::sync::record_change();
+    crate::quick_prompt::configure(&app);
+    crate::os_search::configure(&app);
+    crate::share_service::configure(&app);
+    crate::autostart::configure(&app);
+    crate::clipboard_watch::configure(&app);
+    crate::mini_chat::configure(&app);
+    crate::appearance::configure(&app);
+    crate::power::configure(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_offline_mode(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut settings = load(&app)?;
+    settings.offline_mode = enabled;
+    save(&app, &settings)?;
+    app.emit("offline-mode-changed", enabled).map_err(|e| e.to_string())
+}
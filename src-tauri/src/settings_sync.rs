@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::Emitter;
+
+use crate::settings::{self, Settings};
+use crate::{assistants, prompts};
+
+// The rest of the user's setup that follows them between devices: the
+// settings (presets included), the system prompt library and assistants.
+// Each goes to the sync remote as one file next to the chats, and a side
+// changed on both devices is merged against the last synced copy, see
+// conflicts::merge_document. API keys and sign-in secrets stay on each
+// device, and so does everything that describes the device itself.
+
+pub const DOCUMENTS: &[&str] = &["settings", "prompts", "assistants"];
+// Settings that belong to this device, never sent or overwritten
+const LOCAL_SETTINGS: &[&str] = &[
+    "sync",
+    "gitSync",
+    "lanSync",
+    "appLock",
+    "offlineMode",
+    "quickPrompt",
+    "osSearch",
+    "shareService",
+    "launchAtLogin",
+    "startHidden",
+    "clipboardWatch",
+    "menuBarChat",
+    "power",
+];
+const SYNCED_EVENT: &str = "settings-synced";
+
+// A document as it's stored on a remote
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub updated_at: u64,
+    pub data: Value,
+}
+
+pub fn remote_name(kind: &str) -> String {
+    format!("anchor-{}.json", kind)
+}
+
+// Which document a remote file name stands for
+pub fn kind(name: &str) -> Option<&'static str> {
+    DOCUMENTS.iter().copied().find(|kind| remote_name(kind) == name)
+}
+
+// The document as this device would send it, stamped with when its file last
+// changed
+pub fn local(app: &tauri::AppHandle, kind: &str) -> Result<Document, String> {
+    let mut data = match kind {
+        "settings" => serde_json::to_value(settings::load(app)?),
+        "prompts" => serde_json::to_value(prompts::load(app)?),
+        _ => serde_json::to_value(assistants::load(app)?),
+    }
+    .map_err(|e| e.to_string())?;
+    if kind == "settings" {
+        if let Some(obj) = data.as_object_mut() {
+            for key in LOCAL_SETTINGS {
+                obj.remove(*key);
+            }
+        }
+        for provider in data["providers"].as_array_mut().into_iter().flatten() {
+            if let Some(provider) = provider.as_object_mut() {
+                provider.remove("apiKey");
+            }
+        }
+    }
+    let path = crate::app_data_dir(app)?.join(format!("{}.json", kind));
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let updated_at = modified
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64);
+    Ok(Document { updated_at, data })
+}
+
+// Replaces the document on this device with `data`, keeping its own secrets
+// and device settings
+pub fn apply(app: &tauri::AppHandle, kind: &str, data: &Value) -> Result<(), String> {
+    match kind {
+        "settings" => {
+            let current = settings::load(app)?;
+            let ours = serde_json::to_value(&current).map_err(|e| e.to_string())?;
+            let mut data = data.clone();
+            let obj = data.as_object_mut().ok_or("Invalid synced settings")?;
+            for key in LOCAL_SETTINGS {
+                if let Some(value) = ours.get(*key) {
+                    obj.insert(key.to_string(), value.clone());
+                }
+            }
+            for provider in obj.get_mut("providers").and_then(Value::as_array_mut).into_iter().flatten() {
+                let id = provider["id"].as_str().unwrap_or_default();
+                let key = current.providers.iter().find(|p| p.id == id).map(|p| p.api_key.clone());
+                provider["apiKey"] = Value::String(key.unwrap_or_default());
+            }
+            let merged: Settings = serde_json::from_value(data).map_err(|e| format!("Invalid synced settings: {}", e))?;
+            settings::save(app, &merged)?;
+        }
+        "prompts" => {
+            let merged: Vec<prompts::Prompt> =
+                serde_json::from_value(data.clone()).map_err(|e| format!("Invalid synced prompts: {}", e))?;
+            prompts::save(app, &merged)?;
+        }
+        _ => {
+            let merged: Vec<assistants::Assistant> =
+                serde_json::from_value(data.clone()).map_err(|e| format!("Invalid synced assistants: {}", e))?;
+            assistants::save(app, &merged)?;
+        }
+    }
+    let _ = app.emit(SYNCED_EVENT, kind);
+    Ok(())
+}
+
+// Each document as it was after the last sync in `scope`, the bases of
+// three-way merges
+fn bases_path(app: &tauri::AppHandle, scope: &str) -> Result<PathBuf, String> {
+    let name = if scope.is_empty() {
+        "sync_documents.json".to_string()
+    } else {
+        format!("sync_documents_{}.json", scope)
+    };
+    Ok(crate::app_data_dir(app)?.join(name))
+}
+
+pub fn load_bases(app: &tauri::AppHandle, scope: &str) -> BTreeMap<String, Value> {
+    bases_path(app, scope)
+        .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_bases(app: &tauri::AppHandle, scope: &str, bases: &BTreeMap<String, Value>) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(bases).map_err(|e| e.to_string())?;
+    fs::write(bases_path(app, scope)?, content).map_err(|e| format!("Failed to save the sync state: {}", e))
+}
+
+fn enabled(app: &tauri::AppHandle) -> bool {
+    settings::load(app).is_ok_and(|s| s.sync.include_settings)
+}
+
+// What a LAN peer is sent when it asks for a document
+pub fn serve(app: &tauri::AppHandle, kind: &str) -> Result<Option<Vec<u8>>, String> {
+    if !enabled(app) {
+        return Ok(None);
+    }
+    serde_json::to_vec(&local(app, kind)?).map(Some).map_err(|e| e.to_string())
+}
+
+// A LAN peer finished merging a document and sent the result
+pub fn receive(app: &tauri::AppHandle, scope: &str, kind: &str, data: &[u8]) -> Result<(), String> {
+    if !enabled(app) {
+        return Ok(());
+    }
+    let document: Document = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+    if local(app, kind)?.data != document.data {
+        apply(app, kind, &document.data)?;
+    }
+    let mut bases = load_bases(app, scope);
+    bases.insert(kind.to_string(), document.data);
+    save_bases(app, scope, &bases)
+}
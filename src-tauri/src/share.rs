@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::settings;
+
+// Serves one chat, rendered like an HTML export, from a throwaway web server
+// so it can be opened in a browser on another device or a colleague's screen
+// without writing a file. The page is a snapshot taken when sharing starts,
+// only answers at a random token, and the server shuts down when it expires.
+
+const DEFAULT_MINUTES: u64 = 30;
+const MAX_MINUTES: u64 = 24 * 60;
+const TOKEN_LEN: usize = 24;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// Only the request line is looked at
+const MAX_REQUEST: usize = 8 * 1024;
+// Inline styles and data: images are all an exported page uses
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; img-src data:; style-src 'unsafe-inline'";
+
+struct Running {
+    info: SharedChat,
+    stop: Arc<AtomicBool>,
+}
+
+static SHARES: Mutex<Option<HashMap<String, Running>>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedChat {
+    pub id: String,
+    pub chat_id: String,
+    pub url: String,
+    // Reachable from other devices on the network, not just this one
+    pub lan: bool,
+    pub expires_at: u64,
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// The address other devices reach this one at. Connecting a UDP socket sends
+// nothing; it only makes the OS pick the interface.
+pub fn lan_address() -> Result<IpAddr, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|e| e.to_string())?;
+    socket.connect((crate::mdns::GROUP, crate::mdns::PORT)).map_err(|e| e.to_string())?;
+    let ip = socket.local_addr().map_err(|e| e.to_string())?.ip();
+    if ip.is_unspecified() || ip.is_loopback() {
+        return Err("This device doesn't seem to be on a network".to_string());
+    }
+    Ok(ip)
+}
+
+// Answers one request; whether it was for `token` and got `body`
+pub fn respond(mut stream: TcpStream, token: &str, content_type: &str, body: &[u8]) -> Result<bool, String> {
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+    let mut buf = vec![0; MAX_REQUEST];
+    let mut len = 0;
+    while len < buf.len() && !buf[..len].contains(&b'\n') {
+        match stream.read(&mut buf[len..]).map_err(|e| e.to_string())? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut parts = request.lines().next().unwrap_or_default().split(' ');
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = path.split('?').next().unwrap_or_default().trim_start_matches('/');
+
+    let served = path == token && method == "GET";
+    let (status, content_type, body) = if method != "GET" && method != "HEAD" {
+        ("405 Method Not Allowed", "text/plain", &b"Method not allowed"[..])
+    } else if path == token {
+        ("200 OK", content_type, body)
+    } else {
+        ("404 Not Found", "text/plain", &b"Not found"[..])
+    };
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n\
+         Referrer-Policy: no-referrer\r\nX-Content-Type-Options: nosniff\r\n\
+         Content-Security-Policy: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len(),
+        CONTENT_SECURITY_POLICY
+    );
+    stream.write_all(head.as_bytes()).map_err(|e| e.to_string())?;
+    if method != "HEAD" {
+        stream.write_all(body).map_err(|e| e.to_string())?;
+    }
+    stream.flush().map_err(|e| e.to_string())?;
+    Ok(served)
+}
+
+fn serve(
+    app: tauri::AppHandle,
+    listener: TcpListener,
+    info: SharedChat,
+    token: String,
+    page: String,
+    stop: Arc<AtomicBool>,
+) {
+    let page: Arc<str> = page.into();
+    let token: Arc<str> = token.into();
+    while !stop.load(Ordering::SeqCst) && (crate::chats::now_millis() as u64) < info.expires_at {
+        match listener.accept() {
+            Ok((stream, from)) => {
+                if !from.ip().is_loopback() {
+                    crate::audit::record("network", &format!("shared chat {} opened from {}", info.chat_id, from.ip()));
+                }
+                let (page, token) = (page.clone(), token.clone());
+                std::thread::spawn(move || {
+                    if let Err(e) = respond(stream, &token, "text/html; charset=utf-8", page.as_bytes()) {
+                        log::debug!("Failed to answer a share request from {}: {}", from, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::warn!("The share server for {} stopped: {}", info.chat_id, e);
+                break;
+            }
+        }
+    }
+    if let Ok(mut shares) = SHARES.lock() {
+        if let Some(shares) = shares.as_mut() {
+            shares.remove(&info.id);
+        }
+    }
+    let _ = app.emit("chat-share-ended", &info.id);
+}
+
+#[tauri::command]
+pub async fn share_chat(
+    app: tauri::AppHandle,
+    chat_id: String,
+    lan: Option<bool>,
+    minutes: Option<u64>,
+) -> Result<SharedChat, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let lan = lan.unwrap_or(false);
+    if lan && settings::load(&app)?.offline_mode {
+        return Err("Offline mode is on, so chats can only be shared with this device".to_string());
+    }
+    let minutes = minutes.unwrap_or(DEFAULT_MINUTES).clamp(1, MAX_MINUTES);
+    let handle = app.clone();
+    let id = chat_id.clone();
+    let page = tauri::async_runtime::spawn_blocking(move || crate::exports::render_html(&handle, &id))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    // Bound to that one address, so a local share isn't reachable from outside
+    let host = if lan { lan_address()? } else { IpAddr::V4(Ipv4Addr::LOCALHOST) };
+    let listener = TcpListener::bind(SocketAddr::new(host, 0)).map_err(|e| format!("Failed to start sharing: {}", e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let token = hex(&crate::encryption::random(TOKEN_LEN)?);
+    let info = SharedChat {
+        id: hex(&crate::encryption::random(6)?),
+        chat_id,
+        url: format!("http://{}:{}/{}", host, port, token),
+        lan,
+        expires_at: crate::chats::now_millis() as u64 + minutes * 60 * 1000,
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    SHARES
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get_or_insert_with(HashMap::new)
+        .insert(info.id.clone(), Running { info: info.clone(), stop: stop.clone() });
+    let (server_info, server_app) = (info.clone(), app.clone());
+    std::thread::spawn(move || serve(server_app, listener, server_info, token, page, stop));
+    let scope = if lan { "the network" } else { "this device" };
+    crate::audit::record("share", &format!("{} with {} for {} minutes", info.chat_id, scope, minutes));
+    Ok(info)
+}
+
+#[tauri::command]
+pub fn list_shares() -> Result<Vec<SharedChat>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let shares = SHARES.lock().map_err(|e| e.to_string())?;
+    let now = crate::chats::now_millis() as u64;
+    let running = shares.iter().flat_map(|s| s.values()).filter(|s| s.info.expires_at > now);
+    Ok(running.map(|s| s.info.clone()).collect())
+}
+
+#[tauri::command]
+pub fn stop_share(share_id: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut shares = SHARES.lock().map_err(|e| e.to_string())?;
+    let running = shares.as_mut().and_then(|s| s.remove(&share_id)).ok_or("That share has already ended")?;
+    running.stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
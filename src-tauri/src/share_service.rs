@@ -0,0 +1,339 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::attachments::{self, AttachmentSource};
+use crate::chats;
+
+// Text and files sent to Anchor from other apps (share.rs is the other
+// way around). On macOS two Services, in
+// the Services menu and the share menu of Finder and most text views, run
+// `anchor share`: selected text arrives on stdin, files as arguments. The
+// command stores them as a new chat with the text waiting in `draft` and the
+// files in `draftAttachments`, then opens anchor://chat/<id> so the running
+// app (or a new launch) shows it, see deep_link.rs. Nothing is sent until
+// the user does. While the app is running the command doesn't write the
+// chat itself: it leaves what was shared in the app's inbox and opens
+// anchor://share/<id>, and the running app stores it.
+//
+// The Services are Automator Quick Actions, which Anchor writes to
+// ~/Library/Services while the setting is on, pointed at wherever the app
+// is installed now.
+
+const MAX_TITLE_CHARS: usize = 60;
+const MAX_TEXT_CHARS: usize = 200_000;
+const INBOX_DIR: &str = "share_inbox";
+
+#[derive(Serialize, Deserialize)]
+struct Shared {
+    text: String,
+    // Absolute, since the app runs elsewhere
+    files: Vec<PathBuf>,
+}
+
+// (menu title, bundle name, whether it takes files)
+#[cfg(target_os = "macos")]
+const SERVICES: &[(&str, &str, bool)] = &[
+    ("Send to Anchor", "Send to Anchor.workflow", false),
+    ("Send Files to Anchor", "Send Files to Anchor.workflow", true),
+];
+
+fn title(text: &str, files: &[PathBuf]) -> String {
+    let first = text.lines().map(str::trim).find(|line| !line.is_empty());
+    let first = first.map(str::to_string).or_else(|| {
+        files.first().and_then(|file| file.file_name()).map(|name| name.to_string_lossy().into_owned())
+    });
+    let first = first.unwrap_or_else(|| "Shared".to_string());
+    if first.chars().count() <= MAX_TITLE_CHARS {
+        return first;
+    }
+    format!("{}…", first.chars().take(MAX_TITLE_CHARS - 1).collect::<String>())
+}
+
+// Stores what was shared as a new chat and returns its id
+pub fn share(app: &tauri::AppHandle, text: &str, files: &[PathBuf]) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    if crate::encryption::is_locked(app) {
+        return Err("Unlock the chat store in Anchor first".to_string());
+    }
+    let text = text.trim();
+    if text.is_empty() && files.is_empty() {
+        return Err("Nothing was shared".to_string());
+    }
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(format!("Shared text is longer than {} characters", MAX_TEXT_CHARS));
+    }
+    let mut ids = Vec::new();
+    for file in files {
+        if !file.is_file() {
+            return Err(format!("{}: only files can be shared", file.display()));
+        }
+        let name = file.file_name().map(|n| n.to_string_lossy().into_owned());
+        let source = AttachmentSource::Path(file.to_string_lossy().into_owned());
+        ids.push(attachments::save(app, source, name)?.id);
+    }
+    let session = json!({
+        "id": "",
+        "title": title(text, files),
+        "timestamp": chats::now_millis() as u64,
+        "messages": [],
+        "draft": text,
+        "draftAttachments": ids,
+        "sharedFrom": "service",
+    });
+    let id = chats::write(app, session)?;
+    crate::audit::record("share", &format!("{} characters and {} files into {}", text.len(), files.len(), id));
+    Ok(id)
+}
+
+fn inbox_path(app: &tauri::AppHandle, id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid share id: {}", id));
+    }
+    Ok(crate::app_data_dir(app)?.join(INBOX_DIR).join(format!("{}.json", id)))
+}
+
+// Hands what was shared to the running app through its inbox
+pub fn forward(app: &tauri::AppHandle, text: &str, files: &[PathBuf]) -> Result<(), String> {
+    let files = files.iter().map(|file| file.canonicalize().map_err(|e| format!("{}: {}", file.display(), e)));
+    let shared = Shared { text: text.to_string(), files: files.collect::<Result<_, _>>()? };
+    let id = crate::share::hex(&crate::encryption::random(8)?);
+    let path = inbox_path(app, &id)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create the share inbox: {}", e))?;
+    }
+    let content = serde_json::to_vec(&shared).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write to the share inbox: {}", e))?;
+    launch(&format!("{}://share/{}", crate::deep_link::SCHEME, id))
+}
+
+// Stores what `forward` left in the inbox as a new chat, in the running app
+pub fn take_from_inbox(app: &tauri::AppHandle, id: &str) -> Result<String, String> {
+    let path = inbox_path(app, id)?;
+    let content = fs::read(&path).map_err(|_| format!("Nothing waiting in the share inbox under {}", id))?;
+    // Gone whether or not it can be stored, since it's plaintext
+    if let Err(e) = crate::shred::remove_file(app, &path) {
+        log::warn!("Failed to clear the share inbox: {}", e);
+    }
+    let shared: Shared = serde_json::from_slice(&content).map_err(|e| e.to_string())?;
+    share(app, &shared.text, &shared.files)
+}
+
+// Shows the chat in Anchor, starting it when it isn't running
+pub fn open(id: &str) -> Result<(), String> {
+    launch(&format!("{}://chat/{}", crate::deep_link::SCHEME, id))
+}
+
+fn launch(link: &str) -> Result<(), String> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(program)
+        .arg(link)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open {}: {}", link, e))
+}
+
+// `path` quoted for /bin/sh
+#[cfg(target_os = "macos")]
+fn shell_quote(path: &std::path::Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+#[cfg(target_os = "macos")]
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(target_os = "macos")]
+fn info_plist(menu: &str, files: bool) -> String {
+    let send = if files {
+        "<key>NSSendFileTypes</key>\n\t\t\t<array>\n\t\t\t\t<string>public.item</string>\n\t\t\t</array>"
+    } else {
+        "<key>NSSendTypes</key>\n\t\t\t<array>\n\t\t\t\t<string>public.utf8-plain-text</string>\n\t\t\t</array>"
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>NSServices</key>
+	<array>
+		<dict>
+			<key>NSMenuItem</key>
+			<dict>
+				<key>default</key>
+				<string>{}</string>
+			</dict>
+			<key>NSMessage</key>
+			<string>runWorkflowAsService</string>
+			{}
+		</dict>
+	</array>
+</dict>
+</plist>
+"#,
+        xml_escape(menu),
+        send
+    )
+}
+
+// One "Run Shell Script" step handing its input to `anchor share`
+#[cfg(target_os = "macos")]
+fn document(exe: &std::path::Path, files: bool) -> String {
+    let (command, input_method, input_type, accepts) = if files {
+        let command = format!("{} share \"$@\"", shell_quote(exe));
+        (command, 1, "com.apple.Automator.fileSystemObject", "com.apple.cocoa.path")
+    } else {
+        (format!("{} share --text", shell_quote(exe)), 0, "com.apple.Automator.text", "com.apple.cocoa.string")
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AMApplicationBuild</key>
+	<string>523</string>
+	<key>AMApplicationVersion</key>
+	<string>2.10</string>
+	<key>AMDocumentVersion</key>
+	<string>2</string>
+	<key>actions</key>
+	<array>
+		<dict>
+			<key>action</key>
+			<dict>
+				<key>AMAccepts</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Optional</key>
+					<true/>
+					<key>Types</key>
+					<array>
+						<string>{accepts}</string>
+					</array>
+				</dict>
+				<key>AMActionVersion</key>
+				<string>2.0.3</string>
+				<key>AMApplication</key>
+				<array>
+					<string>Automator</string>
+				</array>
+				<key>AMProvides</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.string</string>
+					</array>
+				</dict>
+				<key>ActionBundlePath</key>
+				<string>/System/Library/Automator/Run Shell Script.action</string>
+				<key>ActionName</key>
+				<string>Run Shell Script</string>
+				<key>ActionParameters</key>
+				<dict>
+					<key>COMMAND_STRING</key>
+					<string>{command}</string>
+					<key>CheckedForUserDefaultShell</key>
+					<true/>
+					<key>inputMethod</key>
+					<integer>{input_method}</integer>
+					<key>shell</key>
+					<string>/bin/sh</string>
+					<key>source</key>
+					<string></string>
+				</dict>
+				<key>BundleIdentifier</key>
+				<string>com.apple.RunShellScript</string>
+				<key>CFBundleVersion</key>
+				<string>2.0.3</string>
+				<key>CanShowSelectedItemsWhenRun</key>
+				<false/>
+				<key>CanShowWhenRun</key>
+				<true/>
+				<key>Category</key>
+				<array>
+					<string>AMCategoryUtilities</string>
+				</array>
+				<key>Class Name</key>
+				<string>RunShellScriptAction</string>
+				<key>isViewVisible</key>
+				<integer>1</integer>
+			</dict>
+		</dict>
+	</array>
+	<key>connectors</key>
+	<dict/>
+	<key>workflowMetaData</key>
+	<dict>
+		<key>serviceInputTypeIdentifier</key>
+		<string>{input_type}</string>
+		<key>serviceOutputTypeIdentifier</key>
+		<string>com.apple.Automator.nothing</string>
+		<key>serviceProcessesInput</key>
+		<integer>0</integer>
+		<key>workflowTypeIdentifier</key>
+		<string>com.apple.Automator.servicesMenu</string>
+	</dict>
+</dict>
+</plist>
+"#,
+        accepts = accepts,
+        command = xml_escape(&command),
+        input_method = input_method,
+        input_type = input_type,
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn install(app: &tauri::AppHandle, wanted: bool) -> Result<(), String> {
+    use std::fs;
+    use tauri::Manager;
+
+    let dir = app.path().home_dir().map_err(|e| e.to_string())?.join("Library/Services");
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    for (menu, bundle, files) in SERVICES {
+        let root = dir.join(bundle);
+        if !wanted {
+            if root.exists() {
+                fs::remove_dir_all(&root).map_err(|e| format!("Failed to remove {}: {}", bundle, e))?;
+            }
+            continue;
+        }
+        let contents = root.join("Contents");
+        fs::create_dir_all(&contents).map_err(|e| format!("Failed to create {}: {}", bundle, e))?;
+        for (name, content) in [("Info.plist", info_plist(menu, *files)), ("document.wflow", document(&exe, *files))] {
+            let path = contents.join(name);
+            // Rewritten only when the app has moved, so the menu isn't rebuilt
+            // on every launch
+            if fs::read_to_string(&path).is_ok_and(|current| current == content) {
+                continue;
+            }
+            fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", bundle, e))?;
+        }
+    }
+    Ok(())
+}
+
+// Adds or removes the Services to match the setting
+pub fn configure(app: &tauri::AppHandle) {
+    #[cfg(target_os = "macos")]
+    {
+        let wanted = crate::settings::load(app).is_ok_and(|s| s.share_service);
+        if let Err(e) = install(app, wanted) {
+            log::warn!("{}", e);
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = app;
+}
@@ -0,0 +1,48 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+// Written at a time so large attachments don't need a buffer their size
+const CHUNK: usize = 1024 * 1024;
+
+pub fn enabled(app: &tauri::AppHandle) -> bool {
+    crate::settings::load(app).is_ok_and(|s| s.secure_delete)
+}
+
+// Overwrites the file with zeros and flushes it to disk before unlinking.
+// This is best effort: SSDs remap writes to fresh cells, and copy-on-write
+// filesystems (APFS, btrfs, ZFS), snapshots and backups can all keep the
+// old blocks. Full-disk encryption is what covers those.
+fn overwrite(path: &Path) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len() as usize;
+    let zeros = vec![0u8; CHUNK.min(remaining)];
+    while remaining > 0 {
+        let n = remaining.min(CHUNK);
+        file.write_all(&zeros[..n])?;
+        remaining -= n;
+    }
+    file.sync_all()
+}
+
+// Deletes a file, overwriting it first when secure deletion is on
+pub fn remove_file(app: &tauri::AppHandle, path: &Path) -> std::io::Result<()> {
+    if enabled(app) {
+        if let Err(e) = overwrite(path) {
+            log::warn!("Failed to overwrite {} before deleting it: {}", path.display(), e);
+        }
+    }
+    fs::remove_file(path)
+}
+
+// Like fs::rename, but the file being replaced is overwritten first when
+// secure deletion is on, rather than left in the freed blocks
+pub fn replace(app: &tauri::AppHandle, from: &Path, to: &Path) -> std::io::Result<()> {
+    if !enabled(app) || !to.exists() {
+        return fs::rename(from, to);
+    }
+    let old = to.with_extension("old");
+    fs::rename(to, &old)?;
+    fs::rename(from, to)?;
+    remove_file(app, &old)
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::completion::{self, ChatMessage, CompletionRequest};
+use crate::settings::{self, ProviderConfig};
+
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const MAX_REPORTED_ERRORS: usize = 10;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputSchema {
+    #[serde(default = "default_schema_name")]
+    pub name: String,
+    pub schema: Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructuredResult {
+    pub value: Value,
+    pub attempts: u32,
+}
+
+fn default_schema_name() -> String {
+    "response".to_string()
+}
+
+// Models love to wrap JSON in reasoning or code fences even when told not to.
+fn extract_json(content: &str) -> &str {
+    let content = match content.rfind("</think>") {
+        Some(end) => &content[end + "</think>".len()..],
+        None => content,
+    };
+    let start = content.find(['{', '[']);
+    let end = content.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if end >= start => &content[start..=end],
+        _ => content.trim(),
+    }
+}
+
+fn validate(validator: &jsonschema::Validator, content: &str) -> Result<Value, String> {
+    let value: Value =
+        serde_json::from_str(extract_json(content)).map_err(|e| format!("response is not valid JSON ({})", e))?;
+
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .take(MAX_REPORTED_ERRORS)
+        .map(|e| format!("{}: {}", e.instance_path, e))
+        .collect();
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn schema_instruction(schema: &OutputSchema) -> String {
+    format!(
+        "Respond with a single JSON value that validates against this JSON Schema, and nothing else:\n{}",
+        schema.schema
+    )
+}
+
+pub async fn complete_structured(
+    provider: &ProviderConfig,
+    mut request: CompletionRequest,
+    schema: &OutputSchema,
+    max_retries: u32,
+) -> Result<StructuredResult, String> {
+    let validator = jsonschema::validator_for(&schema.schema).map_err(|e| format!("Invalid JSON Schema: {}", e))?;
+
+    if provider.structured_outputs {
+        request.options.insert(
+            "response_format".to_string(),
+            json!({
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "schema": schema.schema, "strict": true }
+            }),
+        );
+    } else {
+        request.messages.insert(0, ChatMessage::new("system", schema_instruction(schema)));
+    }
+
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let content = completion::complete(provider, &request, |_| {}).await?;
+
+        let errors = match validate(&validator, &content) {
+            Ok(value) => return Ok(StructuredResult { value, attempts }),
+            Err(errors) => errors,
+        };
+        if attempts > max_retries {
+            return Err(format!("Model output failed schema validation after {} attempts:\n{}", attempts, errors));
+        }
+
+        log::warn!("Structured output attempt {} failed validation: {}", attempts, errors);
+        request.messages.push(ChatMessage::new("assistant", content));
+        request.messages.push(ChatMessage::new(
+            "user",
+            format!(
+                "That response did not match the required schema:\n{}\n\nReply again with only the corrected JSON.",
+                errors
+            ),
+        ));
+    }
+}
+
+#[tauri::command]
+pub async fn structured_completion(
+    app: tauri::AppHandle,
+    mut request: CompletionRequest,
+    schema: OutputSchema,
+    max_retries: Option<u32>,
+) -> Result<StructuredResult, String> {
+    crate::app_lock::ensure_unlocked()?;
+    crate::attachments::resolve_images(&app, &mut request.messages)?;
+    crate::extraction::inline_documents(&app, &mut request).await?;
+    let settings = settings::load(&app)?;
+    let provider = completion::resolve_provider(&settings, request.provider_id.as_deref())?;
+    complete_structured(&provider, request, &schema, max_retries.unwrap_or(DEFAULT_MAX_RETRIES)).await
+}
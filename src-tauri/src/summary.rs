@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chats;
+use crate::completion::{self, ChatMessage, CompletionRequest};
+use crate::context::{self, ContextSummary};
+use crate::settings::{self, ProviderConfig};
+
+const SUMMARY_MAX_TOKENS: u64 = 300;
+// Room for the instructions and the answer when sizing the transcript
+const PROMPT_RESERVE: usize = 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSummary {
+    pub text: String,
+    pub model: String,
+    // Length of the transcript when the summary was made, to tell when it's stale
+    pub message_count: usize,
+}
+
+pub fn cached(session: &serde_json::Value) -> Option<ChatSummary> {
+    let summary: ChatSummary = serde_json::from_value(session["summary"].clone()).ok()?;
+    let count = session["messages"].as_array().map(Vec::len).unwrap_or(0);
+    (summary.message_count == count).then_some(summary)
+}
+
+pub async fn summarize_session(
+    settings: &settings::Settings,
+    provider: &ProviderConfig,
+    model: &str,
+    session: &serde_json::Value,
+) -> Result<ChatSummary, String> {
+    let messages: Vec<ChatMessage> = chats::messages(session).into_iter().filter(|m| m.role != "system").collect();
+    if messages.is_empty() {
+        return Err("Chat has no messages to summarize".to_string());
+    }
+
+    // Long chats: lean on the rolling context summary for the early part and
+    // keep as many recent messages as the window allows
+    let budget = context::context_window(settings, model).saturating_sub(PROMPT_RESERVE);
+    let previous: Option<ContextSummary> = serde_json::from_value(session["contextSummary"].clone()).ok();
+    let (previous, mut start) = match previous {
+        Some(prev) if context::conversation_tokens(&messages) > budget && prev.covered_messages < messages.len() => {
+            (Some(prev.text), prev.covered_messages)
+        }
+        _ => (None, 0),
+    };
+    let budget = budget.saturating_sub(previous.as_deref().map(context::estimate_tokens).unwrap_or(0));
+    while start < messages.len() - 1 && context::conversation_tokens(&messages[start..]) > budget {
+        start += 1;
+    }
+
+    let mut request = CompletionRequest {
+        provider_id: Some(provider.id.clone()),
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage::new(
+                "system",
+                "Summarize this conversation in two or three sentences: what the user wanted and what was \
+                 concluded. Write plain text without a preamble.",
+            ),
+            ChatMessage::new("user", context::transcript(previous.as_deref(), &messages[start..])),
+        ],
+        anonymize: crate::anonymize::key(settings, provider, session["id"].as_str()),
+        ..Default::default()
+    };
+    request.options.insert("max_tokens".to_string(), json!(SUMMARY_MAX_TOKENS));
+
+    let text = completion::complete(provider, &request, |_| {}).await?;
+    Ok(ChatSummary {
+        text: text.trim().to_string(),
+        model: model.to_string(),
+        message_count: session["messages"].as_array().map(Vec::len).unwrap_or(0),
+    })
+}
+
+#[tauri::command]
+pub async fn summarize_chat(
+    app: tauri::AppHandle,
+    chat_id: String,
+    provider_id: Option<String>,
+    model: String,
+    refresh: Option<bool>,
+) -> Result<ChatSummary, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let result = summarize(&app, chat_id, provider_id, model, refresh).await;
+    crate::analytics::track(&app, "summarize_chat", result)
+}
+
+async fn summarize(
+    app: &tauri::AppHandle,
+    chat_id: String,
+    provider_id: Option<String>,
+    model: String,
+    refresh: Option<bool>,
+) -> Result<ChatSummary, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut session = chats::read(app, &chat_id)?;
+    if !refresh.unwrap_or(false) {
+        if let Some(summary) = cached(&session) {
+            return Ok(summary);
+        }
+    }
+
+    let settings = settings::load(app)?;
+    let provider = completion::resolve_provider(&settings, provider_id.as_deref())?;
+    let summary = summarize_session(&settings, &provider, &model, &session).await?;
+
+    // Re-read so a save that landed while the model was busy isn't clobbered
+    if let Ok(latest) = chats::read(app, &chat_id) {
+        session = latest;
+    }
+    session["summary"] = json!(summary);
+    chats::write(app, session)?;
+    Ok(summary)
+}
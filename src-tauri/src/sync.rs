@@ -0,0 +1,937 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::Emitter;
+
+use crate::chats;
+use crate::cloud_folder::CloudFolder;
+use crate::dropbox::Dropbox;
+use crate::conflicts::{self, Merged};
+use crate::devices;
+use crate::e2e;
+use crate::encryption;
+use crate::google_drive::GoogleDrive;
+use crate::keychain;
+use crate::lan_sync::Peer;
+use crate::oauth;
+use crate::s3::S3;
+use crate::settings::{self, SyncBackend};
+use crate::settings_sync::{self, Document};
+use crate::webdav::WebDav;
+
+// Two-way sync of the chat library with a remote store. Everything sent to
+// a backend is end-to-end encrypted first unless that's turned off, see
+// e2e.rs. Chats go as plain sessions underneath even with chat store
+// encryption on, since each device seals its store with a key of its own;
+// they're sealed with this device's key again as they come in. A manifest
+// on the remote lists each chat's updatedAt and hash, so a chat whose hash
+// matches is neither downloaded to compare it nor uploaded again. A chat
+// changed on both sides is merged, see conflicts.rs.
+
+pub const MANIFEST: &str = "anchor-manifest.json";
+// The devices syncing through a remote, see devices.rs
+const DEVICES: &str = "anchor-devices.json";
+// Which remote the configured backend's sync state was kept for
+const REMOTE_FILE: &str = "sync_remote.txt";
+const STATUS_EVENT: &str = "sync-status";
+const SYNCED_EVENT: &str = "chats-synced";
+const PROGRESS_EVENT: &str = "sync-progress";
+const ERROR_EVENT: &str = "sync-error";
+// Progress is reported every this many chats, not for each one
+const PROGRESS_STEP: usize = 25;
+// How often the background loop checks whether a sync is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// Switching windows shouldn't sync every time
+const FOCUS_MIN_GAP_MILLIS: u64 = 2 * 60 * 1000;
+// After a run was put off for the battery or network, when to look again
+const DEFERRED_RECHECK_MILLIS: u64 = 5 * 60 * 1000;
+
+static STATUS: Mutex<Option<SyncStatus>> = Mutex::new(None);
+// Millis of the latest save not synced yet, 0 when there's none
+static LAST_SAVE: AtomicU64 = AtomicU64::new(0);
+static FOCUSED: AtomicBool = AtomicBool::new(false);
+// Backend -> how its last sync went, kept across restarts
+static BACKENDS: Mutex<Option<BTreeMap<String, BackendStatus>>> = Mutex::new(None);
+// One sync at a time, whether from the loop or the command
+static RUNNING: tauri::async_runtime::Mutex<()> = tauri::async_runtime::Mutex::const_new(());
+// Set by saves made while a sync held RUNNING, and turned into LAST_SAVE once
+// it lets go
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+// Holds RUNNING, and on release queues a sync for the saves made meanwhile
+struct Running<G>(Option<G>);
+
+impl<G> Drop for Running<G> {
+    fn drop(&mut self) {
+        self.0.take();
+        if PENDING.swap(false, Ordering::SeqCst) {
+            LAST_SAVE.store(chats::now_millis() as u64, Ordering::SeqCst);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    updated_at: u64,
+    hash: String,
+}
+
+type Manifest = BTreeMap<String, Entry>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    #[default]
+    Idle,
+    Syncing,
+    Error,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub state: SyncState,
+    // Millis since the epoch of the last successful sync
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_synced: Option<u64>,
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub deleted: usize,
+    // Chats changed on both sides: combined, or kept twice with a conflict copy
+    pub merged: usize,
+    pub conflicted: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// One per place chats sync to: the configured backend ("webdav", "s3",
+// "folder"), "git", and "peer_<device id>" for each paired LAN device
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackendStatus {
+    pub backend: String,
+    #[serde(skip_deserializing)]
+    pub syncing: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_synced: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_attempt: Option<u64>,
+    // From the last attempt; cleared by the next one that succeeds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Chats changed here that this backend doesn't have yet
+    #[serde(skip_deserializing)]
+    pub pending_uploads: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncOverview {
+    pub state: SyncState,
+    pub backends: Vec<BackendStatus>,
+    // Conflict copies waiting to be resolved, see conflicts.rs
+    pub conflicts: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Progress<'a> {
+    backend: &'a str,
+    done: usize,
+    total: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncError<'a> {
+    backend: &'a str,
+    error: &'a str,
+}
+
+enum Remote {
+    WebDav(WebDav),
+    S3(S3),
+    Folder(CloudFolder),
+    Dropbox(Dropbox),
+    GoogleDrive(GoogleDrive),
+    Peer(Box<Peer>),
+}
+
+impl Remote {
+    async fn prepare(&self) -> Result<(), String> {
+        match self {
+            Remote::WebDav(dav) => dav.prepare().await,
+            Remote::S3(s3) => s3.prepare().await,
+            Remote::Folder(folder) => folder.prepare().await,
+            Remote::Dropbox(dropbox) => dropbox.prepare().await,
+            Remote::GoogleDrive(drive) => drive.prepare().await,
+            Remote::Peer(_) => Ok(()),
+        }
+    }
+
+    // Called after every run, successful or not
+    async fn finish(&self) -> Result<(), String> {
+        match self {
+            Remote::Folder(folder) => folder.finish().await,
+            Remote::Peer(peer) => peer.finish().await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Remote::WebDav(dav) => dav.get(name).await,
+            Remote::S3(s3) => s3.get(name).await,
+            Remote::Folder(folder) => folder.get(name).await,
+            Remote::Dropbox(dropbox) => dropbox.get(name).await,
+            Remote::GoogleDrive(drive) => drive.get(name).await,
+            Remote::Peer(peer) => peer.get(name).await,
+        }
+    }
+
+    async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        match self {
+            Remote::WebDav(dav) => dav.put(name, data).await,
+            Remote::S3(s3) => s3.put(name, data).await,
+            Remote::Folder(folder) => folder.put(name, data).await,
+            Remote::Dropbox(dropbox) => dropbox.put(name, data).await,
+            Remote::GoogleDrive(drive) => drive.put(name, data).await,
+            Remote::Peer(peer) => peer.put(name, data).await,
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), String> {
+        match self {
+            Remote::WebDav(dav) => dav.delete(name).await,
+            Remote::S3(s3) => s3.delete(name).await,
+            Remote::Folder(folder) => folder.delete(name).await,
+            Remote::Dropbox(dropbox) => dropbox.delete(name).await,
+            Remote::GoogleDrive(drive) => drive.delete(name).await,
+            Remote::Peer(peer) => peer.delete(name).await,
+        }
+    }
+}
+
+// A remote as run() sees it: payloads sealed on the way out when end-to-end
+// encryption is on, and opened on the way in. While it's on, unsealed chats
+// are only taken when they're what this device itself last synced, which is
+// how uploads from before it was turned on get sealed.
+struct Store<'a> {
+    app: &'a tauri::AppHandle,
+    remote: &'a Remote,
+    seal: bool,
+    own: &'a Manifest,
+}
+
+impl Store<'_> {
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(data) = self.remote.get(name).await? else {
+            return Ok(None);
+        };
+        let own = name.strip_suffix(".json").and_then(|id| self.own.get(id)).is_some_and(|e| e.hash == hash(&data));
+        e2e::open(self.app, name, data, !self.seal || own).map(Some)
+    }
+
+    // Settings documents and the device list: unsealed ones are taken as
+    // missing, so this device's replace them
+    async fn get_document(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        match self.remote.get(name).await? {
+            Some(data) if self.seal && !e2e::is_sealed(&data) => {
+                log::warn!("Ignoring {} on the sync remote, which isn't end-to-end encrypted", name);
+                Ok(None)
+            }
+            Some(data) => e2e::open(self.app, name, data, true).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        let data = if self.seal { e2e::seal(self.app, name, &data)? } else { data };
+        self.remote.put(name, data).await
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), String> {
+        self.remote.delete(name).await
+    }
+}
+
+pub fn secret_account(backend: SyncBackend) -> String {
+    format!("sync-{:?}", backend).to_lowercase()
+}
+
+async fn remote(app: &tauri::AppHandle, settings: &settings::SyncSettings) -> Result<Remote, String> {
+    match settings.backend {
+        SyncBackend::Off => Err("Sync is turned off".to_string()),
+        SyncBackend::WebDav => {
+            let password = keychain::load(&secret_account(SyncBackend::WebDav)).unwrap_or_default();
+            Ok(Remote::WebDav(WebDav::new(&settings.webdav, password)?))
+        }
+        SyncBackend::S3 => {
+            let secret_key = keychain::load(&secret_account(SyncBackend::S3)).unwrap_or_default();
+            Ok(Remote::S3(S3::new(&settings.s3, secret_key)?))
+        }
+        SyncBackend::Folder => Ok(Remote::Folder(CloudFolder::new(&settings.folder)?)),
+        SyncBackend::Dropbox => Ok(Remote::Dropbox(Dropbox::new(oauth::access_token(app, settings.backend).await?))),
+        SyncBackend::GoogleDrive => {
+            Ok(Remote::GoogleDrive(GoogleDrive::new(oauth::access_token(app, settings.backend).await?)))
+        }
+    }
+}
+
+// What was on both sides after the last sync, to tell deletions from new
+// chats. `scope` keeps one state per LAN peer; empty for the configured backend.
+fn state_path(app: &tauri::AppHandle, scope: &str) -> Result<PathBuf, String> {
+    let name = if scope.is_empty() { "sync_state.json".to_string() } else { format!("sync_state_{}.json", scope) };
+    Ok(crate::app_data_dir(app)?.join(name))
+}
+
+// The backend and the place on it, hashed: the server and folder, the bucket
+// and prefix, or the folder path. OAuth backends only have the one account.
+fn remote_identity(settings: &settings::SyncSettings) -> String {
+    let place = match settings.backend {
+        SyncBackend::WebDav => {
+            let dav = &settings.webdav;
+            format!("{}|{}|{}", dav.url.trim().trim_end_matches('/'), dav.username.trim(), dav.folder.trim())
+        }
+        SyncBackend::S3 => {
+            let s3 = &settings.s3;
+            format!("{}|{}|{}|{}", s3.endpoint.trim(), s3.region.trim(), s3.bucket.trim(), s3.prefix.trim())
+        }
+        SyncBackend::Folder => settings.folder.path.trim().to_string(),
+        _ => String::new(),
+    };
+    hash(format!("{}|{}", backend_name(settings.backend), place).as_bytes())
+}
+
+// Starts over as a first sync when the backend or where it points changed,
+// so chats the new remote has never seen aren't taken as deleted there.
+// State kept before this file existed is taken to be the current remote's.
+fn reset_if_moved(app: &tauri::AppHandle, settings: &settings::SyncSettings) -> Result<(), String> {
+    let path = crate::app_data_dir(app)?.join(REMOTE_FILE);
+    let identity = remote_identity(settings);
+    let kept = fs::read_to_string(&path).ok();
+    if kept.as_ref().is_some_and(|kept| *kept == identity) {
+        return Ok(());
+    }
+    if kept.is_none() {
+        return fs::write(path, identity).map_err(|e| format!("Failed to save the sync state: {}", e));
+    }
+    let state = state_path(app, "")?;
+    if state.exists() {
+        fs::remove_file(&state).map_err(|e| format!("Failed to reset the sync state: {}", e))?;
+    }
+    let bases = crate::app_data_dir(app)?.join("sync_base");
+    if bases.exists() {
+        fs::remove_dir_all(&bases).map_err(|e| format!("Failed to reset the sync state: {}", e))?;
+    }
+    settings_sync::save_bases(app, "", &BTreeMap::new())?;
+    fs::write(path, identity).map_err(|e| format!("Failed to save the sync state: {}", e))
+}
+
+fn load_state(app: &tauri::AppHandle, scope: &str) -> Manifest {
+    state_path(app, scope)
+        .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// Each chat as it was after the last sync, the base of three-way merges
+fn base_path(app: &tauri::AppHandle, scope: &str, id: &str) -> Result<PathBuf, String> {
+    let name = if scope.is_empty() { "sync_base".to_string() } else { format!("sync_base_{}", scope) };
+    let dir = crate::app_data_dir(app)?.join(name);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join(format!("{}.json", id)))
+}
+
+fn load_base(app: &tauri::AppHandle, scope: &str, id: &str) -> Option<serde_json::Value> {
+    let data = fs::read(base_path(app, scope, id).ok()?).ok()?;
+    let content = String::from_utf8(encryption::open(data).ok()?).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// Writes a chat that came from a remote, sealed with this device's key when
+// the chat store is encrypted. Copies uploaded before chats went plain may
+// still be sealed with it.
+pub fn store_chat(app: &tauri::AppHandle, dir: &std::path::Path, id: &str, data: &[u8]) -> Result<(), String> {
+    let data = encryption::seal(app, encryption::open(data.to_vec())?)?;
+    let partial = dir.join(format!("{}.partial", id));
+    fs::write(&partial, data).map_err(|e| e.to_string())?;
+    fs::rename(&partial, dir.join(format!("{}.json", id))).map_err(|e| e.to_string())
+}
+
+// A stored chat as it's synced: decrypted
+pub fn read_chat(path: &std::path::Path) -> Result<Vec<u8>, String> {
+    encryption::open(fs::read(path).map_err(|e| e.to_string())?)
+}
+
+fn parse_chat(data: &[u8]) -> Result<serde_json::Value, String> {
+    let content = String::from_utf8(encryption::open(data.to_vec())?).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+fn hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Every stored chat, decrypted, with its updatedAt, falling back to the UI's
+// timestamp for chats saved before it was kept
+type LocalChats = BTreeMap<String, (Entry, Vec<u8>)>;
+
+// Whether the sync rules keep `session` out of sync
+pub fn is_excluded(rules: &settings::SyncRules, session: &serde_json::Value) -> bool {
+    let folder = session["folder"].as_str().unwrap_or_default().trim_matches('/');
+    let in_folder = rules
+        .excluded_folders
+        .iter()
+        .map(|f| f.trim_matches('/'))
+        .filter(|f| !f.is_empty())
+        .any(|f| folder == f || folder.starts_with(&format!("{}/", f)));
+    let tagged = session["tags"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.as_str())
+        .any(|tag| rules.excluded_tags.iter().any(|t| t.trim().eq_ignore_ascii_case(tag)));
+    let kept_incognito = rules.exclude_from_incognito && session["fromIncognito"].as_bool() == Some(true);
+    in_folder || tagged || kept_incognito
+}
+
+// Also returns the ids the sync rules exclude, which are left out of the map
+fn local_chats(app: &tauri::AppHandle) -> Result<(LocalChats, BTreeSet<String>), String> {
+    let rules = settings::load(app)?.sync.rules;
+    let mut local = BTreeMap::new();
+    let mut excluded = BTreeSet::new();
+    for path in chats::chat_files(app)? {
+        let Some(id) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let data = read_chat(&path)?;
+        let session: serde_json::Value = String::from_utf8(data.clone())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        if is_excluded(&rules, &session) {
+            excluded.insert(id);
+            continue;
+        }
+        let updated_at = session["updatedAt"].as_f64().or(session["timestamp"].as_f64()).unwrap_or(0.0) as u64;
+        local.insert(id, (Entry { updated_at, hash: hash(&data) }, data));
+    }
+    Ok((local, excluded))
+}
+
+fn set_status(app: &tauri::AppHandle, update: impl FnOnce(&mut SyncStatus)) {
+    let Ok(mut status) = STATUS.lock() else {
+        return;
+    };
+    let status = status.get_or_insert_with(SyncStatus::default);
+    update(status);
+    let _ = app.emit(STATUS_EVENT, status.clone());
+}
+
+fn backend_name(backend: SyncBackend) -> String {
+    serde_json::to_value(backend).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+fn backends_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join("sync_backends.json"))
+}
+
+// Runs `f` on the backend statuses, loading them the first time
+fn with_backends<T>(app: &tauri::AppHandle, f: impl FnOnce(&mut BTreeMap<String, BackendStatus>) -> T) -> Option<T> {
+    let mut backends = BACKENDS.lock().ok()?;
+    let backends = backends.get_or_insert_with(|| {
+        let content = backends_path(app).and_then(|path| fs::read(path).map_err(|e| e.to_string()));
+        content.ok().and_then(|c| serde_json::from_slice(&c).ok()).unwrap_or_default()
+    });
+    Some(f(backends))
+}
+
+fn update_backend(app: &tauri::AppHandle, backend: &str, update: impl FnOnce(&mut BackendStatus)) {
+    let saved = with_backends(app, |backends| {
+        let status = backends
+            .entry(backend.to_string())
+            .or_insert_with(|| BackendStatus { backend: backend.to_string(), ..BackendStatus::default() });
+        update(status);
+        serde_json::to_string_pretty(backends).map_err(|e| e.to_string())
+    });
+    let saved = saved.unwrap_or_else(|| Err("The sync status is unavailable".to_string()));
+    if let Err(e) = saved.and_then(|content| fs::write(backends_path(app)?, content).map_err(|e| e.to_string())) {
+        log::warn!("Failed to save the sync status: {}", e);
+    }
+}
+
+// Called by every backend around each sync, for get_sync_status
+pub fn started(app: &tauri::AppHandle, backend: &str) {
+    update_backend(app, backend, |s| {
+        s.syncing = true;
+        s.last_attempt = Some(chats::now_millis() as u64);
+    });
+}
+
+pub fn finished(app: &tauri::AppHandle, backend: &str, result: Result<(), &str>) {
+    update_backend(app, backend, |s| {
+        s.syncing = false;
+        match result {
+            Ok(()) => {
+                s.last_synced = s.last_attempt;
+                s.error = None;
+            }
+            Err(e) => s.error = Some(e.to_string()),
+        }
+    });
+    if let Err(error) = result {
+        let _ = app.emit(ERROR_EVENT, SyncError { backend, error });
+    }
+}
+
+// Each device lists itself on the remote, so the others learn the name
+// behind the id its chats are stamped with
+async fn exchange_devices(app: &tauri::AppHandle, remote: &Store<'_>) -> Result<(), String> {
+    let theirs: BTreeMap<String, devices::Known> = match remote.get_document(DEVICES).await? {
+        Some(data) => serde_json::from_slice(&data).unwrap_or_default(),
+        None => BTreeMap::new(),
+    };
+    let merged = devices::merge(app, &theirs)?;
+    if merged != theirs {
+        remote.put(DEVICES, serde_json::to_vec_pretty(&merged).map_err(|e| e.to_string())?).await?;
+    }
+    Ok(())
+}
+
+// Settings, prompts and assistants, see settings_sync.rs. A LAN peer is sent
+// every merged document, so it keeps the same merge bases.
+async fn sync_documents(app: &tauri::AppHandle, remote: &Store<'_>, scope: &str) -> Result<(), String> {
+    let peer = matches!(remote.remote, Remote::Peer(_));
+    let mut bases = settings_sync::load_bases(app, scope);
+    for kind in settings_sync::DOCUMENTS {
+        let name = settings_sync::remote_name(kind);
+        let ours = settings_sync::local(app, kind)?;
+        let theirs: Option<Document> = match remote.get_document(&name).await? {
+            Some(data) => Some(serde_json::from_slice(&data).map_err(|e| format!("Invalid {}: {}", name, e))?),
+            None => None,
+        };
+        let merged = match &theirs {
+            Some(theirs) => {
+                let ours_newer = ours.updated_at >= theirs.updated_at;
+                conflicts::merge_document(bases.get(*kind), &ours.data, &theirs.data, ours_newer)
+            }
+            None => ours.data.clone(),
+        };
+        if merged != ours.data {
+            settings_sync::apply(app, kind, &merged)?;
+        }
+        if peer || theirs.map_or(true, |theirs| theirs.data != merged) {
+            let document = Document { updated_at: chats::now_millis() as u64, data: merged.clone() };
+            remote.put(&name, serde_json::to_vec_pretty(&document).map_err(|e| e.to_string())?).await?;
+        }
+        bases.insert(kind.to_string(), merged);
+    }
+    settings_sync::save_bases(app, scope, &bases)
+}
+
+async fn run(app: &tauri::AppHandle, remote: &Remote, scope: &str) -> Result<SyncStatus, String> {
+    remote.prepare().await?;
+    // A LAN peer's channel is encrypted already
+    let seal = !matches!(remote, Remote::Peer(_)) && settings::load(app)?.sync.end_to_end;
+    // Chats leave decrypted, so they'd reach the backend readable
+    if !seal && !matches!(remote, Remote::Peer(_)) && encryption::enabled(app) {
+        return Err("Turn on end-to-end encryption to sync an encrypted chat store".to_string());
+    }
+    let raw = remote.get(MANIFEST).await?;
+    // No manifest is a remote that was never synced to, or a folder that
+    // isn't there yet; either way a first sync, which deletes nothing
+    let last = if raw.is_some() { load_state(app, scope) } else { Manifest::new() };
+    // Uploaded before end-to-end encryption was on: send every chat again sealed
+    let reseal = seal && raw.as_ref().is_some_and(|data| !e2e::is_sealed(data));
+    let manifest: Manifest = match raw {
+        Some(data) => serde_json::from_slice(&e2e::open(app, MANIFEST, data, true)?)
+            .map_err(|e| format!("Invalid sync manifest: {}", e))?,
+        None => Manifest::new(),
+    };
+    // An unsealed manifest is only this device's own when it's the one it
+    // left there last time
+    if reseal && manifest != last {
+        return Err(format!("{} on the sync remote isn't end-to-end encrypted, so it was refused", MANIFEST));
+    }
+    let remote = Store { app, remote, seal, own: &last };
+    // A LAN peer is told who this is when connecting
+    if !matches!(remote.remote, Remote::Peer(_)) {
+        exchange_devices(app, &remote).await?;
+    }
+    let (local, excluded) = local_chats(app)?;
+    let dir = chats::get_chats_dir(app)?;
+
+    let mut next = Manifest::new();
+    // Chats whose synced content changed this run, for the merge bases
+    let mut synced = BTreeMap::new();
+    let mut report = SyncStatus::default();
+    let ids: BTreeSet<&String> = local.keys().chain(manifest.keys()).collect();
+    let backend = if scope.is_empty() { backend_name(settings::load(app)?.sync.backend) } else { scope.to_string() };
+    let total = ids.len();
+    for (done, id) in ids.into_iter().enumerate() {
+        if done % PROGRESS_STEP == 0 {
+            let _ = app.emit(PROGRESS_EVENT, Progress { backend: &backend, done, total });
+        }
+        // Ids come from the remote too, and become file names here
+        if !id.chars().all(|c| c.is_ascii_alphanumeric() || "_-".contains(c)) {
+            log::warn!("Skipping a synced chat with an invalid id: {}", id);
+            continue;
+        }
+        // Left as they are on the remote, so other devices keep their copies
+        if excluded.contains(id) {
+            if let Some(theirs) = manifest.get(id) {
+                next.insert(id.clone(), theirs.clone());
+            }
+            continue;
+        }
+        let name = format!("{}.json", id);
+        let both_changed = |ours: &Entry, theirs: &Entry| {
+            last.get(id).is_some_and(|l| l.hash != ours.hash && l.hash != theirs.hash)
+        };
+        match (local.get(id), manifest.get(id)) {
+            (Some((ours, data)), Some(theirs)) if ours.hash == theirs.hash => {
+                // Chats last synced before merge bases were kept
+                if !base_path(app, scope, id)?.exists() {
+                    synced.insert(id.clone(), data.clone());
+                }
+                if reseal {
+                    remote.put(&name, data.clone()).await?;
+                }
+                next.insert(id.clone(), ours.clone());
+            }
+            // Unchanged here since the last sync, gone there: deleted remotely
+            (Some((ours, _)), None) if last.get(id) == Some(ours) => {
+                chats::remove(app.clone(), id.clone())?;
+                report.deleted += 1;
+            }
+            (None, Some(theirs)) if last.get(id) == Some(theirs) => {
+                remote.delete(&name).await?;
+                report.deleted += 1;
+            }
+            // Changed on both sides since the last sync
+            (Some((ours, data)), Some(theirs)) if both_changed(ours, theirs) => {
+                let remote_data = remote.get(&name).await?.ok_or_else(|| format!("{} is missing on the remote", name))?;
+                if hash(&remote_data) != theirs.hash {
+                    return Err(format!("{} is still syncing; trying again later", name));
+                }
+                let (ours, theirs) = (parse_chat(data)?, parse_chat(&remote_data)?);
+                let keep = match conflicts::merge(load_base(app, scope, id).as_ref(), &ours, &theirs)? {
+                    Merged::Clean(session) => {
+                        report.merged += 1;
+                        session
+                    }
+                    Merged::Conflicted { keep, copy } => {
+                        conflicts::save_copy(app, id, copy)?;
+                        report.conflicted += 1;
+                        keep
+                    }
+                };
+                chats::write(app, keep)?;
+                let data = read_chat(&dir.join(&name))?;
+                let updated_at = parse_chat(&data)?["updatedAt"].as_u64().unwrap_or_default();
+                let entry = Entry { updated_at, hash: hash(&data) };
+                remote.put(&name, data.clone()).await?;
+                synced.insert(id.clone(), data);
+                next.insert(id.clone(), entry);
+            }
+            (Some((ours, data)), theirs) if theirs.map_or(true, |t| ours.updated_at >= t.updated_at) => {
+                remote.put(&name, data.clone()).await?;
+                synced.insert(id.clone(), data.clone());
+                next.insert(id.clone(), ours.clone());
+                report.uploaded += 1;
+            }
+            (_, Some(theirs)) => {
+                let data = remote.get(&name).await?.ok_or_else(|| format!("{} is missing on the remote", name))?;
+                // A copy the remote hasn't finished receiving; the next run gets it
+                if hash(&data) != theirs.hash {
+                    return Err(format!("{} is still syncing; trying again later", name));
+                }
+                store_chat(app, &dir, id, &data)?;
+                next.insert(id.clone(), Entry { updated_at: theirs.updated_at, hash: hash(&data) });
+                synced.insert(id.clone(), data);
+                report.downloaded += 1;
+            }
+            _ => {}
+        }
+    }
+
+    if next != manifest || reseal {
+        let content = serde_json::to_vec_pretty(&next).map_err(|e| e.to_string())?;
+        remote.put(MANIFEST, content).await?;
+    }
+    let content = serde_json::to_string_pretty(&next).map_err(|e| e.to_string())?;
+    fs::write(state_path(app, scope)?, content).map_err(|e| format!("Failed to save the sync state: {}", e))?;
+    for (id, data) in synced {
+        let data = encryption::seal(app, data)?;
+        fs::write(base_path(app, scope, &id)?, data).map_err(|e| format!("Failed to save the sync state: {}", e))?;
+    }
+    for id in last.keys().filter(|id| !next.contains_key(*id)) {
+        let _ = fs::remove_file(base_path(app, scope, id)?);
+    }
+    if settings::load(app)?.sync.include_settings {
+        sync_documents(app, &remote, scope).await?;
+    }
+    let _ = app.emit(PROGRESS_EVENT, Progress { backend: &backend, done: total, total });
+    if report.downloaded > 0 || report.deleted > 0 || report.merged > 0 || report.conflicted > 0 {
+        let _ = app.emit(SYNCED_EVENT, ());
+    }
+    Ok(report)
+}
+
+async fn sync(app: &tauri::AppHandle) -> Result<SyncStatus, String> {
+    let _running = Running(Some(RUNNING.lock().await));
+    let settings = settings::load(app)?.sync;
+    let remote = remote(app, &settings).await?;
+    if encryption::is_locked(app) {
+        return Err(encryption::LOCKED.to_string());
+    }
+    reset_if_moved(app, &settings)?;
+
+    let backend = backend_name(settings.backend);
+    set_status(app, |s| {
+        s.state = SyncState::Syncing;
+        s.error = None;
+    });
+    started(app, &backend);
+    let mut result = run(app, &remote, "").await;
+    if let Err(e) = remote.finish().await {
+        result = result.and(Err(e));
+    }
+    finished(app, &backend, result.as_ref().map(|_| ()).map_err(String::as_str));
+    set_status(app, |s| match &result {
+        Ok(report) => {
+            *s = SyncStatus { state: SyncState::Idle, last_synced: Some(chats::now_millis() as u64), ..report.clone() };
+        }
+        Err(e) => {
+            s.state = SyncState::Error;
+            s.error = Some(e.clone());
+        }
+    });
+    result.map(|_| status())
+}
+
+// A sync with a paired device on the LAN, which serves its chat library in
+// place of a remote store
+pub async fn sync_peer(app: &tauri::AppHandle, peer: Box<Peer>, peer_id: &str) -> Result<SyncStatus, String> {
+    let _running = Running(Some(RUNNING.lock().await));
+    if encryption::is_locked(app) {
+        return Err(encryption::LOCKED.to_string());
+    }
+    let remote = Remote::Peer(peer);
+    let scope = format!("peer_{}", peer_id);
+    started(app, &scope);
+    let mut result = run(app, &remote, &scope).await;
+    if let Err(e) = remote.finish().await {
+        result = result.and(Err(e));
+    }
+    finished(app, &scope, result.as_ref().map(|_| ()).map_err(String::as_str));
+    result
+}
+
+// The manifest a LAN peer serves for its own library. Excluded chats show
+// as the peer last saw them, so it neither deletes nor re-sends its copy.
+pub fn local_manifest(app: &tauri::AppHandle, peer_id: &str) -> Result<Vec<u8>, String> {
+    let (local, excluded) = local_chats(app)?;
+    let mut manifest: Manifest = local.into_iter().map(|(id, (entry, _))| (id, entry)).collect();
+    let last = load_state(app, &format!("peer_{}", peer_id));
+    for id in excluded {
+        if let Some(entry) = last.get(&id) {
+            manifest.insert(id, entry.clone());
+        }
+    }
+    serde_json::to_vec(&manifest).map_err(|e| e.to_string())
+}
+
+pub fn excluded_chats(app: &tauri::AppHandle) -> Result<BTreeSet<String>, String> {
+    Ok(local_chats(app)?.1)
+}
+
+// Keeps the manifest a peer finished its sync with as our state for that
+// peer, so the next sync in the other direction sees the same history
+pub fn save_peer_state(app: &tauri::AppHandle, peer_id: &str, data: &[u8]) -> Result<(), String> {
+    let manifest: Manifest = serde_json::from_slice(data).map_err(|e| format!("Invalid sync manifest: {}", e))?;
+    let content = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    let path = state_path(app, &format!("peer_{}", peer_id))?;
+    fs::write(path, content).map_err(|e| format!("Failed to save the sync state: {}", e))
+}
+
+// Runs `serve` with the sync lock held, so a peer's changes don't land in
+// the middle of one of our syncs
+pub fn while_idle<T>(serve: impl FnOnce() -> T) -> T {
+    let _running = Running(Some(tauri::async_runtime::block_on(RUNNING.lock())));
+    serve()
+}
+
+fn status() -> SyncStatus {
+    STATUS.lock().ok().and_then(|s| s.clone()).unwrap_or_default()
+}
+
+// Called whenever a chat is saved or deleted. A save can't be told apart
+// from a sync's own writes while one runs, so it's held until the sync is
+// done; the run that follows finds the sync's writes already in place.
+pub fn record_change() {
+    if RUNNING.try_lock().is_err() {
+        PENDING.store(true, Ordering::SeqCst);
+        return;
+    }
+    LAST_SAVE.store(chats::now_millis() as u64, Ordering::SeqCst);
+}
+
+pub fn on_window_event(event: &tauri::WindowEvent) {
+    if matches!(event, tauri::WindowEvent::Focused(true)) {
+        FOCUSED.store(true, Ordering::SeqCst);
+    }
+}
+
+// Why a due run should wait, if the battery or network say so
+fn deferred(schedule: &settings::SyncSchedule) -> Option<String> {
+    if schedule.skip_low_battery {
+        if let Some(charge) = crate::device_status::battery_discharging() {
+            if charge < schedule.low_battery_percent {
+                return Some(format!("the battery is at {}%", charge));
+            }
+        }
+    }
+    if schedule.skip_metered && crate::device_status::metered() == Some(true) {
+        return Some("the network connection is metered".to_string());
+    }
+    None
+}
+
+// Syncs every interval_minutes, a little after saves, and when the window is
+// focused again, unless the battery is low or the connection metered
+pub fn schedule(app: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let mut recheck_at = 0;
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            let Ok(settings) = settings::load(&app).map(|s| s.sync) else {
+                continue;
+            };
+            let now = chats::now_millis() as u64;
+            let last_synced = status().last_synced;
+            let interval = settings.interval_minutes * 60 * 1000;
+            let interval_due = interval > 0 && last_synced.map_or(true, |last| now >= last + interval);
+            let last_save = LAST_SAVE.load(Ordering::SeqCst);
+            let save_due = settings.schedule.on_save
+                && last_save > 0
+                && now >= last_save + settings.schedule.debounce_seconds * 1000;
+            let focus_due = FOCUSED.swap(false, Ordering::SeqCst)
+                && settings.schedule.on_focus
+                && last_synced.map_or(true, |last| now >= last + FOCUS_MIN_GAP_MILLIS);
+            if settings.backend == SyncBackend::Off || !(interval_due || save_due || focus_due) || now < recheck_at {
+                continue;
+            }
+            if let Some(reason) = deferred(&settings.schedule) {
+                log::info!("Putting off the background sync because {}", reason);
+                recheck_at = now + DEFERRED_RECHECK_MILLIS;
+                continue;
+            }
+            LAST_SAVE.store(0, Ordering::SeqCst);
+            if let Err(e) = tauri::async_runtime::block_on(sync(&app)) {
+                log::warn!("Failed to sync chats: {}", e);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn sync_now(app: tauri::AppHandle) -> Result<SyncStatus, String> {
+    crate::app_lock::ensure_unlocked()?;
+    sync(&app).await
+}
+
+#[tauri::command]
+pub fn sync_status() -> SyncStatus {
+    status()
+}
+
+// Chats that differ from what the backend got last time, excluded ones aside
+fn pending_uploads(app: &tauri::AppHandle, local: &LocalChats, scope: &str) -> usize {
+    let last = load_state(app, scope);
+    local.iter().filter(|(id, (entry, _))| last.get(*id) != Some(entry)).count()
+}
+
+#[tauri::command]
+pub async fn get_sync_status(app: tauri::AppHandle) -> Result<SyncOverview, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let settings = settings::load(&app)?;
+        let mut wanted: Vec<String> = Vec::new();
+        if settings.sync.backend != SyncBackend::Off {
+            wanted.push(backend_name(settings.sync.backend));
+        }
+        if settings.git_sync.enabled {
+            wanted.push("git".to_string());
+        }
+        if settings.lan_sync.enabled {
+            wanted.extend(crate::lan_sync::paired_ids(&app).into_iter().map(|id| format!("peer_{}", id)));
+        }
+
+        let known = with_backends(&app, |backends| backends.clone()).unwrap_or_default();
+        let local = if encryption::is_locked(&app) { None } else { Some(local_chats(&app)?.0) };
+        let backends: Vec<BackendStatus> = wanted
+            .into_iter()
+            .map(|backend| {
+                let mut status = known
+                    .get(&backend)
+                    .cloned()
+                    .unwrap_or_else(|| BackendStatus { backend: backend.clone(), ..BackendStatus::default() });
+                status.pending_uploads = match (&local, backend.as_str()) {
+                    (_, "git") => crate::git_sync::pending(),
+                    (Some(local), name) if name.starts_with("peer_") => pending_uploads(&app, local, name),
+                    (Some(local), _) => pending_uploads(&app, local, ""),
+                    (None, _) => 0,
+                };
+                status
+            })
+            .collect();
+        let state = if backends.iter().any(|b| b.syncing) {
+            SyncState::Syncing
+        } else if backends.iter().any(|b| b.error.is_some()) {
+            SyncState::Error
+        } else {
+            SyncState::Idle
+        };
+        let conflicts = conflicts::list_conflicts(app.clone())?.len();
+        Ok(SyncOverview { state, backends, conflicts })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn sync_rules(app: tauri::AppHandle) -> Result<settings::SyncRules, String> {
+    crate::app_lock::ensure_unlocked()?;
+    Ok(settings::load(&app)?.sync.rules)
+}
+
+#[tauri::command]
+pub fn set_sync_rules(app: tauri::AppHandle, rules: settings::SyncRules) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut settings = settings::load(&app)?;
+    settings.sync.rules = rules;
+    settings::save(&app, &settings)
+}
+
+// The WebDAV password or S3 secret key, kept in the OS keychain rather than
+// settings.json. Dropbox and Google Drive keep their refresh tokens under the
+// same names, see oauth.rs.
+#[tauri::command]
+pub fn set_sync_secret(backend: SyncBackend, secret: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let account = secret_account(backend);
+    if secret.is_empty() {
+        return keychain::delete(&account);
+    }
+    keychain::store(&account, &secret)
+}
@@ -0,0 +1,418 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::attachments;
+use crate::office::markdown_table;
+
+const SAMPLE_ROWS: usize = 5;
+// Distinct values are only counted up to this, past it the column is "many"
+const MAX_DISTINCT: usize = 1000;
+const DEFAULT_GROUP_LIMIT: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Integer,
+    Number,
+    Boolean,
+    Date,
+    Text,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Column {
+    pub name: String,
+    pub kind: ColumnType,
+    pub empty: usize,
+    // Capped at MAX_DISTINCT
+    pub distinct: usize,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub mean: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableSummary {
+    pub attachment_id: String,
+    pub name: String,
+    pub row_count: usize,
+    pub columns: Vec<Column>,
+    pub sample_rows: Vec<Vec<String>>,
+}
+
+impl TableSummary {
+    // Schema and samples for the prompt, in place of the whole file
+    pub fn markdown(&self) -> String {
+        let mut schema = vec![vec![
+            "column".to_string(),
+            "type".to_string(),
+            "empty".to_string(),
+            "distinct".to_string(),
+            "min".to_string(),
+            "max".to_string(),
+            "mean".to_string(),
+        ]];
+        for column in &self.columns {
+            let distinct = if column.distinct >= MAX_DISTINCT {
+                format!("{}+", MAX_DISTINCT)
+            } else {
+                column.distinct.to_string()
+            };
+            schema.push(vec![
+                column.name.clone(),
+                format!("{:?}", column.kind).to_lowercase(),
+                column.empty.to_string(),
+                distinct,
+                column.min.clone().unwrap_or_default(),
+                column.max.clone().unwrap_or_default(),
+                column.mean.map(|m| format!("{:.4}", m)).unwrap_or_default(),
+            ]);
+        }
+
+        let headers = self.columns.iter().map(|c| c.name.clone()).collect();
+        let samples: Vec<Vec<String>> = std::iter::once(headers).chain(self.sample_rows.iter().cloned()).collect();
+        format!(
+            "{} rows, {} columns. Use the query_table tool with attachmentId \"{}\" for counts, sums and other \
+             aggregates.\n\n{}\n\nFirst {} rows:\n\n{}",
+            self.row_count,
+            self.columns.len(),
+            self.attachment_id,
+            markdown_table(&schema),
+            self.sample_rows.len(),
+            markdown_table(&samples)
+        )
+    }
+}
+
+struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn column(&self, name: &str) -> Result<usize, String> {
+        self.headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown column: {}", name))
+    }
+}
+
+fn cell(row: &[String], index: usize) -> &str {
+    row.get(index).map(|s| s.trim()).unwrap_or("")
+}
+
+pub fn is_table(name: &str, mime: &str) -> bool {
+    let ext = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    matches!(ext.as_str(), "csv" | "tsv") || matches!(mime, "text/csv" | "text/tab-separated-values")
+}
+
+// Tabs for .tsv; otherwise whichever of , ; or tab the header line has most of
+fn delimiter(name: &str, first_line: &str) -> u8 {
+    if name.to_lowercase().ends_with(".tsv") {
+        return b'\t';
+    }
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|d| first_line.bytes().filter(|b| b == d).count())
+        .unwrap_or(b',')
+}
+
+fn read(path: &Path, name: &str) -> Result<Table, String> {
+    let content = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    let text = String::from_utf8_lossy(&content);
+    let text = text.trim_start_matches('\u{feff}');
+    let delimiter = delimiter(name, text.lines().next().unwrap_or_default());
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Invalid table {}: {}", name, e))?
+        .iter()
+        .enumerate()
+        .map(|(i, h)| if h.trim().is_empty() { format!("column_{}", i + 1) } else { h.trim().to_string() })
+        .collect();
+    let rows = reader
+        .records()
+        .map(|record| record.map(|r| r.iter().map(str::to_string).collect()))
+        .collect::<Result<Vec<Vec<String>>, _>>()
+        .map_err(|e| format!("Invalid table {}: {}", name, e))?;
+    Ok(Table { headers, rows })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn is_date(value: &str) -> bool {
+    ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"]
+        .iter()
+        .any(|format| chrono::NaiveDate::parse_from_str(value, format).is_ok())
+        || chrono::DateTime::parse_from_rfc3339(value).is_ok()
+}
+
+fn infer(values: &[&str]) -> ColumnType {
+    if values.is_empty() {
+        ColumnType::Text
+    } else if values.iter().all(|v| v.parse::<i64>().is_ok()) {
+        ColumnType::Integer
+    } else if values.iter().all(|v| v.parse::<f64>().is_ok()) {
+        ColumnType::Number
+    } else if values.iter().all(|v| parse_bool(v).is_some()) {
+        ColumnType::Boolean
+    } else if values.iter().all(|v| is_date(v)) {
+        ColumnType::Date
+    } else {
+        ColumnType::Text
+    }
+}
+
+fn profile(table: &Table, index: usize) -> Column {
+    let values: Vec<&str> = table.rows.iter().map(|row| cell(row, index)).filter(|v| !v.is_empty()).collect();
+    let kind = infer(&values);
+
+    let mut distinct = HashSet::new();
+    for value in &values {
+        if distinct.len() >= MAX_DISTINCT {
+            break;
+        }
+        distinct.insert(*value);
+    }
+
+    let (min, max, mean) = match kind {
+        ColumnType::Integer | ColumnType::Number => {
+            let numbers: Vec<f64> = values.iter().filter_map(|v| v.parse().ok()).collect();
+            let min = numbers.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+            (Some(min.to_string()), Some(max.to_string()), Some(mean))
+        }
+        // ISO dates sort as text, and for text it's still a useful hint
+        ColumnType::Date | ColumnType::Text => (
+            values.iter().min().map(|v| v.to_string()),
+            values.iter().max().map(|v| v.to_string()),
+            None,
+        ),
+        ColumnType::Boolean => (None, None, None),
+    };
+
+    Column {
+        name: table.headers[index].clone(),
+        kind,
+        empty: table.rows.len() - values.len(),
+        distinct: distinct.len(),
+        min,
+        max,
+        mean,
+    }
+}
+
+fn load(app: &tauri::AppHandle, id: &str) -> Result<(attachments::Attachment, Table), String> {
+    let attachment = attachments::get(app, id)?;
+    if !is_table(&attachment.name, &attachment.mime) {
+        return Err(format!("{} is not a CSV or TSV file", attachment.name));
+    }
+    let table = read(&attachments::path(app, id)?, &attachment.name)?;
+    Ok((attachment, table))
+}
+
+pub fn summarize(app: &tauri::AppHandle, id: &str) -> Result<TableSummary, String> {
+    let (attachment, table) = load(app, id)?;
+    Ok(TableSummary {
+        attachment_id: id.to_string(),
+        name: attachment.name,
+        row_count: table.rows.len(),
+        columns: (0..table.headers.len()).map(|i| profile(&table, i)).collect(),
+        sample_rows: table.rows.iter().take(SAMPLE_ROWS).cloned().collect(),
+    })
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Distinct,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Contains,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Filter {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableQuery {
+    pub aggregate: Aggregate,
+    // Not needed for count
+    #[serde(default)]
+    pub column: Option<String>,
+    #[serde(default)]
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryGroup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub rows: usize,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResult {
+    pub matched_rows: usize,
+    pub groups: Vec<QueryGroup>,
+    // Groups past the limit were left out
+    pub truncated: bool,
+}
+
+// Numbers compare as numbers, everything else case-insensitively as text
+fn compare(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+        _ => a.to_lowercase().cmp(&b.to_lowercase()),
+    }
+}
+
+fn matches(filter: &Filter, value: &str) -> bool {
+    use std::cmp::Ordering::*;
+    let ordering = compare(value, filter.value.trim());
+    match filter.op {
+        FilterOp::Eq => ordering == Equal,
+        FilterOp::Ne => ordering != Equal,
+        FilterOp::Gt => ordering == Greater,
+        FilterOp::Gte => ordering != Less,
+        FilterOp::Lt => ordering == Less,
+        FilterOp::Lte => ordering != Greater,
+        FilterOp::Contains => value.to_lowercase().contains(&filter.value.to_lowercase()),
+    }
+}
+
+fn aggregate(op: Aggregate, values: &[&str]) -> Value {
+    let present: Vec<&str> = values.iter().copied().filter(|v| !v.is_empty()).collect();
+    let numbers = || present.iter().filter_map(|v| v.parse::<f64>().ok());
+    match op {
+        Aggregate::Count => json!(present.len()),
+        Aggregate::Distinct => json!(present.iter().collect::<HashSet<_>>().len()),
+        Aggregate::Sum => json!(numbers().sum::<f64>()),
+        Aggregate::Avg => {
+            let count = numbers().count();
+            if count == 0 {
+                Value::Null
+            } else {
+                json!(numbers().sum::<f64>() / count as f64)
+            }
+        }
+        Aggregate::Min => present.iter().copied().min_by(|a, b| compare(a, b)).map_or(Value::Null, |v| json!(v)),
+        Aggregate::Max => present.iter().copied().max_by(|a, b| compare(a, b)).map_or(Value::Null, |v| json!(v)),
+    }
+}
+
+fn run_query(table: &Table, query: &TableQuery) -> Result<QueryResult, String> {
+    let filters = query
+        .filters
+        .iter()
+        .map(|f| Ok((table.column(&f.column)?, f)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let column = match (&query.column, query.aggregate) {
+        (Some(name), _) => Some(table.column(name)?),
+        (None, Aggregate::Count) => None,
+        (None, _) => return Err("This aggregate needs a column".to_string()),
+    };
+    let group_by = query.group_by.as_deref().map(|name| table.column(name)).transpose()?;
+
+    let mut groups: BTreeMap<Option<String>, Vec<&[String]>> = BTreeMap::new();
+    let mut matched_rows = 0;
+    for row in &table.rows {
+        if !filters.iter().all(|(index, filter)| matches(filter, cell(row, *index))) {
+            continue;
+        }
+        matched_rows += 1;
+        let key = group_by.map(|index| cell(row, index).to_string());
+        groups.entry(key).or_default().push(row);
+    }
+
+    let mut results: Vec<QueryGroup> = groups
+        .into_iter()
+        .map(|(key, rows)| {
+            let value = match column {
+                Some(index) => {
+                    let values: Vec<&str> = rows.iter().map(|row| cell(row, index)).collect();
+                    aggregate(query.aggregate, &values)
+                }
+                None => json!(rows.len()),
+            };
+            QueryGroup { key, rows: rows.len(), value }
+        })
+        .collect();
+    // Largest first, which is what "top N" style questions want
+    if group_by.is_some() {
+        results.sort_by(|a, b| compare(&b.value.to_string(), &a.value.to_string()));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_GROUP_LIMIT).max(1);
+    let truncated = results.len() > limit;
+    results.truncate(limit);
+    Ok(QueryResult { matched_rows, groups: results, truncated })
+}
+
+pub fn query(app: &tauri::AppHandle, id: &str, query: &TableQuery) -> Result<QueryResult, String> {
+    let (_, table) = load(app, id)?;
+    run_query(&table, query)
+}
+
+#[tauri::command]
+pub async fn describe_table(app: tauri::AppHandle, attachment_id: String) -> Result<TableSummary, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || summarize(&app, &attachment_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn query_table(
+    app: tauri::AppHandle,
+    attachment_id: String,
+    query: TableQuery,
+) -> Result<QueryResult, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || self::query(&app, &attachment_id, &query))
+        .await
+        .map_err(|e| e.to_string())?
+}
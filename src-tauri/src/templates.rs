@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::prompts::{self, Prompt};
+
+// Snippets may include other snippets, but not forever
+const MAX_SNIPPET_DEPTH: usize = 5;
+// Filled in by the backend unless the caller passes a value. `selection` has no
+// backend source and always comes from the UI.
+const BUILTINS: &[&str] = &["clipboard", "date", "time", "datetime"];
+
+enum Token<'a> {
+    Text(&'a str),
+    // {{name}}
+    Var(&'a str),
+    // {{> snippet name}}, another prompt from the library inlined by name
+    Snippet(&'a str),
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateVariable {
+    pub name: String,
+    pub builtin: bool,
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        let inner = rest[start + 2..start + 2 + len].trim();
+        tokens.push(match inner.strip_prefix('>') {
+            Some(name) => Token::Snippet(name.trim()),
+            None => Token::Var(inner),
+        });
+        rest = &rest[start + 2 + len + 2..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+fn expand_snippets(text: &str, library: &[Prompt], depth: usize) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    for token in tokenize(text) {
+        match token {
+            Token::Text(text) => out.push_str(text),
+            Token::Var(name) => {
+                out.push_str("{{");
+                out.push_str(name);
+                out.push_str("}}");
+            }
+            Token::Snippet(name) => {
+                if depth >= MAX_SNIPPET_DEPTH {
+                    return Err(format!("Snippets nested too deeply at \"{}\"", name));
+                }
+                let snippet = library
+                    .iter()
+                    .find(|p| p.name.eq_ignore_ascii_case(name))
+                    .and_then(Prompt::latest)
+                    .ok_or_else(|| format!("Unknown snippet: {}", name))?;
+                out.push_str(&expand_snippets(&snippet.content, library, depth + 1)?);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn builtin_value(name: &str) -> Option<Result<String, String>> {
+    let now = chrono::Local::now();
+    let value = match name {
+        "date" => now.format("%Y-%m-%d").to_string(),
+        "time" => now.format("%H:%M").to_string(),
+        "datetime" => now.to_rfc3339(),
+        "clipboard" => {
+            return Some(
+                arboard::Clipboard::new()
+                    .and_then(|mut clipboard| clipboard.get_text())
+                    .map_err(|e| format!("Clipboard is unavailable: {}", e)),
+            )
+        }
+        _ => return None,
+    };
+    Some(Ok(value))
+}
+
+// Variables the template needs after snippets are inlined, in first-use order.
+pub fn variables(app: &tauri::AppHandle, text: &str) -> Result<Vec<TemplateVariable>, String> {
+    let expanded = expand_snippets(text, &prompts::load(app)?, 0)?;
+    let mut found: Vec<TemplateVariable> = Vec::new();
+    for token in tokenize(&expanded) {
+        if let Token::Var(name) = token {
+            if !found.iter().any(|v| v.name == name) {
+                found.push(TemplateVariable { name: name.to_string(), builtin: BUILTINS.contains(&name) });
+            }
+        }
+    }
+    Ok(found)
+}
+
+pub fn render(app: &tauri::AppHandle, text: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let expanded = expand_snippets(text, &prompts::load(app)?, 0)?;
+
+    let mut out = String::with_capacity(expanded.len());
+    let mut missing: Vec<&str> = Vec::new();
+    for token in tokenize(&expanded) {
+        match token {
+            Token::Text(text) => out.push_str(text),
+            Token::Var(name) => match vars.get(name) {
+                Some(value) => out.push_str(value),
+                None => match builtin_value(name) {
+                    Some(value) => out.push_str(&value?),
+                    None if !missing.contains(&name) => missing.push(name),
+                    None => {}
+                },
+            },
+            // Snippets are expanded first, but one whose text ends in an open
+            // "{{" can still join up with what follows it into another; that
+            // one stays as written
+            Token::Snippet(name) => {
+                out.push_str("{{> ");
+                out.push_str(name);
+                out.push_str("}}");
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(out)
+    } else {
+        Err(format!("Missing template variables: {}", missing.join(", ")))
+    }
+}
+
+fn prompt_content(app: &tauri::AppHandle, id: &str) -> Result<String, String> {
+    prompts::load(app)?
+        .iter()
+        .find(|p| p.id == id)
+        .and_then(Prompt::latest)
+        .map(|v| v.content.clone())
+        .ok_or_else(|| format!("Unknown prompt: {}", id))
+}
+
+#[tauri::command]
+pub fn render_template(
+    app: tauri::AppHandle,
+    id: String,
+    vars: Option<HashMap<String, String>>,
+) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    render(&app, &prompt_content(&app, &id)?, &vars.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn template_variables(app: tauri::AppHandle, id: String) -> Result<Vec<TemplateVariable>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    variables(&app, &prompt_content(&app, &id)?)
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::PathBuf;
+
+use image::imageops::FilterType;
+
+use crate::attachments;
+use crate::vision;
+
+pub const DEFAULT_SIZE: u32 = 256;
+const MIN_SIZE: u32 = 32;
+const MAX_SIZE: u32 = 1024;
+const EXTENSIONS: &[&str] = &["jpg", "png"];
+
+fn thumbnails_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("thumbnails");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create thumbnails directory: {}", e))?;
+    }
+    Ok(dir)
+}
+
+fn cached(dir: &std::path::Path, id: &str, size: u32) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{}_{}.{}", id, size, ext)))
+        .find(|path| path.exists())
+}
+
+// Path to a thumbnail whose longer edge is at most `size`, generating it on first use.
+pub fn thumbnail(app: &tauri::AppHandle, id: &str, size: Option<u32>) -> Result<PathBuf, String> {
+    let size = size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+    let source = attachments::path(app, id)?;
+    let dir = thumbnails_dir(app)?;
+    if let Some(path) = cached(&dir, id, size) {
+        return Ok(path);
+    }
+
+    // Blobs have no extension, so sniff the format from the content
+    let img = image::ImageReader::open(&source)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    // Small originals are re-encoded at their own size rather than upscaled
+    let img = if img.width() > size || img.height() > size {
+        img.resize(size, size, FilterType::Triangle)
+    } else {
+        img
+    };
+    let encoded = vision::encode(&img)?;
+    let ext = if encoded.mime == "image/png" { "png" } else { "jpg" };
+
+    let path = dir.join(format!("{}_{}.{}", id, size, ext));
+    let partial = path.with_extension("part");
+    fs::write(&partial, &encoded.data).map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+    fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+// Drops every cached size of an attachment's thumbnail.
+pub fn remove(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    let prefix = format!("{}_", id);
+    for entry in fs::read_dir(thumbnails_dir(app)?).map_err(|e| e.to_string())?.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            crate::shred::remove_file(app, &entry.path()).map_err(|e| format!("Failed to delete thumbnail: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_thumbnail(app: tauri::AppHandle, id: String, size: Option<u32>) -> Result<String, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || thumbnail(&app, &id, size))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|path| path.to_string_lossy().to_string())
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::Emitter;
+
+use crate::anonymize::{self, Scope};
+use crate::chats;
+use crate::completion::{ChatMessage, CompletionRequest};
+use crate::context;
+use crate::settings::{ProviderConfig, Settings};
+use crate::structured::{self, OutputSchema};
+
+// Enough of each message to tell what it's about
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMetadata {
+    pub chat_id: String,
+    pub title: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Suggestion {
+    title: String,
+    tags: Vec<String>,
+}
+
+fn schema() -> OutputSchema {
+    OutputSchema {
+        name: "chat_metadata".to_string(),
+        schema: json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "maxLength": 80 },
+                "tags": {
+                    "type": "array",
+                    "items": { "type": "string", "maxLength": 32 },
+                    "minItems": 2,
+                    "maxItems": 3
+                }
+            },
+            "required": ["title", "tags"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+fn clip(content: &str) -> String {
+    match content.char_indices().nth(MAX_MESSAGE_CHARS) {
+        Some((end, _)) => format!("{}…", &content[..end]),
+        None => content.to_string(),
+    }
+}
+
+async fn suggest(
+    provider: &ProviderConfig,
+    model: &str,
+    messages: &[ChatMessage],
+    anonymize: Option<Scope>,
+) -> Result<Suggestion, String> {
+    let visible: Vec<ChatMessage> = messages
+        .iter()
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .map(|m| ChatMessage::new(&m.role, clip(&m.content)))
+        .collect();
+
+    let request = CompletionRequest {
+        provider_id: Some(provider.id.clone()),
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage::new(
+                "system",
+                "Suggest a short title (at most six words, no quotes) and two or three lowercase topical tags \
+                 for the conversation below.",
+            ),
+            ChatMessage::new("user", context::transcript(None, &visible)),
+        ],
+        anonymize,
+        ..Default::default()
+    };
+
+    let result = structured::complete_structured(provider, request, &schema(), 1).await?;
+    serde_json::from_value(result.value).map_err(|e| e.to_string())
+}
+
+async fn apply(
+    app: &tauri::AppHandle,
+    provider: &ProviderConfig,
+    model: &str,
+    chat_id: &str,
+    messages: &[ChatMessage],
+    anonymize: Option<Scope>,
+) -> Result<(), String> {
+    let suggestion = suggest(provider, model, messages, anonymize).await?;
+    let tags: Vec<String> = suggestion.tags.iter().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()).collect();
+    let title = suggestion.title.trim().trim_matches('"').to_string();
+
+    // The UI may not have saved the new turn yet; try again next time if so
+    let mut session = chats::read(app, chat_id)?;
+    session["title"] = json!(title);
+    session["tags"] = json!(tags);
+    chats::write(app, session)?;
+
+    app.emit("chat-metadata-updated", ChatMetadata { chat_id: chat_id.to_string(), title, tags })
+        .map_err(|e| e.to_string())
+}
+
+// Suggests a title and tags in the background once the chat has enough turns.
+// Chats that already have tags are left alone, so this runs once per chat.
+pub fn schedule(
+    app: &tauri::AppHandle,
+    settings: &Settings,
+    provider: &ProviderConfig,
+    model: &str,
+    chat_id: String,
+    messages: Vec<ChatMessage>,
+) {
+    let after_turns = settings.auto_title_after_turns;
+    let user_turns = messages.iter().filter(|m| m.role == "user").count();
+    if after_turns == 0 || user_turns < after_turns {
+        return;
+    }
+    let already_tagged = chats::read(app, &chat_id).map(|s| s.get("tags").is_some()).unwrap_or(false);
+    if already_tagged {
+        return;
+    }
+
+    let anonymize = anonymize::key(settings, provider, Some(&chat_id));
+    let app = app.clone();
+    let provider = provider.clone();
+    let model = model.to_string();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = apply(&app, &provider, &model, &chat_id, &messages, anonymize).await {
+            log::warn!("Failed to suggest a title for {}: {}", chat_id, e);
+        }
+    });
+}
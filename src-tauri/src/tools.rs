@@ -0,0 +1,356 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::Read;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<Value, String>> + Send>>;
+
+// Keeps a single tool result from swallowing the model's context window
+const MAX_OUTPUT_CHARS: usize = 20_000;
+const DEFAULT_SHELL_TIMEOUT_SECS: u64 = 60;
+const MAX_SHELL_TIMEOUT_SECS: u64 = 300;
+// How long to wait for the output once the command is gone; a background
+// process it started can keep the pipes open
+const OUTPUT_GRACE: Duration = Duration::from_millis(500);
+
+// What a tool call runs with
+pub struct ToolContext {
+    pub app: tauri::AppHandle,
+    // For an incognito chat, whose calls stay out of the audit log
+    pub private: bool,
+}
+
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: fn() -> Value,
+    // Whether each call needs the user's go-ahead, see permissions.rs
+    pub needs_permission: bool,
+    pub run: fn(ToolContext, Value) -> ToolFuture,
+}
+
+static TOOLS: &[ToolSpec] = &[
+    ToolSpec {
+        name: "current_time",
+        description: "Get the current local date and time.",
+        parameters: || json!({ "type": "object", "properties": {} }),
+        needs_permission: false,
+        run: |_, _| Box::pin(current_time()),
+    },
+    ToolSpec {
+        name: "read_file",
+        description: "Read a UTF-8 text file from the user's computer.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": { "path": { "type": "string", "description": "Absolute file path" } },
+                "required": ["path"]
+            })
+        },
+        needs_permission: true,
+        run: |context, args| Box::pin(read_file(context, args)),
+    },
+    ToolSpec {
+        name: "list_directory",
+        description: "List the entries of a directory on the user's computer.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": { "path": { "type": "string", "description": "Absolute directory path" } },
+                "required": ["path"]
+            })
+        },
+        needs_permission: true,
+        run: |context, args| Box::pin(list_directory(context, args)),
+    },
+    ToolSpec {
+        name: "run_shell",
+        description: "Run a shell command and return its exit code, stdout and stderr.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string" },
+                    "cwd": { "type": "string", "description": "Working directory" },
+                    "timeoutSeconds": { "type": "integer", "description": "Wall-clock limit, 60 by default" }
+                },
+                "required": ["command"]
+            })
+        },
+        needs_permission: true,
+        run: |context, args| Box::pin(run_shell(context, args)),
+    },
+    ToolSpec {
+        name: "run_code",
+        description: "Run a Python, JavaScript or shell program in a sandbox without network access and return its \
+                      exit code, stdout, stderr and any files it wrote to its working directory.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "language": { "type": "string", "enum": ["python", "javascript", "shell"] },
+                    "code": { "type": "string" },
+                    "timeoutSeconds": { "type": "integer", "description": "Wall-clock limit, 30 by default" }
+                },
+                "required": ["language", "code"]
+            })
+        },
+        needs_permission: true,
+        run: |context, args| Box::pin(run_code(context, args)),
+    },
+    ToolSpec {
+        name: "http_get",
+        description: "Fetch a web page or API response over HTTP GET and return its body as text.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": { "url": { "type": "string" } },
+                "required": ["url"]
+            })
+        },
+        needs_permission: true,
+        run: |context, args| Box::pin(http_get(context, args)),
+    },
+    ToolSpec {
+        name: "query_table",
+        description: "Run an aggregate query (count, sum, avg, min, max, distinct) over an attached CSV or TSV file, \
+                      optionally filtered and grouped by a column.",
+        parameters: || {
+            let filter = json!({
+                "type": "object",
+                "properties": {
+                    "column": { "type": "string" },
+                    "op": { "type": "string", "enum": ["eq", "ne", "gt", "gte", "lt", "lte", "contains"] },
+                    "value": { "type": "string" }
+                },
+                "required": ["column", "op", "value"]
+            });
+            json!({
+                "type": "object",
+                "properties": {
+                    "attachmentId": { "type": "string" },
+                    "aggregate": { "type": "string", "enum": ["count", "sum", "avg", "min", "max", "distinct"] },
+                    "column": { "type": "string", "description": "Column to aggregate; not needed for count" },
+                    "groupBy": { "type": "string" },
+                    "filters": { "type": "array", "items": filter },
+                    "limit": { "type": "integer", "description": "Maximum number of groups to return" }
+                },
+                "required": ["attachmentId", "aggregate"]
+            })
+        },
+        needs_permission: false,
+        run: |context, args| Box::pin(query_table(context.app, args)),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static ToolSpec> {
+    TOOLS.iter().find(|tool| tool.name == name)
+}
+
+// OpenAI-style `tools` payload for the requested subset.
+pub fn definitions(names: &[String]) -> Result<Vec<Value>, String> {
+    names
+        .iter()
+        .map(|name| {
+            let tool = find(name).ok_or_else(|| format!("Unknown tool: {}", name))?;
+            Ok(json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": (tool.parameters)(),
+                }
+            }))
+        })
+        .collect()
+}
+
+pub async fn execute(app: &tauri::AppHandle, name: &str, arguments: &str, private: bool) -> Result<Value, String> {
+    let tool = find(name).ok_or_else(|| format!("Unknown tool: {}", name))?;
+    let args: Value = if arguments.trim().is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str(arguments).map_err(|e| format!("Invalid tool arguments: {}", e))?
+    };
+    (tool.run)(ToolContext { app: app.clone(), private }, args).await
+}
+
+fn audit(context: &ToolContext, kind: &str, detail: &str) {
+    if !context.private {
+        crate::audit::record(kind, detail);
+    }
+}
+
+fn truncate(mut text: String) -> String {
+    if text.len() > MAX_OUTPUT_CHARS {
+        let mut end = MAX_OUTPUT_CHARS;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n[output truncated]");
+    }
+    text
+}
+
+fn string_arg(args: &Value, key: &str) -> Result<String, String> {
+    args[key]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("Missing argument: {}", key))
+}
+
+async fn current_time() -> Result<Value, String> {
+    let now = chrono::Local::now();
+    Ok(json!({ "localTime": now.to_rfc3339(), "utcOffset": now.format("%:z").to_string() }))
+}
+
+async fn read_file(context: ToolContext, args: Value) -> Result<Value, String> {
+    let path = PathBuf::from(string_arg(&args, "path")?);
+    audit(&context, "file", &format!("read_file {}", path.display()));
+    // One byte past the limit, so truncate can tell the file was longer
+    let bytes = tauri::async_runtime::spawn_blocking(move || {
+        let mut bytes = Vec::new();
+        File::open(&path)?.take(MAX_OUTPUT_CHARS as u64 + 1).read_to_end(&mut bytes)?;
+        Ok::<_, std::io::Error>(bytes)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("Failed to read file: {}", e))?;
+    Ok(json!({ "content": truncate(String::from_utf8_lossy(&bytes).to_string()) }))
+}
+
+async fn list_directory(context: ToolContext, args: Value) -> Result<Value, String> {
+    let path = PathBuf::from(string_arg(&args, "path")?);
+    audit(&context, "file", &format!("list_directory {}", path.display()));
+    let entries = tauri::async_runtime::spawn_blocking(move || -> Result<Vec<Value>, String> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&path).map_err(|e| format!("Failed to list directory: {}", e))?.flatten() {
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            entries.push(json!({ "name": entry.file_name().to_string_lossy(), "isDir": is_dir }));
+        }
+        Ok(entries)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(json!({ "entries": entries }))
+}
+
+async fn run_shell(context: ToolContext, args: Value) -> Result<Value, String> {
+    let command = string_arg(&args, "command")?;
+    let cwd = args["cwd"].as_str().map(PathBuf::from);
+    let timeout = args["timeoutSeconds"].as_u64().unwrap_or(DEFAULT_SHELL_TIMEOUT_SECS);
+    let timeout = timeout.clamp(1, MAX_SHELL_TIMEOUT_SECS);
+    audit(&context, "shell", &command);
+
+    let output = tauri::async_runtime::spawn_blocking(move || -> Result<Value, String> {
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.args(["/C", &command]);
+            cmd
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", &command]);
+            cmd
+        };
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = cmd.spawn().map_err(|e| format!("Failed to run command: {}", e))?;
+        let stdout = child.stdout.take().map(capture);
+        let stderr = child.stderr.take().map(capture);
+
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        let (status, timed_out) = loop {
+            if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                break (Some(status), false);
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                break (None, true);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        Ok(json!({
+            "exitCode": status.and_then(|s| s.code()),
+            "timedOut": timed_out,
+            "stdout": stdout.map(captured).unwrap_or_default(),
+            "stderr": stderr.map(captured).unwrap_or_default(),
+        }))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(output)
+}
+
+// What a pipe's reader has kept so far, and word once it's done
+type Capture = (Arc<Mutex<Vec<u8>>>, mpsc::Receiver<()>);
+
+// Reads a pipe on a thread of its own, keeping one byte past the limit and
+// throwing the rest away so the command never blocks on a full pipe
+fn capture(mut pipe: impl Read + Send + 'static) -> Capture {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let (done, finished) = mpsc::channel();
+    let shared = buffer.clone();
+    std::thread::spawn(move || {
+        let mut chunk = [0; 8192];
+        while let Ok(read) = pipe.read(&mut chunk) {
+            if read == 0 {
+                break;
+            }
+            if let Ok(mut buffer) = shared.lock() {
+                let room = (MAX_OUTPUT_CHARS + 1).saturating_sub(buffer.len());
+                buffer.extend_from_slice(&chunk[..read.min(room)]);
+            }
+        }
+        let _ = done.send(());
+    });
+    (buffer, finished)
+}
+
+fn captured((buffer, finished): Capture) -> String {
+    let _ = finished.recv_timeout(OUTPUT_GRACE);
+    let bytes = buffer.lock().map(|b| b.clone()).unwrap_or_default();
+    truncate(String::from_utf8_lossy(&bytes).to_string())
+}
+
+async fn run_code(context: ToolContext, args: Value) -> Result<Value, String> {
+    let language = string_arg(&args, "language")?;
+    let code = string_arg(&args, "code")?;
+    let timeout = args["timeoutSeconds"].as_u64();
+    audit(&context, "shell", &format!("run_code {}", language));
+    let app = context.app;
+    let output = tauri::async_runtime::spawn_blocking(move || crate::sandbox::run(&app, &language, &code, timeout))
+        .await
+        .map_err(|e| e.to_string())??;
+    serde_json::to_value(output).map_err(|e| e.to_string())
+}
+
+async fn http_get(context: ToolContext, args: Value) -> Result<Value, String> {
+    let url = string_arg(&args, "url")?;
+    let request = crate::http::client().get(&url);
+    let response = crate::http::send_audited(request, !context.private)
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+    let status = response.status().as_u16();
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    Ok(json!({ "status": status, "body": truncate(body) }))
+}
+
+async fn query_table(app: tauri::AppHandle, args: Value) -> Result<Value, String> {
+    let id = string_arg(&args, "attachmentId")?;
+    let query: crate::tables::TableQuery =
+        serde_json::from_value(args).map_err(|e| format!("Invalid table query: {}", e))?;
+    let result = tauri::async_runtime::spawn_blocking(move || crate::tables::query(&app, &id, &query))
+        .await
+        .map_err(|e| e.to_string())??;
+    serde_json::to_value(result).map_err(|e| e.to_string())
+}
@@ -0,0 +1,283 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::Emitter;
+
+use crate::extraction;
+use crate::settings;
+
+// openai/whisper-<name>, run in-process by whisper.rs
+const MODEL_BASE_URL: &str = "https://huggingface.co/openai";
+// The weights last, so a model only looks downloaded once they're in
+const MODEL_FILES: &[&str] = &["config.json", "tokenizer.json", "model.safetensors"];
+
+// (name, approximate download size in MB)
+const MODELS: &[(&str, u64)] = &[
+    ("tiny", 150),
+    ("tiny.en", 150),
+    ("base", 290),
+    ("base.en", 290),
+    ("small", 970),
+    ("small.en", 970),
+    ("medium", 3060),
+    ("medium.en", 3060),
+    ("large-v3-turbo", 1620),
+    ("large-v3", 3090),
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AudioSource {
+    Path(String),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhisperModel {
+    pub name: String,
+    pub size_mb: u64,
+    pub downloaded: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptionProgress {
+    // Set when an attachment is being transcribed in the background
+    pub attachment_id: Option<String>,
+    pub percent: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+    pub model: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptSegment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    pub text: String,
+    pub language: Option<String>,
+    pub segments: Vec<TranscriptSegment>,
+}
+
+fn models_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::app_data_dir(app)?.join("whisper");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create whisper directory: {}", e))?;
+    }
+    Ok(dir)
+}
+
+fn model_dir(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    // Only known names, so a model name can never escape the models directory
+    if !MODELS.iter().any(|(known, _)| *known == name) {
+        return Err(format!("Unknown whisper model: {}", name));
+    }
+    Ok(models_dir(app)?.join(name))
+}
+
+fn is_downloaded(dir: &Path) -> bool {
+    MODEL_FILES.iter().all(|file| dir.join(file).exists())
+}
+
+pub(crate) fn find_in_path(name: &str) -> Option<PathBuf> {
+    let exe = if cfg!(windows) { format!("{}.exe", name) } else { name.to_string() };
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(&exe))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+fn temp_path(extension: &str) -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("anchor-{}-{}.{}", std::process::id(), nanos, extension))
+}
+
+// Blocking; transcribes with the configured model.
+pub fn transcribe_file(
+    app: &tauri::AppHandle,
+    input: &Path,
+    language: Option<&str>,
+    model: Option<&str>,
+    on_progress: impl FnMut(u32) + Send,
+) -> Result<Transcript, String> {
+    let speech = settings::load(app)?.speech;
+    let dir = model_dir(app, model.unwrap_or(&speech.whisper_model))?;
+    if !is_downloaded(&dir) {
+        return Err("Whisper model not downloaded".to_string());
+    }
+    let samples = crate::audio::load_for_whisper(input)?;
+    let mut model = crate::whisper::load(&dir)?;
+    let run = || crate::whisper::transcribe(&mut model, &samples, language, on_progress);
+    // Like the embedding model, the matrix work runs on whichever rayon pool
+    // it's called in
+    match crate::power::threads(app) {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| e.to_string())?
+            .install(run),
+        None => run(),
+    }
+}
+
+#[tauri::command]
+pub fn list_whisper_models(app: tauri::AppHandle) -> Result<Vec<WhisperModel>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    MODELS
+        .iter()
+        .map(|(name, size_mb)| {
+            Ok(WhisperModel {
+                name: name.to_string(),
+                size_mb: *size_mb,
+                downloaded: is_downloaded(&model_dir(&app, name)?),
+            })
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub async fn download_whisper_model(app: tauri::AppHandle, name: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let dir = model_dir(&app, &name)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create whisper directory: {}", e))?;
+    let mut downloaded = 0u64;
+    let mut last_emit = 0u64;
+
+    for file_name in MODEL_FILES {
+        let path = dir.join(file_name);
+        if path.exists() {
+            continue;
+        }
+        let url = format!("{}/whisper-{}/resolve/main/{}", MODEL_BASE_URL, name, file_name);
+        let request = crate::http::client().get(&url);
+        let mut response = crate::http::send(request)
+            .await
+            .map_err(|e| format!("Failed to download model: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Model download returned {}", response.status()));
+        }
+
+        // Download next to the target and rename at the end so a cancelled
+        // download never looks like a usable model
+        let partial = path.with_extension("part");
+        let mut file = fs::File::create(&partial).map_err(|e| e.to_string())?;
+        let total = response.content_length().map(|length| downloaded + length);
+
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("Download interrupted: {}", e))? {
+            file.write_all(&chunk).map_err(|e| e.to_string())?;
+            downloaded += chunk.len() as u64;
+            if downloaded - last_emit >= 1024 * 1024 {
+                last_emit = downloaded;
+                let _ = app.emit(
+                    "whisper-download-progress",
+                    DownloadProgress { model: name.clone(), downloaded, total },
+                );
+            }
+        }
+        drop(file);
+        fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    }
+
+    let _ = app.emit(
+        "whisper-download-progress",
+        DownloadProgress { model: name, downloaded, total: Some(downloaded) },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_whisper_model(app: tauri::AppHandle, name: String) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let dir = model_dir(&app, &name)?;
+    if dir.exists() {
+        fs::remove_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentTranscript {
+    pub attachment_id: String,
+    pub text: Option<String>,
+    pub error: Option<String>,
+}
+
+// With auto-transcription on, newly attached audio is transcribed in the
+// background; the text is cached with the attachment and goes along with
+// the message it's attached to.
+pub fn schedule_auto(app: &tauri::AppHandle, attachment_id: &str) {
+    let enabled = settings::load(app).map(|s| s.speech.auto_transcribe).unwrap_or(false);
+    if !enabled {
+        return;
+    }
+
+    let app = app.clone();
+    let id = attachment_id.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        if extraction::format_of(&app, &id).ok() != Some("audio") {
+            return;
+        }
+        let result = extraction::extract(&app, &id).map(|document| document.text());
+        if let Err(e) = &result {
+            log::warn!("Failed to transcribe attachment {}: {}", id, e);
+        }
+        let (text, error) = match result {
+            Ok(text) => (Some(text), None),
+            Err(e) => (None, Some(e)),
+        };
+        let _ = app.emit("attachment-transcribed", AttachmentTranscript { attachment_id: id, text, error });
+    });
+}
+
+#[tauri::command]
+pub async fn transcribe_audio(
+    app: tauri::AppHandle,
+    source: AudioSource,
+    language: Option<String>,
+    model: Option<String>,
+) -> Result<Transcript, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let handle = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let (input, cleanup) = match source {
+            AudioSource::Path(path) => (PathBuf::from(path), false),
+            AudioSource::Bytes(bytes) => {
+                let path = temp_path("audio");
+                fs::write(&path, bytes).map_err(|e| e.to_string())?;
+                (path, true)
+            }
+        };
+
+        let result = transcribe_file(&handle, &input, language.as_deref(), model.as_deref(), |percent| {
+            let _ = handle.emit("transcription-progress", TranscriptionProgress { attachment_id: None, percent });
+        });
+        if cleanup {
+            let _ = fs::remove_file(&input);
+        }
+        result
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    crate::analytics::track(&app, "transcribe_audio", result)
+}
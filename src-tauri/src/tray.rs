@@ -0,0 +1,223 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{Emitter, Manager, Wry};
+
+use crate::settings::{self, SyncBackend};
+use crate::sync::{self, SyncState};
+
+// The tray (menu bar on macOS) icon: a new chat, the most recent chats, the
+// sync status and quit. The menu is handled here rather than in the UI, so it
+// works while the window is closed; Anchor keeps running in the tray then and
+// opens the window again when something is picked. What was picked waits in
+// take_tray_action for a window that has only just opened.
+
+pub const TRAY_ID: &str = "anchor";
+const RECENT_CHATS: usize = 5;
+const MAX_TITLE_CHARS: usize = 40;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+const ACTION_EVENT: &str = "tray-action";
+const CHAT_PREFIX: &str = "chat:";
+const MAIN_WINDOW: &str = "main";
+
+// Set when the chats change, so the menu is rebuilt
+static STALE: AtomicBool = AtomicBool::new(true);
+static PENDING: Mutex<Option<TrayAction>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TrayAction {
+    NewChat,
+    #[serde(rename_all = "camelCase")]
+    OpenChat { chat_id: String },
+}
+
+fn enabled(app: &tauri::AppHandle) -> bool {
+    settings::load(app).map_or(true, |s| s.tray_icon)
+}
+
+pub fn chats_changed() {
+    STALE.store(true, Ordering::SeqCst);
+}
+
+fn truncate(title: &str) -> String {
+    let title = title.trim();
+    if title.chars().count() <= MAX_TITLE_CHARS {
+        return title.to_string();
+    }
+    format!("{}…", title.chars().take(MAX_TITLE_CHARS - 1).collect::<String>())
+}
+
+// (id, title) of the latest chats; None while the library is locked
+pub fn recent_chats(app: &tauri::AppHandle) -> Option<Vec<(String, String)>> {
+    let mut sessions = crate::chats::list_chats(app.clone()).ok()?;
+    let updated = |s: &serde_json::Value| s["updatedAt"].as_f64().or(s["timestamp"].as_f64()).unwrap_or(0.0);
+    sessions.sort_by(|a, b| updated(b).total_cmp(&updated(a)));
+    let recent = sessions.iter().filter(|s| s["conflictOf"].is_null()).take(RECENT_CHATS);
+    Some(
+        recent
+            .filter_map(|s| {
+                let title = s["title"].as_str().filter(|t| !t.trim().is_empty()).unwrap_or("Untitled chat");
+                Some((s["id"].as_str()?.to_string(), truncate(title)))
+            })
+            .collect(),
+    )
+}
+
+fn sync_label(app: &tauri::AppHandle) -> String {
+    if settings::load(app).map_or(true, |s| s.sync.backend == SyncBackend::Off) {
+        return "Sync is off".to_string();
+    }
+    let status = sync::sync_status();
+    match (status.state, status.last_synced) {
+        (SyncState::Syncing, _) => "Syncing…".to_string(),
+        (SyncState::Error, _) => "Sync failed".to_string(),
+        (SyncState::Idle, Some(millis)) => match chrono::DateTime::from_timestamp_millis(millis as i64) {
+            Some(time) => format!("Synced at {}", time.with_timezone(&chrono::Local).format("%H:%M")),
+            None => "Synced".to_string(),
+        },
+        (SyncState::Idle, None) => "Not synced yet".to_string(),
+    }
+}
+
+fn menu(app: &tauri::AppHandle) -> tauri::Result<Menu<Wry>> {
+    let menu = Menu::new(app)?;
+    menu.append(&MenuItem::with_id(app, "new-chat", "New Chat", true, None::<&str>)?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    match recent_chats(app) {
+        Some(chats) => {
+            for (id, title) in chats {
+                menu.append(&MenuItem::with_id(app, format!("{}{}", CHAT_PREFIX, id), title, true, None::<&str>)?)?;
+            }
+        }
+        None => menu.append(&MenuItem::with_id(app, "locked", "Unlock Anchor to see chats", false, None::<&str>)?)?,
+    }
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    let syncing = sync::sync_status().state == SyncState::Syncing;
+    menu.append(&MenuItem::with_id(app, "sync-status", sync_label(app), false, None::<&str>)?)?;
+    let backend_on = settings::load(app).is_ok_and(|s| s.sync.backend != SyncBackend::Off);
+    menu.append(&MenuItem::with_id(app, "sync-now", "Sync Now", backend_on && !syncing, None::<&str>)?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "show", "Show Anchor", true, None::<&str>)?)?;
+    menu.append(&MenuItem::with_id(app, "quit", "Quit Anchor", true, None::<&str>)?)?;
+    Ok(menu)
+}
+
+// Brings the main window back, opening it again if it was closed
+pub fn show_window(app: &tauri::AppHandle) -> Result<(), String> {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        return window.set_focus().map_err(|e| e.to_string());
+    }
+    let mut config = app.config().app.windows.first().cloned().ok_or("No window is configured")?;
+    // Held back in the config when launched at login, see autostart.rs
+    config.visible = true;
+    let window = tauri::WebviewWindowBuilder::from_config(app, &config)
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("Failed to open the window: {}", e))?;
+    crate::window_state::restore(&window);
+    Ok(())
+}
+
+fn act(app: &tauri::AppHandle, action: TrayAction) {
+    let open = app.get_webview_window(MAIN_WINDOW).is_some();
+    if let Err(e) = show_window(app) {
+        log::warn!("{}", e);
+        return;
+    }
+    if open {
+        let _ = app.emit(ACTION_EVENT, action);
+    } else if let Ok(mut pending) = PENDING.lock() {
+        *pending = Some(action);
+    }
+}
+
+fn on_menu_event(app: &tauri::AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    if let Some(chat_id) = id.strip_prefix(CHAT_PREFIX) {
+        act(app, TrayAction::OpenChat { chat_id: chat_id.to_string() });
+        return;
+    }
+    match id {
+        "new-chat" => act(app, TrayAction::NewChat),
+        "show" => {
+            if let Err(e) = show_window(app) {
+                log::warn!("{}", e);
+            }
+        }
+        "sync-now" => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = sync::sync_now(app).await {
+                    log::warn!("Sync from the tray failed: {}", e);
+                }
+            });
+        }
+        "quit" => app.exit(0),
+        _ => {}
+    }
+}
+
+// Adds the icon and keeps its menu current
+pub fn init(app: &tauri::AppHandle) {
+    if !enabled(app) {
+        return;
+    }
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Anchor")
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| crate::mini_chat::on_tray_event(tray.app_handle(), event));
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    let tray = match menu(app).and_then(|menu| builder.menu(&menu).build(app)) {
+        Ok(tray) => tray,
+        Err(e) => {
+            log::warn!("Failed to add the tray icon: {}", e);
+            return;
+        }
+    };
+    STALE.store(false, Ordering::SeqCst);
+    crate::mini_chat::configure(app);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let mut shown = sync_label(&app);
+        loop {
+            std::thread::sleep(REFRESH_INTERVAL);
+            let label = sync_label(&app);
+            if !STALE.swap(false, Ordering::SeqCst) && label == shown {
+                continue;
+            }
+            shown = label;
+            match menu(&app) {
+                Ok(menu) => {
+                    if let Err(e) = tray.set_menu(Some(menu)) {
+                        log::warn!("Failed to update the tray menu: {}", e);
+                    }
+                }
+                Err(e) => log::warn!("Failed to update the tray menu: {}", e),
+            }
+        }
+    });
+}
+
+// Keeps Anchor in the tray when its last window closes, unless it was told
+// to quit
+pub fn on_run_event(app: &tauri::AppHandle, event: &tauri::RunEvent) {
+    if let tauri::RunEvent::ExitRequested { code: None, api, .. } = event {
+        if enabled(app) && app.tray_by_id(TRAY_ID).is_some() {
+            api.prevent_exit();
+        }
+    }
+}
+
+// What the user picked in the tray before the window was open
+#[tauri::command]
+pub fn take_tray_action() -> Option<TrayAction> {
+    PENDING.lock().ok().and_then(|mut pending| pending.take())
+}
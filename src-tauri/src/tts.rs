@@ -0,0 +1,350 @@
+use std::io::{Cursor, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+use tauri::{Emitter, Manager, State};
+
+use crate::settings::{self, ProviderConfig, ProviderKind};
+
+const SYSTEM_VOICE_PREFIX: &str = "system";
+const OPENAI_VOICES: &[&str] = &["alloy", "ash", "coral", "echo", "fable", "nova", "onyx", "sage", "shimmer"];
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Voice {
+    // "<system|provider id>:<voice name>", the form `speak` accepts
+    pub id: String,
+    pub name: String,
+    pub provider_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpeechProgress {
+    utterance_id: u64,
+    index: usize,
+    total: usize,
+    text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpeechFinished {
+    utterance_id: u64,
+    cancelled: bool,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct SpeechState {
+    next_id: AtomicU64,
+    current: Mutex<Option<Arc<AtomicBool>>>,
+}
+
+impl SpeechState {
+    fn cancel_current(&self) {
+        if let Ok(mut current) = self.current.lock() {
+            if let Some(cancelled) = current.take() {
+                cancelled.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+enum Engine {
+    System(Option<String>),
+    Provider(ProviderConfig, String, String),
+}
+
+// Drops code blocks, reasoning and markdown syntax that sounds awful read aloud.
+pub fn speakable_text(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut in_code = false;
+    let mut in_think = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if trimmed.contains("<think>") {
+            in_think = true;
+        }
+        if in_think {
+            if trimmed.contains("</think>") {
+                in_think = false;
+            }
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+
+        let cleaned: String = trimmed
+            .trim_start_matches(['#', '>', '-', '*', '+'])
+            .chars()
+            .filter(|c| !matches!(c, '*' | '_' | '`' | '#'))
+            .collect();
+        if !cleaned.trim().is_empty() {
+            out.push_str(cleaned.trim());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// Sentence-sized chunks keep latency low and give us natural progress points.
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        current.push(c);
+        let boundary = matches!(c, '.' | '!' | '?' | '\n' | '。' | '！' | '？');
+        if boundary && current.trim().len() > 1 {
+            chunks.push(current.trim().to_string());
+            current.clear();
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current.trim().to_string());
+    }
+    chunks
+}
+
+fn system_command(voice: Option<&str>) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("say");
+        if let Some(voice) = voice {
+            cmd.args(["-v", voice]);
+        }
+        // Read from stdin so quotes in the text never need escaping
+        cmd.args(["-f", "-"]);
+        cmd
+    } else if cfg!(windows) {
+        let select = voice
+            .map(|v| format!("$s.SelectVoice('{}');", v.replace('\'', "''")))
+            .unwrap_or_default();
+        let mut cmd = Command::new("powershell");
+        cmd.args([
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "Add-Type -AssemblyName System.Speech; $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; {} $s.Speak([Console]::In.ReadToEnd())",
+                select
+            ),
+        ]);
+        cmd
+    } else {
+        let mut cmd = Command::new("espeak-ng");
+        if let Some(voice) = voice {
+            cmd.args(["-v", voice]);
+        }
+        cmd.arg("--stdin");
+        cmd
+    }
+}
+
+fn wait_or_cancel(mut child: Child, cancelled: &AtomicBool) -> Result<(), String> {
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(());
+        }
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("Speech synthesizer exited with {}", status)),
+            None => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+fn speak_system(text: &str, voice: Option<&str>, cancelled: &AtomicBool) -> Result<(), String> {
+    let mut child = system_command(voice)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("No system speech synthesizer available: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    wait_or_cancel(child, cancelled)
+}
+
+async fn synthesize(provider: &ProviderConfig, model: &str, voice: &str, text: &str) -> Result<Vec<u8>, String> {
+    let mut request = crate::http::client()
+        .post(format!("{}/audio/speech", provider.openai_base()))
+        .json(&json!({ "model": model, "voice": voice, "input": text, "response_format": "mp3" }));
+    if !provider.api_key.trim().is_empty() {
+        request = request.bearer_auth(provider.api_key.trim());
+    }
+
+    let response = crate::http::send(request).await.map_err(|e| format!("Failed to reach {}: {}", provider.name, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{} TTS returned {}", provider.name, response.status()));
+    }
+    Ok(response.bytes().await.map_err(|e| e.to_string())?.to_vec())
+}
+
+fn play_audio(bytes: Vec<u8>, cancelled: &AtomicBool) -> Result<(), String> {
+    let (_stream, handle) = rodio::OutputStream::try_default().map_err(|e| format!("No audio output: {}", e))?;
+    let sink = rodio::Sink::try_new(&handle).map_err(|e| e.to_string())?;
+    let source = rodio::Decoder::new(Cursor::new(bytes)).map_err(|e| format!("Failed to decode speech: {}", e))?;
+    sink.append(source);
+
+    while !sink.empty() {
+        if cancelled.load(Ordering::SeqCst) {
+            sink.stop();
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+fn speak_all(app: &tauri::AppHandle, id: u64, engine: &Engine, chunks: &[String], cancelled: &AtomicBool) -> Result<(), String> {
+    for (index, chunk) in chunks.iter().enumerate() {
+        if cancelled.load(Ordering::SeqCst) {
+            break;
+        }
+        let _ = app.emit(
+            "tts-progress",
+            SpeechProgress { utterance_id: id, index, total: chunks.len(), text: chunk.clone() },
+        );
+
+        match engine {
+            Engine::System(voice) => speak_system(chunk, voice.as_deref(), cancelled)?,
+            Engine::Provider(provider, model, voice) => {
+                let audio = tauri::async_runtime::block_on(synthesize(provider, model, voice, chunk))?;
+                play_audio(audio, cancelled)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn system_voices() -> Vec<String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("say").args(["-v", "?"]).output()
+    } else if cfg!(windows) {
+        Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-Command",
+                "Add-Type -AssemblyName System.Speech; (New-Object System.Speech.Synthesis.SpeechSynthesizer).GetInstalledVoices() | ForEach-Object { $_.VoiceInfo.Name }",
+            ])
+            .output()
+    } else {
+        Command::new("espeak-ng").arg("--voices").output()
+    };
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    if cfg!(target_os = "macos") {
+        // "Samantha            en_US    # Hello, my name is Samantha."
+        stdout
+            .lines()
+            .filter_map(|line| line.split("  ").next().map(|name| name.trim().to_string()))
+            .filter(|name| !name.is_empty())
+            .collect()
+    } else if cfg!(windows) {
+        stdout.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect()
+    } else {
+        // "Pty Language       Age/Gender VoiceName          File ..." - keep the language column
+        stdout
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().nth(1).map(str::to_string))
+            .collect()
+    }
+}
+
+#[tauri::command]
+pub async fn list_voices(app: tauri::AppHandle) -> Result<Vec<Voice>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let mut voices: Vec<Voice> = tauri::async_runtime::spawn_blocking(system_voices)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|name| Voice {
+            id: format!("{}:{}", SYSTEM_VOICE_PREFIX, name),
+            name,
+            provider_id: None,
+        })
+        .collect();
+
+    for provider in settings::load(&app)?.providers {
+        if provider.enabled && provider.kind == ProviderKind::OpenAi && !provider.api_key.is_empty() {
+            voices.extend(OPENAI_VOICES.iter().map(|voice| Voice {
+                id: format!("{}:{}", provider.id, voice),
+                name: format!("{} ({})", voice, provider.name),
+                provider_id: Some(provider.id.clone()),
+            }));
+        }
+    }
+    Ok(voices)
+}
+
+#[tauri::command]
+pub fn speak(
+    app: tauri::AppHandle,
+    state: State<'_, SpeechState>,
+    text: String,
+    voice: Option<String>,
+) -> Result<u64, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let settings = settings::load(&app)?;
+    let voice = voice.or(settings.speech.voice.clone());
+
+    let engine = match voice.as_deref().and_then(|v| v.split_once(':')) {
+        None => Engine::System(None),
+        Some((SYSTEM_VOICE_PREFIX, name)) => Engine::System(Some(name.to_string())),
+        Some((provider_id, name)) => {
+            let provider = settings
+                .provider(provider_id)
+                .cloned()
+                .ok_or_else(|| format!("Unknown provider: {}", provider_id))?;
+            Engine::Provider(provider, settings.speech.tts_model.clone(), name.to_string())
+        }
+    };
+
+    let chunks = split_sentences(&speakable_text(&text));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    state.cancel_current();
+    *state.current.lock().map_err(|e| e.to_string())? = Some(cancelled.clone());
+    let id = state.next_id.fetch_add(1, Ordering::SeqCst);
+
+    std::thread::spawn(move || {
+        let result = speak_all(&app, id, &engine, &chunks, &cancelled);
+        let was_cancelled = cancelled.load(Ordering::SeqCst);
+
+        // Only clear the slot if a newer utterance hasn't already replaced us
+        let state = app.state::<SpeechState>();
+        if let Ok(mut current) = state.current.lock() {
+            if current.as_ref().is_some_and(|c| Arc::ptr_eq(c, &cancelled)) {
+                *current = None;
+            }
+        }
+
+        let _ = app.emit(
+            "tts-finished",
+            SpeechFinished { utterance_id: id, cancelled: was_cancelled, error: result.err() },
+        );
+    });
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn stop_speaking(state: State<'_, SpeechState>) {
+    state.cancel_current();
+}
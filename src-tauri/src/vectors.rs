@@ -0,0 +1,622 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tauri::Manager;
+
+use crate::shred;
+
+// HNSW parameters: links per node on the upper layers and on layer 0, and
+// the candidate list sizes while building and searching
+const M: usize = 16;
+const M0: usize = 32;
+const EF_CONSTRUCTION: usize = 100;
+const EF_SEARCH: usize = 100;
+const MAX_LEVEL: usize = 12;
+// Below this many live vectors a linear scan is exact and about as fast
+const EXACT_BELOW: usize = 2000;
+// The graph is rebuilt once this share of its nodes has been deleted
+const MAX_DELETED_RATIO: f64 = 0.3;
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorRecord {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorHit {
+    pub id: String,
+    // Cosine similarity, 1.0 being identical
+    pub score: f32,
+    pub metadata: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    id: String,
+    #[serde(default)]
+    metadata: Map<String, Value>,
+    // Deleted nodes stay in the graph until the next rebuild so searches can
+    // still route through them
+    #[serde(default)]
+    deleted: bool,
+    level: usize,
+    neighbors: Vec<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    version: u32,
+    dimensions: usize,
+    entry: Option<u32>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Scored {
+    distance: f32,
+    node: u32,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Exact match on every key; an array in the filter matches any of its values
+pub fn matches(metadata: &Map<String, Value>, filter: &Map<String, Value>) -> bool {
+    filter.iter().all(|(key, expected)| {
+        let actual = metadata.get(key).unwrap_or(&Value::Null);
+        match expected {
+            Value::Array(options) => options.contains(actual),
+            expected => actual == expected,
+        }
+    })
+}
+
+#[derive(Default)]
+struct Collection {
+    dimensions: usize,
+    entry: Option<u32>,
+    nodes: Vec<Node>,
+    // Normalized, `dimensions` floats per node
+    vectors: Vec<f32>,
+    live: HashMap<String, u32>,
+}
+
+impl Collection {
+    fn vector(&self, node: u32) -> &[f32] {
+        let start = node as usize * self.dimensions;
+        &self.vectors[start..start + self.dimensions]
+    }
+
+    fn distance(&self, query: &[f32], node: u32) -> f32 {
+        1.0 - dot(query, self.vector(node))
+    }
+
+    // Drawn from a hash of the id and position rather than an RNG
+    fn level_for(id: &str, node: u32) -> usize {
+        let digest = Sha256::digest(format!("{}:{}", id, node).as_bytes());
+        let bits = u64::from_le_bytes(digest[..8].try_into().unwrap_or_default());
+        let uniform = (bits >> 11) as f64 / (1u64 << 53) as f64;
+        let level = -(1.0 - uniform).ln() / (M as f64).ln();
+        (level as usize).min(MAX_LEVEL)
+    }
+
+    fn search_layer(&self, query: &[f32], entries: &[u32], ef: usize, level: usize) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entries.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut results = BinaryHeap::new();
+        for &node in entries {
+            let scored = Scored { distance: self.distance(query, node), node };
+            candidates.push(Reverse(scored));
+            results.push(scored);
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let worst = results.peek().map_or(f32::MAX, |s: &Scored| s.distance);
+            if current.distance > worst && results.len() >= ef {
+                break;
+            }
+            let Some(neighbors) = self.nodes[current.node as usize].neighbors.get(level) else {
+                continue;
+            };
+            for &neighbor in neighbors {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.distance(query, neighbor);
+                let worst = results.peek().map_or(f32::MAX, |s| s.distance);
+                if results.len() < ef || distance < worst {
+                    let scored = Scored { distance, node: neighbor };
+                    candidates.push(Reverse(scored));
+                    results.push(scored);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+        results.into_sorted_vec()
+    }
+
+    // The HNSW heuristic: prefer candidates that aren't closer to an already
+    // picked neighbor than to the node itself, which keeps links spread out.
+    // The closest of the rest fill any remaining slots.
+    fn select_neighbors(&self, candidates: &[Scored], m: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(m);
+        let mut pruned = Vec::new();
+        for candidate in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let vector = self.vector(candidate.node);
+            if selected.iter().all(|&s| 1.0 - dot(vector, self.vector(s)) > candidate.distance) {
+                selected.push(candidate.node);
+            } else {
+                pruned.push(candidate.node);
+            }
+        }
+        selected.extend(pruned.into_iter().take(m - selected.len()));
+        selected
+    }
+
+    fn descend(&self, query: &[f32], entry: u32, down_to: usize) -> u32 {
+        let top = self.nodes[entry as usize].level;
+        let mut current = entry;
+        for level in (down_to + 1..=top).rev() {
+            if let Some(closest) = self.search_layer(query, &[current], 1, level).first() {
+                current = closest.node;
+            }
+        }
+        current
+    }
+
+    fn insert(&mut self, id: String, vector: Vec<f32>, metadata: Map<String, Value>) {
+        let node = self.nodes.len() as u32;
+        let level = Self::level_for(&id, node);
+        self.vectors.extend_from_slice(&vector);
+        self.live.insert(id.clone(), node);
+        self.nodes.push(Node { id, metadata, deleted: false, level, neighbors: vec![Vec::new(); level + 1] });
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+        let top = self.nodes[entry as usize].level;
+        let mut entries = vec![self.descend(&vector, entry, level)];
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&vector, &entries, EF_CONSTRUCTION, layer);
+            let m = if layer == 0 { M0 } else { M };
+            let neighbors = self.select_neighbors(&found, m);
+            for &neighbor in &neighbors {
+                let links = &mut self.nodes[neighbor as usize].neighbors[layer];
+                links.push(node);
+                if links.len() > m {
+                    let base = self.vector(neighbor);
+                    let mut scored: Vec<Scored> = self.nodes[neighbor as usize].neighbors[layer]
+                        .iter()
+                        .map(|&n| Scored { distance: 1.0 - dot(base, self.vector(n)), node: n })
+                        .collect();
+                    scored.sort();
+                    self.nodes[neighbor as usize].neighbors[layer] = self.select_neighbors(&scored, m);
+                }
+            }
+            self.nodes[node as usize].neighbors[layer] = neighbors;
+            entries = found.iter().map(|s| s.node).collect();
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        let Some(node) = self.live.remove(id) else {
+            return false;
+        };
+        self.nodes[node as usize].deleted = true;
+        true
+    }
+
+    fn upsert(&mut self, record: VectorRecord) -> Result<(), String> {
+        if self.live.is_empty() && self.nodes.is_empty() {
+            self.dimensions = record.vector.len();
+        }
+        if record.vector.len() != self.dimensions || self.dimensions == 0 {
+            return Err(format!("Expected {} dimensions, got {}", self.dimensions, record.vector.len()));
+        }
+        self.remove(&record.id);
+        self.insert(record.id, normalize(record.vector), record.metadata);
+        Ok(())
+    }
+
+    fn compact_if_needed(&mut self) {
+        let deleted = self.nodes.len() - self.live.len();
+        if deleted == 0 || (deleted as f64) < self.nodes.len() as f64 * MAX_DELETED_RATIO {
+            return;
+        }
+        self.compact();
+    }
+
+    // Drops deleted nodes by inserting the live ones into a fresh graph
+    fn compact(&mut self) {
+        let mut fresh = Collection { dimensions: self.dimensions, ..Default::default() };
+        for (index, node) in std::mem::take(&mut self.nodes).into_iter().enumerate() {
+            if !node.deleted {
+                let vector = self.vector(index as u32).to_vec();
+                fresh.insert(node.id, vector, node.metadata);
+            }
+        }
+        *self = fresh;
+    }
+
+    fn exact(&self, query: &[f32], k: usize, filter: &Map<String, Value>) -> Vec<Scored> {
+        let mut scored: Vec<Scored> = self
+            .live
+            .values()
+            .filter(|&&node| matches(&self.nodes[node as usize].metadata, filter))
+            .map(|&node| Scored { distance: self.distance(query, node), node })
+            .collect();
+        scored.sort();
+        scored.truncate(k);
+        scored
+    }
+
+    // Approximate through the graph; with a filter that leaves too few hits,
+    // falls back to scanning the matching vectors exactly
+    fn search(&self, query: &[f32], k: usize, filter: &Map<String, Value>) -> Vec<Scored> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        if self.live.len() <= EXACT_BELOW {
+            return self.exact(query, k, filter);
+        }
+        let ef = if filter.is_empty() { EF_SEARCH.max(k) } else { EF_SEARCH.max(k * 4) };
+        let start = self.descend(query, entry, 0);
+        let hits: Vec<Scored> = self
+            .search_layer(query, &[start], ef, 0)
+            .into_iter()
+            .filter(|s| {
+                let node = &self.nodes[s.node as usize];
+                !node.deleted && matches(&node.metadata, filter)
+            })
+            .take(k)
+            .collect();
+        if hits.len() < k && !filter.is_empty() {
+            return self.exact(query, k, filter);
+        }
+        hits
+    }
+
+    fn hits(&self, scored: Vec<Scored>) -> Vec<VectorHit> {
+        scored
+            .into_iter()
+            .map(|s| {
+                let node = &self.nodes[s.node as usize];
+                VectorHit { id: node.id.clone(), score: 1.0 - s.distance, metadata: node.metadata.clone() }
+            })
+            .collect()
+    }
+}
+
+// Collections are loaded on first use and kept in memory; every change is
+// written straight back.
+#[derive(Default)]
+pub struct VectorState {
+    collections: Mutex<HashMap<String, Arc<Mutex<Collection>>>>,
+}
+
+fn collection_dir(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!("Invalid vector collection name: {}", name));
+    }
+    Ok(crate::app_data_dir(app)?.join("vectors").join(name))
+}
+
+fn read_collection(app: &tauri::AppHandle, name: &str) -> Result<Collection, String> {
+    let dir = collection_dir(app, name)?;
+    let manifest_path = dir.join("index.json");
+    if !manifest_path.exists() {
+        return Ok(Collection::default());
+    }
+    let content = fs::read(&manifest_path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    let content = crate::encryption::open(content)?;
+    let manifest: Manifest =
+        serde_json::from_slice(&content).map_err(|e| format!("Invalid vector index {}: {}", name, e))?;
+    let bytes = fs::read(dir.join("vectors.bin")).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    if bytes.len() != manifest.nodes.len() * manifest.dimensions * 4 {
+        return Err(format!("Vector index {} is corrupt, reindex it", name));
+    }
+
+    let vectors = bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+    let live = manifest
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| !node.deleted)
+        .map(|(index, node)| (node.id.clone(), index as u32))
+        .collect();
+    Ok(Collection { dimensions: manifest.dimensions, entry: manifest.entry, nodes: manifest.nodes, vectors, live })
+}
+
+fn write_collection(app: &tauri::AppHandle, name: &str, collection: &Collection) -> Result<(), String> {
+    write_collection_to(app, &collection_dir(app, name)?, collection)
+}
+
+fn write_collection_to(app: &tauri::AppHandle, dir: &Path, collection: &Collection) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create vector index: {}", e))?;
+
+    let bytes: Vec<u8> = collection.vectors.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let manifest = serde_json::to_vec(&Manifest {
+        version: FORMAT_VERSION,
+        dimensions: collection.dimensions,
+        entry: collection.entry,
+        nodes: collection.nodes.clone(),
+    })
+    .map_err(|e| e.to_string())?;
+    // The metadata holds the indexed text, so it's sealed while chat
+    // encryption is on; the vectors alone aren't
+    let manifest = crate::encryption::seal(app, manifest)?;
+
+    // Vectors first: a manifest is only ever next to vectors that fit it
+    let write = |file: &str, content: &[u8]| {
+        let partial = dir.join(format!("{}.part", file));
+        fs::write(&partial, content)
+            .and_then(|_| shred::replace(app, &partial, &dir.join(file)))
+            .map_err(|e| format!("Failed to write vector index: {}", e))
+    };
+    write("vectors.bin", &bytes)?;
+    write("index.json", &manifest)
+}
+
+fn collection(app: &tauri::AppHandle, name: &str) -> Result<Arc<Mutex<Collection>>, String> {
+    let state = app.state::<VectorState>();
+    let mut collections = state.collections.lock().map_err(|e| e.to_string())?;
+    if let Some(collection) = collections.get(name) {
+        return Ok(collection.clone());
+    }
+    let collection = Arc::new(Mutex::new(read_collection(app, name)?));
+    collections.insert(name.to_string(), collection.clone());
+    Ok(collection)
+}
+
+// Inserts the records, replacing any with the same id
+pub fn upsert(app: &tauri::AppHandle, name: &str, records: Vec<VectorRecord>) -> Result<(), String> {
+    let collection = collection(app, name)?;
+    let mut collection = collection.lock().map_err(|e| e.to_string())?;
+    for record in records {
+        collection.upsert(record)?;
+    }
+    collection.compact_if_needed();
+    write_collection(app, name, &collection)
+}
+
+// Replaces the metadata of existing vectors, leaving the vectors as they are.
+// Ids that aren't in the collection are skipped.
+pub fn set_metadata(
+    app: &tauri::AppHandle,
+    name: &str,
+    entries: Vec<(String, Map<String, Value>)>,
+) -> Result<(), String> {
+    let collection = collection(app, name)?;
+    let mut collection = collection.lock().map_err(|e| e.to_string())?;
+    let mut changed = false;
+    for (id, metadata) in entries {
+        if let Some(&node) = collection.live.get(&id) {
+            let node = &mut collection.nodes[node as usize];
+            changed |= node.metadata != metadata;
+            node.metadata = metadata;
+        }
+    }
+    if changed {
+        write_collection(app, name, &collection)?;
+    }
+    Ok(())
+}
+
+pub fn delete(app: &tauri::AppHandle, name: &str, ids: &[String]) -> Result<usize, String> {
+    let collection = collection(app, name)?;
+    let mut collection = collection.lock().map_err(|e| e.to_string())?;
+    let removed = ids.iter().filter(|id| collection.remove(id)).count();
+    if removed > 0 {
+        // Deleted nodes keep their metadata until compacted, so with secure
+        // deletion on they go right away
+        if shred::enabled(app) {
+            collection.compact();
+        } else {
+            collection.compact_if_needed();
+        }
+        write_collection(app, name, &collection)?;
+    }
+    Ok(removed)
+}
+
+// Ids of the live vectors whose metadata matches the filter
+pub fn ids(app: &tauri::AppHandle, name: &str, filter: &Map<String, Value>) -> Result<Vec<String>, String> {
+    let collection = collection(app, name)?;
+    let collection = collection.lock().map_err(|e| e.to_string())?;
+    Ok(collection
+        .live
+        .values()
+        .map(|&node| &collection.nodes[node as usize])
+        .filter(|node| matches(&node.metadata, filter))
+        .map(|node| node.id.clone())
+        .collect())
+}
+
+pub fn delete_matching(app: &tauri::AppHandle, name: &str, filter: &Map<String, Value>) -> Result<usize, String> {
+    let ids = ids(app, name, filter)?;
+    delete(app, name, &ids)
+}
+
+pub fn search(
+    app: &tauri::AppHandle,
+    name: &str,
+    query: Vec<f32>,
+    k: usize,
+    filter: &Map<String, Value>,
+) -> Result<Vec<VectorHit>, String> {
+    let collection = collection(app, name)?;
+    let collection = collection.lock().map_err(|e| e.to_string())?;
+    if collection.live.is_empty() {
+        return Ok(Vec::new());
+    }
+    if query.len() != collection.dimensions {
+        return Err(format!("Expected a {}-dimensional query, got {}", collection.dimensions, query.len()));
+    }
+    let scored = collection.search(&normalize(query), k, filter);
+    Ok(collection.hits(scored))
+}
+
+// Live vectors in the collection
+pub fn count(app: &tauri::AppHandle, name: &str) -> Result<usize, String> {
+    let collection = collection(app, name)?;
+    let collection = collection.lock().map_err(|e| e.to_string())?;
+    Ok(collection.live.len())
+}
+
+// Names of the collections on disk
+pub fn collections(app: &tauri::AppHandle) -> Result<Vec<String>, String> {
+    let Ok(entries) = fs::read_dir(crate::app_data_dir(app)?.join("vectors")) else {
+        return Ok(Vec::new());
+    };
+    Ok(entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect())
+}
+
+// Swaps in a collection of just these records. It's built and written next
+// to the old one first, so until the swap searches keep using the old one,
+// and a failure leaves it as it was.
+pub fn replace(app: &tauri::AppHandle, name: &str, records: Vec<VectorRecord>) -> Result<(), String> {
+    let dir = collection_dir(app, name)?;
+    let data_dir = crate::app_data_dir(app)?;
+    let (staged, retired) = (data_dir.join("vectors.next").join(name), data_dir.join("vectors.old").join(name));
+    for leftover in [&staged, &retired] {
+        if leftover.exists() {
+            fs::remove_dir_all(leftover).map_err(|e| format!("Failed to clear {}: {}", leftover.display(), e))?;
+        }
+    }
+
+    let mut collection = Collection::default();
+    for record in records {
+        collection.upsert(record)?;
+    }
+    write_collection_to(app, &staged, &collection)?;
+
+    let state = app.state::<VectorState>();
+    let mut collections = state.collections.lock().map_err(|e| e.to_string())?;
+    // Lets a write already under way finish before its directory moves
+    let old = collections.get(name).cloned();
+    let _old = old.as_ref().map(|old| old.lock()).transpose().map_err(|e| e.to_string())?;
+    if dir.exists() {
+        fs::create_dir_all(retired.parent().unwrap_or(&data_dir)).map_err(|e| e.to_string())?;
+        fs::rename(&dir, &retired).map_err(|e| format!("Failed to replace vector index: {}", e))?;
+    }
+    if let Err(e) = fs::create_dir_all(dir.parent().unwrap_or(&data_dir)).and_then(|_| fs::rename(&staged, &dir)) {
+        let _ = fs::rename(&retired, &dir);
+        return Err(format!("Failed to replace vector index: {}", e));
+    }
+    collections.insert(name.to_string(), Arc::new(Mutex::new(collection)));
+    // The old manifest holds the indexed text too
+    for file in ["vectors.bin", "index.json"] {
+        let _ = shred::remove_file(app, &retired.join(file));
+    }
+    if let Err(e) = fs::remove_dir_all(&retired) {
+        log::warn!("Failed to remove the old vector index {}: {}", name, e);
+    }
+    Ok(())
+}
+
+pub fn drop_collection(app: &tauri::AppHandle, name: &str) -> Result<(), String> {
+    let dir = collection_dir(app, name)?;
+    app.state::<VectorState>().collections.lock().map_err(|e| e.to_string())?.remove(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to delete vector index: {}", e))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn upsert_vectors(
+    app: tauri::AppHandle,
+    collection: String,
+    records: Vec<VectorRecord>,
+) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || upsert(&app, &collection, records))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Deletes the given ids, or everything matching `filter` when no ids are given
+#[tauri::command]
+pub async fn delete_vectors(
+    app: tauri::AppHandle,
+    collection: String,
+    ids: Option<Vec<String>>,
+    filter: Option<Map<String, Value>>,
+) -> Result<usize, String> {
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || match (ids, filter) {
+        (Some(ids), _) => delete(&app, &collection, &ids),
+        (None, Some(filter)) if !filter.is_empty() => delete_matching(&app, &collection, &filter),
+        _ => Err("Pass ids or a non-empty filter".to_string()),
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn search_vectors(
+    app: tauri::AppHandle,
+    collection: String,
+    vector: Vec<f32>,
+    k: Option<usize>,
+    filter: Option<Map<String, Value>>,
+) -> Result<Vec<VectorHit>, String> {
+    // Hits carry their text, which for history memory is chat content
+    crate::app_lock::ensure_unlocked()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        search(&app, &collection, vector, k.unwrap_or(10), &filter.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
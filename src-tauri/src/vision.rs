@@ -0,0 +1,203 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use base64::Engine;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::ProviderConfig;
+
+const JPEG_QUALITY: u8 = 85;
+// Tried in turn before giving up more resolution
+const FALLBACK_QUALITIES: &[u8] = &[75, 60];
+// Below this the picture stops being useful to a model
+const MIN_EDGE: u32 = 512;
+
+// What an endpoint takes without rescaling on its side or rejecting the
+// request; sizes are of the image before base64 encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageLimits {
+    pub max_edge: u32,
+    pub max_bytes: usize,
+}
+
+impl ImageLimits {
+    pub fn for_provider(provider: &ProviderConfig) -> Self {
+        const MB: usize = 1024 * 1024;
+        let base = provider.api_base().to_lowercase();
+        let (max_edge, max_bytes) = if provider.is_local() {
+            // Local vision models tile small patches; bigger only costs time
+            (1344, 10 * MB)
+        } else if base.contains("anthropic.com") {
+            (1568, 5 * MB)
+        } else if base.contains("openai.com") {
+            (2048, 20 * MB)
+        } else if base.contains("googleapis.com") {
+            (3072, 7 * MB)
+        } else if base.contains("groq.com") {
+            // 4 MB once base64 encoded
+            (2048, 3 * MB)
+        } else if base.contains("mistral.ai") {
+            (2048, 10 * MB)
+        } else {
+            (2048, 5 * MB)
+        };
+        Self {
+            max_edge: provider.max_image_edge.unwrap_or(max_edge).max(MIN_EDGE),
+            max_bytes: provider.max_image_bytes.unwrap_or(max_bytes),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageInput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // Id in the attachment store, resolved to `path` before sending
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment_id: Option<String>,
+}
+
+pub struct EncodedImage {
+    pub mime: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl EncodedImage {
+    pub fn data_url(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.mime,
+            base64::engine::general_purpose::STANDARD.encode(&self.data)
+        )
+    }
+}
+
+fn mime_for(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Gif => Some("image/gif"),
+        ImageFormat::WebP => Some("image/webp"),
+        _ => None,
+    }
+}
+
+// Passes images through untouched when they're within `limits`, otherwise
+// downscales and re-encodes to JPEG (or PNG when there's alpha), lowering the
+// quality and then the resolution until the result fits. Re-encoding never
+// carries metadata over; `strip_metadata` removes it from passed-through
+// images too.
+pub fn encode_bytes(bytes: Vec<u8>, limits: ImageLimits, strip_metadata: bool) -> Result<EncodedImage, String> {
+    let format = image::guess_format(&bytes).map_err(|e| format!("Unrecognized image format: {}", e))?;
+    let img = image::load_from_memory_with_format(&bytes, format)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    let (width, height) = img.dimensions();
+    let fits = width <= limits.max_edge && height <= limits.max_edge && bytes.len() <= limits.max_bytes;
+    if fits && mime_for(format).is_some() {
+        let data = if strip_metadata { crate::image_metadata::strip(bytes)? } else { bytes };
+        // Stripping may have re-encoded a rotated image
+        let mime = image::guess_format(&data).ok().and_then(mime_for).unwrap_or("image/jpeg");
+        return Ok(EncodedImage { mime, data });
+    }
+
+    let mut edge = limits.max_edge.min(width.max(height));
+    loop {
+        let scaled = if width > edge || height > edge {
+            img.resize(edge, edge, FilterType::Lanczos3)
+        } else {
+            img.clone()
+        };
+        let mut encoded = encode(&scaled)?;
+        if encoded.mime == "image/jpeg" {
+            for quality in FALLBACK_QUALITIES {
+                if encoded.data.len() <= limits.max_bytes {
+                    break;
+                }
+                encoded = encode_jpeg(&scaled, *quality)?;
+            }
+        }
+        if encoded.data.len() <= limits.max_bytes || edge <= MIN_EDGE {
+            return Ok(encoded);
+        }
+        edge = (edge * 3 / 4).max(MIN_EDGE);
+    }
+}
+
+fn encode_jpeg(img: &DynamicImage, quality: u8) -> Result<EncodedImage, String> {
+    let mut data = Vec::new();
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, quality);
+    img.to_rgb8()
+        .write_with_encoder(encoder)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(EncodedImage { mime: "image/jpeg", data })
+}
+
+// JPEG, or PNG when the image has transparency to keep.
+pub fn encode(img: &DynamicImage) -> Result<EncodedImage, String> {
+    let mut data = Vec::new();
+    if img.color().has_alpha() {
+        img.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode image: {}", e))?;
+        Ok(EncodedImage { mime: "image/png", data })
+    } else {
+        encode_jpeg(img, JPEG_QUALITY)
+    }
+}
+
+async fn encode_off_thread(bytes: Vec<u8>, limits: ImageLimits, strip_metadata: bool) -> Result<EncodedImage, String> {
+    tauri::async_runtime::spawn_blocking(move || encode_bytes(bytes, limits, strip_metadata))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+async fn read_file(path: PathBuf) -> Result<Vec<u8>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, String> {
+    let request = crate::http::client().get(url);
+    let response = crate::http::send(request)
+        .await
+        .map_err(|e| format!("Failed to download image: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Image download returned {}", response.status()));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok(bytes.to_vec())
+}
+
+// Builds an OpenAI-style `image_url` content part. Cloud providers fetch
+// remote URLs themselves; local engines only understand inline base64,
+// wherever they run. Metadata is only kept for providers on this machine or
+// when asked to.
+pub async fn image_part(
+    provider: &ProviderConfig,
+    input: &ImageInput,
+    keep_metadata: bool,
+) -> Result<serde_json::Value, String> {
+    let strip = !keep_metadata && !provider.is_on_device();
+    let limits = ImageLimits::for_provider(provider);
+    let url = match (&input.path, &input.url) {
+        (_, Some(url)) if !provider.is_local() => url.clone(),
+        (_, Some(url)) => encode_off_thread(download(url).await?, limits, strip).await?.data_url(),
+        (Some(path), None) => {
+            encode_off_thread(read_file(PathBuf::from(path)).await?, limits, strip).await?.data_url()
+        }
+        (None, None) => return Err("Image attachment has no path, URL or stored file".to_string()),
+    };
+
+    Ok(serde_json::json!({
+        "type": "image_url",
+        "image_url": { "url": url }
+    }))
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use kuchikiki::traits::TendrilSink;
+use kuchikiki::NodeRef;
+use serde::Serialize;
+use tauri_plugin_http::reqwest;
+
+use crate::office::markdown_table;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// Pages past this are almost always not articles
+const MAX_PAGE_BYTES: usize = 5 * 1024 * 1024;
+
+// Never part of the readable content
+const STRIP_TAGS: &str = "script, style, noscript, template, iframe, svg, canvas, form, button, input, select, \
+                          nav, footer, aside, dialog";
+// class/id fragments that mark page furniture rather than content
+const BOILERPLATE_HINTS: &[&str] = &[
+    "comment", "sidebar", "footer", "navbar", "menu", "share", "social", "advert", "promo", "related", "cookie",
+    "banner", "subscribe", "newsletter", "popup", "modal", "breadcrumb",
+];
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebPage {
+    // After redirects
+    pub url: String,
+    pub title: String,
+    pub byline: Option<String>,
+    pub site_name: Option<String>,
+    pub description: Option<String>,
+    pub published: Option<String>,
+    pub lang: Option<String>,
+    pub markdown: String,
+    pub word_count: usize,
+}
+
+fn meta(document: &NodeRef, names: &[&str]) -> Option<String> {
+    for name in names {
+        for attr in ["property", "name"] {
+            let selector = format!("meta[{}=\"{}\"]", attr, name);
+            let content = document
+                .select_first(&selector)
+                .ok()
+                .and_then(|m| m.attributes.borrow().get("content").map(|c| c.trim().to_string()));
+            if let Some(content) = content.filter(|c| !c.is_empty()) {
+                return Some(content);
+            }
+        }
+    }
+    None
+}
+
+fn tag(node: &NodeRef) -> Option<String> {
+    node.as_element().map(|e| e.name.local.to_string())
+}
+
+fn attr(node: &NodeRef, name: &str) -> Option<String> {
+    node.as_element().and_then(|e| e.attributes.borrow().get(name).map(str::to_string))
+}
+
+fn is_boilerplate(node: &NodeRef) -> bool {
+    let hints = format!("{} {}", attr(node, "class").unwrap_or_default(), attr(node, "id").unwrap_or_default());
+    let hints = hints.to_lowercase();
+    // Content wrappers often carry e.g. "article-comments-disabled" as well
+    let content = ["article", "content", "main", "post", "body"].iter().any(|h| hints.contains(h));
+    (!content && BOILERPLATE_HINTS.iter().any(|h| hints.contains(h)))
+        || attr(node, "hidden").is_some()
+        || attr(node, "aria-hidden").as_deref() == Some("true")
+}
+
+fn strip_boilerplate(document: &NodeRef) {
+    let mut unwanted: Vec<NodeRef> = document
+        .select(STRIP_TAGS)
+        .map(|nodes| nodes.map(|n| n.as_node().clone()).collect())
+        .unwrap_or_default();
+    unwanted.extend(document.descendants().filter(|n| n.as_element().is_some() && is_boilerplate(n)));
+    for node in unwanted {
+        node.detach();
+    }
+}
+
+// Readability-style scoring: every paragraph credits its parent and, at half
+// weight, its grandparent; the best-scoring container is the article.
+fn content_root(document: &NodeRef) -> NodeRef {
+    for selector in ["article", "main", "[role=main]"] {
+        if let Ok(node) = document.select_first(selector) {
+            if node.text_contents().split_whitespace().count() > 100 {
+                return node.as_node().clone();
+            }
+        }
+    }
+
+    let mut scores: HashMap<*const kuchikiki::Node, (NodeRef, f64)> = HashMap::new();
+    for paragraph in document.select("p, pre, td").into_iter().flatten() {
+        let text = paragraph.text_contents();
+        if text.trim().len() < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (text.len() as f64 / 100.0).min(3.0);
+        let parent = paragraph.as_node().parent();
+        let grandparent = parent.as_ref().and_then(|p| p.parent());
+        for (node, weight) in [(parent, 1.0), (grandparent, 0.5)] {
+            if let Some(node) = node {
+                let key = std::rc::Rc::as_ptr(&node.0);
+                scores.entry(key).or_insert((node, 0.0)).1 += score * weight;
+            }
+        }
+    }
+
+    scores
+        .into_values()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(node, _)| node)
+        .or_else(|| document.select_first("body").ok().map(|b| b.as_node().clone()))
+        .unwrap_or_else(|| document.clone())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+            }
+            space = false;
+            out.push(c);
+        }
+    }
+    // Edge spaces separate this text from its inline neighbours; blocks trim them
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+fn absolute(base: &reqwest::Url, href: &str) -> String {
+    base.join(href).map(|u| u.to_string()).unwrap_or_else(|_| href.to_string())
+}
+
+struct Markdown<'a> {
+    base: &'a reqwest::Url,
+    out: String,
+}
+
+impl Markdown<'_> {
+    fn inline(&self, node: &NodeRef) -> String {
+        self.inline_nodes(node.children())
+    }
+
+    fn inline_nodes(&self, nodes: impl Iterator<Item = NodeRef>) -> String {
+        let mut text = String::new();
+        for child in nodes {
+            if let Some(t) = child.as_text() {
+                text.push_str(&collapse_whitespace(&t.borrow()));
+                continue;
+            }
+            let inner = || self.inline(&child).trim().to_string();
+            match tag(&child).as_deref() {
+                Some("br") => text.push('\n'),
+                Some("strong" | "b") => text.push_str(&wrap(&inner(), "**")),
+                Some("em" | "i") => text.push_str(&wrap(&inner(), "_")),
+                Some("code") => text.push_str(&wrap(&child.text_contents(), "`")),
+                Some("a") => {
+                    let label = inner();
+                    match attr(&child, "href").filter(|h| !h.starts_with('#') && !h.starts_with("javascript:")) {
+                        Some(href) if !label.is_empty() => {
+                            text.push_str(&format!("[{}]({})", label, absolute(self.base, &href)))
+                        }
+                        _ => text.push_str(&label),
+                    }
+                }
+                Some("img") => {
+                    if let Some(src) = attr(&child, "src") {
+                        let alt = attr(&child, "alt").unwrap_or_default();
+                        text.push_str(&format!("![{}]({})", alt.trim(), absolute(self.base, &src)));
+                    }
+                }
+                _ => text.push_str(&self.inline(&child)),
+            }
+        }
+        text
+    }
+
+    fn block(&mut self, text: &str) {
+        let text = text.trim();
+        if !text.is_empty() {
+            self.out.push_str(text);
+            self.out.push_str("\n\n");
+        }
+    }
+
+    fn list(&mut self, node: &NodeRef, ordered: bool, depth: usize) {
+        let mut number = 0;
+        for item in node.children().filter(|c| tag(c).as_deref() == Some("li")) {
+            number += 1;
+            let marker = if ordered { format!("{}.", number) } else { "-".to_string() };
+            let text = self.inline_nodes(item.children().filter(|c| !matches!(tag(c).as_deref(), Some("ul" | "ol"))));
+            self.out.push_str(&format!("{}{} {}\n", "  ".repeat(depth), marker, text.trim()));
+            for nested in item.children().filter(|c| matches!(tag(c).as_deref(), Some("ul" | "ol"))) {
+                self.list(&nested, tag(&nested).as_deref() == Some("ol"), depth + 1);
+            }
+        }
+        if depth == 0 {
+            self.out.push('\n');
+        }
+    }
+
+    fn table(&mut self, node: &NodeRef) {
+        let rows: Vec<Vec<String>> = node
+            .select("tr")
+            .into_iter()
+            .flatten()
+            .map(|row| {
+                row.as_node()
+                    .children()
+                    .filter(|c| matches!(tag(c).as_deref(), Some("td" | "th")))
+                    .map(|c| self.inline(&c).trim().to_string())
+                    .collect()
+            })
+            .filter(|row: &Vec<String>| !row.is_empty())
+            .collect();
+        if !rows.is_empty() {
+            let table = markdown_table(&rows);
+            self.block(&table);
+        }
+    }
+
+    fn walk(&mut self, node: &NodeRef) {
+        for child in node.children() {
+            match tag(&child).as_deref() {
+                Some(h @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6")) => {
+                    let level: usize = h[1..].parse().unwrap_or(1);
+                    let text = self.inline(&child);
+                    if !text.trim().is_empty() {
+                        self.block(&format!("{} {}", "#".repeat(level), text.trim()));
+                    }
+                }
+                Some("p") => {
+                    let text = self.inline(&child);
+                    self.block(&text);
+                }
+                Some("pre") => {
+                    let code = child.text_contents();
+                    self.block(&format!("```\n{}\n```", code.trim_end()));
+                }
+                Some("blockquote") => {
+                    let mut inner = Markdown { base: self.base, out: String::new() };
+                    inner.walk(&child);
+                    let quoted: Vec<String> = inner.out.trim().lines().map(|l| format!("> {}", l)).collect();
+                    self.block(&quoted.join("\n"));
+                }
+                Some(list @ ("ul" | "ol")) => self.list(&child, list == "ol", 0),
+                Some("table") => self.table(&child),
+                Some("hr") => self.block("---"),
+                // Inline content loose in a container
+                Some("img" | "a" | "strong" | "em" | "b" | "i" | "span" | "code") => {
+                    let text = self.inline_nodes(std::iter::once(child));
+                    self.block(&text);
+                }
+                Some(_) => self.walk(&child),
+                None => {
+                    if let Some(text) = child.as_text() {
+                        let text = collapse_whitespace(&text.borrow());
+                        self.block(&text);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn wrap(text: &str, marker: &str) -> String {
+    if text.trim().is_empty() {
+        String::new()
+    } else {
+        format!("{}{}{}", marker, text.trim(), marker)
+    }
+}
+
+// Title, metadata and the main content of an HTML page as Markdown.
+pub fn readable(html: &str, url: &reqwest::Url) -> WebPage {
+    let document = kuchikiki::parse_html().one(html).document_node;
+
+    let title = meta(&document, &["og:title", "twitter:title"])
+        .or_else(|| document.select_first("title").ok().map(|t| t.text_contents().trim().to_string()))
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| url.to_string());
+    let lang = document.select_first("html").ok().and_then(|h| h.attributes.borrow().get("lang").map(String::from));
+    let byline = meta(&document, &["author", "article:author"]);
+    let site_name = meta(&document, &["og:site_name", "application-name"]);
+    let description = meta(&document, &["og:description", "description", "twitter:description"]);
+    let published = meta(&document, &["article:published_time", "datePublished", "date"]);
+
+    strip_boilerplate(&document);
+    let root = content_root(&document);
+    let mut markdown = Markdown { base: url, out: String::new() };
+    markdown.walk(&root);
+    let markdown = markdown.out.trim().to_string();
+
+    WebPage {
+        url: url.to_string(),
+        title,
+        byline,
+        site_name,
+        description,
+        published,
+        lang,
+        word_count: markdown.split_whitespace().count(),
+        markdown,
+    }
+}
+
+// Links on an HTML page, resolved against its URL and without fragments.
+// Read from the whole page, so navigation menus count too.
+pub fn links(html: &str, base: &reqwest::Url) -> Vec<reqwest::Url> {
+    let document = kuchikiki::parse_html().one(html).document_node;
+    let mut links: Vec<reqwest::Url> = Vec::new();
+    for anchor in document.select("a[href]").into_iter().flatten() {
+        let Some(href) = attr(anchor.as_node(), "href") else {
+            continue;
+        };
+        let Ok(mut link) = base.join(href.trim()) else {
+            continue;
+        };
+        link.set_fragment(None);
+        if matches!(link.scheme(), "http" | "https") && !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+pub async fn fetch(url: &str) -> Result<WebPage, String> {
+    fetch_with_links(url).await.map(|(page, _)| page)
+}
+
+// Like fetch, also returning the page's links (none for non-HTML content)
+pub async fn fetch_with_links(url: &str) -> Result<(WebPage, Vec<reqwest::Url>), String> {
+    let parsed = reqwest::Url::parse(url.trim()).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("Only http and https links can be fetched".to_string());
+    }
+
+    let request = crate::http::client()
+        .get(parsed)
+        .header(reqwest::header::ACCEPT, "text/html,application/xhtml+xml,text/plain;q=0.8")
+        .timeout(FETCH_TIMEOUT);
+    let response = crate::http::send(request)
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Fetching {} failed with status {}", url, response.status()));
+    }
+    if response.content_length().is_some_and(|len| len as usize > MAX_PAGE_BYTES) {
+        return Err("Page is too large to fetch".to_string());
+    }
+
+    let final_url = response.url().clone();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("text/html")
+        .to_lowercase();
+    let body = response.text().await.map_err(|e| format!("Failed to read page: {}", e))?;
+    if body.len() > MAX_PAGE_BYTES {
+        return Err("Page is too large to fetch".to_string());
+    }
+
+    if content_type.contains("html") {
+        tauri::async_runtime::spawn_blocking(move || (readable(&body, &final_url), links(&body, &final_url)))
+            .await
+            .map_err(|e| e.to_string())
+    } else if content_type.starts_with("text/") || content_type.contains("json") {
+        let page = WebPage {
+            url: final_url.to_string(),
+            title: final_url.path_segments().and_then(|mut s| s.next_back()).unwrap_or_default().to_string(),
+            byline: None,
+            site_name: final_url.host_str().map(str::to_string),
+            description: None,
+            published: None,
+            lang: None,
+            word_count: body.split_whitespace().count(),
+            markdown: body,
+        };
+        Ok((page, Vec::new()))
+    } else {
+        Err(format!("Can't read {} content", content_type))
+    }
+}
+
+#[tauri::command]
+pub async fn fetch_url(app: tauri::AppHandle, url: String) -> Result<WebPage, String> {
+    crate::app_lock::ensure_unlocked()?;
+    crate::analytics::track(&app, "fetch_url", fetch(&url).await)
+}
@@ -0,0 +1,96 @@
+use tauri_plugin_http::reqwest::{Method, StatusCode, Url};
+
+use crate::http;
+use crate::settings::WebDavSettings;
+
+// Plain WebDAV, which is what Nextcloud, ownCloud and most NAS boxes speak.
+// For Nextcloud the server URL is .../remote.php/dav/files/<user>/.
+pub struct WebDav {
+    server: Url,
+    // The remote folder under the server URL, ending in a slash
+    folder: Url,
+    username: String,
+    password: String,
+}
+
+impl WebDav {
+    pub fn new(settings: &WebDavSettings, password: String) -> Result<Self, String> {
+        let server = settings.url.trim();
+        if server.is_empty() {
+            return Err("Set the WebDAV server URL first".to_string());
+        }
+        let server = Url::parse(&format!("{}/", server.trim_end_matches('/')))
+            .map_err(|e| format!("Invalid WebDAV URL: {}", e))?;
+        let folder = Self::below(&server, &settings.folder)?;
+        Ok(Self { server, folder, username: settings.username.clone(), password })
+    }
+
+    fn below(server: &Url, path: &str) -> Result<Url, String> {
+        let mut url = server.clone();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            url = url.join(&format!("{}/", segment)).map_err(|e| e.to_string())?;
+        }
+        Ok(url)
+    }
+
+    async fn request(&self, method: Method, url: Url, body: Option<Vec<u8>>) -> Result<(StatusCode, Vec<u8>), String> {
+        let mut request = http::client().request(method, url).basic_auth(&self.username, Some(&self.password));
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        let response = http::send(request).await.map_err(|e| format!("Failed to reach the WebDAV server: {}", e))?;
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED {
+            return Err("The WebDAV server rejected the username or password".to_string());
+        }
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        Ok((status, body.to_vec()))
+    }
+
+    fn url(&self, name: &str) -> Result<Url, String> {
+        self.folder.join(name).map_err(|e| e.to_string())
+    }
+
+    // Creates the remote folder and its parents below the server URL; 405
+    // means one already exists
+    pub async fn prepare(&self) -> Result<(), String> {
+        let mkcol = Method::from_bytes(b"MKCOL").map_err(|e| e.to_string())?;
+        let relative = self.folder.as_str().strip_prefix(self.server.as_str()).unwrap_or_default();
+        let mut path = String::new();
+        for segment in relative.split('/').filter(|s| !s.is_empty()) {
+            path.push_str(segment);
+            path.push('/');
+            let url = Self::below(&self.server, &path)?;
+            let (status, _) = self.request(mkcol.clone(), url.clone(), None).await?;
+            if !(status.is_success() || status == StatusCode::METHOD_NOT_ALLOWED) {
+                return Err(format!("Failed to create the WebDAV folder {}: {}", url, status));
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let (status, body) = self.request(Method::GET, self.url(name)?, None).await?;
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(body)),
+            status => Err(format!("Failed to download {}: {}", name, status)),
+        }
+    }
+
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        let (status, _) = self.request(Method::PUT, self.url(name)?, Some(data)).await?;
+        if !status.is_success() {
+            return Err(format!("Failed to upload {}: {}", name, status));
+        }
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        let (status, _) = self.request(Method::DELETE, self.url(name)?, None).await?;
+        if !(status.is_success() || status == StatusCode::NOT_FOUND) {
+            return Err(format!("Failed to delete {}: {}", name, status));
+        }
+        Ok(())
+    }
+}
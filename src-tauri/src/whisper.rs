@@ -0,0 +1,264 @@
+use std::fs;
+use std::path::Path;
+
+use candle_core::{Device, IndexOp, Tensor, D};
+use candle_nn::ops::{log_softmax, softmax};
+use candle_nn::VarBuilder;
+use candle_transformers::models::whisper::{self as m, audio, model::Whisper, Config};
+use tokenizers::Tokenizer;
+
+use crate::transcription::{Transcript, TranscriptSegment};
+
+// Whisper run in-process on candle, like the local embedding model: the
+// audio is cut into 30 second windows and each one is decoded greedily,
+// without timestamps, into one segment. The weights are the openai/whisper-*
+// checkpoints as published on Hugging Face.
+
+// English-only checkpoints have one token fewer
+const MULTILINGUAL_VOCAB: usize = 51865;
+
+pub struct Model {
+    whisper: Whisper,
+    tokenizer: Tokenizer,
+    device: Device,
+    filters: Vec<f32>,
+}
+
+struct Tokens {
+    sot: u32,
+    eot: u32,
+    transcribe: u32,
+    no_timestamps: u32,
+    no_speech: u32,
+    // (token, language code), in the tokenizer's order
+    languages: Vec<(u32, String)>,
+}
+
+struct Decoded {
+    text: String,
+    avg_logprob: f64,
+    no_speech_prob: f64,
+}
+
+pub fn load(dir: &Path) -> Result<Model, String> {
+    let device = Device::Cpu;
+    let config: Config = serde_json::from_str(
+        &fs::read_to_string(dir.join("config.json")).map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| format!("Invalid whisper model config: {}", e))?;
+    let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| e.to_string())?;
+
+    // Safety: the weights file is ours and isn't modified while mapped
+    let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[dir.join("model.safetensors")], m::DTYPE, &device) }
+        .map_err(|e| format!("Failed to load whisper weights: {}", e))?;
+    let filters = mel_filters(config.num_mel_bins);
+    let whisper = Whisper::load(&vb, config).map_err(|e| e.to_string())?;
+    Ok(Model { whisper, tokenizer, device, filters })
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    // Slaney's scale, as librosa and OpenAI's Whisper use: linear below 1 kHz
+    // and logarithmic above
+    if hz < 1000.0 {
+        hz * 3.0 / 200.0
+    } else {
+        15.0 + (hz / 1000.0).ln() * 27.0 / 6.4f64.ln()
+    }
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    if mel < 15.0 {
+        mel * 200.0 / 3.0
+    } else {
+        1000.0 * ((mel - 15.0) * 6.4f64.ln() / 27.0).exp()
+    }
+}
+
+// The triangular filter bank pcm_to_mel expects, n_mels rows of one weight
+// per FFT bin, normalized so every filter has the same area
+fn mel_filters(n_mels: usize) -> Vec<f32> {
+    let bins = m::N_FFT / 2 + 1;
+    let max_mel = hz_to_mel(m::SAMPLE_RATE as f64 / 2.0);
+    let edges: Vec<f64> = (0..n_mels + 2).map(|i| mel_to_hz(max_mel * i as f64 / (n_mels + 1) as f64)).collect();
+    let mut filters = vec![0f32; n_mels * bins];
+    for mel in 0..n_mels {
+        let (low, center, high) = (edges[mel], edges[mel + 1], edges[mel + 2]);
+        let area = 2.0 / (high - low);
+        for bin in 0..bins {
+            let hz = bin as f64 * m::SAMPLE_RATE as f64 / m::N_FFT as f64;
+            let weight = ((hz - low) / (center - low)).min((high - hz) / (high - center)).max(0.0);
+            filters[mel * bins + bin] = (weight * area) as f32;
+        }
+    }
+    filters
+}
+
+fn token(tokenizer: &Tokenizer, name: &str) -> Result<u32, String> {
+    tokenizer.token_to_id(name).ok_or_else(|| format!("The whisper tokenizer has no {}", name))
+}
+
+fn tokens(model: &Model) -> Result<Tokens, String> {
+    let tokenizer = &model.tokenizer;
+    let sot = token(tokenizer, m::SOT_TOKEN)?;
+    let translate = token(tokenizer, m::TRANSLATE_TOKEN)?;
+    let no_speech = m::NO_SPEECH_TOKENS
+        .iter()
+        .find_map(|name| tokenizer.token_to_id(name))
+        .ok_or("The whisper tokenizer has no no-speech token")?;
+    // The language tokens sit between the start and task tokens
+    let languages = if model.whisper.config.vocab_size >= MULTILINGUAL_VOCAB {
+        (sot + 1..translate)
+            .filter_map(|id| {
+                let name = tokenizer.id_to_token(id)?;
+                Some((id, name.strip_prefix("<|")?.strip_suffix("|>")?.to_string()))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(Tokens {
+        sot,
+        eot: token(tokenizer, m::EOT_TOKEN)?,
+        transcribe: token(tokenizer, m::TRANSCRIBE_TOKEN)?,
+        no_timestamps: token(tokenizer, m::NO_TIMESTAMPS_TOKEN)?,
+        no_speech,
+        languages,
+    })
+}
+
+// The tokens the config rules out, and every timestamp token since windows
+// are decoded without them
+fn suppression(model: &Model, tokens: &Tokens) -> candle_core::Result<Tensor> {
+    let config = &model.whisper.config;
+    let mask: Vec<f32> = (0..config.vocab_size as u32)
+        .map(|id| {
+            if config.suppress_tokens.contains(&id) || id >= tokens.no_timestamps {
+                f32::NEG_INFINITY
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    Tensor::new(mask.as_slice(), &model.device)
+}
+
+// The language whose token the model finds likeliest after the start token
+fn detect_language(model: &mut Model, tokens: &Tokens, features: &Tensor) -> candle_core::Result<usize> {
+    let sot = Tensor::new(&[[tokens.sot]], &model.device)?;
+    let ys = model.whisper.decoder.forward(&sot, features, true)?;
+    let logits = model.whisper.decoder.final_linear(&ys.i(..1)?)?.i(0)?.i(0)?;
+    let ids: Vec<u32> = tokens.languages.iter().map(|(id, _)| *id).collect();
+    let scores = logits.index_select(&Tensor::new(ids.as_slice(), &model.device)?, 0)?;
+    Ok(scores.argmax(0)?.to_scalar::<u32>()? as usize)
+}
+
+fn decode(
+    model: &mut Model,
+    tokens: &Tokens,
+    features: &Tensor,
+    prompt: &[u32],
+    suppress: &Tensor,
+) -> candle_core::Result<Decoded> {
+    let max_tokens = model.whisper.config.max_target_positions;
+    let mut sequence = prompt.to_vec();
+    let mut sum_logprob = 0.0;
+    let mut no_speech_prob = 0.0;
+    for i in 0..max_tokens / 2 {
+        let input = Tensor::new(sequence.as_slice(), &model.device)?.unsqueeze(0)?;
+        let ys = model.whisper.decoder.forward(&input, features, i == 0)?;
+        if i == 0 {
+            let logits = model.whisper.decoder.final_linear(&ys.i(..1)?)?.i(0)?.i(0)?;
+            no_speech_prob = softmax(&logits, 0)?.i(tokens.no_speech as usize)?.to_scalar::<f32>()? as f64;
+        }
+        let (_, len, _) = ys.dims3()?;
+        let logits = model.whisper.decoder.final_linear(&ys.i((..1, len - 1..))?)?.i(0)?.i(0)?;
+        let logits = logits.broadcast_add(suppress)?;
+        let next = logits.argmax(0)?.to_scalar::<u32>()?;
+        if next == tokens.eot {
+            break;
+        }
+        sum_logprob += log_softmax(&logits, D::Minus1)?.i(next as usize)?.to_scalar::<f32>()? as f64;
+        sequence.push(next);
+        if sequence.len() >= max_tokens {
+            break;
+        }
+    }
+    let generated = &sequence[prompt.len()..];
+    let text = model.tokenizer.decode(generated, true).map_err(candle_core::Error::msg)?;
+    Ok(Decoded {
+        text: text.trim().to_string(),
+        avg_logprob: sum_logprob / generated.len().max(1) as f64,
+        no_speech_prob,
+    })
+}
+
+// Blocking; takes 16 kHz mono samples. `language` is a code such as "en",
+// detected from the first window when it's left out.
+pub fn transcribe(
+    model: &mut Model,
+    samples: &[f32],
+    language: Option<&str>,
+    mut on_progress: impl FnMut(u32),
+) -> Result<Transcript, String> {
+    let tokens = tokens(model)?;
+    let mut language = match language.filter(|l| !l.trim().is_empty() && *l != "auto") {
+        Some(code) if !tokens.languages.is_empty() => Some(
+            tokens
+                .languages
+                .iter()
+                .position(|(_, known)| known == code.trim())
+                .ok_or_else(|| format!("Whisper doesn't know the language {}", code))?,
+        ),
+        _ => None,
+    };
+    let failed = |e: candle_core::Error| format!("Transcription failed: {}", e);
+    let suppress = suppression(model, &tokens).map_err(failed)?;
+
+    let n_mels = model.whisper.config.num_mel_bins;
+    let mel = audio::pcm_to_mel(&model.whisper.config, samples, &model.filters);
+    let frames = mel.len() / n_mels;
+    let mel = Tensor::from_vec(mel, (1, n_mels, frames), &model.device).map_err(failed)?;
+    // pcm_to_mel pads with silence; only the frames with audio are decoded
+    let content_frames = (samples.len() / m::HOP_LENGTH).min(frames);
+    let frame_ms = (m::HOP_LENGTH * 1000 / m::SAMPLE_RATE) as u64;
+
+    let mut segments = Vec::new();
+    for seek in (0..content_frames).step_by(m::N_FRAMES) {
+        let window = m::N_FRAMES.min(frames - seek);
+        let features = mel
+            .narrow(2, seek, window)
+            .and_then(|window| model.whisper.encoder.forward(&window, true))
+            .map_err(failed)?;
+
+        let mut prompt = vec![tokens.sot];
+        if !tokens.languages.is_empty() {
+            let index = match language {
+                Some(index) => index,
+                None => *language.insert(detect_language(model, &tokens, &features).map_err(failed)?),
+            };
+            prompt.extend([tokens.languages[index].0, tokens.transcribe]);
+        }
+        prompt.push(tokens.no_timestamps);
+
+        let decoded = decode(model, &tokens, &features, &prompt, &suppress).map_err(failed)?;
+        let silent = decoded.no_speech_prob > m::NO_SPEECH_THRESHOLD && decoded.avg_logprob < m::LOGPROB_THRESHOLD;
+        let end = (seek + window).min(content_frames);
+        if !silent && !decoded.text.is_empty() {
+            segments.push(TranscriptSegment {
+                start_ms: seek as u64 * frame_ms,
+                end_ms: end as u64 * frame_ms,
+                text: decoded.text,
+            });
+        }
+        on_progress((end * 100 / content_frames) as u32);
+    }
+
+    Ok(Transcript {
+        text: segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" "),
+        language: match language {
+            Some(index) => Some(tokens.languages[index].1.clone()),
+            None => tokens.languages.is_empty().then(|| "en".to_string()),
+        },
+        segments,
+    })
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, PhysicalPosition, PhysicalSize, Runtime};
+
+// Where each window was and which chat it showed, so the next launch opens
+// the same way. Geometry is kept in physical pixels as the OS reports it and
+// only while the window is neither maximized nor minimized, so un-maximizing
+// after a restore goes back to the size it had. A window saved on a monitor
+// that has since gone away opens centered on the main one instead.
+
+const STATE_FILE: &str = "window_state.json";
+// Left where they are: the quick prompt centers itself each time it opens
+// and the menu bar chat opens under the icon
+const SKIPPED: &[&str] = &[crate::quick_prompt::LABEL, crate::mini_chat::LABEL];
+// How much of the title bar has to be on a monitor for a position to count
+const MIN_VISIBLE: i32 = 48;
+
+static STATE: Mutex<Option<HashMap<String, WindowState>>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_chat: Option<String>,
+}
+
+fn state_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::app_data_dir(app)?.join(STATE_FILE))
+}
+
+fn with_state<T>(app: &tauri::AppHandle, f: impl FnOnce(&mut HashMap<String, WindowState>) -> T) -> Result<T, String> {
+    let mut guard = STATE.lock().map_err(|e| e.to_string())?;
+    let states = guard.get_or_insert_with(|| {
+        state_path(app)
+            .and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    });
+    Ok(f(states))
+}
+
+fn save(app: &tauri::AppHandle) -> Result<(), String> {
+    let content = with_state(app, |states| serde_json::to_string_pretty(states))?.map_err(|e| e.to_string())?;
+    fs::write(state_path(app)?, content).map_err(|e| format!("Failed to save the window state: {}", e))
+}
+
+// Whether the top of a window at (x, y) that wide shows on a connected monitor
+fn on_screen<R: Runtime>(window: &tauri::WebviewWindow<R>, x: i32, y: i32, width: u32) -> bool {
+    let monitors = window.available_monitors().unwrap_or_default();
+    monitors.iter().any(|monitor| {
+        let (origin, size) = (monitor.position(), monitor.size());
+        let (right, bottom) = (origin.x + size.width as i32, origin.y + size.height as i32);
+        let overlap = (x + width as i32).min(right) - x.max(origin.x);
+        overlap >= MIN_VISIBLE && y >= origin.y && y + MIN_VISIBLE <= bottom
+    })
+}
+
+// Puts a window where it was last time
+pub fn restore(window: &tauri::WebviewWindow) {
+    let label = window.label().to_string();
+    if SKIPPED.contains(&label.as_str()) {
+        return;
+    }
+    let saved = with_state(window.app_handle(), |states| states.get(&label).cloned()).ok().flatten();
+    let Some(saved) = saved.filter(|s| s.width > 0 && s.height > 0) else {
+        return;
+    };
+    let mut size = PhysicalSize::new(saved.width, saved.height);
+    if on_screen(window, saved.x, saved.y, saved.width) {
+        let _ = window.set_size(size);
+        let _ = window.set_position(PhysicalPosition::new(saved.x, saved.y));
+    } else {
+        // Saved on a monitor that's gone; fit it on the main one
+        if let Ok(Some(monitor)) = window.primary_monitor() {
+            size.width = size.width.min(monitor.size().width);
+            size.height = size.height.min(monitor.size().height);
+        }
+        let _ = window.set_size(size);
+        let _ = window.center();
+    }
+    if saved.maximized {
+        let _ = window.maximize();
+    }
+}
+
+fn record(window: &tauri::Window) {
+    let label = window.label().to_string();
+    if SKIPPED.contains(&label.as_str()) || window.is_minimized().unwrap_or(false) {
+        return;
+    }
+    let maximized = window.is_maximized().unwrap_or(false);
+    let geometry = match (maximized, window.outer_position(), window.inner_size()) {
+        (false, Ok(position), Ok(size)) if size.width > 0 && size.height > 0 => Some((position, size)),
+        _ => None,
+    };
+    let result = with_state(window.app_handle(), |states| {
+        let state = states.entry(label).or_default();
+        state.maximized = maximized;
+        if let Some((position, size)) = geometry {
+            state.x = position.x;
+            state.y = position.y;
+            state.width = size.width;
+            state.height = size.height;
+        }
+    });
+    if let Err(e) = result {
+        log::warn!("{}", e);
+    }
+}
+
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    match event {
+        tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) => record(window),
+        // Written when the window loses focus or closes rather than on every
+        // move of a drag
+        tauri::WindowEvent::Focused(false) | tauri::WindowEvent::CloseRequested { .. } => {
+            record(window);
+            if let Err(e) = save(window.app_handle()) {
+                log::warn!("{}", e);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The UI tells which chat the window shows, to open it again next time
+#[tauri::command]
+pub fn set_window_chat(app: tauri::AppHandle, window: tauri::Window, chat_id: Option<String>) -> Result<(), String> {
+    crate::app_lock::ensure_unlocked()?;
+    let label = window.label().to_string();
+    let changed = with_state(&app, |states| {
+        let state = states.entry(label).or_default();
+        let changed = state.last_chat != chat_id;
+        state.last_chat = chat_id;
+        changed
+    })?;
+    if changed {
+        save(&app)?;
+    }
+    Ok(())
+}
+
+// The chat the window showed when it was last closed, if it still exists
+#[tauri::command]
+pub fn last_window_chat(app: tauri::AppHandle, window: tauri::Window) -> Result<Option<String>, String> {
+    crate::app_lock::ensure_unlocked()?;
+    let label = window.label().to_string();
+    let chat_id = with_state(&app, |states| states.get(&label).and_then(|s| s.last_chat.clone()))?;
+    Ok(chat_id.filter(|id| crate::chats::read(&app, id).is_ok()))
+}
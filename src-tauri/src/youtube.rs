@@ -0,0 +1,195 @@
+use serde::Serialize;
+use serde_json::Value;
+use tauri_plugin_http::reqwest;
+
+const WATCH_URL: &str = "https://www.youtube.com/watch";
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionSegment {
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionTrack {
+    pub language: String,
+    pub name: String,
+    // Speech recognition rather than uploaded by the creator
+    pub auto_generated: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoTranscript {
+    pub video_id: String,
+    pub title: String,
+    pub channel: Option<String>,
+    pub track: CaptionTrack,
+    pub available_tracks: Vec<CaptionTrack>,
+    pub segments: Vec<CaptionSegment>,
+    // "[mm:ss] text" lines, ready to paste into a chat
+    pub text: String,
+}
+
+fn valid_id(id: &str) -> bool {
+    id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Accepts watch, youtu.be, shorts, embed and live links, or a bare video id.
+pub fn video_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    if valid_id(input) {
+        return Some(input.to_string());
+    }
+    let url = reqwest::Url::parse(input).ok()?;
+    let host = url.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+    let mut segments = url.path_segments()?;
+    let id = match host {
+        "youtu.be" => segments.next().map(str::to_string),
+        "youtube.com" | "music.youtube.com" | "youtube-nocookie.com" => match segments.next() {
+            Some("watch") => url.query_pairs().find(|(k, _)| k == "v").map(|(_, v)| v.to_string()),
+            Some("shorts" | "embed" | "live" | "v") => segments.next().map(str::to_string),
+            _ => None,
+        },
+        _ => None,
+    };
+    id.filter(|id| valid_id(id))
+}
+
+fn timestamp(ms: u64) -> String {
+    let seconds = ms / 1000;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+// The watch page embeds the player config as `ytInitialPlayerResponse = {...};`
+fn player_response(html: &str) -> Result<Value, String> {
+    let start = html
+        .find("ytInitialPlayerResponse = ")
+        .map(|i| i + "ytInitialPlayerResponse = ".len())
+        .ok_or("Couldn't read the video page")?;
+    serde_json::Deserializer::from_str(&html[start..])
+        .into_iter::<Value>()
+        .next()
+        .ok_or("Couldn't read the video page")?
+        .map_err(|e| format!("Invalid player response: {}", e))
+}
+
+fn track_info(track: &Value) -> CaptionTrack {
+    let name = track["name"]["simpleText"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| track["name"]["runs"][0]["text"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    CaptionTrack {
+        language: track["languageCode"].as_str().unwrap_or_default().to_string(),
+        name,
+        auto_generated: track["kind"].as_str() == Some("asr"),
+    }
+}
+
+// Creator captions in the requested language, then auto captions in it, then
+// the same for English, then whatever comes first.
+fn pick_track<'a>(tracks: &'a [Value], language: Option<&str>) -> Option<&'a Value> {
+    let matches = |track: &&Value, lang: &str, auto: bool| {
+        let info = track_info(track);
+        info.auto_generated == auto && (info.language == lang || info.language.split('-').next() == Some(lang))
+    };
+    let mut preferred: Vec<&str> = language.into_iter().collect();
+    preferred.push("en");
+    preferred
+        .iter()
+        .flat_map(|lang| [(*lang, false), (*lang, true)])
+        .find_map(|(lang, auto)| tracks.iter().find(|t| matches(t, lang, auto)))
+        .or_else(|| tracks.first())
+}
+
+fn parse_json3(captions: &Value) -> Vec<CaptionSegment> {
+    let Some(events) = captions["events"].as_array() else {
+        return Vec::new();
+    };
+    events
+        .iter()
+        .filter_map(|event| {
+            let text: String = event["segs"].as_array()?.iter().filter_map(|s| s["utf8"].as_str()).collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                return None;
+            }
+            Some(CaptionSegment {
+                start_ms: event["tStartMs"].as_u64().unwrap_or(0),
+                duration_ms: event["dDurationMs"].as_u64().unwrap_or(0),
+                text,
+            })
+        })
+        .collect()
+}
+
+pub async fn transcript(url: &str, language: Option<&str>) -> Result<VideoTranscript, String> {
+    let id = video_id(url).ok_or("Not a YouTube video link")?;
+    let client = crate::http::client();
+
+    let watch = client
+        .get(WATCH_URL)
+        .query(&[("v", id.as_str()), ("hl", "en")])
+        .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
+        // Skips the EU consent interstitial
+        .header(reqwest::header::COOKIE, "CONSENT=YES+1");
+    let html = crate::http::send(watch)
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    let player = player_response(&html)?;
+
+    let status = player["playabilityStatus"]["status"].as_str().unwrap_or("OK");
+    if status != "OK" {
+        let reason = player["playabilityStatus"]["reason"].as_str().unwrap_or(status);
+        return Err(format!("Video unavailable: {}", reason));
+    }
+    let tracks = player["captions"]["playerCaptionsTracklistRenderer"]["captionTracks"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let track = pick_track(&tracks, language).ok_or("This video has no captions")?;
+    let base_url = track["baseUrl"].as_str().ok_or("Caption track has no URL")?;
+
+    let captions: Value = crate::http::send(client.get(format!("{}&fmt=json3", base_url)))
+        .await
+        .map_err(|e| format!("Failed to fetch captions: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid captions: {}", e))?;
+    let segments = parse_json3(&captions);
+    if segments.is_empty() {
+        return Err("The captions for this video are empty".to_string());
+    }
+
+    let text = segments
+        .iter()
+        .map(|s| format!("[{}] {}", timestamp(s.start_ms), s.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(VideoTranscript {
+        title: player["videoDetails"]["title"].as_str().unwrap_or(&id).to_string(),
+        channel: player["videoDetails"]["author"].as_str().map(str::to_string),
+        track: track_info(track),
+        available_tracks: tracks.iter().map(track_info).collect(),
+        video_id: id,
+        segments,
+        text,
+    })
+}
+
+#[tauri::command]
+pub async fn youtube_transcript(url: String, language: Option<String>) -> Result<VideoTranscript, String> {
+    crate::app_lock::ensure_unlocked()?;
+    transcript(&url, language.as_deref()).await
+}
@@ -0,0 +1,163 @@
+"use client";
+
+import { useState, useEffect, useRef, KeyboardEvent } from "react";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import ReactMarkdown from "react-markdown";
+import remarkGfm from "remark-gfm";
+import { Send, Trash2, Save } from "lucide-react";
+import { Message } from "@/types";
+
+// The chat that drops down from the menu bar icon, see mini_chat.rs. The
+// conversation is kept by the backend, so it's still there when the window
+// opens again.
+
+type StreamEvent =
+  | { type: "delta"; content: string }
+  | { type: "done"; content: string }
+  | { type: string };
+
+export default function MiniChat() {
+  const [messages, setMessages] = useState<Message[]>([]);
+  const [input, setInput] = useState("");
+  const [streaming, setStreaming] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const inputRef = useRef<HTMLTextAreaElement>(null);
+  const bottomRef = useRef<HTMLDivElement>(null);
+
+  const reload = () =>
+    invoke<Message[]>("mini_chat_messages")
+      .then(setMessages)
+      .catch((e) => setError(String(e)));
+
+  useEffect(() => {
+    // The window is hidden rather than closed, so catch up each time it shows
+    const onFocus = () => {
+      reload();
+      inputRef.current?.focus();
+    };
+    onFocus();
+    window.addEventListener("focus", onFocus);
+    return () => window.removeEventListener("focus", onFocus);
+  }, []);
+
+  useEffect(() => {
+    bottomRef.current?.scrollIntoView({ block: "end" });
+  }, [messages, streaming]);
+
+  const send = async () => {
+    const prompt = input.trim();
+    if (!prompt || streaming !== null) return;
+    setMessages((current) => [...current, { role: "user", content: prompt }]);
+    setInput("");
+    setStreaming("");
+    setError(null);
+    const onEvent = new Channel<StreamEvent>();
+    onEvent.onmessage = (event) => {
+      if (event.type === "delta" && "content" in event) {
+        setStreaming((current) => (current ?? "") + event.content);
+      }
+    };
+    try {
+      await invoke<string>("send_mini_prompt", { prompt, onEvent });
+    } catch (e) {
+      setError(String(e));
+      setInput(prompt);
+    } finally {
+      setStreaming(null);
+      reload();
+    }
+  };
+
+  const clear = () => {
+    invoke("clear_mini_chat")
+      .then(() => setMessages([]))
+      .catch((e) => setError(String(e)));
+  };
+
+  const save = () => {
+    invoke<string>("save_mini_chat")
+      .then(() => setMessages([]))
+      .catch((e) => setError(String(e)));
+  };
+
+  const handleKeyDown = (e: KeyboardEvent<HTMLTextAreaElement>) => {
+    if (e.key === "Enter" && !e.shiftKey) {
+      e.preventDefault();
+      send();
+    } else if (e.key === "Escape") {
+      e.preventDefault();
+      invoke("hide_mini_chat").catch(console.error);
+    }
+  };
+
+  return (
+    <main className="flex h-screen w-full flex-col overflow-hidden bg-black/90 rounded-xl border border-white/10">
+      <header className="flex items-center justify-between px-3 py-2 border-b border-white/10">
+        <span className="text-white font-medium text-xs tracking-wide font-mono">
+          Anchor
+        </span>
+        <div className="flex items-center gap-1">
+          <button
+            onClick={save}
+            disabled={messages.length === 0 || streaming !== null}
+            className="p-1.5 rounded-lg text-white/50 hover:text-white transition-colors hover:bg-white/5 disabled:opacity-30"
+            title="Save as Chat"
+          >
+            <Save size={14} />
+          </button>
+          <button
+            onClick={clear}
+            disabled={messages.length === 0 || streaming !== null}
+            className="p-1.5 rounded-lg text-white/50 hover:text-white transition-colors hover:bg-white/5 disabled:opacity-30"
+            title="Clear"
+          >
+            <Trash2 size={14} />
+          </button>
+        </div>
+      </header>
+      <div className="flex-1 overflow-y-auto p-3 space-y-3 text-sm">
+        {messages.map((message, i) =>
+          message.role === "user" ? (
+            <div key={i} className="ml-8 rounded-lg bg-white/10 px-3 py-2 text-white whitespace-pre-wrap">
+              {message.content}
+            </div>
+          ) : (
+            <div key={i} className="text-white/80 prose prose-invert prose-sm max-w-none">
+              <ReactMarkdown remarkPlugins={[remarkGfm]}>{message.content}</ReactMarkdown>
+            </div>
+          ),
+        )}
+        {streaming !== null && (
+          <div className="text-white/80 prose prose-invert prose-sm max-w-none">
+            {streaming ? (
+              <ReactMarkdown remarkPlugins={[remarkGfm]}>{streaming}</ReactMarkdown>
+            ) : (
+              <p className="text-white/30">Thinking...</p>
+            )}
+          </div>
+        )}
+        {error && <p className="text-red-400">{error}</p>}
+        <div ref={bottomRef} />
+      </div>
+      <div className="flex items-start gap-2 p-3 border-t border-white/10">
+        <textarea
+          ref={inputRef}
+          value={input}
+          onChange={(e) => setInput(e.target.value)}
+          onKeyDown={handleKeyDown}
+          placeholder="Ask anything..."
+          rows={1}
+          className="flex-1 resize-none bg-transparent text-white text-sm placeholder-white/30 outline-none"
+        />
+        <button
+          onClick={send}
+          disabled={streaming !== null || !input.trim()}
+          className="p-1.5 rounded-lg text-white/50 hover:text-white transition-colors hover:bg-white/5 disabled:opacity-30"
+          title="Send"
+        >
+          <Send size={14} />
+        </button>
+      </div>
+    </main>
+  );
+}
@@ -0,0 +1,96 @@
+"use client";
+
+import { useState, useEffect, useRef, KeyboardEvent } from "react";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import ReactMarkdown from "react-markdown";
+import remarkGfm from "remark-gfm";
+import { Send } from "lucide-react";
+
+// The window opened by the quick prompt shortcut, see quick_prompt.rs. The
+// backend saves the prompt and reply to a quick chat; this only shows them.
+
+type StreamEvent =
+  | { type: "delta"; content: string }
+  | { type: "done"; content: string }
+  | { type: string };
+
+export default function QuickPrompt() {
+  const [prompt, setPrompt] = useState("");
+  const [reply, setReply] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [isLoading, setIsLoading] = useState(false);
+  const inputRef = useRef<HTMLTextAreaElement>(null);
+
+  useEffect(() => {
+    // The window is hidden rather than closed, so focus it each time it shows
+    const focus = () => inputRef.current?.focus();
+    focus();
+    window.addEventListener("focus", focus);
+    return () => window.removeEventListener("focus", focus);
+  }, []);
+
+  const send = async () => {
+    if (!prompt.trim() || isLoading) return;
+    setIsLoading(true);
+    setReply("");
+    setError(null);
+    const onEvent = new Channel<StreamEvent>();
+    onEvent.onmessage = (event) => {
+      if (event.type === "delta" && "content" in event) {
+        setReply((current) => current + event.content);
+      }
+    };
+    try {
+      await invoke<string>("send_quick_prompt", { prompt, onEvent });
+      setPrompt("");
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
+  const handleKeyDown = (e: KeyboardEvent<HTMLTextAreaElement>) => {
+    if (e.key === "Enter" && !e.shiftKey) {
+      e.preventDefault();
+      send();
+    } else if (e.key === "Escape") {
+      e.preventDefault();
+      invoke("hide_quick_prompt").catch(console.error);
+    }
+  };
+
+  return (
+    <main className="flex h-screen w-full flex-col overflow-hidden bg-black/90 rounded-xl border border-white/10">
+      <div className="flex items-start gap-2 p-3 border-b border-white/10">
+        <textarea
+          ref={inputRef}
+          value={prompt}
+          onChange={(e) => setPrompt(e.target.value)}
+          onKeyDown={handleKeyDown}
+          placeholder="Ask anything..."
+          rows={2}
+          disabled={isLoading}
+          className="flex-1 resize-none bg-transparent text-white text-sm placeholder-white/30 outline-none"
+        />
+        <button
+          onClick={send}
+          disabled={isLoading || !prompt.trim()}
+          className="p-2 rounded-lg text-white/50 hover:text-white transition-colors hover:bg-white/5 disabled:opacity-30"
+          title="Send"
+        >
+          <Send size={16} />
+        </button>
+      </div>
+      <div className="flex-1 overflow-y-auto p-3 text-sm text-white/80 prose prose-invert prose-sm max-w-none">
+        {error ? (
+          <p className="text-red-400">{error}</p>
+        ) : reply ? (
+          <ReactMarkdown remarkPlugins={[remarkGfm]}>{reply}</ReactMarkdown>
+        ) : (
+          isLoading && <p className="text-white/30">Thinking...</p>
+        )}
+      </div>
+    </main>
+  );
+}